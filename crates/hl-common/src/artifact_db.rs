@@ -0,0 +1,275 @@
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use rusqlite::{params, types::Type, Connection, OptionalExtension, Row};
+use serde_json::Value;
+use tracing::warn;
+
+use crate::artifacts::{ActionLogRecord, RoutedOrderRecord};
+
+/// Websocket events buffered before they are written in one transaction.
+pub(crate) const WS_BATCH_SIZE: usize = 256;
+
+/// Applied in order; `PRAGMA user_version` records how many have run.
+const MIGRATIONS: &[&str] = &["
+CREATE TABLE per_action (
+    id INTEGER PRIMARY KEY,
+    step_idx INTEGER NOT NULL,
+    action TEXT NOT NULL,
+    submit_ts_ms INTEGER NOT NULL,
+    window_key_ms INTEGER NOT NULL,
+    request TEXT NOT NULL,
+    ack TEXT,
+    observed TEXT,
+    notes TEXT,
+    duration_ms INTEGER,
+    branch TEXT,
+    checksum TEXT
+);
+CREATE TABLE ws_stream (
+    id INTEGER PRIMARY KEY,
+    channel TEXT,
+    event TEXT NOT NULL
+);
+CREATE TABLE routed_orders (
+    id INTEGER PRIMARY KEY,
+    ts_ms INTEGER NOT NULL,
+    oid INTEGER,
+    coin TEXT NOT NULL,
+    side TEXT NOT NULL,
+    px REAL NOT NULL,
+    sz REAL NOT NULL,
+    tif TEXT NOT NULL,
+    reduce_only INTEGER NOT NULL,
+    builder_code TEXT,
+    spot INTEGER NOT NULL,
+    duplicate_of_row INTEGER
+);
+CREATE TABLE run_meta (
+    key TEXT PRIMARY KEY,
+    value TEXT NOT NULL
+);
+CREATE INDEX idx_per_action_step ON per_action(step_idx);
+CREATE INDEX idx_routed_orders_coin ON routed_orders(coin);
+"];
+
+/// `run_artifacts.db`: the SQLite artifact backend. JSON-valued fields are stored as JSON
+/// text; `routed_orders.duplicate_of_row` replaces `orders_routed_duplicates.csv`.
+///
+/// Websocket events are buffered and committed in batches: once `WS_BATCH_SIZE` are
+/// pending, before each action record or meta write, and on drop.
+pub struct ArtifactDb {
+    conn: Connection,
+    pending_ws: Vec<Value>,
+}
+
+impl ArtifactDb {
+    /// Opens (or creates) the database and applies any pending migrations.
+    pub fn open(path: &Path) -> Result<Self> {
+        let mut conn =
+            Connection::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+        migrate(&mut conn).with_context(|| format!("failed to migrate {}", path.display()))?;
+        Ok(Self {
+            conn,
+            pending_ws: Vec::new(),
+        })
+    }
+
+    pub fn insert_action(&mut self, record: &ActionLogRecord) -> Result<()> {
+        self.insert_actions(std::slice::from_ref(record))
+    }
+
+    /// Inserts `records` in one transaction, for bulk imports.
+    pub fn insert_actions(&mut self, records: &[ActionLogRecord]) -> Result<()> {
+        self.flush_ws_events()?;
+        let json = |value: Option<&Value>| value.map(Value::to_string);
+        let tx = self.conn.unchecked_transaction()?;
+        {
+            let mut insert = tx.prepare(
+                "INSERT INTO per_action (step_idx, action, submit_ts_ms, window_key_ms, request,
+                     ack, observed, notes, duration_ms, branch, checksum)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+            )?;
+            for record in records {
+                insert.execute(params![
+                    record.step_idx as i64,
+                    record.action,
+                    record.submit_ts_ms,
+                    record.window_key_ms,
+                    record.request.to_string(),
+                    json(record.ack.as_ref()),
+                    json(record.observed.as_ref()),
+                    record.notes,
+                    record.duration_ms,
+                    record.branch,
+                    record.checksum,
+                ])?;
+            }
+        }
+        tx.commit().context("failed to insert per_action rows")
+    }
+
+    pub fn insert_ws_event(&mut self, raw: &Value) -> Result<()> {
+        self.pending_ws.push(raw.clone());
+        if self.pending_ws.len() >= WS_BATCH_SIZE {
+            self.flush_ws_events()?;
+        }
+        Ok(())
+    }
+
+    /// Commits every buffered websocket event in one transaction.
+    pub fn flush_ws_events(&mut self) -> Result<()> {
+        if self.pending_ws.is_empty() {
+            return Ok(());
+        }
+        let tx = self.conn.unchecked_transaction()?;
+        {
+            let mut insert =
+                tx.prepare("INSERT INTO ws_stream (channel, event) VALUES (?1, ?2)")?;
+            for raw in &self.pending_ws {
+                insert.execute(params![
+                    raw.get("channel").and_then(Value::as_str),
+                    raw.to_string()
+                ])?;
+            }
+        }
+        tx.commit().context("failed to insert ws_stream rows")?;
+        self.pending_ws.clear();
+        Ok(())
+    }
+
+    /// `duplicate_of_row` is the 1-based row of the oid's first order, as in the CSV.
+    pub fn insert_routed_order(
+        &self,
+        record: &RoutedOrderRecord,
+        duplicate_of_row: Option<usize>,
+    ) -> Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute(
+            "INSERT INTO routed_orders (ts_ms, oid, coin, side, px, sz, tif, reduce_only,
+                 builder_code, spot, duplicate_of_row)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+            params![
+                record.ts_ms,
+                record.oid.map(|oid| oid as i64),
+                record.coin,
+                record.side,
+                record.px,
+                record.sz,
+                record.tif,
+                record.reduce_only,
+                record.builder_code,
+                record.spot,
+                duplicate_of_row.map(|row| row as i64),
+            ],
+        )?;
+        tx.commit().context("failed to insert routed_orders row")
+    }
+
+    /// Stores each top-level field of `meta` as one row, replacing earlier values.
+    pub fn write_meta(&mut self, meta: &Value) -> Result<()> {
+        self.flush_ws_events()?;
+        let tx = self.conn.unchecked_transaction()?;
+        if let Some(fields) = meta.as_object() {
+            for (key, value) in fields {
+                tx.execute(
+                    "INSERT OR REPLACE INTO run_meta (key, value) VALUES (?1, ?2)",
+                    params![key, value.to_string()],
+                )?;
+            }
+        }
+        tx.commit().context("failed to write run_meta")
+    }
+
+    pub fn action_records(&self) -> Result<Vec<ActionLogRecord>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT step_idx, action, submit_ts_ms, window_key_ms, request, ack, observed, notes,
+                 duration_ms, branch, checksum
+             FROM per_action ORDER BY id",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok(ActionLogRecord {
+                step_idx: row.get::<_, i64>(0)? as usize,
+                action: row.get(1)?,
+                submit_ts_ms: row.get(2)?,
+                window_key_ms: row.get(3)?,
+                request: json_column(row, 4)?.unwrap_or_default(),
+                ack: json_column(row, 5)?,
+                observed: json_column(row, 6)?,
+                notes: row.get(7)?,
+                duration_ms: row.get(8)?,
+                branch: row.get(9)?,
+                checksum: row.get(10)?,
+            })
+        })?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// Committed events; ones still buffered by this handle are not included.
+    pub fn ws_events(&self) -> Result<Vec<Value>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT event FROM ws_stream ORDER BY id")?;
+        let rows = stmt.query_map([], |row| row.get::<_, String>(0))?;
+        rows.map(|raw| Ok(serde_json::from_str(&raw?)?)).collect()
+    }
+
+    /// Primary routed orders, without rows marked as duplicates.
+    pub fn routed_orders(&self) -> Result<Vec<RoutedOrderRecord>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT ts_ms, oid, coin, side, px, sz, tif, reduce_only, builder_code, spot
+             FROM routed_orders WHERE duplicate_of_row IS NULL ORDER BY id",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok(RoutedOrderRecord {
+                ts_ms: row.get(0)?,
+                oid: row.get::<_, Option<i64>>(1)?.map(|oid| oid as u64),
+                coin: row.get(2)?,
+                side: row.get(3)?,
+                px: row.get(4)?,
+                sz: row.get(5)?,
+                tif: row.get(6)?,
+                reduce_only: row.get(7)?,
+                builder_code: row.get(8)?,
+                spot: row.get(9)?,
+            })
+        })?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    pub fn meta_value(&self, key: &str) -> Result<Option<Value>> {
+        let raw: Option<String> = self
+            .conn
+            .query_row("SELECT value FROM run_meta WHERE key = ?1", [key], |row| {
+                row.get(0)
+            })
+            .optional()?;
+        Ok(raw.map(|raw| serde_json::from_str(&raw)).transpose()?)
+    }
+}
+
+impl Drop for ArtifactDb {
+    fn drop(&mut self) {
+        if let Err(err) = self.flush_ws_events() {
+            warn!("failed to write buffered ws_stream rows: {err:#}");
+        }
+    }
+}
+
+fn json_column(row: &Row<'_>, idx: usize) -> rusqlite::Result<Option<Value>> {
+    row.get::<_, Option<String>>(idx)?
+        .map(|raw| serde_json::from_str(&raw))
+        .transpose()
+        .map_err(|err| rusqlite::Error::FromSqlConversionFailure(idx, Type::Text, Box::new(err)))
+}
+
+fn migrate(conn: &mut Connection) -> Result<()> {
+    let applied: usize = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    let tx = conn.transaction()?;
+    for (version, migration) in MIGRATIONS.iter().enumerate().skip(applied) {
+        tx.execute_batch(migration)?;
+        tx.pragma_update(None, "user_version", version + 1)?;
+    }
+    tx.commit()?;
+    Ok(())
+}
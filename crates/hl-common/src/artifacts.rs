@@ -1,18 +1,28 @@
 use std::{
+    collections::HashMap,
     fs::{self, File},
-    io::{BufWriter, Write},
+    io::{BufRead, BufReader, BufWriter, Write},
     path::{Path, PathBuf},
+    time::{Duration, Instant},
 };
 
-use anyhow::{Context, Result};
-use serde::{Deserialize, Serialize};
+use anyhow::{anyhow, Context, Result};
+#[cfg(feature = "gzip")]
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use serde_json::Value;
+use sha2::{Digest, Sha256};
+use tracing::{info, warn};
 
+#[cfg(feature = "sqlite")]
+use crate::artifact_db::ArtifactDb;
 use crate::time::window_start_ms;
 
 const DEFAULT_WINDOW_MS: i64 = 200;
+/// Minimum gap between flushes of gzipped JSONL logs; each one is a deflate sync-flush.
+const GZIP_FLUSH_INTERVAL: Duration = Duration::from_secs(1);
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ActionLogRecord {
     pub step_idx: usize,
@@ -26,11 +36,94 @@ pub struct ActionLogRecord {
     pub observed: Option<Value>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub notes: Option<String>,
+    /// Wall-clock time from just before the API call until confirmation (or timeout),
+    /// including post-processing. Absent in logs written before it was recorded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration_ms: Option<i64>,
+    /// Position inside an `if` step, e.g. `3.else.0`; `step_idx` is then the top-level
+    /// `if` step's index.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub branch: Option<String>,
+    /// Hex SHA-256 of the canonical JSON of this record without the checksum. Unkeyed, so it
+    /// catches truncated or corrupted records, not edits: anyone can recompute it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checksum: Option<String>,
 }
 
-#[derive(Debug, Serialize)]
+impl ActionLogRecord {
+    pub fn compute_checksum(&self) -> Result<String> {
+        let mut unsigned = self.clone();
+        unsigned.checksum = None;
+        // Round-trip through Value so object keys are emitted in sorted order.
+        let canonical = serde_json::to_value(&unsigned)
+            .with_context(|| {
+                format!(
+                    "failed to serialize step {} ({}) for its checksum",
+                    self.step_idx, self.action
+                )
+            })?
+            .to_string();
+        Ok(Sha256::digest(canonical.as_bytes())
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect())
+    }
+
+    /// Sets `duration_ms` to the time elapsed since `start`. Any checksum is dropped, since
+    /// it no longer matches; [`RunArtifacts::log_action`] stamps a fresh one.
+    pub fn with_duration(mut self, start: Instant) -> Self {
+        self.duration_ms = Some(start.elapsed().as_millis() as i64);
+        self.checksum = None;
+        self
+    }
+
+    /// `None` when the record carries no checksum (legacy logs). A record that cannot be
+    /// serialized does not match.
+    pub fn verify_checksum(&self) -> Option<bool> {
+        self.checksum.as_ref().map(|expected| {
+            self.compute_checksum()
+                .is_ok_and(|actual| actual == *expected)
+        })
+    }
+
+    /// Whether the ack reports a failure: a status other than `ok`, `dryRun` or
+    /// `skipped`, or an `ok` ack listing a rejected order. Records without an ack never fail.
+    pub fn ack_failed(&self) -> bool {
+        let Some(ack) = &self.ack else {
+            return false;
+        };
+        let status = ack.get("status").and_then(Value::as_str).unwrap_or("");
+        let order_error = ack
+            .pointer("/data/statuses")
+            .and_then(Value::as_array)
+            .is_some_and(|statuses| statuses.iter().any(|s| s["kind"] == "error"));
+        !matches!(status, "ok" | "dryRun" | "skipped") || order_error
+    }
+
+    /// Whether the ack lists at least one order and every one of them filled.
+    pub fn all_orders_filled(&self) -> bool {
+        self.ack
+            .as_ref()
+            .and_then(|ack| ack.pointer("/data/statuses"))
+            .and_then(Value::as_array)
+            .is_some_and(|statuses| {
+                !statuses.is_empty() && statuses.iter().all(|s| s["kind"] == "filled")
+            })
+    }
+
+    pub fn is_order_action(&self) -> bool {
+        matches!(
+            self.action.as_str(),
+            "perp_orders" | "multi_coin_orders" | "spot_orders"
+        )
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct RoutedOrderRecord {
+    /// `ts` is the `orders_routed.csv` column name consumers already read.
+    #[serde(rename = "ts")]
     pub ts_ms: i64,
     pub oid: Option<u64>,
     pub coin: String,
@@ -40,12 +133,293 @@ pub struct RoutedOrderRecord {
     pub tif: String,
     pub reduce_only: bool,
     pub builder_code: Option<String>,
+    /// Spot market order; absent (false) in files written before spot support.
+    #[serde(default)]
+    pub spot: bool,
+}
+
+impl RoutedOrderRecord {
+    /// CSV column names, taken from the serde field names so the header cannot drift from
+    /// the rows.
+    fn csv_header() -> Result<csv::StringRecord> {
+        let mut writer = csv::Writer::from_writer(Vec::new());
+        writer.serialize(Self::default())?;
+        let data = writer.into_inner().context("failed to flush csv header")?;
+        Ok(csv::Reader::from_reader(data.as_slice()).headers()?.clone())
+    }
+
+    fn csv_row(&self) -> Result<csv::StringRecord> {
+        let mut writer = csv::WriterBuilder::new()
+            .has_headers(false)
+            .from_writer(Vec::new());
+        writer.serialize(self)?;
+        let data = writer.into_inner().context("failed to flush csv row")?;
+        csv::ReaderBuilder::new()
+            .has_headers(false)
+            .from_reader(data.as_slice())
+            .records()
+            .next()
+            .context("routed order serialized to no csv row")?
+            .context("failed to read back routed order csv row")
+    }
+}
+
+/// Top-of-book levels captured right before an order step, one line of `l2_snapshots.jsonl`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct L2Snapshot {
+    pub ts_ms: i64,
+    pub coin: String,
+    pub step_idx: usize,
+    /// `[px, sz]` pairs, best price first.
+    pub bids: Vec<[f64; 2]>,
+    pub asks: Vec<[f64; 2]>,
+}
+
+/// Order outcomes over a run, stored under `tradingStats` in `run_meta.json`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TradingStats {
+    /// Orders submitted by order steps; acks such as `success` or `dryRun` count here
+    /// but in none of the outcome buckets below.
+    pub orders_placed: usize,
+    pub filled: usize,
+    pub resting: usize,
+    pub errored: usize,
+    /// From an order's `submit_ts_ms` to the `time` of its first logged `userFills` event.
+    pub mean_fill_latency_ms: Option<f64>,
+    pub p99_fill_latency_ms: Option<i64>,
+    /// Mean fill price versus `resolvedPx`, in basis points; positive means a worse price
+    /// than requested (paid more on a buy, received less on a sell).
+    pub avg_slippage_bps: Option<f64>,
+}
+
+/// Fill statistics over the order steps in `records`.
+pub fn compute_stats(records: &[ActionLogRecord]) -> TradingStats {
+    let mut stats = StatsAccumulator::default();
+    for record in records {
+        stats.add(record);
+    }
+    stats.finish()
+}
+
+/// [`TradingStats`] built up one record at a time, keeping only per-order state.
+#[derive(Debug, Default)]
+pub struct StatsAccumulator {
+    stats: TradingStats,
+    /// oid -> (submit_ts_ms, resolvedPx, is_buy, avgPx from the ack)
+    placed: HashMap<u64, (i64, Option<f64>, bool, Option<f64>)>,
+    /// oid -> (time, px) of its first logged `userFills` event.
+    first_fills: HashMap<u64, (Option<i64>, Option<f64>)>,
+    /// Slippage of fills whose status carried no oid.
+    slippages: Vec<f64>,
+}
+
+impl StatsAccumulator {
+    pub fn add(&mut self, record: &ActionLogRecord) {
+        if record.is_order_action() {
+            self.add_order_step(record);
+        }
+        for event in observed_events(record) {
+            if event.get("channel").and_then(Value::as_str) != Some("userFills") {
+                continue;
+            }
+            if let Some(oid) = event.get("oid").and_then(Value::as_u64) {
+                self.first_fills.entry(oid).or_insert_with(|| {
+                    (
+                        event.get("time").and_then(Value::as_i64),
+                        event.get("px").and_then(json_f64),
+                    )
+                });
+            }
+        }
+    }
+
+    fn add_order_step(&mut self, record: &ActionLogRecord) {
+        let orders = request_orders(record);
+        let ack = record.ack.as_ref();
+        let statuses = ack
+            .and_then(|ack| ack.pointer("/data/statuses"))
+            .and_then(Value::as_array);
+        let rejected = ack
+            .and_then(|ack| ack.get("status"))
+            .and_then(Value::as_str)
+            .is_some_and(|status| status == "err");
+        self.stats.orders_placed += orders.len().max(statuses.map_or(0, Vec::len));
+        if rejected {
+            self.stats.errored += orders.len();
+            return;
+        }
+        for (idx, status) in statuses.into_iter().flatten().enumerate() {
+            let (resolved_px, is_buy) = orders.get(idx).copied().unwrap_or((None, true));
+            let avg_px = status.get("avgPx").and_then(json_f64);
+            match status.get("kind").and_then(Value::as_str) {
+                Some("filled") => self.stats.filled += 1,
+                Some("resting") => self.stats.resting += 1,
+                Some("error") => self.stats.errored += 1,
+                _ => {}
+            }
+            if let Some(oid) = status.get("oid").and_then(Value::as_u64) {
+                self.placed
+                    .insert(oid, (record.submit_ts_ms, resolved_px, is_buy, avg_px));
+            } else if let (Some(resolved), Some(fill)) = (resolved_px, avg_px) {
+                self.slippages.push(slippage_bps(resolved, fill, is_buy));
+            }
+        }
+    }
+
+    /// The statistics over every record added so far.
+    pub fn finish(&self) -> TradingStats {
+        let mut stats = self.stats.clone();
+        let mut latencies = Vec::new();
+        let mut slippages = self.slippages.clone();
+        for (oid, (submit_ts, resolved_px, is_buy, avg_px)) in &self.placed {
+            let first_fill = self.first_fills.get(oid);
+            if let Some(time) = first_fill.and_then(|(time, _)| *time) {
+                latencies.push((time - submit_ts).max(0));
+            }
+            let fill = avg_px.or_else(|| first_fill.and_then(|(_, px)| *px));
+            if let (Some(resolved), Some(fill)) = (resolved_px, fill) {
+                slippages.push(slippage_bps(*resolved, fill, *is_buy));
+            }
+        }
+
+        if !latencies.is_empty() {
+            latencies.sort_unstable();
+            let rank = (latencies.len() as f64 * 0.99).ceil() as usize;
+            stats.mean_fill_latency_ms =
+                Some(latencies.iter().sum::<i64>() as f64 / latencies.len() as f64);
+            stats.p99_fill_latency_ms = Some(latencies[rank.max(1) - 1]);
+        }
+        if !slippages.is_empty() {
+            stats.avg_slippage_bps = Some(slippages.iter().sum::<f64>() / slippages.len() as f64);
+        }
+        stats
+    }
+}
+
+/// `(resolvedPx, is_buy)` for each order an order step submitted, in ack order.
+fn request_orders(record: &ActionLogRecord) -> Vec<(Option<f64>, bool)> {
+    let Some(request) = record.request.get(&record.action) else {
+        return Vec::new();
+    };
+    let is_buy = |value: &Value| value.get("side").and_then(Value::as_str) != Some("sell");
+    if record.action == "multi_coin_orders" {
+        let side = is_buy(request);
+        let prices = request.get("resolvedPx").and_then(Value::as_array);
+        let coins = request
+            .get("coins")
+            .and_then(Value::as_array)
+            .map_or(0, Vec::len);
+        return (0..coins)
+            .map(|idx| (prices.and_then(|px| px.get(idx)).and_then(json_f64), side))
+            .collect();
+    }
+    request
+        .get("orders")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .map(|order| (order.get("resolvedPx").and_then(json_f64), is_buy(order)))
+        .collect()
+}
+
+fn observed_events(record: &ActionLogRecord) -> Vec<&Value> {
+    match &record.observed {
+        Some(Value::Array(events)) => events.iter().collect(),
+        Some(event) => vec![event],
+        None => Vec::new(),
+    }
+}
+
+/// Prices arrive as numbers or, from the exchange, as decimal strings.
+fn json_f64(value: &Value) -> Option<f64> {
+    value
+        .as_f64()
+        .or_else(|| value.as_str().and_then(|raw| raw.parse().ok()))
+}
+
+fn slippage_bps(resolved_px: f64, fill_px: f64, is_buy: bool) -> f64 {
+    let diff = if is_buy {
+        fill_px - resolved_px
+    } else {
+        resolved_px - fill_px
+    };
+    diff / resolved_px * 10_000.0
+}
+
+/// A plain or gzip-compressed JSONL artifact.
+type JsonlWriter = BufWriter<JsonlSink>;
+
+/// The file under a JSONL artifact, written directly or through gzip.
+enum JsonlSink {
+    Plain(File),
+    #[cfg(feature = "gzip")]
+    Gzip(GzEncoder<File>),
+}
+
+impl JsonlSink {
+    /// Flushes, and for gzip writes the trailer that makes the file readable to the end.
+    fn finish(self) -> std::io::Result<()> {
+        match self {
+            JsonlSink::Plain(mut file) => file.flush(),
+            #[cfg(feature = "gzip")]
+            JsonlSink::Gzip(encoder) => encoder.finish().map(drop),
+        }
+    }
+}
+
+impl Write for JsonlSink {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            JsonlSink::Plain(file) => file.write(buf),
+            #[cfg(feature = "gzip")]
+            JsonlSink::Gzip(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            JsonlSink::Plain(file) => file.flush(),
+            #[cfg(feature = "gzip")]
+            JsonlSink::Gzip(encoder) => encoder.flush(),
+        }
+    }
 }
 
 pub struct RunArtifacts {
-    per_action: BufWriter<File>,
-    ws_stream: BufWriter<File>,
-    routed_csv: csv::Writer<File>,
+    /// `None` (like `ws_stream`) once [`RunArtifacts::use_sqlite`] has switched backends.
+    per_action: Option<JsonlWriter>,
+    ws_stream: Option<JsonlWriter>,
+    /// Gzipped logs are flushed every `gzip_flush_interval` rather than per record, and
+    /// finished when dropped.
+    compress: bool,
+    gzip_flush_interval: Duration,
+    last_gzip_flush: Instant,
+    #[cfg(feature = "sqlite")]
+    db: Option<ArtifactDb>,
+    /// `None` once [`RunArtifacts::disable_routed_csv`] has removed the CSV.
+    routed_csv: Option<csv::Writer<File>>,
+    routed_path: PathBuf,
+    /// Every row of the primary routed CSV, for [`RunArtifacts::write_routed_parquet`];
+    /// `None` unless [`RunArtifacts::buffer_routed_orders`] asked for them.
+    routed_orders: Option<Vec<RoutedOrderRecord>>,
+    /// Primary CSV row (1-based, excluding headers) for each oid already routed.
+    seen_oids: HashMap<u64, usize>,
+    routed_rows: usize,
+    duplicates_csv: Option<csv::Writer<File>>,
+    duplicates_path: PathBuf,
+    duplicate_oid_count: usize,
+    l2_snapshots: Option<BufWriter<File>>,
+    l2_snapshots_path: PathBuf,
+    /// Extra destination for every `per_action.jsonl` line, e.g. a live evaluator's stdin.
+    action_tee: Option<Box<dyn Write + Send>>,
+    /// Stamped on new records while an `if` branch runs.
+    branch: Option<String>,
+    last_action: Option<ActionLogRecord>,
+    /// Trading statistics over every record logged so far.
+    stats: StatsAccumulator,
+    last_order_action: Option<ActionLogRecord>,
     window_ms: i64,
     per_action_path: PathBuf,
     ws_stream_path: PathBuf,
@@ -53,45 +427,37 @@ pub struct RunArtifacts {
 }
 
 impl RunArtifacts {
+    /// With `compress`, `per_action` and `ws_stream` are written gzipped as `.jsonl.gz`.
     pub fn create<P: AsRef<Path>>(
         out_dir: P,
         plan: &Value,
         plan_raw: Option<&str>,
         window_ms: Option<i64>,
+        compress: bool,
     ) -> Result<Self> {
         let out_dir = out_dir.as_ref();
         fs::create_dir_all(out_dir)
             .with_context(|| format!("failed to create run directory {}", out_dir.display()))?;
 
-        let per_action_path = out_dir.join("per_action.jsonl");
-        let ws_stream_path = out_dir.join("ws_stream.jsonl");
+        let jsonl_ext = if compress { "jsonl.gz" } else { "jsonl" };
+        let per_action_path = out_dir.join(format!("per_action.{jsonl_ext}"));
+        let ws_stream_path = out_dir.join(format!("ws_stream.{jsonl_ext}"));
         let routed_path = out_dir.join("orders_routed.csv");
+        let duplicates_path = out_dir.join("orders_routed_duplicates.csv");
+        let l2_snapshots_path = out_dir.join("l2_snapshots.jsonl");
         let meta_path = out_dir.join("run_meta.json");
         let plan_path = out_dir.join("plan.json");
         let plan_raw_path = plan_raw.map(|_| out_dir.join("plan_raw.txt"));
 
-        let per_action = BufWriter::new(
-            File::create(&per_action_path)
-                .with_context(|| format!("failed to create {}", per_action_path.display()))?,
-        );
-        let ws_stream = BufWriter::new(
-            File::create(&ws_stream_path)
-                .with_context(|| format!("failed to create {}", ws_stream_path.display()))?,
-        );
+        let per_action = create_jsonl(&per_action_path, compress)?;
+        let ws_stream = create_jsonl(&ws_stream_path, compress)?;
         let routed_file = File::create(&routed_path)
             .with_context(|| format!("failed to create {}", routed_path.display()))?;
-        let mut routed_csv = csv::Writer::from_writer(routed_file);
-        routed_csv.write_record([
-            "ts",
-            "oid",
-            "coin",
-            "side",
-            "px",
-            "sz",
-            "tif",
-            "reduceOnly",
-            "builderCode",
-        ])?;
+        // Written up front so a run without orders still has a header.
+        let mut routed_csv = csv::WriterBuilder::new()
+            .has_headers(false)
+            .from_writer(routed_file);
+        routed_csv.write_record(&RoutedOrderRecord::csv_header()?)?;
 
         let plan_writer = File::create(&plan_path)
             .with_context(|| format!("failed to create {}", plan_path.display()))?;
@@ -107,9 +473,28 @@ impl RunArtifacts {
         }
 
         Ok(Self {
-            per_action,
-            ws_stream,
-            routed_csv,
+            per_action: Some(per_action),
+            ws_stream: Some(ws_stream),
+            compress,
+            gzip_flush_interval: GZIP_FLUSH_INTERVAL,
+            last_gzip_flush: Instant::now(),
+            #[cfg(feature = "sqlite")]
+            db: None,
+            routed_csv: Some(routed_csv),
+            routed_path,
+            routed_orders: None,
+            seen_oids: HashMap::new(),
+            routed_rows: 0,
+            duplicates_csv: None,
+            duplicates_path,
+            duplicate_oid_count: 0,
+            l2_snapshots: None,
+            l2_snapshots_path,
+            action_tee: None,
+            branch: None,
+            last_action: None,
+            stats: StatsAccumulator::default(),
+            last_order_action: None,
             window_ms: window_ms.unwrap_or(DEFAULT_WINDOW_MS),
             per_action_path,
             ws_stream_path,
@@ -118,35 +503,261 @@ impl RunArtifacts {
     }
 
     pub fn log_action(&mut self, record: &ActionLogRecord) -> Result<()> {
-        serde_json::to_writer(&mut self.per_action, record).with_context(|| {
-            format!(
-                "failed to write action log to {}",
-                self.per_action_path.display()
-            )
-        })?;
-        self.per_action.write_all(b"\n")?;
-        self.per_action.flush()?;
+        let mut record = record.clone();
+        record.checksum = Some(record.compute_checksum()?);
+        if let Some(per_action) = self.per_action.as_mut() {
+            serde_json::to_writer(&mut *per_action, &record).with_context(|| {
+                format!(
+                    "failed to write action log to {}",
+                    self.per_action_path.display()
+                )
+            })?;
+            per_action.write_all(b"\n")?;
+            if !self.compress {
+                per_action.flush()?;
+            }
+        }
+        self.flush_gzip_if_due()?;
+        #[cfg(feature = "sqlite")]
+        if let Some(db) = self.db.as_mut() {
+            db.insert_action(&record)?;
+        }
+        if let Some(tee) = self.action_tee.as_mut() {
+            let written = serde_json::to_writer(&mut *tee, &record)
+                .map_err(std::io::Error::from)
+                .and_then(|_| tee.write_all(b"\n"))
+                .and_then(|_| tee.flush());
+            if let Err(err) = written {
+                // The reader going away must not fail the run; per_action.jsonl is complete.
+                warn!("stopped mirroring action records: {err}");
+                self.action_tee = None;
+            }
+        }
+        if record.is_order_action() {
+            self.last_order_action = Some(record.clone());
+        }
+        self.stats.add(&record);
+        self.last_action = Some(record);
         Ok(())
     }
 
+    /// Mirrors every subsequent action record, one JSON line each, into `sink`.
+    pub fn tee_actions(&mut self, sink: Box<dyn Write + Send>) {
+        self.action_tee = Some(sink);
+    }
+
+    /// Drops the sink given to [`Self::tee_actions`], closing it.
+    pub fn close_action_tee(&mut self) {
+        self.action_tee = None;
+    }
+
+    /// Sets the `branch` stamped on records made from now on (`None` outside `if` steps).
+    pub fn set_branch(&mut self, branch: Option<String>) {
+        self.branch = branch;
+    }
+
+    /// The most recently logged record.
+    pub fn last_action(&self) -> Option<&ActionLogRecord> {
+        self.last_action.as_ref()
+    }
+
+    /// Trading statistics over every record logged so far.
+    pub fn trading_stats(&self) -> TradingStats {
+        self.stats.finish()
+    }
+
+    /// The most recently logged order step record.
+    pub fn last_order_action(&self) -> Option<&ActionLogRecord> {
+        self.last_order_action.as_ref()
+    }
+
+    /// Distinct oids routed so far.
+    pub fn placed_order_count(&self) -> usize {
+        self.seen_oids.len()
+    }
+
     pub fn log_ws_event(&mut self, raw: &Value) -> Result<()> {
-        serde_json::to_writer(&mut self.ws_stream, raw).with_context(|| {
+        #[cfg(feature = "sqlite")]
+        if let Some(db) = self.db.as_mut() {
+            return db.insert_ws_event(raw);
+        }
+        let Some(ws_stream) = self.ws_stream.as_mut() else {
+            return Ok(());
+        };
+        serde_json::to_writer(&mut *ws_stream, raw).with_context(|| {
             format!(
                 "failed to write ws event to {}",
                 self.ws_stream_path.display()
             )
         })?;
-        self.ws_stream.write_all(b"\n")?;
+        ws_stream.write_all(b"\n")?;
+        self.flush_gzip_if_due()
+    }
+
+    /// Ends `per_action` and `ws_stream`, so they can be read back (compressed ones included)
+    /// while `self` is alive. Records and events logged afterwards are not written to them.
+    pub fn close_logs(&mut self) -> Result<()> {
+        for (writer, path) in [
+            (self.per_action.take(), &self.per_action_path),
+            (self.ws_stream.take(), &self.ws_stream_path),
+        ] {
+            if let Some(writer) = writer {
+                writer
+                    .into_inner()
+                    .map_err(|err| err.into_error())
+                    .and_then(JsonlSink::finish)
+                    .with_context(|| format!("failed to finish {}", path.display()))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn flush_gzip_if_due(&mut self) -> Result<()> {
+        if !self.compress || self.last_gzip_flush.elapsed() < self.gzip_flush_interval {
+            return Ok(());
+        }
+        self.last_gzip_flush = Instant::now();
+        for writer in [self.per_action.as_mut(), self.ws_stream.as_mut()]
+            .into_iter()
+            .flatten()
+        {
+            writer.flush()?;
+        }
         Ok(())
     }
 
+    /// Appends to `orders_routed.csv`, diverting repeated oids to
+    /// `orders_routed_duplicates.csv` so the primary file stays unique per oid.
     pub fn log_routed_order(&mut self, record: &RoutedOrderRecord) -> Result<()> {
-        self.routed_csv.serialize(record)?;
-        self.routed_csv.flush()?;
+        if let Some(oid) = record.oid {
+            if let Some(&row) = self.seen_oids.get(&oid) {
+                warn!("duplicate routed oid {oid} (first seen at row {row})");
+                self.duplicate_oid_count += 1;
+                #[cfg(feature = "sqlite")]
+                if let Some(db) = self.db.as_ref() {
+                    return db.insert_routed_order(record, Some(row));
+                }
+                return self.log_duplicate_order(record, row);
+            }
+            self.seen_oids.insert(oid, self.routed_rows + 1);
+        }
+        #[cfg(feature = "sqlite")]
+        if let Some(db) = self.db.as_ref() {
+            db.insert_routed_order(record, None)?;
+        }
+        if let Some(routed_csv) = self.routed_csv.as_mut() {
+            routed_csv.serialize(record)?;
+            routed_csv.flush()?;
+        }
+        if let Some(routed_orders) = self.routed_orders.as_mut() {
+            routed_orders.push(record.clone());
+        }
+        self.routed_rows += 1;
+        Ok(())
+    }
+
+    fn log_duplicate_order(&mut self, record: &RoutedOrderRecord, row: usize) -> Result<()> {
+        if self.duplicates_csv.is_none() {
+            let file = File::create(&self.duplicates_path)
+                .with_context(|| format!("failed to create {}", self.duplicates_path.display()))?;
+            let mut writer = csv::WriterBuilder::new()
+                .has_headers(false)
+                .from_writer(file);
+            let mut header = RoutedOrderRecord::csv_header()?;
+            header.push_field("duplicateOfRow");
+            writer.write_record(&header)?;
+            self.duplicates_csv = Some(writer);
+        }
+        let writer = self
+            .duplicates_csv
+            .as_mut()
+            .expect("duplicates writer initialised");
+        let mut fields = record.csv_row()?;
+        fields.push_field(&row.to_string());
+        writer.write_record(&fields)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Switches to the SQLite backend: action records, websocket events, routed orders
+    /// (duplicates included) and meta go to `run_artifacts.db` instead of their JSONL, CSV
+    /// and JSON files, which are deleted. Call before anything is logged.
+    #[cfg(feature = "sqlite")]
+    pub fn use_sqlite(&mut self) -> Result<PathBuf> {
+        let db_path = self.meta_path.with_file_name("run_artifacts.db");
+        self.db = Some(ArtifactDb::open(&db_path)?);
+        self.per_action = None;
+        self.ws_stream = None;
+        for path in [&self.per_action_path, &self.ws_stream_path] {
+            fs::remove_file(path)
+                .with_context(|| format!("failed to remove {}", path.display()))?;
+        }
+        self.disable_routed_csv()?;
+        Ok(db_path)
+    }
+
+    /// Keeps primary routed rows in memory for [`Self::write_routed_parquet`]. Call before
+    /// anything is routed.
+    pub fn buffer_routed_orders(&mut self) {
+        self.routed_orders.get_or_insert_with(Vec::new);
+    }
+
+    /// Stops writing `orders_routed.csv` and deletes it, for runs that only want Parquet.
+    /// Duplicate oids still go to `orders_routed_duplicates.csv`.
+    pub fn disable_routed_csv(&mut self) -> Result<()> {
+        if self.routed_csv.take().is_some() {
+            fs::remove_file(&self.routed_path)
+                .with_context(|| format!("failed to remove {}", self.routed_path.display()))?;
+        }
+        Ok(())
+    }
+
+    /// Writes the routed orders so far (the primary CSV's rows) to `routed_orders.parquet`,
+    /// one column per [`RoutedOrderRecord`] field under its camelCase name. Needs
+    /// [`Self::buffer_routed_orders`].
+    #[cfg(feature = "parquet")]
+    pub fn write_routed_parquet(&self) -> Result<PathBuf> {
+        let path = self.routed_path.with_file_name("routed_orders.parquet");
+        let routed_orders = self
+            .routed_orders
+            .as_deref()
+            .ok_or_else(|| anyhow!("routed orders were not buffered for parquet"))?;
+        crate::routed_parquet::write(&path, routed_orders)?;
+        Ok(path)
+    }
+
+    /// Appends to `l2_snapshots.jsonl`, creating it on first use.
+    pub fn log_l2_snapshot(&mut self, snap: &L2Snapshot) -> Result<()> {
+        if self.l2_snapshots.is_none() {
+            let file = File::create(&self.l2_snapshots_path).with_context(|| {
+                format!("failed to create {}", self.l2_snapshots_path.display())
+            })?;
+            self.l2_snapshots = Some(BufWriter::new(file));
+        }
+        let writer = self
+            .l2_snapshots
+            .as_mut()
+            .expect("l2 snapshot writer initialised");
+        serde_json::to_writer(&mut *writer, snap).with_context(|| {
+            format!(
+                "failed to write l2 snapshot to {}",
+                self.l2_snapshots_path.display()
+            )
+        })?;
+        writer.write_all(b"\n")?;
+        writer.flush()?;
         Ok(())
     }
 
-    pub fn write_meta(&self, meta: &Value) -> Result<()> {
+    pub fn duplicate_oid_count(&self) -> usize {
+        self.duplicate_oid_count
+    }
+
+    pub fn write_meta(&mut self, meta: &Value) -> Result<()> {
+        #[cfg(feature = "sqlite")]
+        if let Some(db) = self.db.as_mut() {
+            return db.write_meta(meta);
+        }
         let meta_file = File::create(&self.meta_path)
             .with_context(|| format!("failed to create {}", self.meta_path.display()))?;
         let mut writer = BufWriter::new(meta_file);
@@ -161,6 +772,63 @@ impl RunArtifacts {
         self.window_ms
     }
 
+    /// Opens an existing run directory for reading.
+    pub fn open(out_dir: &Path) -> Result<RunArtifactsReader> {
+        if !out_dir.is_dir() {
+            return Err(anyhow!(
+                "run directory {} does not exist",
+                out_dir.display()
+            ));
+        }
+        Ok(RunArtifactsReader {
+            out_dir: out_dir.to_path_buf(),
+        })
+    }
+
+    /// Deletes the oldest `YYYYMMDD-HHMMSS[-N]` run directories under `base_dir`, keeping
+    /// the `max_runs` most recent. `current` (a run inside `base_dir`) is never deleted and
+    /// counts as one of the kept runs. Returns how many were removed.
+    ///
+    /// `current` is excluded explicitly rather than trusted to sort newest: runs are ordered
+    /// by creation time, falling back to mtime where the filesystem has none. A `--watch` run
+    /// or a clock step can leave the run still being written looking like the oldest, and
+    /// deleting it would pull its artifact files out from under the open writers.
+    pub fn prune_old_runs(base_dir: &Path, max_runs: usize, current: &Path) -> Result<usize> {
+        if max_runs == 0 {
+            return Err(anyhow!("max_runs must be at least 1"));
+        }
+        let entries = fs::read_dir(base_dir)
+            .with_context(|| format!("failed to read runs directory {}", base_dir.display()))?;
+        let mut runs = Vec::new();
+        for entry in entries {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let metadata = entry.metadata()?;
+            if !metadata.is_dir()
+                || !is_run_dir_name(&name)
+                || current.file_name() == Some(entry.file_name().as_os_str())
+            {
+                continue;
+            }
+            let created = metadata.created().or_else(|_| metadata.modified())?;
+            runs.push((created, name, entry.path()));
+        }
+        let keep = max_runs - 1;
+        if runs.len() <= keep {
+            return Ok(0);
+        }
+
+        runs.sort();
+        let excess = runs.len() - keep;
+        for (_, _, path) in runs.iter().take(excess) {
+            fs::remove_dir_all(path)
+                .with_context(|| format!("failed to delete old run {}", path.display()))?;
+            info!("pruned old run directory {}", path.display());
+        }
+        Ok(excess)
+    }
+
+    /// The checksum is left unset; [`Self::log_action`] stamps it when the record is written.
     #[allow(clippy::too_many_arguments)]
     pub fn make_action_record(
         &self,
@@ -182,14 +850,784 @@ impl RunArtifacts {
             ack,
             observed,
             notes,
+            duration_ms: None,
+            branch: self.branch.clone(),
+            checksum: None,
         }
     }
+
+    /// [`Self::make_action_record`] with `duration_ms` measured from `start`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn make_action_record_with_duration(
+        &self,
+        step_idx: usize,
+        action: impl Into<String>,
+        submit_ts_ms: i64,
+        request: Value,
+        ack: Option<Value>,
+        observed: Option<Value>,
+        notes: Option<String>,
+        start: Instant,
+    ) -> ActionLogRecord {
+        self.make_action_record(
+            step_idx,
+            action,
+            submit_ts_ms,
+            request,
+            ack,
+            observed,
+            notes,
+        )
+        .with_duration(start)
+    }
 }
 
 impl Drop for RunArtifacts {
     fn drop(&mut self) {
-        let _ = self.per_action.flush();
-        let _ = self.ws_stream.flush();
-        let _ = self.routed_csv.flush();
+        for writer in [self.per_action.as_mut(), self.ws_stream.as_mut()]
+            .into_iter()
+            .flatten()
+        {
+            let _ = writer.flush();
+        }
+        if let Some(writer) = self.routed_csv.as_mut() {
+            let _ = writer.flush();
+        }
+        if let Some(writer) = self.duplicates_csv.as_mut() {
+            let _ = writer.flush();
+        }
+    }
+}
+
+/// Typed, streaming access to the files of a finished run, see [`RunArtifacts::open`].
+#[derive(Debug, Clone)]
+pub struct RunArtifactsReader {
+    out_dir: PathBuf,
+}
+
+impl RunArtifactsReader {
+    pub fn out_dir(&self) -> &Path {
+        &self.out_dir
+    }
+
+    /// `per_action.jsonl`, or `per_action.jsonl.gz` for a compressed run.
+    pub fn per_action_path(&self) -> PathBuf {
+        self.jsonl_path("per_action")
+    }
+
+    pub fn ws_stream_path(&self) -> PathBuf {
+        self.jsonl_path("ws_stream")
+    }
+
+    fn jsonl_path(&self, stem: &str) -> PathBuf {
+        let plain = self.out_dir.join(format!("{stem}.jsonl"));
+        let compressed = plain.with_extension("jsonl.gz");
+        if !plain.exists() && compressed.exists() {
+            compressed
+        } else {
+            plain
+        }
+    }
+
+    pub fn iter_action_records(&self) -> impl Iterator<Item = Result<ActionLogRecord>> {
+        read_jsonl(self.per_action_path())
+    }
+
+    pub fn iter_ws_events(&self) -> impl Iterator<Item = Result<Value>> {
+        read_jsonl(self.ws_stream_path())
+    }
+
+    /// Rows of `orders_routed.csv`, mapped by header name so files without the newer
+    /// columns still parse. Extra header rows (older writers emitted two) are skipped.
+    pub fn iter_routed_orders(&self) -> impl Iterator<Item = Result<RoutedOrderRecord>> {
+        let path = self.out_dir.join("orders_routed.csv");
+        let reader = csv::Reader::from_path(&path)
+            .with_context(|| format!("failed to open {}", path.display()));
+        let rows: Box<dyn Iterator<Item = Result<RoutedOrderRecord>>> =
+            match reader.and_then(|mut reader| {
+                let headers = reader
+                    .headers()
+                    .with_context(|| format!("failed to read {}", path.display()))?
+                    .clone();
+                Ok((reader, headers))
+            }) {
+                Ok((reader, headers)) => Box::new(reader.into_records().filter_map(move |row| {
+                    let row = match row {
+                        Ok(row) => row,
+                        Err(err) => {
+                            return Some(
+                                Err(err)
+                                    .with_context(|| format!("failed to read {}", path.display())),
+                            )
+                        }
+                    };
+                    if row.get(0).is_some_and(|ts| ts.parse::<i64>().is_err()) {
+                        return None;
+                    }
+                    Some(row.deserialize(Some(&headers)).with_context(|| {
+                        format!("failed to parse routed order in {}", path.display())
+                    }))
+                })),
+                Err(err) => Box::new(std::iter::once(Err(err))),
+            };
+        rows
+    }
+
+    /// Primary routed orders from whichever backend wrote them: `orders_routed.csv`,
+    /// `run_artifacts.db` or `routed_orders.parquet`. Empty when the run routed nothing.
+    pub fn routed_orders(&self) -> Result<Vec<RoutedOrderRecord>> {
+        let db_path = self.out_dir.join("run_artifacts.db");
+        let parquet_path = self.out_dir.join("routed_orders.parquet");
+        if self.out_dir.join("orders_routed.csv").exists() {
+            self.iter_routed_orders().collect()
+        } else if db_path.exists() {
+            #[cfg(feature = "sqlite")]
+            return ArtifactDb::open(&db_path)?.routed_orders();
+            #[cfg(not(feature = "sqlite"))]
+            Err(anyhow!(
+                "{} needs hl-common's sqlite feature",
+                db_path.display()
+            ))
+        } else if parquet_path.exists() {
+            #[cfg(feature = "parquet")]
+            return crate::routed_parquet::read(&parquet_path);
+            #[cfg(not(feature = "parquet"))]
+            Err(anyhow!(
+                "{} needs hl-common's parquet feature",
+                parquet_path.display()
+            ))
+        } else {
+            Ok(Vec::new())
+        }
+    }
+
+    pub fn read_meta(&self) -> Result<Value> {
+        let path = self.out_dir.join("run_meta.json");
+        let file =
+            File::open(&path).with_context(|| format!("failed to open {}", path.display()))?;
+        serde_json::from_reader(BufReader::new(file))
+            .with_context(|| format!("failed to parse {}", path.display()))
+    }
+}
+
+fn create_jsonl(path: &Path, compress: bool) -> Result<JsonlWriter> {
+    if compress && !cfg!(feature = "gzip") {
+        return Err(anyhow!(
+            "writing {} needs hl-common's gzip feature",
+            path.display()
+        ));
+    }
+    let file =
+        File::create(path).with_context(|| format!("failed to create {}", path.display()))?;
+    #[cfg(feature = "gzip")]
+    if compress {
+        let encoder = GzEncoder::new(file, Compression::default());
+        return Ok(BufWriter::new(JsonlSink::Gzip(encoder)));
+    }
+    Ok(BufWriter::new(JsonlSink::Plain(file)))
+}
+
+/// Opens a JSONL artifact for reading, decompressing it when the path ends in `.gz`.
+pub fn open_jsonl(path: &Path) -> Result<Box<dyn BufRead>> {
+    let file = File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+    if path.extension().is_some_and(|ext| ext == "gz") {
+        #[cfg(feature = "gzip")]
+        return Ok(Box::new(BufReader::new(GzDecoder::new(file))));
+        #[cfg(not(feature = "gzip"))]
+        return Err(anyhow!(
+            "reading {} needs hl-common's gzip feature",
+            path.display()
+        ));
+    }
+    Ok(Box::new(BufReader::new(file)))
+}
+
+/// Deserializes one `T` per non-empty line; an unreadable file yields a single error.
+fn read_jsonl<T: DeserializeOwned + 'static>(path: PathBuf) -> Box<dyn Iterator<Item = Result<T>>> {
+    let reader = match open_jsonl(&path) {
+        Ok(reader) => reader,
+        Err(err) => return Box::new(std::iter::once(Err(err))),
+    };
+    Box::new(
+        reader
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| line.as_ref().map_or(true, |line| !line.trim().is_empty()))
+            .map(move |(idx, line)| {
+                let line = line.with_context(|| {
+                    format!("failed to read line {} of {}", idx + 1, path.display())
+                })?;
+                serde_json::from_str(&line).with_context(|| {
+                    format!("failed to parse line {} of {}", idx + 1, path.display())
+                })
+            }),
+    )
+}
+
+/// `YYYYMMDD-HHMMSS`, optionally followed by `-N` as `--watch` names its runs.
+fn is_run_dir_name(name: &str) -> bool {
+    let (stamp, suffix) = match name.get(15..) {
+        Some(rest) if !rest.is_empty() => (&name[..15], Some(rest)),
+        _ => (name, None),
+    };
+    if let Some(suffix) = suffix {
+        let Some(idx) = suffix.strip_prefix('-') else {
+            return false;
+        };
+        if idx.is_empty() || !idx.bytes().all(|b| b.is_ascii_digit()) {
+            return false;
+        }
+    }
+    let bytes = stamp.as_bytes();
+    bytes.len() == 15
+        && bytes[8] == b'-'
+        && bytes
+            .iter()
+            .enumerate()
+            .all(|(idx, b)| idx == 8 || b.is_ascii_digit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{action_record, RunFixture};
+
+    fn fixture_record(
+        step_idx: usize,
+        action: &str,
+        submit_ts_ms: i64,
+        request: Value,
+        ack: Value,
+        observed: Option<Value>,
+    ) -> ActionLogRecord {
+        ActionLogRecord {
+            submit_ts_ms,
+            window_key_ms: window_start_ms(submit_ts_ms, DEFAULT_WINDOW_MS),
+            observed,
+            ..action_record(step_idx, action, request, ack)
+        }
+    }
+
+    #[test]
+    fn trading_stats_count_outcomes_latency_and_slippage() {
+        let records = vec![
+            fixture_record(
+                0,
+                "perp_orders",
+                1_000,
+                serde_json::json!({"perp_orders": {"orders": [
+                    {"coin": "ETH", "side": "buy", "resolvedPx": 100.0},
+                    {"coin": "ETH", "side": "sell", "resolvedPx": 200.0},
+                    {"coin": "BTC", "side": "buy", "resolvedPx": 50.0},
+                    {"coin": "BTC", "side": "buy", "resolvedPx": 10.0},
+                ]}}),
+                serde_json::json!({"status": "ok", "data": {"statuses": [
+                    {"kind": "filled", "oid": 1, "avgPx": "100.1", "totalSz": "1"},
+                    {"kind": "resting", "oid": 2},
+                    {"kind": "error", "message": "too small"},
+                    {"kind": "resting", "oid": 3},
+                ]}}),
+                None,
+            ),
+            fixture_record(
+                1,
+                "wait_for_fill",
+                1_010,
+                serde_json::json!({"wait_for_fill": {}}),
+                serde_json::json!({"status": "ok"}),
+                Some(serde_json::json!({
+                    "channel": "userFills", "oid": 1, "px": "100.1", "time": 1_040
+                })),
+            ),
+            fixture_record(
+                2,
+                "wait_for_fill",
+                1_050,
+                serde_json::json!({"wait_for_fill": {}}),
+                serde_json::json!({"status": "ok"}),
+                Some(serde_json::json!({
+                    "channel": "userFills", "oid": 2, "px": "199.8", "time": 1_300
+                })),
+            ),
+            fixture_record(
+                3,
+                "multi_coin_orders",
+                2_000,
+                serde_json::json!({"multi_coin_orders": {
+                    "coins": ["ETH", "SOL"], "side": "buy", "resolvedPx": [100.0, 20.0]
+                }}),
+                serde_json::json!({"status": "err", "message": "rejected"}),
+                None,
+            ),
+        ];
+
+        let stats = compute_stats(&records);
+        assert_eq!(stats.orders_placed, 6);
+        assert_eq!(stats.filled, 1);
+        assert_eq!(stats.resting, 2);
+        assert_eq!(stats.errored, 3);
+        assert_eq!(stats.mean_fill_latency_ms, Some(170.0));
+        assert_eq!(stats.p99_fill_latency_ms, Some(300));
+        // Buy filled 10 bps above, sell filled 10 bps below its resolved price.
+        let slippage = stats.avg_slippage_bps.unwrap();
+        assert!((slippage - 10.0).abs() < 1e-6, "{slippage}");
+
+        // Logging the same records builds the same stats without keeping the records.
+        let fixture = RunFixture::empty("hl-stats");
+        let mut artifacts = fixture.artifacts(false);
+        for record in &records {
+            artifacts.log_action(record).unwrap();
+        }
+        let logged = artifacts.trading_stats();
+        assert_eq!(logged.mean_fill_latency_ms, stats.mean_fill_latency_ms);
+        assert_eq!(
+            (logged.orders_placed, logged.filled, logged.errored),
+            (6, 1, 3)
+        );
+        assert!((logged.avg_slippage_bps.unwrap() - slippage).abs() < 1e-9);
+    }
+
+    #[test]
+    fn trading_stats_without_orders_are_empty() {
+        let records = vec![fixture_record(
+            0,
+            "sleep_ms",
+            0,
+            serde_json::json!({"sleep_ms": {"duration_ms": 1}}),
+            serde_json::json!({"status": "ok"}),
+            None,
+        )];
+        let stats = compute_stats(&records);
+        assert_eq!(stats, TradingStats::default());
+        assert_eq!(
+            serde_json::to_value(&stats).unwrap()["meanFillLatencyMs"],
+            Value::Null
+        );
+    }
+
+    #[test]
+    fn prune_old_runs_keeps_most_recent() {
+        let fixture = RunFixture::empty("hl-prune");
+        let base = &fixture.dir;
+        for idx in 0..5 {
+            fs::create_dir_all(base.join(format!("20250101-00000{idx}"))).unwrap();
+        }
+        fs::create_dir_all(base.join("keep-me")).unwrap();
+
+        let current = base.join("20250101-000004");
+        let deleted = RunArtifacts::prune_old_runs(base, 3, &current).unwrap();
+        assert_eq!(deleted, 2);
+
+        let mut remaining: Vec<String> = fs::read_dir(base)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        remaining.sort();
+        assert_eq!(remaining.len(), 4);
+        assert!(remaining.contains(&"keep-me".to_string()));
+        assert!(RunArtifacts::prune_old_runs(base, 0, &current).is_err());
+    }
+
+    #[test]
+    fn prune_old_runs_spares_the_current_run_and_sees_watch_runs() {
+        let fixture = RunFixture::empty("hl-prune");
+        let base = &fixture.dir;
+        // Watch-mode runs carry a `-N` suffix; the oldest name is the current run here.
+        for name in [
+            "20250101-000000-1",
+            "20250101-000001-2",
+            "20250101-000002-3",
+        ] {
+            fs::create_dir_all(base.join(name)).unwrap();
+        }
+        fs::create_dir_all(base.join("20250101-000003-x")).unwrap();
+        let current = base.join("20250101-000000-1");
+
+        let deleted = RunArtifacts::prune_old_runs(base, 1, &current).unwrap();
+        assert_eq!(deleted, 2);
+        assert!(current.exists());
+        assert!(base.join("20250101-000003-x").exists());
+        assert!(!base.join("20250101-000001-2").exists());
+    }
+
+    #[test]
+    fn duplicate_oids_go_to_separate_csv() {
+        let fixture = RunFixture::empty("hl-routed");
+        let out_dir = &fixture.dir;
+        let mut artifacts = fixture.artifacts(false);
+        let record = RoutedOrderRecord {
+            ts_ms: 1,
+            oid: Some(42),
+            coin: "ETH".to_string(),
+            side: "buy".to_string(),
+            px: 3000.0,
+            sz: 0.01,
+            tif: "Gtc".to_string(),
+            reduce_only: false,
+            builder_code: None,
+            spot: false,
+        };
+        artifacts.log_routed_order(&record).unwrap();
+        artifacts.log_routed_order(&record).unwrap();
+        assert_eq!(artifacts.duplicate_oid_count(), 1);
+        drop(artifacts);
+
+        let header = "ts,oid,coin,side,px,sz,tif,reduceOnly,builderCode,spot";
+        let primary = fs::read_to_string(out_dir.join("orders_routed.csv")).unwrap();
+        let primary_rows: Vec<&str> = primary.lines().collect();
+        assert_eq!(
+            primary_rows,
+            vec![header, "1,42,ETH,buy,3000.0,0.01,Gtc,false,,false"]
+        );
+        let duplicates = fs::read_to_string(out_dir.join("orders_routed_duplicates.csv")).unwrap();
+        let rows: Vec<&str> = duplicates.lines().collect();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0], format!("{header},duplicateOfRow"));
+        assert!(rows[1].starts_with("1,42,ETH"));
+        assert!(rows[1].ends_with(",1"));
+    }
+
+    #[cfg(feature = "parquet")]
+    #[test]
+    fn routed_parquet_round_trips_records() {
+        let fixture = RunFixture::empty("hl-parquet");
+        let out_dir = &fixture.dir;
+        let mut artifacts = fixture.artifacts(false);
+        assert!(artifacts.write_routed_parquet().is_err());
+        artifacts.buffer_routed_orders();
+        artifacts.disable_routed_csv().unwrap();
+        let records: Vec<RoutedOrderRecord> = (0..3u64)
+            .map(|idx| RoutedOrderRecord {
+                ts_ms: 1_000 + idx as i64,
+                oid: (idx != 1).then_some(100 + idx),
+                coin: ["ETH", "BTC", "HYPE"][idx as usize].to_string(),
+                side: "buy".to_string(),
+                px: 3000.5 + idx as f64,
+                sz: 0.01,
+                tif: "Alo".to_string(),
+                reduce_only: idx == 2,
+                builder_code: (idx == 0).then(|| "0xabc".to_string()),
+                spot: idx == 1,
+            })
+            .collect();
+        for record in &records {
+            artifacts.log_routed_order(record).unwrap();
+        }
+        let path = artifacts.write_routed_parquet().unwrap();
+        drop(artifacts);
+        assert_eq!(path, out_dir.join("routed_orders.parquet"));
+        assert!(!out_dir.join("orders_routed.csv").exists());
+
+        let reader = RunArtifacts::open(out_dir).unwrap();
+        assert_eq!(reader.routed_orders().unwrap(), records);
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn sqlite_ws_events_are_committed_in_batches() {
+        use crate::artifact_db::WS_BATCH_SIZE;
+
+        let fixture = RunFixture::empty("hl-sqlite-ws");
+        let mut artifacts = fixture.artifacts(false);
+        let db_path = artifacts.use_sqlite().unwrap();
+        let reader = ArtifactDb::open(&db_path).unwrap();
+
+        for idx in 0..3 {
+            artifacts
+                .log_ws_event(&serde_json::json!({"channel": "trades", "idx": idx}))
+                .unwrap();
+        }
+        assert!(reader.ws_events().unwrap().is_empty());
+
+        // The next action record is a step boundary: pending events are committed first.
+        let record = artifacts.make_action_record(
+            0,
+            "sleep_ms",
+            1_000,
+            serde_json::json!({}),
+            None,
+            None,
+            None,
+        );
+        artifacts.log_action(&record).unwrap();
+        assert_eq!(reader.ws_events().unwrap().len(), 3);
+
+        for idx in 0..WS_BATCH_SIZE {
+            artifacts
+                .log_ws_event(&serde_json::json!({"channel": "trades", "idx": idx}))
+                .unwrap();
+        }
+        assert_eq!(reader.ws_events().unwrap().len(), 3 + WS_BATCH_SIZE);
+
+        artifacts
+            .log_ws_event(&serde_json::json!({"channel": "trades"}))
+            .unwrap();
+        drop(artifacts);
+        assert_eq!(reader.ws_events().unwrap().len(), 4 + WS_BATCH_SIZE);
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn sqlite_backend_reads_back_logged_records() {
+        let fixture = RunFixture::empty("hl-sqlite");
+        let out_dir = &fixture.dir;
+        let mut artifacts = fixture.artifacts(false);
+        let db_path = artifacts.use_sqlite().unwrap();
+        assert!(!out_dir.join("per_action.jsonl").exists());
+
+        let mut record = artifacts.make_action_record(
+            2,
+            "perp_orders",
+            1_000,
+            serde_json::json!({"perp_orders": {"orders": []}}),
+            Some(serde_json::json!({"status": "ok"})),
+            None,
+            Some("note".to_string()),
+        );
+        record.duration_ms = Some(7);
+        record.checksum = Some(record.compute_checksum().unwrap());
+        artifacts.log_action(&record).unwrap();
+        let event = serde_json::json!({"channel": "userFills", "data": {"fills": [1, 2]}});
+        artifacts.log_ws_event(&event).unwrap();
+        let routed = RoutedOrderRecord {
+            ts_ms: 1_000,
+            oid: Some(9),
+            coin: "ETH".to_string(),
+            side: "sell".to_string(),
+            px: 3000.5,
+            sz: 0.02,
+            tif: "Ioc".to_string(),
+            reduce_only: true,
+            builder_code: Some("0xabc".to_string()),
+            spot: false,
+        };
+        artifacts.log_routed_order(&routed).unwrap();
+        artifacts.log_routed_order(&routed).unwrap();
+        artifacts
+            .write_meta(&serde_json::json!({"demo": true, "stepCount": 1}))
+            .unwrap();
+        drop(artifacts);
+
+        let db = ArtifactDb::open(&db_path).unwrap();
+        let actions = db.action_records().unwrap();
+        assert_eq!(actions.len(), 1);
+        assert_eq!(
+            serde_json::to_value(&actions[0]).unwrap(),
+            serde_json::to_value(&record).unwrap()
+        );
+        assert_eq!(actions[0].verify_checksum(), Some(true));
+        assert_eq!(db.ws_events().unwrap(), vec![event]);
+        assert_eq!(db.routed_orders().unwrap(), vec![routed]);
+        assert_eq!(
+            db.meta_value("demo").unwrap(),
+            Some(serde_json::json!(true))
+        );
+        assert!(!out_dir.join("run_meta.json").exists());
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn compressed_artifacts_read_back_unchanged() {
+        let fixture = RunFixture::empty("hl-gzip");
+        let out_dir = &fixture.dir;
+        let mut artifacts = fixture.artifacts(true);
+        let records: Vec<ActionLogRecord> = (0..3)
+            .map(|idx| {
+                artifacts.make_action_record(
+                    idx,
+                    "sleep_ms",
+                    1_000 + idx as i64,
+                    serde_json::json!({"sleep_ms": {"duration_ms": idx}}),
+                    None,
+                    None,
+                    None,
+                )
+            })
+            .collect();
+        for record in &records {
+            artifacts.log_action(record).unwrap();
+        }
+        let event = serde_json::json!({"channel": "orderUpdates", "data": []});
+        artifacts.log_ws_event(&event).unwrap();
+        drop(artifacts);
+
+        assert!(!out_dir.join("per_action.jsonl").exists());
+        let reader = RunArtifacts::open(out_dir).unwrap();
+        assert_eq!(
+            reader.per_action_path(),
+            out_dir.join("per_action.jsonl.gz")
+        );
+        let read: Vec<ActionLogRecord> = reader.iter_action_records().map(Result::unwrap).collect();
+        assert!(read
+            .iter()
+            .all(|record| record.verify_checksum() == Some(true)));
+        let read: Vec<ActionLogRecord> = read
+            .into_iter()
+            .map(|record| ActionLogRecord {
+                checksum: None,
+                ..record
+            })
+            .collect();
+        assert_eq!(
+            serde_json::to_value(&read).unwrap(),
+            serde_json::to_value(&records).unwrap()
+        );
+        let events: Vec<Value> = reader.iter_ws_events().map(Result::unwrap).collect();
+        assert_eq!(events, vec![event]);
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn compressed_logs_are_not_flushed_per_record() {
+        let fixture = RunFixture::empty("hl-gzip");
+        let out_dir = &fixture.dir;
+        let mut artifacts = fixture.artifacts(true);
+        artifacts.gzip_flush_interval = Duration::MAX;
+        for idx in 0..3 {
+            let record = artifacts.make_action_record(
+                idx,
+                "sleep_ms",
+                1_000,
+                serde_json::json!({}),
+                None,
+                None,
+                None,
+            );
+            artifacts.log_action(&record).unwrap();
+        }
+        let gz_path = out_dir.join("per_action.jsonl.gz");
+        assert_eq!(fs::metadata(&gz_path).unwrap().len(), 0);
+
+        artifacts.gzip_flush_interval = Duration::ZERO;
+        artifacts.log_ws_event(&serde_json::json!({})).unwrap();
+        assert!(fs::metadata(&gz_path).unwrap().len() > 0);
+
+        // Readable to the end before the artifacts are dropped.
+        artifacts.close_logs().unwrap();
+        let reader = RunArtifacts::open(out_dir).unwrap();
+        let read: Vec<ActionLogRecord> =
+            reader.iter_action_records().collect::<Result<_>>().unwrap();
+        assert_eq!(read.len(), 3);
+        assert_eq!(reader.iter_ws_events().count(), 1);
+    }
+
+    #[test]
+    fn action_tee_mirrors_per_action_lines() {
+        let fixture = RunFixture::empty("hl-tee");
+        let dir = &fixture.dir;
+        let mut artifacts = fixture.artifacts(false);
+        artifacts.tee_actions(Box::new(File::create(dir.join("tee.jsonl")).unwrap()));
+        let record = artifacts.make_action_record(
+            0,
+            "sleep_ms",
+            1_000,
+            serde_json::json!({}),
+            None,
+            None,
+            None,
+        );
+        artifacts.log_action(&record).unwrap();
+        artifacts.close_action_tee();
+        artifacts.log_action(&record).unwrap();
+
+        let mirrored = fs::read_to_string(dir.join("tee.jsonl")).unwrap();
+        let logged = fs::read_to_string(dir.join("per_action.jsonl")).unwrap();
+        assert_eq!(mirrored.lines().count(), 1);
+        assert_eq!(logged.lines().next(), mirrored.lines().next());
+    }
+
+    #[test]
+    fn reader_round_trips_written_run() {
+        let fixture = RunFixture::empty("hl-reader");
+        let dir = &fixture.dir;
+        {
+            let mut artifacts = fixture.artifacts(false);
+            let record = artifacts.make_action_record(
+                0,
+                "sleep_ms",
+                1_000,
+                serde_json::json!({"sleep_ms": {"durationMs": 10}}),
+                None,
+                None,
+                None,
+            );
+            artifacts.log_action(&record).unwrap();
+            artifacts
+                .log_ws_event(&serde_json::json!({"channel": "fills"}))
+                .unwrap();
+            artifacts
+                .log_routed_order(&RoutedOrderRecord {
+                    ts_ms: 1_000,
+                    oid: Some(7),
+                    coin: "ETH".to_string(),
+                    side: "buy".to_string(),
+                    px: 3000.0,
+                    sz: 0.1,
+                    tif: "Gtc".to_string(),
+                    reduce_only: false,
+                    builder_code: None,
+                    spot: true,
+                })
+                .unwrap();
+            artifacts
+                .write_meta(&serde_json::json!({"network": "demo"}))
+                .unwrap();
+        }
+
+        let reader = RunArtifacts::open(dir).unwrap();
+        let records: Vec<ActionLogRecord> =
+            reader.iter_action_records().collect::<Result<_>>().unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].verify_checksum(), Some(true));
+        let events: Vec<Value> = reader.iter_ws_events().collect::<Result<_>>().unwrap();
+        assert_eq!(events[0]["channel"], "fills");
+        let orders: Vec<RoutedOrderRecord> =
+            reader.iter_routed_orders().collect::<Result<_>>().unwrap();
+        assert_eq!(orders.len(), 1);
+        assert_eq!(orders[0].oid, Some(7));
+        assert_eq!(orders[0].builder_code, None);
+        assert!(orders[0].spot);
+        assert_eq!(reader.read_meta().unwrap()["network"], "demo");
+
+        assert!(RunArtifacts::open(&dir.join("missing")).is_err());
+    }
+
+    #[test]
+    fn duration_is_optional_for_legacy_records() {
+        let legacy = r#"{"stepIdx":0,"action":"sleep_ms","submitTsMs":1000,"windowKeyMs":1000,"request":{}}"#;
+        let record: ActionLogRecord = serde_json::from_str(legacy).unwrap();
+        assert_eq!(record.duration_ms, None);
+        assert!(!serde_json::to_string(&record)
+            .unwrap()
+            .contains("durationMs"));
+
+        let timed = record.with_duration(Instant::now());
+        assert!(timed.duration_ms.is_some_and(|ms| ms >= 0));
+        assert_eq!(timed.verify_checksum(), None);
+    }
+
+    #[test]
+    fn checksum_detects_tampering() {
+        let mut record = ActionLogRecord {
+            step_idx: 0,
+            action: "perp_orders".to_string(),
+            submit_ts_ms: 1_000,
+            window_key_ms: 1_000,
+            request: serde_json::json!({"perp_orders": {"orders": [{"coin": "ETH", "sz": 0.01}]}}),
+            ack: Some(serde_json::json!({"status": "ok"})),
+            ..Default::default()
+        };
+        record.checksum = Some(record.compute_checksum().unwrap());
+
+        let line = serde_json::to_string(&record).unwrap();
+        let parsed: ActionLogRecord = serde_json::from_str(&line).unwrap();
+        assert_eq!(parsed.verify_checksum(), Some(true));
+
+        let tampered = line.replace("0.01", "5.0");
+        let parsed: ActionLogRecord = serde_json::from_str(&tampered).unwrap();
+        assert_eq!(parsed.verify_checksum(), Some(false));
+
+        record.checksum = None;
+        assert_eq!(record.verify_checksum(), None);
     }
 }
@@ -1,11 +1,24 @@
+#[cfg(feature = "sqlite")]
+pub mod artifact_db;
 pub mod artifacts;
+pub mod normalize;
 pub mod plan;
+#[cfg(feature = "plan-url")]
+pub mod plan_url;
+#[cfg(feature = "parquet")]
+mod routed_parquet;
 pub mod sig;
+#[cfg(any(test, feature = "test-support"))]
+pub mod test_support;
 pub mod time;
 
-pub use artifacts::{ActionLogRecord, RoutedOrderRecord, RunArtifacts};
+pub use artifacts::{
+    compute_stats, ActionLogRecord, L2Snapshot, RoutedOrderRecord, RunArtifacts,
+    RunArtifactsReader, TradingStats,
+};
 pub use plan::{
-    load_plan_from_spec, ActionStep, CancelScope, OrderPrice, OrderSide, PerpOrder, Plan,
+    load_plan_from_spec, validate, ActionStep, CancelScope, OrderPrice, OrderSide, PerpOrder, Plan,
+    ValidationResult,
 };
 pub use sig::{normalize_tif, normalize_trigger, Signature};
-pub use time::{timestamp_ms, window_start_ms};
+pub use time::{timestamp_ms, window_count, window_start_ms, windows_iter};
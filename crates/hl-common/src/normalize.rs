@@ -0,0 +1,276 @@
+use std::{collections::BTreeSet, io::BufRead, path::Path};
+
+use anyhow::{Context, Result};
+use serde_json::{json, Value};
+use thiserror::Error;
+
+use crate::{artifacts::open_jsonl, normalize_tif, normalize_trigger, ActionLogRecord, Signature};
+
+#[derive(Error, Debug)]
+pub enum NormalizeError {
+    #[error("missing acknowledgement")]
+    MissingAck,
+    #[error("ack status not ok")]
+    AckNotOk,
+    #[error("missing request payload")]
+    MissingRequest,
+    #[error("no effectful actions detected")]
+    NoEffect,
+    #[error("ack missing status entries for some orders")]
+    IncompleteAck,
+    #[error("checksum mismatch")]
+    ChecksumMismatch,
+    #[error("unsupported action '{0}'")]
+    UnsupportedAction(String),
+}
+
+/// Reads a `per_action.jsonl` log and returns every signature it produced.
+pub fn unique_signatures_from_log(path: &Path) -> Result<BTreeSet<String>> {
+    let mut signatures = BTreeSet::new();
+    for (line_no, line) in open_jsonl(path)?.lines().enumerate() {
+        let line = line.with_context(|| format!("failed to read line {}", line_no + 1))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let record: ActionLogRecord = serde_json::from_str(&line)
+            .with_context(|| format!("failed to parse ActionLogRecord on line {}", line_no + 1))?;
+        signatures.extend(action_signatures(&record).0);
+    }
+    Ok(signatures)
+}
+
+/// Maps one action log record to its coverage signatures.
+pub fn action_signatures(record: &ActionLogRecord) -> (Vec<String>, Option<NormalizeError>) {
+    match record.action.as_str() {
+        "perp_orders" | "multi_coin_orders" => normalize_perp_orders(record),
+        "spot_orders" => normalize_spot_orders(record),
+        "cancel_last" => normalize_cancel(record, "last"),
+        "cancel_oids" => normalize_cancel(record, "oids"),
+        "cancel_all" => normalize_cancel(record, "all"),
+        "usd_class_transfer" => normalize_transfer(record),
+        "set_leverage" => normalize_leverage(record),
+        "wait_for_fill" => normalize_wait_for_fill(record),
+        "fetch_funding_rate" => normalize_funding_rate(record),
+        "assert_balance" => normalize_assert_balance(record),
+        "sub_account_transfer" => normalize_sub_account_transfer(record),
+        "sleep_ms" => (Vec::new(), None),
+        other => (
+            Vec::new(),
+            Some(NormalizeError::UnsupportedAction(other.to_string())),
+        ),
+    }
+}
+
+pub fn normalize_perp_orders(record: &ActionLogRecord) -> (Vec<String>, Option<NormalizeError>) {
+    normalize_orders(record, |order, tif| {
+        let reduce_only = order
+            .get("reduceOnly")
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false);
+        let trigger = normalize_trigger(order);
+        Signature::perp_order(tif, reduce_only, trigger.as_str()).into_inner()
+    })
+}
+
+pub fn normalize_spot_orders(record: &ActionLogRecord) -> (Vec<String>, Option<NormalizeError>) {
+    normalize_orders(record, |_, tif| Signature::spot_order(tif).into_inner())
+}
+
+/// One signature per order the ack accepted, built from the request order and its
+/// normalized TIF.
+fn normalize_orders(
+    record: &ActionLogRecord,
+    signature: impl Fn(&Value, &str) -> String,
+) -> (Vec<String>, Option<NormalizeError>) {
+    let ack = match ok_ack(record) {
+        Ok(ack) => ack,
+        Err(err) => return (Vec::new(), Some(err)),
+    };
+
+    let orders = request_orders(&record.request);
+
+    if orders.is_empty() {
+        return (Vec::new(), Some(NormalizeError::MissingRequest));
+    }
+
+    let order_statuses = ack_statuses(ack);
+
+    let mut signatures = Vec::new();
+    let mut incomplete = false;
+    for (idx, order) in orders.iter().enumerate() {
+        let status_kind = order_statuses
+            .get(idx)
+            .and_then(|v| v.get("kind"))
+            .and_then(|v| v.as_str());
+
+        match status_kind {
+            Some(kind) if kind.eq_ignore_ascii_case("error") => continue,
+            Some(_) => {}
+            None => {
+                incomplete = true;
+                continue;
+            }
+        }
+
+        let tif_raw = order.get("tif").and_then(|v| v.as_str()).unwrap_or("GTC");
+        signatures.push(signature(order, normalize_tif(tif_raw)));
+    }
+
+    if signatures.is_empty() {
+        if incomplete {
+            (signatures, Some(NormalizeError::IncompleteAck))
+        } else {
+            (signatures, Some(NormalizeError::NoEffect))
+        }
+    } else if incomplete {
+        (signatures, Some(NormalizeError::IncompleteAck))
+    } else {
+        (signatures, None)
+    }
+}
+
+/// Per-order request entries, expanding a `multi_coin_orders` step into one order per coin
+/// the way the runner submitted it.
+fn request_orders(request: &Value) -> Vec<Value> {
+    if let Some(multi) = request.get("multi_coin_orders") {
+        let tif = multi.get("tif").cloned().unwrap_or(Value::Null);
+        return multi
+            .get("coins")
+            .and_then(|v| v.as_array())
+            .map(|coins| {
+                coins
+                    .iter()
+                    .map(|coin| json!({ "coin": coin, "tif": tif, "reduceOnly": false }))
+                    .collect()
+            })
+            .unwrap_or_default();
+    }
+    request
+        .get("perp_orders")
+        .or_else(|| request.get("spot_orders"))
+        .and_then(|v| v.get("orders"))
+        .and_then(|v| v.as_array())
+        .cloned()
+        .unwrap_or_default()
+}
+
+pub fn normalize_cancel(
+    record: &ActionLogRecord,
+    scope: &str,
+) -> (Vec<String>, Option<NormalizeError>) {
+    if let Err(err) = ok_ack(record) {
+        return (Vec::new(), Some(err));
+    }
+    let signature = Signature::perp_cancel(scope).into_inner();
+    (vec![signature], None)
+}
+
+pub fn normalize_transfer(record: &ActionLogRecord) -> (Vec<String>, Option<NormalizeError>) {
+    if let Err(err) = ok_ack(record) {
+        return (Vec::new(), Some(err));
+    }
+    let dir = record
+        .request
+        .get("usd_class_transfer")
+        .and_then(|v| v.get("toPerp"))
+        .and_then(|v| v.as_bool())
+        .map(|to_perp| if to_perp { "toPerp" } else { "fromPerp" })
+        .unwrap_or("toPerp");
+    let signature = Signature::account_usd_class_transfer(dir).into_inner();
+    (vec![signature], None)
+}
+
+pub fn normalize_sub_account_transfer(
+    record: &ActionLogRecord,
+) -> (Vec<String>, Option<NormalizeError>) {
+    if let Err(err) = ok_ack(record) {
+        return (Vec::new(), Some(err));
+    }
+    let dir = record
+        .request
+        .get("sub_account_transfer")
+        .and_then(|v| v.get("isDeposit"))
+        .and_then(|v| v.as_bool())
+        .map(|is_deposit| if is_deposit { "deposit" } else { "withdraw" })
+        .unwrap_or("deposit");
+    let signature = Signature::account_sub_account_transfer(dir).into_inner();
+    (vec![signature], None)
+}
+
+pub fn normalize_leverage(record: &ActionLogRecord) -> (Vec<String>, Option<NormalizeError>) {
+    if let Err(err) = ok_ack(record) {
+        return (Vec::new(), Some(err));
+    }
+    let coin = record
+        .request
+        .get("set_leverage")
+        .and_then(|v| v.get("coin"))
+        .and_then(|v| v.as_str())
+        .unwrap_or("UNKNOWN");
+    let signature = Signature::risk_set_leverage(coin).into_inner();
+    (vec![signature], None)
+}
+
+pub fn normalize_wait_for_fill(record: &ActionLogRecord) -> (Vec<String>, Option<NormalizeError>) {
+    if let Err(err) = ok_ack(record) {
+        return (Vec::new(), Some(err));
+    }
+    if record.observed.is_none() {
+        return (Vec::new(), Some(NormalizeError::NoEffect));
+    }
+    let signature = Signature::perp_wait_fill().into_inner();
+    (vec![signature], None)
+}
+
+pub fn normalize_funding_rate(record: &ActionLogRecord) -> (Vec<String>, Option<NormalizeError>) {
+    if let Err(err) = ok_ack(record) {
+        return (Vec::new(), Some(err));
+    }
+    let has_rate = record
+        .observed
+        .as_ref()
+        .and_then(|v| v.get("fundingRate"))
+        .and_then(Value::as_f64)
+        .is_some();
+    if !has_rate {
+        return (Vec::new(), Some(NormalizeError::NoEffect));
+    }
+    let coin = record
+        .request
+        .get("fetch_funding_rate")
+        .and_then(|v| v.get("coin"))
+        .and_then(|v| v.as_str())
+        .unwrap_or("UNKNOWN");
+    let signature = Signature::market_funding_rate(coin).into_inner();
+    (vec![signature], None)
+}
+
+/// A passing `assert_balance` step acks `ok`; a failed one (logged under `failSoft`) acks `err`.
+pub fn normalize_assert_balance(record: &ActionLogRecord) -> (Vec<String>, Option<NormalizeError>) {
+    if let Err(err) = ok_ack(record) {
+        return (Vec::new(), Some(err));
+    }
+    (vec![Signature::account_assert_balance().into_inner()], None)
+}
+
+/// The record's ack, or why it cannot be credited: missing, or a status other than `ok`.
+fn ok_ack(record: &ActionLogRecord) -> Result<&Value, NormalizeError> {
+    let ack = record.ack.as_ref().ok_or(NormalizeError::MissingAck)?;
+    let ok = ack
+        .get("status")
+        .and_then(|v| v.as_str())
+        .is_some_and(|status| status.eq_ignore_ascii_case("ok"));
+    if ok {
+        Ok(ack)
+    } else {
+        Err(NormalizeError::AckNotOk)
+    }
+}
+
+/// Per-order `data.statuses` entries of an order ack; empty when the ack has none.
+fn ack_statuses(ack: &Value) -> &[Value] {
+    ack.get("data")
+        .and_then(|d| d.get("statuses"))
+        .and_then(|s| s.as_array())
+        .map_or(&[], Vec::as_slice)
+}
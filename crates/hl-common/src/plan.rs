@@ -1,6 +1,7 @@
 use std::{
+    collections::{BTreeSet, HashMap, HashSet},
     fs::File,
-    io::{BufRead, BufReader},
+    io::{BufRead, BufReader, Read},
     path::{Path, PathBuf},
     str::FromStr,
 };
@@ -9,6 +10,21 @@ use anyhow::{anyhow, Context, Result};
 use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 use serde_json::Value;
 
+use crate::artifacts::ActionLogRecord;
+
+/// Largest order size accepted from generated plans.
+pub const MAX_ORDER_SIZE: f64 = 1.0;
+/// Highest leverage the venue accepts for any asset.
+pub const MAX_VENUE_LEVERAGE: u32 = 50;
+/// Most passes a loop may run, counting enclosing loops' passes multiplied in.
+pub const MAX_LOOP_PASSES: u64 = 10_000;
+
+const LARGE_ORDER_RATIO: f64 = 0.9;
+const MIN_SLEEP_MS: u64 = 10;
+const KNOWN_COINS: &[&str] = &[
+    "BTC", "ETH", "SOL", "APT", "ARB", "AVAX", "BNB", "DOGE", "HYPE", "LINK", "OP", "SUI", "XRP",
+];
+
 /// Parsed representation of a runner plan.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -16,10 +32,68 @@ pub struct Plan {
     pub steps: Vec<ActionStep>,
 }
 
+/// Assumed wall-clock cost of one non-sleep step (a typical network round-trip).
+pub const DEFAULT_STEP_DURATION_MS: u64 = 500;
+
 impl Plan {
     pub fn as_json(&self) -> Value {
         serde_json::to_value(self).expect("plan must serialize")
     }
+
+    /// YAML that [`load_plan_from_spec`] reads back. Goes through [`Plan::as_json`] so
+    /// prices become plain maps rather than YAML enum tags, which untagged steps reject.
+    pub fn to_yaml(&self) -> String {
+        serde_yaml::to_string(&self.as_json()).expect("plan must serialize")
+    }
+
+    /// Rough run time: sleep durations plus [`DEFAULT_STEP_DURATION_MS`] per other step.
+    pub fn estimated_duration_ms(&self) -> u64 {
+        self.estimated_duration_with_step_ms(DEFAULT_STEP_DURATION_MS)
+    }
+
+    /// An `if` step counts as its longer branch; a `loop` as `count` passes over its body.
+    pub fn estimated_duration_with_step_ms(&self, default_step_ms: u64) -> u64 {
+        steps_duration_ms(&self.steps, default_step_ms)
+    }
+
+    /// Distinct coins, sorted, that order steps (including those nested in `if`/`loop`) trade.
+    pub fn coins_used(&self) -> Vec<String> {
+        let mut coins = BTreeSet::new();
+        let mut steps: Vec<&ActionStep> = self.steps.iter().collect();
+        while let Some(step) = steps.pop() {
+            match step {
+                ActionStep::PerpOrders { perp_orders } => {
+                    coins.extend(perp_orders.orders.iter().map(|order| order.coin.clone()))
+                }
+                ActionStep::MultiCoinOrders { multi_coin_orders } => {
+                    coins.extend(multi_coin_orders.coins.iter().cloned())
+                }
+                ActionStep::SpotOrders { spot_orders } => {
+                    coins.extend(spot_orders.orders.iter().map(|order| order.coin.clone()))
+                }
+                ActionStep::If { if_step } => {
+                    steps.extend(if_step.then_steps.iter().chain(&if_step.else_steps))
+                }
+                ActionStep::Loop { loop_step } => steps.extend(&loop_step.steps),
+                _ => {}
+            }
+        }
+        coins.into_iter().collect()
+    }
+}
+
+fn steps_duration_ms(steps: &[ActionStep], default_step_ms: u64) -> u64 {
+    steps
+        .iter()
+        .map(|step| match step {
+            ActionStep::Sleep { sleep_ms } => sleep_ms.duration_ms,
+            ActionStep::If { if_step } => steps_duration_ms(&if_step.then_steps, default_step_ms)
+                .max(steps_duration_ms(&if_step.else_steps, default_step_ms)),
+            ActionStep::Loop { loop_step } => steps_duration_ms(&loop_step.steps, default_step_ms)
+                .saturating_mul(u64::from(loop_step.count)),
+            _ => default_step_ms,
+        })
+        .fold(0, u64::saturating_add)
 }
 
 /// Step variants supported by the runner.
@@ -29,6 +103,12 @@ pub enum ActionStep {
     PerpOrders {
         perp_orders: PerpOrdersStep,
     },
+    MultiCoinOrders {
+        multi_coin_orders: MultiCoinOrdersStep,
+    },
+    SpotOrders {
+        spot_orders: SpotOrdersStep,
+    },
     CancelLast {
         cancel_last: CancelLastStep,
     },
@@ -47,18 +127,46 @@ pub enum ActionStep {
     Sleep {
         sleep_ms: SleepMsStep,
     },
+    WaitForFill {
+        wait_for_fill: WaitForFillStep,
+    },
+    FetchFundingRate {
+        fetch_funding_rate: FetchFundingRateStep,
+    },
+    AssertBalance {
+        assert_balance: AssertBalanceStep,
+    },
+    SubAccountTransfer {
+        sub_account_transfer: SubAccountTransferStep,
+    },
+    If {
+        #[serde(rename = "if")]
+        if_step: IfStep,
+    },
+    Loop {
+        #[serde(rename = "loop")]
+        loop_step: LoopStep,
+    },
 }
 
 impl ActionStep {
     pub fn kind(&self) -> &'static str {
         match self {
             ActionStep::PerpOrders { .. } => "perp_orders",
+            ActionStep::MultiCoinOrders { .. } => "multi_coin_orders",
+            ActionStep::SpotOrders { .. } => "spot_orders",
             ActionStep::CancelLast { .. } => "cancel_last",
             ActionStep::CancelOids { .. } => "cancel_oids",
             ActionStep::CancelAll { .. } => "cancel_all",
             ActionStep::UsdClassTransfer { .. } => "usd_class_transfer",
             ActionStep::SetLeverage { .. } => "set_leverage",
             ActionStep::Sleep { .. } => "sleep_ms",
+            ActionStep::WaitForFill { .. } => "wait_for_fill",
+            ActionStep::FetchFundingRate { .. } => "fetch_funding_rate",
+            ActionStep::AssertBalance { .. } => "assert_balance",
+            ActionStep::SubAccountTransfer { .. } => "sub_account_transfer",
+            ActionStep::If { .. } => "if",
+            ActionStep::Loop { .. } => "loop",
         }
     }
 
@@ -69,6 +177,58 @@ impl ActionStep {
         }
     }
 
+    /// Coins this step names itself, not counting steps nested in `if` / `loop`.
+    pub fn coins(&self) -> Vec<&str> {
+        match self {
+            ActionStep::PerpOrders { perp_orders } => perp_orders
+                .orders
+                .iter()
+                .map(|order| order.coin.as_str())
+                .collect(),
+            ActionStep::MultiCoinOrders { multi_coin_orders } => {
+                multi_coin_orders.coins.iter().map(String::as_str).collect()
+            }
+            ActionStep::SpotOrders { spot_orders } => spot_orders
+                .orders
+                .iter()
+                .map(|order| order.coin.as_str())
+                .collect(),
+            ActionStep::CancelLast { cancel_last } => {
+                cancel_last.coin.as_deref().into_iter().collect()
+            }
+            ActionStep::CancelOids { cancel_oids } => vec![cancel_oids.coin.as_str()],
+            ActionStep::CancelAll { cancel_all } => {
+                cancel_all.coin.as_deref().into_iter().collect()
+            }
+            ActionStep::SetLeverage { set_leverage } => vec![set_leverage.coin.as_str()],
+            ActionStep::WaitForFill { wait_for_fill } => {
+                wait_for_fill.coin.as_deref().into_iter().collect()
+            }
+            ActionStep::FetchFundingRate { fetch_funding_rate } => {
+                vec![fetch_funding_rate.coin.as_str()]
+            }
+            ActionStep::UsdClassTransfer { .. }
+            | ActionStep::Sleep { .. }
+            | ActionStep::AssertBalance { .. }
+            | ActionStep::SubAccountTransfer { .. }
+            | ActionStep::If { .. }
+            | ActionStep::Loop { .. } => Vec::new(),
+        }
+    }
+
+    /// Steps nested directly inside this one: both `if` branches, or a `loop` body.
+    pub fn children(&self) -> Vec<&ActionStep> {
+        match self {
+            ActionStep::If { if_step } => if_step
+                .then_steps
+                .iter()
+                .chain(&if_step.else_steps)
+                .collect(),
+            ActionStep::Loop { loop_step } => loop_step.steps.iter().collect(),
+            _ => Vec::new(),
+        }
+    }
+
     pub fn as_cancel_scope(&self) -> Option<CancelScope<'_>> {
         match self {
             ActionStep::CancelLast { cancel_last } => Some(CancelScope::Last { cancel_last }),
@@ -79,6 +239,73 @@ impl ActionStep {
     }
 }
 
+/// Runs `then_steps` when `condition` holds at this point of the run, else `else_steps`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IfStep {
+    pub condition: StepCondition,
+    #[serde(default, alias = "then_steps")]
+    pub then_steps: Vec<ActionStep>,
+    #[serde(default, alias = "else_steps")]
+    pub else_steps: Vec<ActionStep>,
+}
+
+impl IfStep {
+    /// The branch name (`then` / `else`) and its steps.
+    pub fn branch(&self, condition_holds: bool) -> (&'static str, &[ActionStep]) {
+        if condition_holds {
+            ("then", &self.then_steps)
+        } else {
+            ("else", &self.else_steps)
+        }
+    }
+}
+
+/// Runs `steps` `count` times in a row.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LoopStep {
+    pub count: u32,
+    pub steps: Vec<ActionStep>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum StepCondition {
+    /// The last logged step's ack reported no failure.
+    #[serde(alias = "previous_step_succeeded")]
+    PreviousStepSucceeded,
+    /// The last logged step's ack reported an error or a rejected order.
+    #[serde(alias = "previous_step_failed")]
+    PreviousStepFailed,
+    /// At least `ge` orders have been placed so far in the run.
+    #[serde(alias = "placed_order_count")]
+    PlacedOrderCount { ge: usize },
+    /// Every order of the most recent order step filled.
+    #[serde(alias = "all_orders_filled")]
+    AllOrdersFilled,
+}
+
+impl StepCondition {
+    pub fn holds(
+        &self,
+        previous: Option<&ActionLogRecord>,
+        last_orders: Option<&ActionLogRecord>,
+        placed_orders: usize,
+    ) -> bool {
+        match self {
+            StepCondition::PreviousStepSucceeded => {
+                previous.is_some_and(|record| !record.ack_failed())
+            }
+            StepCondition::PreviousStepFailed => previous.is_some_and(ActionLogRecord::ack_failed),
+            StepCondition::PlacedOrderCount { ge } => placed_orders >= *ge,
+            StepCondition::AllOrdersFilled => {
+                last_orders.is_some_and(ActionLogRecord::all_orders_filled)
+            }
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum CancelScope<'a> {
     Last { cancel_last: &'a CancelLastStep },
@@ -92,6 +319,145 @@ pub struct PerpOrdersStep {
     pub orders: Vec<PerpOrder>,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub builder_code: Option<String>,
+    /// Replaces the `tif` of every order in the step when set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub time_in_force_override: Option<PerpTif>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retry: Option<RetryPolicy>,
+    /// Submit one bulk order per coin, concurrently, instead of a single bulk order.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub parallel: bool,
+}
+
+/// Re-submits a step whose post fails, doubling the delay after each attempt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RetryPolicy {
+    /// Total attempts, including the first.
+    pub max_attempts: u32,
+    #[serde(default)]
+    pub initial_delay_ms: u64,
+}
+
+impl RetryPolicy {
+    /// Delay before attempt `attempt + 1`, for `attempt` >= 1.
+    pub fn delay_ms(&self, attempt: u32) -> u64 {
+        let doublings = attempt.saturating_sub(1).min(20);
+        self.initial_delay_ms.saturating_mul(1 << doublings)
+    }
+}
+
+impl PerpOrdersStep {
+    /// Returns a copy with `time_in_force_override` folded into each order.
+    pub fn with_tif_override(&self) -> Self {
+        let mut step = self.clone();
+        if let Some(tif) = step.time_in_force_override.take() {
+            for order in &mut step.orders {
+                order.tif = tif;
+            }
+        }
+        step
+    }
+}
+
+/// The same order placed on each of `coins`, submitted as one bulk order.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MultiCoinOrdersStep {
+    pub coins: Vec<String>,
+    pub side: OrderSide,
+    pub sz: f64,
+    #[serde(deserialize_with = "deserialize_order_price")]
+    pub px: OrderPrice,
+    #[serde(default)]
+    pub tif: PerpTif,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub builder_code: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retry: Option<RetryPolicy>,
+}
+
+impl MultiCoinOrdersStep {
+    /// One order per coin, in `coins` order.
+    pub fn to_perp_orders(&self) -> PerpOrdersStep {
+        PerpOrdersStep {
+            orders: self
+                .coins
+                .iter()
+                .map(|coin| PerpOrder {
+                    coin: coin.clone(),
+                    tif: self.tif,
+                    side: self.side,
+                    sz: self.sz,
+                    reduce_only: false,
+                    builder_code: None,
+                    cloid: None,
+                    trigger: None,
+                    px: self.px.clone(),
+                    snap_to_tick: false,
+                })
+                .collect(),
+            builder_code: self.builder_code.clone(),
+            time_in_force_override: None,
+            retry: self.retry,
+            parallel: false,
+        }
+    }
+}
+
+/// Spot market orders (`coin` is a spot pair such as `PURR/USDC` or an `@<index>` name),
+/// submitted as one bulk order.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SpotOrdersStep {
+    pub orders: Vec<SpotOrder>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub builder_code: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retry: Option<RetryPolicy>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SpotOrder {
+    pub coin: String,
+    pub side: OrderSide,
+    pub sz: f64,
+    #[serde(deserialize_with = "deserialize_order_price")]
+    pub px: OrderPrice,
+    #[serde(default)]
+    pub tif: SpotTif,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub builder_code: Option<String>,
+}
+
+impl SpotOrdersStep {
+    /// The same orders in perp form (never reduce-only or triggered), which the exchange
+    /// accepts for spot pairs too.
+    pub fn to_perp_orders(&self) -> PerpOrdersStep {
+        PerpOrdersStep {
+            orders: self
+                .orders
+                .iter()
+                .map(|order| PerpOrder {
+                    coin: order.coin.clone(),
+                    tif: order.tif,
+                    side: order.side,
+                    sz: order.sz,
+                    reduce_only: false,
+                    builder_code: order.builder_code.clone(),
+                    cloid: None,
+                    trigger: None,
+                    px: order.px.clone(),
+                    snap_to_tick: false,
+                })
+                .collect(),
+            builder_code: self.builder_code.clone(),
+            time_in_force_override: None,
+            retry: self.retry,
+            parallel: false,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -106,6 +472,12 @@ pub struct CancelLastStep {
 pub struct CancelOidsStep {
     pub coin: String,
     pub oids: Vec<u64>,
+    /// Drop oids the runner did not place before submitting (default false).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub validate_tracked: Option<bool>,
+    /// Warn about untracked oids but still submit them (default true).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub warn_untracked: Option<bool>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -122,6 +494,19 @@ pub struct UsdClassTransferStep {
     pub usdc: f64,
 }
 
+/// Moves USDC between the wallet and one of its sub-accounts.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SubAccountTransferStep {
+    /// Sub-account address (`0x` + 40 hex digits).
+    #[serde(alias = "to_address")]
+    pub to_address: String,
+    pub amount: f64,
+    /// `true` moves funds into the sub-account, `false` back out of it.
+    #[serde(alias = "is_deposit")]
+    pub is_deposit: bool,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct SetLeverageStep {
@@ -129,6 +514,12 @@ pub struct SetLeverageStep {
     pub leverage: u32,
     #[serde(default)]
     pub cross: bool,
+    /// Abort the run instead of updating leverage when the coin already has an open position.
+    #[serde(default)]
+    pub fail_if_position: bool,
+    /// Run the pre-checks and log the step without submitting the leverage update.
+    #[serde(default)]
+    pub dry_set_leverage: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -139,6 +530,73 @@ pub struct SleepMsStep {
     pub duration_ms: u64,
 }
 
+/// Blocks until a tracked order is observed filled.
+///
+/// The order is picked by `oid`, else `oid_index`, else the latest order on `coin`; with
+/// none of them set, the most recently placed order is awaited.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WaitForFillStep {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub oid: Option<u64>,
+    /// Position among the orders placed so far, oldest first; negative counts back from
+    /// the newest (`-1`).
+    #[serde(default, alias = "oid_index", skip_serializing_if = "Option::is_none")]
+    pub oid_index: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub coin: Option<String>,
+    #[serde(default, alias = "timeout_ms", skip_serializing_if = "Option::is_none")]
+    pub timeout_ms: Option<u64>,
+    /// Abort the run on timeout instead of noting it and continuing.
+    #[serde(
+        default,
+        alias = "fail_on_timeout",
+        skip_serializing_if = "std::ops::Not::not"
+    )]
+    pub fail_on_timeout: bool,
+}
+
+/// Reads the latest funding rate for a coin and records it as observed data.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FetchFundingRateStep {
+    pub coin: String,
+}
+
+/// Checks the available perp USDC balance against inclusive bounds mid-plan.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AssertBalanceStep {
+    #[serde(
+        default,
+        alias = "min_perp_usdc",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub min_perp_usdc: Option<f64>,
+    #[serde(
+        default,
+        alias = "max_perp_usdc",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub max_perp_usdc: Option<f64>,
+    /// Log a failed assertion and keep going instead of aborting the run.
+    #[serde(default, alias = "fail_soft")]
+    pub fail_soft: bool,
+}
+
+impl AssertBalanceStep {
+    /// Describes why `balance` falls outside the bounds, or `None` when it passes.
+    pub fn violation(&self, balance: f64) -> Option<String> {
+        if let Some(min) = self.min_perp_usdc.filter(|min| balance < *min) {
+            return Some(format!("balance {balance:?} < min {min:?}"));
+        }
+        if let Some(max) = self.max_perp_usdc.filter(|max| balance > *max) {
+            return Some(format!("balance {balance:?} > max {max:?}"));
+        }
+        None
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct PerpOrder {
@@ -157,6 +615,9 @@ pub struct PerpOrder {
     pub trigger: Option<OrderTrigger>,
     #[serde(deserialize_with = "deserialize_order_price")]
     pub px: OrderPrice,
+    /// Round the resolved price to the coin's tick size before submitting.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub snap_to_tick: bool,
 }
 
 impl PerpOrder {
@@ -165,18 +626,41 @@ impl PerpOrder {
     }
 }
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Default)]
 #[serde(rename_all = "UPPERCASE")]
 pub enum PerpTif {
-    #[serde(alias = "Alo", alias = "alo")]
     Alo,
     #[default]
-    #[serde(alias = "Gtc", alias = "gtc")]
     Gtc,
-    #[serde(alias = "Ioc", alias = "ioc")]
     Ioc,
 }
 
+impl FromStr for PerpTif {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_uppercase().as_str() {
+            "ALO" => Ok(PerpTif::Alo),
+            "GTC" => Ok(PerpTif::Gtc),
+            "IOC" => Ok(PerpTif::Ioc),
+            _ => Err(format!("invalid tif '{s}' (expected ALO, GTC or IOC)")),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for PerpTif {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let value = String::deserialize(deserializer)?;
+        PerpTif::from_str(&value).map_err(de::Error::custom)
+    }
+}
+
+/// Spot orders take the same time-in-force values as perps.
+pub type SpotTif = PerpTif;
+
 impl PerpTif {
     pub fn as_sdk_str(&self) -> &'static str {
         match self {
@@ -236,17 +720,105 @@ pub enum OrderTrigger {
 #[derive(Debug, Clone, Serialize)]
 pub enum OrderPrice {
     Absolute(f64),
-    MidPercent { offset_pct: f64 },
+    MidPercent {
+        offset_pct: f64,
+    },
+    /// Offset from the best bid (`"bid-0.1%"`).
+    BidPercent {
+        offset_pct: f64,
+    },
+    /// Offset from the best ask (`"ask+0.05%"`).
+    AskPercent {
+        offset_pct: f64,
+    },
 }
 
 impl OrderPrice {
+    /// Resolves against `mid`; bid/ask offsets are applied to `mid` too, which is
+    /// close enough for notional estimates but not for quoting.
     pub fn resolve_with_mid(&self, mid: f64) -> f64 {
+        self.resolve_with_reference(mid)
+    }
+
+    /// Resolves a relative price against its reference (mid, best bid or best ask).
+    pub fn resolve_with_reference(&self, reference: f64) -> f64 {
+        match self.offset_pct() {
+            Some(offset_pct) => reference * (1.0 + offset_pct / 100.0),
+            None => self.absolute().unwrap_or(reference),
+        }
+    }
+
+    pub fn absolute(&self) -> Option<f64> {
         match self {
-            OrderPrice::Absolute(px) => *px,
-            OrderPrice::MidPercent { offset_pct } => {
-                let factor = 1.0 + offset_pct / 100.0;
-                mid * factor
+            OrderPrice::Absolute(px) => Some(*px),
+            _ => None,
+        }
+    }
+
+    pub fn offset_pct(&self) -> Option<f64> {
+        match self {
+            OrderPrice::Absolute(_) => None,
+            OrderPrice::MidPercent { offset_pct }
+            | OrderPrice::BidPercent { offset_pct }
+            | OrderPrice::AskPercent { offset_pct } => Some(*offset_pct),
+        }
+    }
+
+    /// Rounds `price` to `decimals` decimal places, ties to even as `snapToTick` promises
+    /// in docs/FORMAT.md so snapping has no upward or downward bias.
+    ///
+    /// The tie is judged on the price as written (its shortest round-trip decimal form),
+    /// not its binary value: `3412.65` is stored as `3412.6500000000000909...`, which plain
+    /// `f64` rounding would push up to `3412.7`.
+    pub fn round_to_decimals(price: f64, decimals: u32) -> f64 {
+        if !price.is_finite() {
+            return price;
+        }
+        let written = price.abs().to_string();
+        let (int_part, frac) = written.split_once('.').unwrap_or((&written, ""));
+        let keep = decimals as usize;
+        if frac.len() <= keep {
+            return price;
+        }
+        let (kept_frac, dropped) = frac.split_at(keep);
+        let last_kept = kept_frac.bytes().last().or(int_part.bytes().last());
+        let round_up = match dropped.as_bytes()[0] {
+            b'6'..=b'9' => true,
+            b'5' => {
+                dropped.bytes().skip(1).any(|digit| digit != b'0')
+                    || last_kept.is_some_and(|digit| (digit - b'0') % 2 == 1)
             }
+            _ => false,
+        };
+        let kept: f64 = format!("{int_part}.{kept_frac}0")
+            .parse()
+            .expect("digits of a formatted f64 parse back");
+        let magnitude = if round_up {
+            kept + 10f64.powi(-(decimals as i32))
+        } else {
+            kept
+        };
+        // The sum above can pick up float noise; re-printing at `decimals` places drops it.
+        let magnitude: f64 = format!("{magnitude:.keep$}")
+            .parse()
+            .expect("a formatted f64 parses back");
+        magnitude.copysign(price)
+    }
+}
+
+#[derive(Clone, Copy)]
+enum OrderPriceAnchor {
+    Mid,
+    Bid,
+    Ask,
+}
+
+impl OrderPriceAnchor {
+    fn with_offset(self, offset_pct: f64) -> OrderPrice {
+        match self {
+            OrderPriceAnchor::Mid => OrderPrice::MidPercent { offset_pct },
+            OrderPriceAnchor::Bid => OrderPrice::BidPercent { offset_pct },
+            OrderPriceAnchor::Ask => OrderPrice::AskPercent { offset_pct },
         }
     }
 }
@@ -284,27 +856,53 @@ where
             self.visit_f64(value as f64)
         }
 
+        fn visit_i128<E>(self, value: i128) -> Result<Self::Value, E>
+        where
+            E: de::Error,
+        {
+            self.visit_f64(value as f64)
+        }
+
+        fn visit_u128<E>(self, value: u128) -> Result<Self::Value, E>
+        where
+            E: de::Error,
+        {
+            self.visit_f64(value as f64)
+        }
+
+        fn visit_f32<E>(self, value: f32) -> Result<Self::Value, E>
+        where
+            E: de::Error,
+        {
+            self.visit_f64(value as f64)
+        }
+
         fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
         where
             E: de::Error,
         {
             let trimmed = v.trim();
-            if let Some(rest) = trimmed.strip_prefix("mid") {
+            let relative = [
+                ("mid", OrderPriceAnchor::Mid),
+                ("bid", OrderPriceAnchor::Bid),
+                ("ask", OrderPriceAnchor::Ask),
+            ]
+            .into_iter()
+            .find_map(|(prefix, anchor)| Some((prefix, anchor, trimmed.strip_prefix(prefix)?)));
+            if let Some((prefix, anchor, rest)) = relative {
                 let rest = rest.trim();
                 let (sign, magnitude) = if let Some(v) = rest.strip_prefix('+') {
                     (1.0_f64, v)
                 } else if let Some(v) = rest.strip_prefix('-') {
                     (-1.0_f64, v)
                 } else {
-                    return Err(E::custom("expected '+' or '-' after 'mid'"));
+                    return Err(E::custom(format!("expected '+' or '-' after '{prefix}'")));
                 };
                 let magnitude = magnitude.trim_end_matches('%').trim();
                 let pct = magnitude
                     .parse::<f64>()
-                    .map_err(|_| E::custom("invalid mid% offset"))?;
-                Ok(OrderPrice::MidPercent {
-                    offset_pct: sign * pct,
-                })
+                    .map_err(|_| E::custom(format!("invalid {prefix}% offset")))?;
+                Ok(anchor.with_offset(sign * pct))
             } else {
                 let value = trimmed
                     .parse::<f64>()
@@ -312,6 +910,37 @@ where
                 Ok(OrderPrice::Absolute(value))
             }
         }
+
+        /// The derived `Serialize` form written to `plan.json`, e.g. `{"Absolute": 3000.0}`.
+        fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+        where
+            A: de::MapAccess<'de>,
+        {
+            #[derive(Deserialize)]
+            struct Offset {
+                offset_pct: f64,
+            }
+
+            let key: String = map
+                .next_key()?
+                .ok_or_else(|| de::Error::custom("empty price object"))?;
+            match key.as_str() {
+                "Absolute" => Ok(OrderPrice::Absolute(map.next_value()?)),
+                "MidPercent" => Ok(OrderPrice::MidPercent {
+                    offset_pct: map.next_value::<Offset>()?.offset_pct,
+                }),
+                "BidPercent" => Ok(OrderPrice::BidPercent {
+                    offset_pct: map.next_value::<Offset>()?.offset_pct,
+                }),
+                "AskPercent" => Ok(OrderPrice::AskPercent {
+                    offset_pct: map.next_value::<Offset>()?.offset_pct,
+                }),
+                other => Err(de::Error::unknown_variant(
+                    other,
+                    &["Absolute", "MidPercent", "BidPercent", "AskPercent"],
+                )),
+            }
+        }
     }
 
     deserializer.deserialize_any(PriceVisitor)
@@ -326,21 +955,663 @@ impl<'de> Deserialize<'de> for OrderPrice {
     }
 }
 
-/// Loads a plan from a JSON file or JSONL specification.
+/// Outcome of [`validate`]: hard errors block execution, warnings are advisory.
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ValidationResult {
+    pub errors: Vec<ValidationError>,
+    pub warnings: Vec<ValidationWarning>,
+}
+
+impl ValidationResult {
+    pub fn is_valid(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    fn error(&mut self, step_idx: usize, message: String) {
+        self.errors.push(ValidationError { step_idx, message });
+    }
+
+    fn warn(&mut self, step_idx: usize, message: String) {
+        self.warnings.push(ValidationWarning { step_idx, message });
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ValidationError {
+    pub step_idx: usize,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ValidationWarning {
+    pub step_idx: usize,
+    pub message: String,
+}
+
+impl std::fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "step {}: {}", self.step_idx, self.message)
+    }
+}
+
+impl std::fmt::Display for ValidationWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "step {}: {}", self.step_idx, self.message)
+    }
+}
+
+fn check_retry(result: &mut ValidationResult, idx: usize, retry: Option<RetryPolicy>) {
+    if retry.is_some_and(|retry| retry.max_attempts == 0) {
+        result.error(idx, "retry.maxAttempts must be at least 1".to_string());
+    }
+}
+
+fn check_orders(
+    result: &mut ValidationResult,
+    idx: usize,
+    orders: &[PerpOrder],
+    allowed_coins: Option<&[String]>,
+) {
+    let mut cloids = HashSet::new();
+    for order in orders {
+        check_coin(result, idx, &order.coin, allowed_coins);
+        // Retries re-send an order under its cloid, so each cloid must name one order.
+        if let Some(cloid) = &order.cloid {
+            if !cloids.insert(cloid.to_ascii_lowercase()) {
+                result.error(idx, format!("duplicate cloid {cloid}"));
+            }
+        }
+        if order.sz <= 0.0 {
+            result.error(
+                idx,
+                format!(
+                    "order size {} for {} must be positive",
+                    order.sz, order.coin
+                ),
+            );
+        } else if order.sz >= MAX_ORDER_SIZE * LARGE_ORDER_RATIO {
+            result.warn(
+                idx,
+                format!(
+                    "order size {} for {} is close to or above the {} limit",
+                    order.sz, order.coin, MAX_ORDER_SIZE
+                ),
+            );
+        }
+    }
+}
+
+/// Statically checks a parsed plan without touching the network.
+///
+/// When `allowed_coins` is given, any coin outside the list is an error;
+/// otherwise coins are only compared against a built-in list to flag likely typos.
+pub fn validate(plan: &Plan, allowed_coins: Option<&[String]>) -> ValidationResult {
+    let mut result = ValidationResult::default();
+    for (idx, step) in plan.steps.iter().enumerate() {
+        validate_step(&mut result, idx, step, allowed_coins, 1);
+    }
+    result
+}
+
+/// Issues inside `if` branches are reported against the top-level `if` step.
+fn validate_step(
+    result: &mut ValidationResult,
+    idx: usize,
+    step: &ActionStep,
+    allowed_coins: Option<&[String]>,
+    // How many times enclosing loops run this step.
+    passes: u64,
+) {
+    match step {
+        ActionStep::PerpOrders { perp_orders } => {
+            if perp_orders.orders.is_empty() {
+                result.warn(idx, "perp_orders step has no orders".to_string());
+            }
+            check_orders(result, idx, &perp_orders.orders, allowed_coins);
+            check_retry(result, idx, perp_orders.retry);
+        }
+        ActionStep::MultiCoinOrders { multi_coin_orders } => {
+            if multi_coin_orders.coins.is_empty() {
+                result.error(idx, "multi_coin_orders.coins must not be empty".to_string());
+            }
+            let expanded = multi_coin_orders.to_perp_orders();
+            check_orders(result, idx, &expanded.orders, allowed_coins);
+            check_retry(result, idx, expanded.retry);
+        }
+        ActionStep::SpotOrders { spot_orders } => {
+            if spot_orders.orders.is_empty() {
+                result.warn(idx, "spot_orders step has no orders".to_string());
+            }
+            let expanded = spot_orders.to_perp_orders();
+            check_orders(result, idx, &expanded.orders, allowed_coins);
+            check_retry(result, idx, expanded.retry);
+        }
+        ActionStep::CancelLast { cancel_last } => {
+            if let Some(coin) = &cancel_last.coin {
+                check_coin(result, idx, coin, allowed_coins);
+            }
+        }
+        ActionStep::CancelOids { cancel_oids } => {
+            check_coin(result, idx, &cancel_oids.coin, allowed_coins);
+            if cancel_oids.oids.is_empty() {
+                result.error(idx, "cancel_oids.oids must not be empty".to_string());
+            }
+            if cancel_oids.oids.contains(&0) {
+                result.error(idx, "cancel_oids.oids must be non-zero".to_string());
+            }
+        }
+        ActionStep::CancelAll { cancel_all } => {
+            if let Some(coin) = &cancel_all.coin {
+                check_coin(result, idx, coin, allowed_coins);
+            }
+        }
+        ActionStep::UsdClassTransfer { .. } => {}
+        ActionStep::SetLeverage { set_leverage } => {
+            check_coin(result, idx, &set_leverage.coin, allowed_coins);
+            if set_leverage.leverage == 0 || set_leverage.leverage > MAX_VENUE_LEVERAGE {
+                result.error(
+                    idx,
+                    format!(
+                        "leverage {} must be between 1 and {}",
+                        set_leverage.leverage, MAX_VENUE_LEVERAGE
+                    ),
+                );
+            }
+        }
+        ActionStep::Sleep { sleep_ms } => {
+            if sleep_ms.duration_ms < MIN_SLEEP_MS {
+                result.warn(
+                    idx,
+                    format!(
+                        "sleep of {} ms is shorter than {} ms and unlikely to separate windows",
+                        sleep_ms.duration_ms, MIN_SLEEP_MS
+                    ),
+                );
+            }
+        }
+        ActionStep::WaitForFill { wait_for_fill } => {
+            if wait_for_fill.oid.is_some() && wait_for_fill.oid_index.is_some() {
+                result.warn(
+                    idx,
+                    "wait_for_fill sets both oid and oidIndex; oidIndex is ignored".to_string(),
+                );
+            }
+            if let Some(coin) = &wait_for_fill.coin {
+                check_coin(result, idx, coin, allowed_coins);
+            }
+        }
+        ActionStep::FetchFundingRate { fetch_funding_rate } => {
+            check_coin(result, idx, &fetch_funding_rate.coin, allowed_coins);
+        }
+        ActionStep::AssertBalance { assert_balance } => {
+            match (assert_balance.min_perp_usdc, assert_balance.max_perp_usdc) {
+                (None, None) => {
+                    result.warn(idx, "assert_balance step has no bounds".to_string());
+                }
+                (Some(min), Some(max)) if min > max => {
+                    result.error(idx, format!("assert_balance min {min} exceeds max {max}"));
+                }
+                _ => {}
+            }
+        }
+        ActionStep::SubAccountTransfer {
+            sub_account_transfer,
+        } => {
+            if !is_eth_address(&sub_account_transfer.to_address) {
+                result.error(
+                    idx,
+                    format!(
+                        "sub-account address '{}' is not 0x followed by 40 hex digits",
+                        sub_account_transfer.to_address
+                    ),
+                );
+            }
+            if sub_account_transfer.amount <= 0.0 {
+                result.error(
+                    idx,
+                    format!(
+                        "sub-account transfer amount {} must be positive",
+                        sub_account_transfer.amount
+                    ),
+                );
+            }
+        }
+        ActionStep::If { if_step } => {
+            if if_step.then_steps.is_empty() && if_step.else_steps.is_empty() {
+                result.warn(idx, "if step has no steps in either branch".to_string());
+            }
+            for child in if_step.then_steps.iter().chain(&if_step.else_steps) {
+                validate_step(result, idx, child, allowed_coins, passes);
+            }
+        }
+        ActionStep::Loop { loop_step } => {
+            if loop_step.count == 0 || loop_step.steps.is_empty() {
+                result.warn(idx, "loop step repeats nothing".to_string());
+            }
+            let count = u64::from(loop_step.count);
+            let mut total = passes.saturating_mul(count);
+            if count > MAX_LOOP_PASSES {
+                result.error(idx, format!("loop count {count} exceeds {MAX_LOOP_PASSES}"));
+            } else if total > MAX_LOOP_PASSES {
+                result.error(
+                    idx,
+                    format!("nested loops run {total} passes, more than {MAX_LOOP_PASSES}"),
+                );
+            }
+            if total > MAX_LOOP_PASSES {
+                // Already reported; keep loops nested inside from repeating the error.
+                total = 1;
+            }
+            for child in &loop_step.steps {
+                validate_step(result, idx, child, allowed_coins, total);
+            }
+        }
+    }
+}
+
+/// Warns about sub-account transfers addressed to `wallet` itself, which usually means
+/// the primary address was pasted where the sub-account belongs.
+pub fn check_wallet(plan: &Plan, wallet: &str, result: &mut ValidationResult) {
+    for (idx, step) in plan.steps.iter().enumerate() {
+        if let ActionStep::SubAccountTransfer {
+            sub_account_transfer,
+        } = step
+        {
+            if sub_account_transfer.to_address.eq_ignore_ascii_case(wallet) {
+                result.warn(
+                    idx,
+                    format!("sub-account transfer targets the primary wallet {wallet}"),
+                );
+            }
+        }
+    }
+}
+
+/// Errors for every step naming a coin in `rejected`, including steps nested in `if` /
+/// `loop` (reported against their top-level step).
+pub fn check_rejected_coins(plan: &Plan, rejected: &[String], result: &mut ValidationResult) {
+    for (idx, step) in plan.steps.iter().enumerate() {
+        let mut pending = vec![step];
+        while let Some(step) = pending.pop() {
+            for coin in step.coins() {
+                if rejected
+                    .iter()
+                    .any(|reject| reject.eq_ignore_ascii_case(coin))
+                {
+                    result.error(idx, format!("coin {coin} is rejected"));
+                }
+            }
+            pending.extend(step.children());
+        }
+    }
+}
+
+/// `0x` followed by exactly 40 hex digits.
+pub fn is_eth_address(raw: &str) -> bool {
+    raw.strip_prefix("0x")
+        .or_else(|| raw.strip_prefix("0X"))
+        .is_some_and(|hex| hex.len() == 40 && hex.chars().all(|ch| ch.is_ascii_hexdigit()))
+}
+
+fn check_coin(
+    result: &mut ValidationResult,
+    step_idx: usize,
+    coin: &str,
+    allowed_coins: Option<&[String]>,
+) {
+    if let Some(allowed) = allowed_coins {
+        if !allowed.iter().any(|c| c.eq_ignore_ascii_case(coin)) {
+            result.error(step_idx, format!("coin {coin} is not in the allowed list"));
+        }
+        return;
+    }
+
+    let upper = coin.to_ascii_uppercase();
+    if KNOWN_COINS.contains(&upper.as_str()) {
+        return;
+    }
+    if let Some(closest) = KNOWN_COINS
+        .iter()
+        .filter(|known| edit_distance(known, &upper) <= 1)
+        .min_by_key(|known| edit_distance(known, &upper))
+    {
+        result.warn(
+            step_idx,
+            format!("coin {coin} looks like a typo of {closest}"),
+        );
+    }
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b_chars: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b_chars.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut curr = vec![i + 1; b_chars.len() + 1];
+        for (j, cb) in b_chars.iter().enumerate() {
+            let cost = usize::from(ca != *cb);
+            curr[j + 1] = (prev[j] + cost).min(prev[j + 1] + 1).min(curr[j] + 1);
+        }
+        prev = curr;
+    }
+    prev[b_chars.len()]
+}
+
+#[cfg(feature = "plan-url")]
+fn load_plan_url(spec: &str, vars: &HashMap<String, String>) -> Result<Plan> {
+    crate::plan_url::fetch_plan(
+        spec,
+        &reqwest::blocking::Client::new(),
+        crate::plan_url::DEFAULT_FETCH_TIMEOUT,
+        vars,
+    )
+}
+
+#[cfg(not(feature = "plan-url"))]
+fn load_plan_url(spec: &str, _vars: &HashMap<String, String>) -> Result<Plan> {
+    Err(anyhow!(
+        "cannot load {spec}: hl-common was built without the `plan-url` feature"
+    ))
+}
+
+/// Plan spec (before any `:line` selector) that reads the plan from standard input.
+pub const STDIN_PLAN_SPEC: &str = "-";
+
+/// Whether a plan spec names a plan served over HTTP(S) rather than a local file.
+pub fn is_plan_url(spec: &str) -> bool {
+    spec.starts_with("http://") || spec.starts_with("https://")
+}
+
+/// Loads a plan from a JSON, YAML (`.yaml` / `.yml`) or JSONL specification; `-` reads it
+/// from stdin, and `http(s)://` specs are fetched (`plan-url` feature).
 pub fn load_plan_from_spec(spec: &str) -> Result<Plan> {
+    load_plan_with_vars(spec, &HashMap::new())
+}
+
+/// [`load_plan_from_spec`], filling `{{KEY}}` placeholders from `vars` (see
+/// [`apply_template`]) before parsing.
+pub fn load_plan_with_vars(spec: &str, vars: &HashMap<String, String>) -> Result<Plan> {
+    if is_plan_url(spec) {
+        return load_plan_url(spec, vars);
+    }
     let (path, selector) = split_spec(spec)?;
+    if path.as_os_str() == STDIN_PLAN_SPEC {
+        return read_plan_from(std::io::stdin().lock(), selector, vars);
+    }
+    let origin = path.display().to_string();
+    if is_yaml_path(&path) {
+        if selector.is_some() {
+            return Err(anyhow!(
+                "line selectors only apply to JSONL plans, not {origin}"
+            ));
+        }
+        let source = std::fs::read_to_string(&path)
+            .with_context(|| format!("failed to read plan file {origin}"))?;
+        let source =
+            apply_yaml_template(&source, vars).with_context(|| format!("in plan {origin}"))?;
+        return parse_yaml_plan(&source, &origin);
+    }
     let plan_source = if let Some(index) = selector {
         read_jsonl_entry(&path, index)?
     } else {
         std::fs::read_to_string(&path)
-            .with_context(|| format!("failed to read plan file {}", path.display()))?
+            .with_context(|| format!("failed to read plan file {origin}"))?
     };
+    let plan_source =
+        apply_template(&plan_source, vars).with_context(|| format!("in plan {origin}"))?;
 
     let plan: Plan = serde_json::from_str(&plan_source)
-        .with_context(|| format!("failed to deserialize plan from {}", path.display()))?;
+        .with_context(|| format!("failed to deserialize plan from {origin}"))?;
     Ok(plan)
 }
 
+/// Replaces each `{{KEY}}` in a raw plan with `vars[KEY]`. Inside a quoted string the value
+/// is escaped as string content; elsewhere it must be a number (`{"sz": {{SIZE}}}`).
+/// Placeholders without a value are reported together.
+pub fn apply_template(raw: &str, vars: &HashMap<String, String>) -> Result<String> {
+    let mut out = String::with_capacity(raw.len());
+    let mut missing = BTreeSet::new();
+    let mut in_string = false;
+    let mut escaped = false;
+    let mut rest = raw;
+    while let Some(ch) = rest.chars().next() {
+        if let Some((key, after)) = split_placeholder(rest) {
+            match vars.get(key) {
+                Some(value) if in_string => out.push_str(&json_string_content(value)?),
+                Some(value) if is_plain_number(value) => out.push_str(value),
+                Some(value) => {
+                    return Err(anyhow!(
+                        "plan variable {key}={value:?} is used outside a string, so it must be a number"
+                    ))
+                }
+                None => {
+                    missing.insert(key);
+                }
+            }
+            rest = after;
+            continue;
+        }
+        match ch {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            _ => {}
+        }
+        out.push(ch);
+        rest = &rest[ch.len_utf8()..];
+    }
+    report_missing(&missing)?;
+    Ok(out)
+}
+
+/// [`apply_template`] for YAML plans. Double-quoted values are escaped as in JSON and
+/// single-quoted ones double their `'`. A bare value may be any plain scalar (`coin:
+/// {{COIN}}`) but nothing YAML would read as structure. Comments are left alone.
+pub fn apply_yaml_template(raw: &str, vars: &HashMap<String, String>) -> Result<String> {
+    #[derive(PartialEq)]
+    enum Context {
+        Plain,
+        Double,
+        Single,
+        Comment,
+    }
+
+    let mut out = String::with_capacity(raw.len());
+    let mut missing = BTreeSet::new();
+    let mut context = Context::Plain;
+    let mut escaped = false;
+    // Last non-blank character outside quotes on this line, to tell where a scalar starts.
+    let mut previous: Option<char> = None;
+    let mut rest = raw;
+    while let Some(ch) = rest.chars().next() {
+        if context != Context::Comment {
+            if let Some((key, after)) = split_placeholder(rest) {
+                match (vars.get(key), &context) {
+                    (Some(value), Context::Double) => out.push_str(&json_string_content(value)?),
+                    (Some(value), Context::Single) => out.push_str(&value.replace('\'', "''")),
+                    (Some(value), _) if is_plain_number(value) || is_plain_yaml_scalar(value) => {
+                        out.push_str(value);
+                        previous = value.chars().last();
+                    }
+                    (Some(value), _) => {
+                        return Err(anyhow!(
+                            "plan variable {key}={value:?} is used outside a string, so it must be a plain scalar"
+                        ))
+                    }
+                    (None, _) => {
+                        missing.insert(key);
+                    }
+                }
+                rest = after;
+                continue;
+            }
+        }
+        let starts_scalar = matches!(previous, None | Some(':' | '-' | '[' | '{' | ',' | '?'));
+        match context {
+            Context::Plain => match ch {
+                '\n' => previous = None,
+                '"' if starts_scalar => context = Context::Double,
+                '\'' if starts_scalar => context = Context::Single,
+                '#' if out.is_empty() || out.ends_with(char::is_whitespace) => {
+                    context = Context::Comment
+                }
+                _ if !ch.is_whitespace() => previous = Some(ch),
+                _ => {}
+            },
+            Context::Double => match ch {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => {
+                    context = Context::Plain;
+                    previous = Some(ch);
+                }
+                _ => {}
+            },
+            // A doubled `''` closes and reopens, which leaves the state unchanged.
+            Context::Single if ch == '\'' => {
+                context = Context::Plain;
+                previous = Some(ch);
+            }
+            Context::Single => {}
+            Context::Comment if ch == '\n' => {
+                context = Context::Plain;
+                previous = None;
+            }
+            Context::Comment => {}
+        }
+        out.push(ch);
+        rest = &rest[ch.len_utf8()..];
+    }
+    report_missing(&missing)?;
+    Ok(out)
+}
+
+/// The key and the text after a `{{KEY}}` placeholder at the start of `rest`.
+fn split_placeholder(rest: &str) -> Option<(&str, &str)> {
+    rest.strip_prefix("{{")
+        .and_then(|tail| tail.split_once("}}"))
+        .map(|(key, after)| (key.trim(), after))
+        .filter(|(key, _)| is_template_key(key))
+}
+
+/// `value` escaped for use between double quotes.
+fn json_string_content(value: &str) -> Result<String> {
+    let quoted = serde_json::to_string(value)?;
+    Ok(quoted[1..quoted.len() - 1].to_string())
+}
+
+fn report_missing(missing: &BTreeSet<&str>) -> Result<()> {
+    if missing.is_empty() {
+        return Ok(());
+    }
+    let names: Vec<String> = missing.iter().map(|key| format!("{{{{{key}}}}}")).collect();
+    Err(anyhow!(
+        "no value for plan variable(s) {}",
+        names.join(", ")
+    ))
+}
+
+/// Whether `value` reads back as the same plain scalar wherever a YAML value can go.
+fn is_plain_yaml_scalar(value: &str) -> bool {
+    !value.is_empty()
+        && value.trim() == value
+        && !value.starts_with(['-', '?', '!', '&', '*', '|', '>', '%', '@', '`'])
+        && !value.contains([':', '#', ',', '[', ']', '{', '}', '"', '\'', '\n', '\r'])
+}
+
+fn is_template_key(key: &str) -> bool {
+    !key.is_empty()
+        && key
+            .chars()
+            .all(|ch| ch.is_ascii_alphanumeric() || ch == '_' || ch == '-' || ch == '.')
+}
+
+fn is_plain_number(value: &str) -> bool {
+    value.parse::<u64>().is_ok() || value.parse::<f64>().is_ok_and(f64::is_finite)
+}
+
+fn is_yaml_path(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("yaml") || ext.eq_ignore_ascii_case("yml"))
+}
+
+/// Parses a YAML plan, falling back to JSON for `.yaml` files that hold JSON the YAML
+/// parser rejects; when both fail, both errors are reported.
+pub fn parse_yaml_plan(source: &str, origin: &str) -> Result<Plan> {
+    let yaml_err = match serde_yaml::from_str(source) {
+        Ok(plan) => return Ok(plan),
+        Err(err) => err,
+    };
+    serde_json::from_str(source).map_err(|json_err| {
+        anyhow!(
+            "failed to deserialize plan from {origin}: as YAML: {yaml_err}; as JSON: {json_err}"
+        )
+    })
+}
+
+/// Whether `spec` reads its plan from stdin (`-` or `-:N`).
+pub fn is_stdin_plan_spec(spec: &str) -> bool {
+    split_plan_spec(spec).0 == STDIN_PLAN_SPEC
+}
+
+/// Buffers all of `reader` (normally stdin) before parsing, so a truncated or malformed
+/// plan fails with one clean error.
+pub fn read_plan_from(
+    mut reader: impl Read,
+    selector: Option<usize>,
+    vars: &HashMap<String, String>,
+) -> Result<Plan> {
+    let mut source = String::new();
+    reader
+        .read_to_string(&mut source)
+        .context("failed to read plan from stdin")?;
+    parse_plan_source(&source, selector, vars, "stdin")
+}
+
+/// Parses plan text read from `origin` (e.g. a URL); `selector` picks a 1-based JSONL line,
+/// whose placeholders are then filled from `vars`.
+pub fn parse_plan_source(
+    source: &str,
+    selector: Option<usize>,
+    vars: &HashMap<String, String>,
+    origin: &str,
+) -> Result<Plan> {
+    let plan_source = match selector {
+        Some(index) => index
+            .checked_sub(1)
+            .and_then(|line| source.lines().nth(line))
+            .filter(|line| !line.is_empty())
+            .ok_or_else(|| anyhow!("line {index} not found in {origin}"))?,
+        None => source,
+    };
+    let plan_source =
+        apply_template(plan_source, vars).with_context(|| format!("in plan {origin}"))?;
+    serde_json::from_str(&plan_source)
+        .with_context(|| format!("failed to deserialize plan from {origin}"))
+}
+
+/// Splits a plan spec into its source and optional `:line` selector. A trailing `:N`
+/// right after a URL host is its port, not a selector.
+pub fn split_plan_spec(spec: &str) -> (&str, Option<usize>) {
+    if let Some((prefix, trailing)) = spec.rsplit_once(':') {
+        let is_port = prefix
+            .split_once("://")
+            .is_some_and(|(_, rest)| !rest.contains('/'));
+        if let (Ok(index), false) = (usize::from_str(trailing), is_port) {
+            return (prefix, Some(index));
+        }
+    }
+    (spec, None)
+}
+
+/// Returns the file a plan spec reads from, without any `:line` selector.
+pub fn plan_spec_path(spec: &str) -> Result<PathBuf> {
+    split_spec(spec).map(|(path, _)| path)
+}
+
 fn read_jsonl_entry(path: &Path, index: usize) -> Result<String> {
     let file = File::open(path)
         .with_context(|| format!("failed to open plan jsonl {}", path.display()))?;
@@ -363,12 +1634,615 @@ fn read_jsonl_entry(path: &Path, index: usize) -> Result<String> {
 }
 
 fn split_spec(spec: &str) -> Result<(PathBuf, Option<usize>)> {
-    let mut parts = spec.rsplitn(2, ':');
-    let trailing = parts.next().unwrap_or(spec);
-    if let Some(prefix) = parts.next() {
-        if let Ok(index) = usize::from_str(trailing) {
-            return Ok((PathBuf::from(prefix), Some(index)));
+    let (path, selector) = split_plan_spec(spec);
+    Ok((PathBuf::from(path), selector))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::RunFixture;
+
+    fn plan(raw: &str) -> Plan {
+        serde_json::from_str(raw).unwrap()
+    }
+
+    #[test]
+    fn yaml_plans_round_trip_with_scalar_prices() {
+        let yaml = r#"
+steps:
+  - perp_orders:
+      orders:
+        - { coin: ETH, side: buy, sz: 0.01, px: mid-0.5%, tif: ALO }
+        - { coin: BTC, side: sell, sz: 0.001, px: 65000 }
+        - { coin: SOL, side: buy, sz: 0.1, px: { MidPercent: { offset_pct: 1.5 } } }
+  - sleep_ms: { duration_ms: 250 }
+  - loop:
+      count: 2
+      steps:
+        - cancel_last: {}
+"#;
+        let plan = parse_yaml_plan(yaml, "inline").unwrap();
+        let orders = &plan.steps[0].as_perp_orders().unwrap().orders;
+        assert_eq!(orders[0].px.resolve_with_mid(100.0), 99.5);
+        assert_eq!(orders[1].px.resolve_with_mid(100.0), 65000.0);
+        assert!((orders[2].px.resolve_with_mid(100.0) - 101.5).abs() < 1e-9);
+
+        let round_trip = parse_yaml_plan(&plan.to_yaml(), "serialized").unwrap();
+        assert_eq!(round_trip.as_json(), plan.as_json());
+
+        let fixture = RunFixture::empty("hl-plan-yaml");
+        let path = fixture.write("plan.yml", yaml);
+        let loaded = load_plan_from_spec(path.to_str().unwrap()).unwrap();
+        assert_eq!(loaded.as_json(), plan.as_json());
+        assert!(load_plan_from_spec(&format!("{}:1", path.display())).is_err());
+
+        std::fs::write(&path, "steps: [ {perp_orders: ").unwrap();
+        let err = load_plan_from_spec(path.to_str().unwrap())
+            .unwrap_err()
+            .to_string();
+        assert!(
+            err.contains("as YAML:") && err.contains("as JSON:"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn stdin_plans_are_buffered_and_honour_selectors() {
+        let piped = std::io::Cursor::new(
+            b"{\"steps\":[]}\n{\"steps\":[{\"sleep_ms\":{\"duration_ms\":150}}]}\n".to_vec(),
+        );
+        assert!(is_stdin_plan_spec("-:2"));
+        assert!(!is_stdin_plan_spec("plans/-.json"));
+        let (_, selector) = split_plan_spec("-:2");
+        let plan = read_plan_from(piped, selector, &HashMap::new()).unwrap();
+        assert!(matches!(
+            plan.steps[..],
+            [ActionStep::Sleep { ref sleep_ms }] if sleep_ms.duration_ms == 150
+        ));
+
+        let err = read_plan_from(
+            std::io::Cursor::new(b"{\"steps\":".to_vec()),
+            None,
+            &HashMap::new(),
+        )
+        .unwrap_err();
+        assert_eq!(err.to_string(), "failed to deserialize plan from stdin");
+    }
+
+    #[test]
+    fn plan_specs_split_selectors_but_not_ports() {
+        assert_eq!(split_plan_spec("plans.jsonl:3"), ("plans.jsonl", Some(3)));
+        assert_eq!(split_plan_spec("plan.json"), ("plan.json", None));
+        assert_eq!(
+            split_plan_spec("https://example.com/plans.jsonl:3"),
+            ("https://example.com/plans.jsonl", Some(3))
+        );
+        assert_eq!(
+            split_plan_spec("http://127.0.0.1:8080"),
+            ("http://127.0.0.1:8080", None)
+        );
+        assert_eq!(
+            split_plan_spec("http://127.0.0.1:8080/p.jsonl:2"),
+            ("http://127.0.0.1:8080/p.jsonl", Some(2))
+        );
+
+        let jsonl = "{\"steps\":[]}\n{\"steps\":[{\"cancel_all\":{}}]}\n";
+        assert_eq!(
+            parse_plan_source(jsonl, Some(2), &HashMap::new(), "x")
+                .unwrap()
+                .steps
+                .len(),
+            1
+        );
+        let err =
+            parse_plan_source(jsonl, Some(3), &HashMap::new(), "https://x/p.jsonl").unwrap_err();
+        assert_eq!(err.to_string(), "line 3 not found in https://x/p.jsonl");
+        assert!(parse_plan_source(jsonl, Some(0), &HashMap::new(), "x").is_err());
+    }
+
+    #[test]
+    fn validate_flags_hard_errors() {
+        let plan = plan(
+            r#"{"steps":[
+                {"perp_orders":{"orders":[{"coin":"ETH","side":"buy","sz":-0.1,"px":3000}]}},
+                {"set_leverage":{"coin":"ETH","leverage":75}},
+                {"cancel_oids":{"coin":"ETH","oids":[]}},
+                {"cancel_oids":{"coin":"ETH","oids":[7,0]}}
+            ]}"#,
+        );
+        let result = validate(&plan, None);
+        let steps: Vec<usize> = result.errors.iter().map(|e| e.step_idx).collect();
+        assert_eq!(steps, vec![0, 1, 2, 3]);
+        assert_eq!(
+            result.errors[3].message,
+            "cancel_oids.oids must be non-zero"
+        );
+        assert!(!result.is_valid());
+    }
+
+    #[test]
+    fn rejected_coins_are_found_in_nested_steps() {
+        let plan = plan(
+            r#"{"steps":[
+                {"set_leverage":{"coin":"ETH","leverage":5}},
+                {"loop":{"count":2,"steps":[
+                    {"if":{"condition":"previousStepFailed","thenSteps":[
+                        {"multi_coin_orders":{"coins":["BTC","doge"],"side":"buy","sz":0.1,"px":"mid-1%"}}
+                    ]}}
+                ]}},
+                {"fetch_funding_rate":{"coin":"SOL"}}
+            ]}"#,
+        );
+        let mut result = ValidationResult::default();
+        check_rejected_coins(&plan, &["DOGE".to_string(), "sol".to_string()], &mut result);
+        let errors: Vec<String> = result.errors.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            errors,
+            vec![
+                "step 1: coin doge is rejected",
+                "step 2: coin SOL is rejected"
+            ]
+        );
+    }
+
+    #[test]
+    fn retry_policy_parses_and_backs_off() {
+        let plan = plan(
+            r#"{"steps":[
+                {"perp_orders":{"orders":[{"coin":"ETH","side":"buy","sz":0.1,"px":3000}],
+                    "retry":{"maxAttempts":3,"initialDelayMs":100}}},
+                {"multi_coin_orders":{"coins":["ETH"],"side":"buy","sz":0.1,"px":3000,
+                    "retry":{"maxAttempts":0}}},
+                {"perp_orders":{"orders":[
+                    {"coin":"ETH","side":"buy","sz":0.1,"px":3000,"cloid":"7f2c1c1e-0000-4000-8000-000000000001"},
+                    {"coin":"BTC","side":"buy","sz":0.1,"px":3000,"cloid":"7F2C1C1E-0000-4000-8000-000000000001"}]}}
+            ]}"#,
+        );
+        let ActionStep::PerpOrders { perp_orders } = &plan.steps[0] else {
+            panic!("expected perp_orders");
+        };
+        let retry = perp_orders.retry.unwrap();
+        assert_eq!(
+            (retry.delay_ms(1), retry.delay_ms(2), retry.delay_ms(3)),
+            (100, 200, 400)
+        );
+
+        let result = validate(&plan, None);
+        let steps: Vec<usize> = result.errors.iter().map(|e| e.step_idx).collect();
+        assert_eq!(steps, vec![1, 2]);
+        assert!(result.errors[1].message.starts_with("duplicate cloid"));
+    }
+
+    #[test]
+    fn if_step_round_trips_and_recurses() {
+        let plan = plan(
+            r#"{"steps":[
+                {"perp_orders":{"orders":[{"coin":"ETH","side":"buy","sz":0.1,"px":3000}]}},
+                {"if":{
+                    "condition":{"placed_order_count":{"ge":2}},
+                    "then_steps":[{"cancel_all":{"coin":"ETH"}}],
+                    "else_steps":[
+                        {"sleep_ms":{"duration_ms":1500}},
+                        {"if":{"condition":"previous_step_failed","thenSteps":[
+                            {"spot_orders":{"orders":[{"coin":"PURR/USDC","side":"buy","sz":0.5,"px":0.2}]}}
+                        ]}}
+                    ]
+                }}
+            ]}"#,
+        );
+        let ActionStep::If { if_step } = &plan.steps[1] else {
+            panic!("expected if step");
+        };
+        assert_eq!(plan.steps[1].kind(), "if");
+        assert_eq!(if_step.condition, StepCondition::PlacedOrderCount { ge: 2 });
+        assert_eq!(if_step.branch(false).0, "else");
+
+        let json = plan.as_json();
+        assert_eq!(
+            json["steps"][1]["if"]["condition"]["placedOrderCount"]["ge"],
+            2
+        );
+        assert_eq!(
+            json["steps"][1]["if"]["elseSteps"][1]["if"]["condition"],
+            "previousStepFailed"
+        );
+        let round_trip: Plan = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(round_trip.as_json(), json);
+
+        assert_eq!(round_trip.coins_used(), vec!["ETH", "PURR/USDC"]);
+        // 500 for the order step, then the longer (else) branch: 1500 + 500.
+        assert_eq!(round_trip.estimated_duration_ms(), 2500);
+
+        let result = validate(&round_trip, Some(&["ETH".to_string()]));
+        let steps: Vec<usize> = result.errors.iter().map(|e| e.step_idx).collect();
+        assert_eq!(steps, vec![1]);
+    }
+
+    #[test]
+    fn loop_step_round_trips_and_scales_duration() {
+        let plan = plan(
+            r#"{"steps":[
+                {"loop":{"count":3,"steps":[
+                    {"perp_orders":{"orders":[{"coin":"BTC","side":"buy","sz":0.01,"px":"mid-1%"}]}},
+                    {"sleep_ms":{"duration_ms":250}}
+                ]}},
+                {"loop":{"count":0,"steps":[{"cancel_all":{}}]}}
+            ]}"#,
+        );
+        let ActionStep::Loop { loop_step } = &plan.steps[0] else {
+            panic!("expected loop step");
+        };
+        assert_eq!(plan.steps[0].kind(), "loop");
+        assert_eq!(loop_step.count, 3);
+        assert_eq!(loop_step.steps.len(), 2);
+
+        let json = plan.as_json();
+        assert_eq!(json["steps"][0]["loop"]["count"], 3);
+        assert!(json["steps"][0]["loop"]["steps"][0]["perp_orders"].is_object());
+        let round_trip: Plan = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(round_trip.as_json(), json);
+
+        assert_eq!(round_trip.coins_used(), vec!["BTC"]);
+        // Three passes of (500 + 250); the empty loop adds nothing.
+        assert_eq!(round_trip.estimated_duration_ms(), 2250);
+
+        let result = validate(&round_trip, None);
+        assert!(result.errors.is_empty());
+        let warned: Vec<usize> = result.warnings.iter().map(|w| w.step_idx).collect();
+        assert_eq!(warned, vec![1]);
+    }
+
+    #[test]
+    fn loop_passes_are_capped_across_nesting() {
+        let plan = plan(
+            r#"{"steps":[
+                {"loop":{"count":100,"steps":[
+                    {"loop":{"count":100,"steps":[{"sleep_ms":{"duration_ms":250}}]}}
+                ]}},
+                {"loop":{"count":1000,"steps":[
+                    {"loop":{"count":11,"steps":[
+                        {"loop":{"count":2,"steps":[{"cancel_all":{}}]}}
+                    ]}}
+                ]}},
+                {"loop":{"count":4000000000,"steps":[{"cancel_all":{}}]}}
+            ]}"#,
+        );
+        let result = validate(&plan, None);
+        let messages: Vec<(usize, &str)> = result
+            .errors
+            .iter()
+            .map(|e| (e.step_idx, e.message.as_str()))
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                (1, "nested loops run 11000 passes, more than 10000"),
+                (2, "loop count 4000000000 exceeds 10000"),
+            ]
+        );
+    }
+
+    #[test]
+    fn step_conditions_read_previous_records() {
+        let record = |action: &str, ack: Value| ActionLogRecord {
+            step_idx: 0,
+            action: action.to_string(),
+            request: Value::Null,
+            ack: Some(ack),
+            ..Default::default()
+        };
+        let filled = record(
+            "perp_orders",
+            serde_json::json!({"status":"ok","data":{"statuses":[{"kind":"filled","oid":1}]}}),
+        );
+        let rejected = record(
+            "perp_orders",
+            serde_json::json!({"status":"ok","data":{"statuses":[{"kind":"error"}]}}),
+        );
+
+        let succeeded = StepCondition::PreviousStepSucceeded;
+        let failed = StepCondition::PreviousStepFailed;
+        assert!(succeeded.holds(Some(&filled), None, 0));
+        assert!(failed.holds(Some(&rejected), None, 0));
+        assert!(!succeeded.holds(None, None, 0) && !failed.holds(None, None, 0));
+        assert!(StepCondition::AllOrdersFilled.holds(None, Some(&filled), 1));
+        assert!(!StepCondition::AllOrdersFilled.holds(None, Some(&rejected), 1));
+        assert!(StepCondition::PlacedOrderCount { ge: 2 }.holds(None, None, 2));
+        assert!(!StepCondition::PlacedOrderCount { ge: 2 }.holds(None, None, 1));
+    }
+
+    #[test]
+    fn validate_warnings_do_not_fail() {
+        let plan = plan(
+            r#"{"steps":[
+                {"perp_orders":{"orders":[{"coin":"ETHH","side":"buy","sz":0.95,"px":"mid-1%"}]}},
+                {"sleep_ms":{"duration_ms":5}}
+            ]}"#,
+        );
+        let result = validate(&plan, None);
+        assert!(result.is_valid());
+        assert_eq!(result.warnings.len(), 3);
+        assert!(result.warnings[0].message.contains("typo of ETH"));
+    }
+
+    #[test]
+    fn validate_enforces_allowed_coins() {
+        let plan = plan(r#"{"steps":[{"cancel_all":{"coin":"DOGE"}}]}"#);
+        let allowed = vec!["ETH".to_string(), "BTC".to_string()];
+        let result = validate(&plan, Some(&allowed));
+        assert_eq!(result.errors.len(), 1);
+        assert!(validate(&plan, None).is_valid());
+    }
+
+    #[test]
+    fn multi_coin_orders_expand_per_coin() {
+        let plan = plan(
+            r#"{"steps":[
+                {"multi_coin_orders":{"coins":["ETH","BTC"],"side":"sell","sz":0.01,"px":"mid+1%","tif":"ALO","builderCode":"b"}},
+                {"multi_coin_orders":{"coins":[],"side":"buy","sz":0.01,"px":3000}}
+            ]}"#,
+        );
+        let ActionStep::MultiCoinOrders { multi_coin_orders } = &plan.steps[0] else {
+            panic!("expected multi_coin_orders step");
+        };
+        let expanded = multi_coin_orders.to_perp_orders();
+        let coins: Vec<&str> = expanded.orders.iter().map(|o| o.coin.as_str()).collect();
+        assert_eq!(coins, vec!["ETH", "BTC"]);
+        assert!(expanded
+            .orders
+            .iter()
+            .all(|o| !o.is_buy() && !o.reduce_only));
+        assert_eq!(expanded.builder_code.as_deref(), Some("b"));
+
+        let result = validate(&plan, None);
+        let steps: Vec<usize> = result.errors.iter().map(|e| e.step_idx).collect();
+        assert_eq!(steps, vec![1]);
+        assert_eq!(plan.coins_used(), vec!["BTC", "ETH"]);
+    }
+
+    #[test]
+    fn spot_orders_round_trip() {
+        let plan = plan(
+            r#"{"steps":[{"spot_orders":{"orders":[
+                {"coin":"PURR/USDC","side":"sell","sz":5,"px":0.2,"tif":"IOC","builderCode":"b"}
+            ]}}]}"#,
+        );
+        let reparsed: Plan = serde_json::from_value(plan.as_json()).unwrap();
+        let ActionStep::SpotOrders { spot_orders } = &reparsed.steps[0] else {
+            panic!("expected spot_orders step");
+        };
+        let order = &spot_orders.orders[0];
+        assert_eq!(order.tif, SpotTif::Ioc);
+        assert_eq!(order.builder_code.as_deref(), Some("b"));
+        assert!(matches!(order.px, OrderPrice::Absolute(px) if px == 0.2));
+        assert_eq!(reparsed.steps[0].kind(), "spot_orders");
+        assert!(validate(&reparsed, None).is_valid());
+    }
+
+    #[test]
+    fn sub_account_transfer_checks_address_and_amount() {
+        let plan = plan(
+            r#"{"steps":[
+                {"sub_account_transfer":{"to_address":"0x1111111111111111111111111111111111111111","amount":5.0,"is_deposit":true}},
+                {"sub_account_transfer":{"toAddress":"0x1234","amount":0,"isDeposit":false}}
+            ]}"#,
+        );
+        let mut result = validate(&plan, None);
+        assert_eq!(result.errors.len(), 2);
+        assert!(result.errors.iter().all(|error| error.step_idx == 1));
+
+        check_wallet(
+            &plan,
+            "0x1111111111111111111111111111111111111111",
+            &mut result,
+        );
+        assert_eq!(result.warnings.len(), 1);
+        assert_eq!(result.warnings[0].step_idx, 0);
+    }
+
+    #[test]
+    fn assert_balance_bounds() {
+        let plan = plan(
+            r#"{"steps":[
+                {"assert_balance":{"min_perp_usdc":10.0,"failSoft":true}},
+                {"assert_balance":{"minPerpUsdc":50,"maxPerpUsdc":20}}
+            ]}"#,
+        );
+        let ActionStep::AssertBalance { assert_balance } = &plan.steps[0] else {
+            panic!("expected assert_balance step");
+        };
+        assert!(assert_balance.fail_soft);
+        assert_eq!(
+            assert_balance.violation(4.5).as_deref(),
+            Some("balance 4.5 < min 10.0")
+        );
+        assert_eq!(assert_balance.violation(10.0), None);
+
+        let result = validate(&plan, None);
+        assert_eq!(result.errors.len(), 1);
+        assert_eq!(result.errors[0].step_idx, 1);
+    }
+
+    #[test]
+    fn estimated_duration_counts_sleeps_and_steps() {
+        let plan = plan(
+            r#"{"steps":[
+                {"cancel_all":{}},
+                {"sleep_ms":{"duration_ms":1000}},
+                {"cancel_last":{}}
+            ]}"#,
+        );
+        assert_eq!(plan.estimated_duration_ms(), 2000);
+        assert_eq!(plan.estimated_duration_with_step_ms(100), 1200);
+    }
+
+    #[test]
+    fn order_price_from_yaml() {
+        let price = |px: &str| {
+            let raw = format!("coin: ETH\nside: buy\nsz: 0.01\npx: {px}\n");
+            serde_yaml::from_str::<PerpOrder>(&raw).unwrap().px
+        };
+        assert!(matches!(price("3500"), OrderPrice::Absolute(v) if v == 3500.0));
+        assert!(matches!(price("3500.5"), OrderPrice::Absolute(v) if v == 3500.5));
+        assert!(matches!(
+            price("\"mid+1%\""),
+            OrderPrice::MidPercent { offset_pct } if offset_pct == 1.0
+        ));
+        assert!(matches!(
+            price("\"mid-0.5%\""),
+            OrderPrice::MidPercent { offset_pct } if offset_pct == -0.5
+        ));
+    }
+
+    #[test]
+    fn order_price_parses_book_relative_offsets() {
+        let parse = |px: &str| serde_json::from_value::<OrderPrice>(serde_json::json!(px));
+        let cases = [
+            ("bid-0.1%", "bid", -0.1),
+            ("bid+0.2%", "bid", 0.2),
+            ("bid - 1.5 %", "bid", -1.5),
+            ("ask+0.05%", "ask", 0.05),
+            ("ask-0%", "ask", 0.0),
+            (" ask+3 ", "ask", 3.0),
+            ("mid-0.5%", "mid", -0.5),
+        ];
+        for (raw, anchor, expected) in cases {
+            let price = parse(raw).unwrap();
+            let parsed_anchor = match price {
+                OrderPrice::MidPercent { .. } => "mid",
+                OrderPrice::BidPercent { .. } => "bid",
+                OrderPrice::AskPercent { .. } => "ask",
+                OrderPrice::Absolute(_) => "absolute",
+            };
+            assert_eq!(parsed_anchor, anchor, "{raw}");
+            assert_eq!(price.offset_pct(), Some(expected), "{raw}");
+        }
+
+        for bad in ["bid", "ask0.1%", "bid*1%", "bid-x%", "bidd-1%", "ask+"] {
+            assert!(parse(bad).is_err(), "{bad} should not parse");
         }
+
+        let price = parse("bid-1%").unwrap();
+        assert!((price.resolve_with_reference(3000.0) - 2970.0).abs() < 1e-9);
+        let round_trip: OrderPrice =
+            serde_json::from_value(serde_json::to_value(&price).unwrap()).unwrap();
+        assert!(matches!(round_trip, OrderPrice::BidPercent { offset_pct } if offset_pct == -1.0));
+        let ask: OrderPrice =
+            serde_json::from_value(serde_json::json!({"AskPercent": {"offset_pct": 0.05}}))
+                .unwrap();
+        assert!(matches!(ask, OrderPrice::AskPercent { offset_pct } if offset_pct == 0.05));
+    }
+
+    #[test]
+    fn round_to_decimals_ties_to_even() {
+        // Already within the allowed decimals.
+        assert_eq!(OrderPrice::round_to_decimals(3412.7, 1), 3412.7);
+        assert_eq!(OrderPrice::round_to_decimals(42.0, 0), 42.0);
+        // Round up and down to the nearest tick.
+        assert_eq!(OrderPrice::round_to_decimals(3412.72837, 1), 3412.7);
+        assert_eq!(OrderPrice::round_to_decimals(3412.76, 1), 3412.8);
+        assert_eq!(OrderPrice::round_to_decimals(3412.651, 1), 3412.7);
+        assert_eq!(OrderPrice::round_to_decimals(0.123456, 4), 0.1235);
+        assert_eq!(OrderPrice::round_to_decimals(0.99996, 4), 1.0);
+        // Exact ties (as written) go to the even tick.
+        assert_eq!(OrderPrice::round_to_decimals(3412.75, 1), 3412.8);
+        assert_eq!(OrderPrice::round_to_decimals(3412.65, 1), 3412.6);
+        assert_eq!(OrderPrice::round_to_decimals(0.12345, 4), 0.1234);
+        assert_eq!(OrderPrice::round_to_decimals(60_122.5, 0), 60_122.0);
+        assert_eq!(OrderPrice::round_to_decimals(60_123.5, 0), 60_124.0);
+    }
+
+    #[test]
+    fn perp_tif_parses_case_insensitively() {
+        assert_eq!("ioc".parse::<PerpTif>().unwrap().as_sdk_str(), "Ioc");
+        assert_eq!("Alo".parse::<PerpTif>().unwrap(), PerpTif::Alo);
+        assert!("fok".parse::<PerpTif>().is_err());
+
+        let tif: PerpTif = serde_json::from_str("\"gtc\"").unwrap();
+        assert_eq!(tif, PerpTif::Gtc);
+        assert!(serde_json::from_str::<PerpTif>("\"fok\"").is_err());
+    }
+
+    #[test]
+    fn apply_template_escapes_strings_and_checks_numbers() {
+        let vars: HashMap<String, String> = [
+            ("COIN", "ETH"),
+            ("SZ", "0.01"),
+            ("NOTE", "say \"hi\"\n"),
+            ("BAD", "1; DROP"),
+        ]
+        .into_iter()
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect();
+
+        let raw = r#"{"coin":"{{COIN}}-PERP","sz":{{ SZ }},"note":"\"{{NOTE}}\""}"#;
+        let filled = apply_template(raw, &vars).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&filled).unwrap();
+        assert_eq!(value["coin"], "ETH-PERP");
+        assert_eq!(value["sz"], 0.01);
+        assert_eq!(value["note"], "\"say \"hi\"\n\"");
+
+        // Not placeholders: braces inside JSON objects and non-key text are left alone.
+        assert_eq!(
+            apply_template(r#"{"a":{"b":{}}}"#, &vars).unwrap(),
+            r#"{"a":{"b":{}}}"#
+        );
+
+        let err = apply_template(r#"{"sz":{{BAD}}}"#, &vars).unwrap_err();
+        assert!(err.to_string().contains("must be a number"), "{err}");
+
+        let err = apply_template(r#"{"a":"{{Z}}","b":{{A}},"c":"{{Z}}"}"#, &vars).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "no value for plan variable(s) {{A}}, {{Z}}"
+        );
+    }
+
+    #[test]
+    fn yaml_plans_take_bare_and_quoted_variables() {
+        let vars: HashMap<String, String> = [
+            ("COIN", "ETH"),
+            ("SZ", "0.01"),
+            ("PX", "-1"),
+            ("NOTE", "it's \"x\""),
+            ("BAD", "ETH\nsteps: []"),
+        ]
+        .into_iter()
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect();
+
+        let raw = r#"# {{UNSET}} in a comment, with a " quote
+steps:
+  - perp_orders:
+      orders:
+        - coin: {{COIN}}
+          side: buy
+          sz: {{SZ}}
+          px: {{PX}}
+          cloid: "a{{NOTE}}"
+          builderCode: '{{NOTE}}'
+"#;
+        let filled = apply_yaml_template(raw, &vars).unwrap();
+        let value: serde_json::Value = serde_yaml::from_str(&filled).unwrap();
+        let order = &value["steps"][0]["perp_orders"]["orders"][0];
+        assert_eq!(order["coin"], "ETH");
+        assert_eq!(order["sz"], 0.01);
+        assert_eq!(order["px"], -1);
+        assert_eq!(order["cloid"], "ait's \"x\"");
+        assert_eq!(order["builderCode"], "it's \"x\"");
+
+        let fixture = RunFixture::empty("hl-plan-yaml-vars");
+        let path = fixture.write(
+            "plan.yaml",
+            "steps:\n  - set_leverage:\n      coin: {{COIN}}\n      leverage: {{SZ}}\n",
+        );
+        let mut vars = vars;
+        vars.insert("SZ".to_string(), "5".to_string());
+        let plan = load_plan_with_vars(path.to_str().unwrap(), &vars).unwrap();
+        assert_eq!(plan.steps[0].kind(), "set_leverage");
+
+        let err = apply_yaml_template("coin: {{BAD}}\n", &vars).unwrap_err();
+        assert!(err.to_string().contains("plain scalar"), "{err}");
     }
-    Ok((PathBuf::from(spec), None))
 }
@@ -0,0 +1,92 @@
+use std::{collections::HashMap, time::Duration};
+
+use anyhow::{anyhow, Context, Result};
+use reqwest::{blocking::Client, Url};
+use tracing::info;
+
+use crate::plan::{parse_plan_source, split_plan_spec, Plan};
+
+/// How long [`crate::plan::load_plan_from_spec`] waits for a plan URL.
+pub const DEFAULT_FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Fetches a `http(s)://...[:line]` plan spec and parses it like a plan file.
+///
+/// Blocks the calling thread; async callers should run it on a blocking task.
+pub fn fetch_plan(
+    spec: &str,
+    http: &Client,
+    timeout: Duration,
+    vars: &HashMap<String, String>,
+) -> Result<Plan> {
+    let (raw_url, selector) = split_plan_spec(spec);
+    let url = Url::parse(raw_url).with_context(|| format!("invalid plan URL {raw_url}"))?;
+    if url.host_str().is_none() {
+        return Err(anyhow!("plan URL {url} has no host"));
+    }
+
+    info!("fetching plan from {url}");
+    let response = http
+        .get(url.clone())
+        .timeout(timeout)
+        .send()
+        .with_context(|| format!("failed to fetch plan from {url}"))?;
+    let status = response.status();
+    if !status.is_success() {
+        return Err(anyhow!("fetching plan from {url} returned HTTP {status}"));
+    }
+    let body = response
+        .text()
+        .with_context(|| format!("failed to read plan body from {url}"))?;
+    parse_plan_source(&body, selector, vars, url.as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plan::{is_plan_url, load_plan_from_spec};
+    use crate::ActionStep;
+
+    #[test]
+    fn fetches_jsonl_line_and_reports_http_errors() {
+        let mut server = mockito::Server::new();
+        let plans = server
+            .mock("GET", "/plans.jsonl")
+            .with_status(200)
+            .with_body(concat!(
+                "{\"steps\":[{\"sleep_ms\":{\"duration_ms\":100}}]}\n",
+                "{\"steps\":[{\"cancel_all\":{\"coin\":\"ETH\"}}]}\n",
+            ))
+            .create();
+        let missing = server
+            .mock("GET", "/missing.json")
+            .with_status(404)
+            .create();
+        let http = Client::new();
+        let timeout = Duration::from_secs(5);
+
+        let spec = format!("{}/plans.jsonl:2", server.url());
+        assert!(is_plan_url(&spec));
+        let plan = fetch_plan(&spec, &http, timeout, &HashMap::new()).unwrap();
+        assert!(matches!(plan.steps[..], [ActionStep::CancelAll { .. }]));
+        plans.assert();
+
+        let spec = format!("{}/missing.json", server.url());
+        let err = fetch_plan(&spec, &http, timeout, &HashMap::new()).unwrap_err();
+        assert!(err.to_string().ends_with("returned HTTP 404 Not Found"));
+        missing.assert();
+
+        assert!(fetch_plan("http://", &http, timeout, &HashMap::new()).is_err());
+        assert!(!is_plan_url("plans/ci.jsonl:2"));
+    }
+
+    #[test]
+    fn load_plan_from_spec_follows_urls() {
+        let mut server = mockito::Server::new();
+        server
+            .mock("GET", "/plan.json")
+            .with_body(r#"{"steps":[{"set_leverage":{"coin":"ETH","leverage":3}}]}"#)
+            .create();
+        let plan = load_plan_from_spec(&format!("{}/plan.json", server.url())).unwrap();
+        assert_eq!(plan.steps[0].kind(), "set_leverage");
+    }
+}
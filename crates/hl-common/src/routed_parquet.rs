@@ -0,0 +1,117 @@
+//! `routed_orders.parquet`, behind the `parquet` feature so consumers that only read
+//! plans and JSONL logs skip the arrow/parquet build.
+
+use std::{fs::File, path::Path, sync::Arc};
+
+use anyhow::{anyhow, Context, Result};
+use arrow_array::{
+    Array, ArrayRef, BooleanArray, Float64Array, Int64Array, RecordBatch, StringArray, UInt64Array,
+};
+use arrow_schema::{DataType, Field, Schema};
+use parquet::arrow::{arrow_reader::ParquetRecordBatchReaderBuilder, ArrowWriter};
+
+use crate::artifacts::RoutedOrderRecord;
+
+/// Writes `records` as one row group, one column per field under its camelCase name.
+pub(crate) fn write(path: &Path, records: &[RoutedOrderRecord]) -> Result<()> {
+    let batch = routed_orders_batch(records)?;
+    let file =
+        File::create(path).with_context(|| format!("failed to create {}", path.display()))?;
+    let mut writer = ArrowWriter::try_new(file, batch.schema(), None)?;
+    writer.write(&batch)?;
+    writer
+        .close()
+        .with_context(|| format!("failed to write {}", path.display()))?;
+    Ok(())
+}
+
+fn routed_orders_batch(records: &[RoutedOrderRecord]) -> Result<RecordBatch> {
+    let schema = Schema::new(vec![
+        Field::new("tsMs", DataType::Int64, false),
+        Field::new("oid", DataType::UInt64, true),
+        Field::new("coin", DataType::Utf8, false),
+        Field::new("side", DataType::Utf8, false),
+        Field::new("px", DataType::Float64, false),
+        Field::new("sz", DataType::Float64, false),
+        Field::new("tif", DataType::Utf8, false),
+        Field::new("reduceOnly", DataType::Boolean, false),
+        Field::new("builderCode", DataType::Utf8, true),
+        Field::new("spot", DataType::Boolean, false),
+    ]);
+    let strings = |field: fn(&RoutedOrderRecord) -> &str| -> ArrayRef {
+        Arc::new(StringArray::from_iter_values(records.iter().map(field)))
+    };
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(Int64Array::from_iter_values(
+            records.iter().map(|r| r.ts_ms),
+        )),
+        Arc::new(UInt64Array::from_iter(records.iter().map(|r| r.oid))),
+        strings(|r| &r.coin),
+        strings(|r| &r.side),
+        Arc::new(Float64Array::from_iter_values(records.iter().map(|r| r.px))),
+        Arc::new(Float64Array::from_iter_values(records.iter().map(|r| r.sz))),
+        strings(|r| &r.tif),
+        Arc::new(BooleanArray::from_iter(
+            records.iter().map(|r| Some(r.reduce_only)),
+        )),
+        Arc::new(StringArray::from_iter(
+            records.iter().map(|r| r.builder_code.as_deref()),
+        )),
+        Arc::new(BooleanArray::from_iter(
+            records.iter().map(|r| Some(r.spot)),
+        )),
+    ];
+    RecordBatch::try_new(Arc::new(schema), columns).context("failed to build routed order batch")
+}
+
+/// Reads back a file written by [`write`].
+pub(crate) fn read(path: &Path) -> Result<Vec<RoutedOrderRecord>> {
+    let file = File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+    let reader = ParquetRecordBatchReaderBuilder::try_new(file)
+        .and_then(|builder| builder.build())
+        .with_context(|| format!("failed to read {}", path.display()))?;
+    let mut records = Vec::new();
+    for batch in reader {
+        let batch = batch.with_context(|| format!("failed to read {}", path.display()))?;
+        let ts_ms = typed_column::<Int64Array>(&batch, "tsMs", path)?;
+        let oid = typed_column::<UInt64Array>(&batch, "oid", path)?;
+        let coin = typed_column::<StringArray>(&batch, "coin", path)?;
+        let side = typed_column::<StringArray>(&batch, "side", path)?;
+        let px = typed_column::<Float64Array>(&batch, "px", path)?;
+        let sz = typed_column::<Float64Array>(&batch, "sz", path)?;
+        let tif = typed_column::<StringArray>(&batch, "tif", path)?;
+        let reduce_only = typed_column::<BooleanArray>(&batch, "reduceOnly", path)?;
+        let builder_code = typed_column::<StringArray>(&batch, "builderCode", path)?;
+        let spot = typed_column::<BooleanArray>(&batch, "spot", path)?;
+        for row in 0..batch.num_rows() {
+            records.push(RoutedOrderRecord {
+                ts_ms: ts_ms.value(row),
+                oid: oid.is_valid(row).then(|| oid.value(row)),
+                coin: coin.value(row).to_string(),
+                side: side.value(row).to_string(),
+                px: px.value(row),
+                sz: sz.value(row),
+                tif: tif.value(row).to_string(),
+                reduce_only: reduce_only.value(row),
+                builder_code: builder_code
+                    .is_valid(row)
+                    .then(|| builder_code.value(row).to_string()),
+                spot: spot.value(row),
+            });
+        }
+    }
+    Ok(records)
+}
+
+fn typed_column<'a, T: Array + 'static>(
+    batch: &'a RecordBatch,
+    name: &str,
+    path: &Path,
+) -> Result<&'a T> {
+    batch
+        .column_by_name(name)
+        .ok_or_else(|| anyhow!("{} has no {name} column", path.display()))?
+        .as_any()
+        .downcast_ref::<T>()
+        .ok_or_else(|| anyhow!("{} column {name} has an unexpected type", path.display()))
+}
@@ -1,7 +1,8 @@
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
-/// Normalized coverage signature wrapper used by the evaluator.
-#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
+/// Normalized coverage signature wrapper used by the evaluator. Serializes as the bare
+/// signature string, so it round-trips through `eval_score.json`.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub struct Signature(pub String);
 
 impl Signature {
@@ -14,14 +15,34 @@ impl Signature {
         ))
     }
 
+    pub fn spot_order(tif: &str) -> Self {
+        Self(format!("spot.order.{}", tif.to_ascii_uppercase()))
+    }
+
     pub fn perp_cancel(scope: &str) -> Self {
         Self(format!("perp.cancel.{}", scope))
     }
 
+    pub fn perp_wait_fill() -> Self {
+        Self("perp.wait.fill".to_string())
+    }
+
+    pub fn market_funding_rate(coin: &str) -> Self {
+        Self(format!("market.fundingRate.{}", coin.to_ascii_uppercase()))
+    }
+
     pub fn account_usd_class_transfer(direction: &str) -> Self {
         Self(format!("account.usdClassTransfer.{}", direction))
     }
 
+    pub fn account_sub_account_transfer(direction: &str) -> Self {
+        Self(format!("account.subAccountTransfer.{}", direction))
+    }
+
+    pub fn account_assert_balance() -> Self {
+        Self("account.assertBalance".to_string())
+    }
+
     pub fn risk_set_leverage(coin: &str) -> Self {
         Self(format!("risk.setLeverage.{}", coin.to_ascii_uppercase()))
     }
@@ -35,6 +56,24 @@ impl Signature {
     }
 }
 
+impl From<String> for Signature {
+    fn from(value: String) -> Self {
+        Self(value)
+    }
+}
+
+impl From<&str> for Signature {
+    fn from(value: &str) -> Self {
+        Self(value.to_string())
+    }
+}
+
+impl AsRef<str> for Signature {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
 pub fn normalize_tif(raw: &str) -> &'static str {
     match raw.to_ascii_uppercase().as_str() {
         "ALO" => "ALO",
@@ -58,3 +97,34 @@ pub fn normalize_trigger(value: &serde_json::Value) -> String {
         "none".to_string()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signature_round_trips_as_plain_string() {
+        let signature = Signature::perp_order("gtc", false, "none");
+        let json = serde_json::to_string(&signature).unwrap();
+        assert_eq!(json, "\"perp.order.GTC:false:none\"");
+        let parsed: Vec<Signature> = serde_json::from_str(&format!("[{json}]")).unwrap();
+        assert_eq!(parsed, vec![Signature::from("perp.order.GTC:false:none")]);
+        assert_eq!(parsed[0].as_ref(), signature.as_str());
+    }
+
+    #[test]
+    fn normalize_tif_maps_known_values_and_defaults_to_gtc() {
+        for raw in ["GTC", "gtc", "Gtc"] {
+            assert_eq!(normalize_tif(raw), "GTC");
+        }
+        for raw in ["ALO", "alo"] {
+            assert_eq!(normalize_tif(raw), "ALO");
+        }
+        for raw in ["IOC", "ioc"] {
+            assert_eq!(normalize_tif(raw), "IOC");
+        }
+        for raw in ["", "FOK", "post-only"] {
+            assert_eq!(normalize_tif(raw), "GTC");
+        }
+    }
+}
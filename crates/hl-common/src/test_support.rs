@@ -0,0 +1,110 @@
+//! Fixture factories shared by the workspace's tests. Enabled by the `test-support`
+//! feature, which only dev-dependencies turn on.
+
+use std::path::{Path, PathBuf};
+
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use crate::{ActionLogRecord, RunArtifacts};
+
+/// `domains.yaml` with a single `perp` domain allowing every `perp.order.*` signature.
+pub const PERP_DOMAINS_YAML: &str =
+    "domains:\n  perp:\n    weight: 1.0\n    allow: [\"perp.order.*\"]\n";
+
+/// An `ok` ack whose single status has the given kind (`resting`, `filled`, ...).
+pub fn ack_ok(kind: &str) -> Value {
+    json!({
+        "status": "ok",
+        "data": {
+            "statuses": [{"kind": kind}]
+        }
+    })
+}
+
+/// A record with just the fields scoring looks at; tests override the rest.
+pub fn action_record(step_idx: usize, action: &str, request: Value, ack: Value) -> ActionLogRecord {
+    ActionLogRecord {
+        step_idx,
+        action: action.to_string(),
+        request,
+        ack: Some(ack),
+        ..Default::default()
+    }
+}
+
+/// A resting single-order `perp_orders` record submitted `step_idx` seconds into the run.
+pub fn perp_order_record(step_idx: usize, order: Value) -> ActionLogRecord {
+    ActionLogRecord {
+        submit_ts_ms: step_idx as i64 * 1_000,
+        ..action_record(
+            step_idx,
+            "perp_orders",
+            json!({"perp_orders": {"orders": [order]}}),
+            ack_ok("resting"),
+        )
+    }
+}
+
+/// A scratch run directory, removed on drop.
+pub struct RunFixture {
+    pub dir: PathBuf,
+}
+
+impl RunFixture {
+    /// A run directory holding `domains.yaml` and `records` as `per_action.jsonl`.
+    pub fn new(prefix: &str, domains_yaml: &str, records: &[ActionLogRecord]) -> Self {
+        let fixture = Self::empty(prefix);
+        fixture.write("domains.yaml", domains_yaml);
+        let jsonl: String = records
+            .iter()
+            .map(|record| serde_json::to_string(record).unwrap() + "\n")
+            .collect();
+        fixture.write("per_action.jsonl", jsonl);
+        fixture
+    }
+
+    pub fn empty(prefix: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("{prefix}-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        Self { dir }
+    }
+
+    pub fn path(&self, name: &str) -> String {
+        self.dir.join(name).display().to_string()
+    }
+
+    /// Writes `name` (creating parent directories) and returns its path.
+    pub fn write(&self, name: impl AsRef<Path>, contents: impl AsRef<[u8]>) -> PathBuf {
+        let path = self.dir.join(name);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).unwrap();
+        }
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    /// Artifacts for an empty plan, written into the fixture directory.
+    pub fn artifacts(&self, compress: bool) -> RunArtifacts {
+        RunArtifacts::create(&self.dir, &json!({"steps": []}), None, None, compress).unwrap()
+    }
+
+    /// `--input`/`--domains` pointing at the fixture, followed by `extra`.
+    pub fn evaluator_args(&self, extra: &[&str]) -> Vec<String> {
+        let mut argv = vec![
+            "coverage".to_string(),
+            "--input".to_string(),
+            self.path("per_action.jsonl"),
+            "--domains".to_string(),
+            self.path("domains.yaml"),
+        ];
+        argv.extend(extra.iter().map(|arg| arg.to_string()));
+        argv
+    }
+}
+
+impl Drop for RunFixture {
+    fn drop(&mut self) {
+        std::fs::remove_dir_all(&self.dir).ok();
+    }
+}
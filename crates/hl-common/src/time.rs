@@ -12,3 +12,50 @@ pub fn window_start_ms(ts_ms: i64, window_ms: i64) -> i64 {
     }
     (ts_ms / window_ms) * window_ms
 }
+
+/// Returns the number of windows covering `[start_ms, end_ms)`, counting the
+/// window that contains `start_ms` even when it starts before it.
+pub fn window_count(start_ms: i64, end_ms: i64, window_ms: i64) -> u64 {
+    if window_ms <= 0 || end_ms <= start_ms {
+        return 0;
+    }
+    let first = window_start_ms(start_ms, window_ms);
+    ((end_ms - first + window_ms - 1) / window_ms) as u64
+}
+
+/// Yields the start key of each window overlapping `[start_ms, end_ms)`.
+pub fn windows_iter(start_ms: i64, end_ms: i64, window_ms: i64) -> impl Iterator<Item = i64> {
+    let first = window_start_ms(start_ms, window_ms);
+    let end = if window_ms > 0 && end_ms > start_ms {
+        end_ms
+    } else {
+        first
+    };
+    (first..end).step_by(window_ms.max(1) as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_range_has_no_windows() {
+        assert_eq!(window_count(150, 150, 100), 0);
+        assert_eq!(windows_iter(150, 150, 100).count(), 0);
+    }
+
+    #[test]
+    fn exact_window_span_is_one_window() {
+        assert_eq!(window_count(200, 300, 100), 1);
+        assert_eq!(windows_iter(200, 300, 100).collect::<Vec<_>>(), vec![200]);
+    }
+
+    #[test]
+    fn unaligned_start_includes_enclosing_window() {
+        assert_eq!(window_count(150, 450, 100), 4);
+        assert_eq!(
+            windows_iter(150, 450, 100).collect::<Vec<_>>(),
+            vec![100, 200, 300, 400]
+        );
+    }
+}
@@ -1,36 +1,50 @@
 use std::{
     collections::{BTreeMap, BTreeSet, HashMap, HashSet},
     fs::File,
-    io::{BufRead, BufReader, BufWriter, Write},
+    io::{BufRead, BufWriter, Write},
     path::{Path, PathBuf},
 };
 
 use anyhow::{anyhow, Context, Result};
-use clap::Parser;
-use hl_common::{normalize_tif, normalize_trigger, ActionLogRecord, Signature};
+use clap::{Parser, Subcommand, ValueEnum};
+use hl_common::{
+    artifacts::open_jsonl,
+    normalize::{action_signatures, NormalizeError},
+    ActionLogRecord, RunArtifacts, Signature,
+};
 use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
 use serde_yaml::Value as YamlValue;
-use thiserror::Error;
 
 const PENALTY_PER_EXTRA: f64 = 0.1;
 const BONUS_PER_EXTRA_SIGNATURE: f64 = 0.25;
+/// Longest run of idle windows listed in full in `perWindowBonus`.
+const MAX_LISTED_IDLE_WINDOWS: i64 = 16;
 
 #[derive(Parser, Debug, Clone)]
 #[command(
     author,
     version,
     about = "Evaluate HyperLiquidBench coverage runs",
-    disable_help_subcommand = true
+    disable_help_subcommand = true,
+    subcommand_negates_reqs = true
 )]
 pub struct CoverageArgs {
-    /// Path to per_action.jsonl produced by hl-runner
-    #[arg(long)]
-    input: PathBuf,
+    /// Path to per_action.jsonl produced by hl-runner (`-` reads stdin)
+    #[arg(
+        long,
+        required_unless_present_any = ["test_pattern", "test_all_domains", "live", "batch_runs"]
+    )]
+    input: Option<PathBuf>,
     /// Path to domains-hl.yaml configuration
-    #[arg(long)]
-    domains: PathBuf,
+    #[arg(long, required_unless_present_any = ["test_pattern", "test_all_domains"])]
+    domains: Option<PathBuf>,
+    /// Extra domain YAML merged after --domains (repeatable; later files win)
+    #[arg(long = "include-domain")]
+    include_domains: Vec<PathBuf>,
+    /// Replace a domain's YAML weight (repeatable); the domain must exist in the config
+    #[arg(long = "domain-weight", value_name = "DOMAIN=WEIGHT", value_parser = parse_domain_weight)]
+    domain_weights: Vec<(String, f64)>,
     /// Output directory (defaults to parent directory of file input)
     #[arg(long)]
     out_dir: Option<PathBuf>,
@@ -40,23 +54,307 @@ pub struct CoverageArgs {
     /// Override per-signature cap (defaults to value inside YAML)
     #[arg(long)]
     cap_per_sig: Option<usize>,
+    /// Also import the action records and routed orders into <out_dir>/run_data.db
+    #[arg(long, default_value_t = false)]
+    sqlite: bool,
+    /// Print and write eval_explained_signatures.md describing each unique signature
+    #[arg(long, default_value_t = false)]
+    explain_signatures: bool,
+    /// Accept records whose checksum does not match; records without one (legacy logs) are always accepted
+    #[arg(long, default_value_t = false)]
+    skip_checksum_verification: bool,
+    /// Write eval_timing.json with inter-step interval stats and slow/bursty step detection
+    #[arg(long, default_value_t = false)]
+    timing_analysis: bool,
+    /// Baseline eval_score.json (path or http(s) URL); fail if the score or signature count dropped
+    #[arg(long)]
+    regression_check: Option<String>,
+    /// Allowed final-score drop before --regression-check fails
+    #[arg(long, default_value_t = 0.0)]
+    regression_tolerance: f64,
+    /// Copy eval_score.json to eval_score_snapshot.json as the golden file
+    #[arg(long, default_value_t = false)]
+    update_snapshot: bool,
+    /// Golden eval_score.json to compare field by field; any difference fails the run
+    #[arg(long)]
+    check_snapshot: Option<PathBuf>,
+    /// Comma-separated fields skipped by --check-snapshot (key or dotted path, e.g. perDomain.weight)
+    #[arg(long, value_delimiter = ',', requires = "check_snapshot")]
+    snapshot_ignore_fields: Vec<String>,
+    /// Add scorePerToken (final score / LLM completion tokens) to the run's run_meta.json
+    #[arg(long, default_value_t = false)]
+    enrich_meta_with_score: bool,
+    /// Only score steps in START:END (inclusive); repeat to union several ranges
+    #[arg(long = "step-range", value_name = "START:END", value_parser = parse_step_range)]
+    step_ranges: Vec<(usize, usize)>,
+    /// ws_stream.jsonl whose order updates, fundings and ledger updates add ws.* signatures
+    #[arg(long)]
+    ws_input: Option<PathBuf>,
+    /// Check --test-sig signatures against one allow pattern and print why each fails
+    #[arg(long, value_name = "PATTERN")]
+    test_pattern: Option<String>,
+    /// Print which domains of this YAML match each --test-sig signature and which one claims it
+    #[arg(long, value_name = "DOMAINS_YAML")]
+    test_all_domains: Option<PathBuf>,
+    /// Signature for --test-pattern / --test-all-domains (repeatable)
+    #[arg(long = "test-sig", value_name = "SIG")]
+    test_sigs: Vec<String>,
+    /// JSON array of signatures for --test-pattern / --test-all-domains
+    #[arg(long, value_name = "PATH")]
+    test_sigs_file: Option<PathBuf>,
+    /// text: write the eval_* files and print a summary; jsonl: stream records and the
+    /// score report to stdout instead of writing files
+    #[arg(long, value_enum, default_value = "text")]
+    output_format: OutputFormat,
+    /// Keep writing the eval_* files with --output-format jsonl
+    #[arg(long, default_value_t = false)]
+    also_write_files: bool,
+    /// Leave perDomain[*].contributingRecords out of the score report
+    #[arg(long, default_value_t = false)]
+    no_contributing_records: bool,
+    /// Add coinBreakdown to the score report and write eval_coin_breakdown.json
+    #[arg(long, default_value_t = false)]
+    pivot_by_coin: bool,
+    /// Score records as they arrive (stdin unless --input is given), printing a JSON
+    /// update line after each scored record
+    #[arg(long, default_value_t = false)]
+    live: bool,
+    /// Score `<DIR>/per_action.jsonl` of each run directory in turn, writing the eval_*
+    /// files into that directory (repeatable)
+    #[arg(
+        long = "batch-run",
+        value_name = "DIR",
+        conflicts_with_all = ["input", "out_dir", "live"]
+    )]
+    batch_runs: Vec<PathBuf>,
+    /// Also write eval_report.html: score summary, per-domain chart and signature tables
+    #[arg(long, default_value_t = false)]
+    html_report: bool,
+    /// Add normalizedScore (final score / this maximum, clamped, as 0-100) and scoreGrade
+    #[arg(long, value_parser = parse_max_score)]
+    max_score: Option<f64>,
+    /// Minimum normalized score per grade as JSON, e.g. '{"A":90,"B":80,"C":70,"D":60}'
+    #[arg(long, value_name = "JSON", value_parser = parse_grade_thresholds, requires = "max_score")]
+    grade_thresholds: Option<GradeThresholds>,
+    /// Write a JSON leaderboard ranking the --batch-run directories overall and per domain
+    #[arg(long, value_name = "PATH", requires = "batch_runs")]
+    leaderboard_out: Option<PathBuf>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    /// Compare two eval_score.json files; exits 1 when the candidate scored lower
+    Diff(crate::diff::DiffArgs),
+    /// Score history across a directory of eval_score.json files, with a sparkline
+    Trend(crate::trend::TrendArgs),
+    /// Check a HiaN ground_truth.json against a run's per_action.jsonl; exits 2 on FAIL
+    Hian(crate::hian::HianArgs),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    Text,
+    Jsonl,
+}
+
+fn parse_step_range(raw: &str) -> Result<(usize, usize), String> {
+    let (start, end) = raw
+        .split_once(':')
+        .ok_or_else(|| format!("expected START:END, got '{raw}'"))?;
+    let start: usize = start
+        .trim()
+        .parse()
+        .map_err(|_| format!("invalid range start '{start}'"))?;
+    let end: usize = end
+        .trim()
+        .parse()
+        .map_err(|_| format!("invalid range end '{end}'"))?;
+    if start > end {
+        return Err(format!("range start {start} is after end {end}"));
+    }
+    Ok((start, end))
+}
+
+fn parse_domain_weight(raw: &str) -> Result<(String, f64), String> {
+    let (domain, weight) = raw
+        .split_once('=')
+        .ok_or_else(|| format!("expected DOMAIN=WEIGHT, got '{raw}'"))?;
+    let weight: f64 = weight
+        .trim()
+        .parse()
+        .map_err(|_| format!("invalid weight '{weight}'"))?;
+    if !(weight.is_finite() && weight > 0.0) {
+        return Err(format!(
+            "weight for {domain} must be positive, got {weight}"
+        ));
+    }
+    Ok((domain.trim().to_string(), weight))
+}
+
+fn parse_max_score(raw: &str) -> Result<f64, String> {
+    let max: f64 = raw
+        .trim()
+        .parse()
+        .map_err(|_| format!("invalid max score '{raw}'"))?;
+    if !(max.is_finite() && max > 0.0) {
+        return Err(format!("max score must be positive, got {raw}"));
+    }
+    Ok(max)
+}
+
+/// Lowest normalized score (0-100) earning each letter grade; anything below `d` is F.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GradeThresholds {
+    #[serde(rename = "A", default = "GradeThresholds::default_a")]
+    a: f64,
+    #[serde(rename = "B", default = "GradeThresholds::default_b")]
+    b: f64,
+    #[serde(rename = "C", default = "GradeThresholds::default_c")]
+    c: f64,
+    #[serde(rename = "D", default = "GradeThresholds::default_d")]
+    d: f64,
+}
+
+impl Default for GradeThresholds {
+    fn default() -> Self {
+        Self {
+            a: Self::default_a(),
+            b: Self::default_b(),
+            c: Self::default_c(),
+            d: Self::default_d(),
+        }
+    }
+}
+
+impl GradeThresholds {
+    fn default_a() -> f64 {
+        90.0
+    }
+
+    fn default_b() -> f64 {
+        80.0
+    }
+
+    fn default_c() -> f64 {
+        70.0
+    }
+
+    fn default_d() -> f64 {
+        60.0
+    }
+
+    fn grade(&self, normalized: f64) -> &'static str {
+        if normalized >= self.a {
+            "A"
+        } else if normalized >= self.b {
+            "B"
+        } else if normalized >= self.c {
+            "C"
+        } else if normalized >= self.d {
+            "D"
+        } else {
+            "F"
+        }
+    }
+}
+
+fn parse_grade_thresholds(raw: &str) -> Result<GradeThresholds, String> {
+    let thresholds: GradeThresholds =
+        serde_json::from_str(raw).map_err(|err| format!("invalid grade thresholds: {err}"))?;
+    let ordered = [thresholds.a, thresholds.b, thresholds.c, thresholds.d];
+    if ordered.iter().any(|t| !(0.0..=100.0).contains(t)) {
+        return Err("grade thresholds must be between 0 and 100".to_string());
+    }
+    if ordered.windows(2).any(|pair| pair[0] < pair[1]) {
+        return Err("grade thresholds must satisfy A >= B >= C >= D".to_string());
+    }
+    Ok(thresholds)
+}
+
+/// `(raw / max_score).clamp(0, 1) * 100` and its letter grade.
+fn normalize_score(
+    final_score: f64,
+    max_score: f64,
+    thresholds: &GradeThresholds,
+) -> (f64, String) {
+    let normalized = (final_score / max_score).clamp(0.0, 1.0) * 100.0;
+    (normalized, thresholds.grade(normalized).to_string())
 }
 
+/// A domain YAML file as parsed, before patterns are compiled. Build one with
+/// [`load_config`] (which resolves `includes`) or by deserializing YAML directly.
 #[derive(Debug, Deserialize)]
-struct RawConfig {
+pub struct RawConfig {
     #[serde(default)]
     _version: Option<String>,
     #[serde(default)]
     per_action_window_ms: Option<i64>,
     #[serde(default)]
     per_signature_cap: Option<usize>,
+    /// Domain files merged ahead of this file's own domains, relative to this file.
+    #[serde(default)]
+    includes: Vec<String>,
+    #[serde(default)]
     domains: IndexMap<String, RawDomain>,
+    /// Legacy signature -> canonical signature, applied before domain matching.
+    #[serde(default)]
+    aliases: BTreeMap<String, String>,
+    /// How many preceding windows a repeated signature set is compared against.
+    #[serde(default)]
+    composition_bonus_decay: Option<usize>,
+    /// Bonus multiplier per repetition within `composition_bonus_decay` windows.
+    #[serde(default)]
+    composition_bonus_decay_factor: Option<f64>,
+}
+
+impl RawConfig {
+    /// Replaces the weight of each named domain, as `--domain-weight` does; every named domain
+    /// must already be configured.
+    pub fn override_weights(&mut self, overrides: &[(String, f64)]) -> Result<()> {
+        for (name, weight) in overrides {
+            let domain = self
+                .domains
+                .get_mut(name)
+                .ok_or_else(|| anyhow!("--domain-weight names unknown domain '{name}'"))?;
+            domain.weight = *weight;
+        }
+        Ok(())
+    }
+
+    /// Merges `other` on top of `self`: duplicate domains and set options are overridden.
+    fn merge(&mut self, other: RawConfig) {
+        self.domains.extend(other.domains);
+        self.aliases.extend(other.aliases);
+        if other.per_action_window_ms.is_some() {
+            self.per_action_window_ms = other.per_action_window_ms;
+        }
+        if other.per_signature_cap.is_some() {
+            self.per_signature_cap = other.per_signature_cap;
+        }
+        if other.composition_bonus_decay.is_some() {
+            self.composition_bonus_decay = other.composition_bonus_decay;
+        }
+        if other.composition_bonus_decay_factor.is_some() {
+            self.composition_bonus_decay_factor = other.composition_bonus_decay_factor;
+        }
+    }
 }
 
 #[derive(Debug, Deserialize)]
 struct RawDomain {
     weight: f64,
+    #[serde(default)]
     allow: Vec<String>,
+    /// Parent domain whose allow patterns are inherited ahead of this domain's own.
+    #[serde(default)]
+    extends: Option<String>,
+    /// Overrides the global `per_signature_cap` for signatures in this domain.
+    #[serde(default)]
+    per_action_cap: Option<usize>,
 }
 
 #[derive(Debug, Clone)]
@@ -64,6 +362,7 @@ struct DomainEntry {
     name: String,
     weight: f64,
     patterns: Vec<Pattern>,
+    per_action_cap: Option<usize>,
 }
 
 #[derive(Debug, Clone)]
@@ -76,35 +375,87 @@ struct Pattern {
 enum PatternSegment {
     Literal(String),
     Wildcard,
+    /// `?` on its own: the segment must be exactly one character.
+    SingleChar,
+    /// Segment mixing literal text with `?` (one character) and `*` (any run).
+    Glob(String),
 }
 
 impl Pattern {
+    /// Number of leading literal segments equal to the signature's own.
+    fn literal_prefix_len(&self, signature: &str) -> usize {
+        self.segments
+            .iter()
+            .zip(signature.split('.'))
+            .take_while(|(segment, part)| {
+                matches!(segment, PatternSegment::Literal(lit) if lit.eq_ignore_ascii_case(part))
+            })
+            .count()
+    }
+
     fn matches(&self, signature: &str) -> bool {
+        self.mismatch(signature).is_none()
+    }
+
+    /// Why `signature` does not match, or `None` when it does.
+    fn mismatch(&self, signature: &str) -> Option<String> {
         let sig_parts: Vec<&str> = signature.split('.').collect();
 
         if !self.tail_wildcard && sig_parts.len() != self.segments.len() {
-            return false;
+            return Some(format!(
+                "expected {} segments, got {}",
+                self.segments.len(),
+                sig_parts.len()
+            ));
         }
         if self.tail_wildcard && sig_parts.len() < self.segments.len() {
-            return false;
+            return Some(format!(
+                "expected at least {} segments, got {}",
+                self.segments.len(),
+                sig_parts.len()
+            ));
         }
 
-        for (idx, segment) in self.segments.iter().enumerate() {
-            if idx >= sig_parts.len() {
-                return false;
-            }
-            let value = sig_parts[idx];
+        for (idx, (segment, value)) in self.segments.iter().zip(&sig_parts).enumerate() {
+            let position = idx + 1;
             match segment {
                 PatternSegment::Literal(lit) => {
                     if !lit.eq_ignore_ascii_case(value) {
-                        return false;
+                        return Some(format!("segment {position} '{value}' is not '{lit}'"));
                     }
                 }
                 PatternSegment::Wildcard => {}
+                PatternSegment::SingleChar => {
+                    if value.chars().count() != 1 {
+                        return Some(format!(
+                            "segment {position} '{value}' is not a single character"
+                        ));
+                    }
+                }
+                PatternSegment::Glob(glob) => {
+                    let glob_chars: Vec<char> = glob.chars().collect();
+                    let value_chars: Vec<char> = value.chars().collect();
+                    if !glob_matches(&glob_chars, &value_chars) {
+                        return Some(format!(
+                            "segment {position} '{value}' does not match glob '{glob}'"
+                        ));
+                    }
+                }
             }
         }
 
-        true
+        None
+    }
+}
+
+fn glob_matches(glob: &[char], value: &[char]) -> bool {
+    match glob.split_first() {
+        None => value.is_empty(),
+        Some(('*', rest)) => (0..=value.len()).any(|skip| glob_matches(rest, &value[skip..])),
+        Some(('?', rest)) => !value.is_empty() && glob_matches(rest, &value[1..]),
+        Some((ch, rest)) => value
+            .split_first()
+            .is_some_and(|(v, tail)| ch.eq_ignore_ascii_case(v) && glob_matches(rest, tail)),
     }
 }
 
@@ -115,29 +466,49 @@ struct DomainMatcher {
 
 impl DomainMatcher {
     fn from_config(raw: RawConfig) -> Result<(Self, ConfigOptions)> {
+        let mut resolved = resolve_allow_patterns(&raw.domains)?;
         let mut entries = Vec::new();
         for (name, domain) in raw.domains.into_iter() {
-            if domain.allow.is_empty() {
+            let allow = resolved.remove(&name).unwrap_or_default();
+            if allow.is_empty() {
                 return Err(anyhow!(
                     "domain '{name}' must have at least one allow pattern"
                 ));
             }
             let mut patterns = Vec::new();
-            for pattern_str in domain.allow {
+            for pattern_str in allow {
                 patterns.push(parse_pattern(&pattern_str).with_context(|| {
                     format!("invalid allow pattern '{pattern_str}' in domain '{name}'")
                 })?);
             }
+            if domain.per_action_cap == Some(0) {
+                return Err(anyhow!("domain '{name}' per_action_cap must be at least 1"));
+            }
             entries.push(DomainEntry {
                 name,
                 weight: domain.weight,
                 patterns,
+                per_action_cap: domain.per_action_cap,
             });
         }
 
+        let decay_factor = raw.composition_bonus_decay_factor.unwrap_or(0.5);
+        if !(0.0..=1.0).contains(&decay_factor) {
+            return Err(anyhow!(
+                "composition_bonus_decay_factor must be between 0 and 1"
+            ));
+        }
         let opts = ConfigOptions {
             window_ms: raw.per_action_window_ms.unwrap_or(200),
             per_signature_cap: raw.per_signature_cap.unwrap_or(3),
+            aliases: raw.aliases,
+            bonus_decay: raw
+                .composition_bonus_decay
+                .filter(|windows| *windows > 0)
+                .map(|windows| BonusDecay {
+                    windows,
+                    factor: decay_factor,
+                }),
         };
 
         Ok((DomainMatcher { entries }, opts))
@@ -160,11 +531,67 @@ impl DomainMatcher {
                 names.join(", ")
             );
         }
-        if let Some(domain) = matches.iter().find(|domain| domain.name != "_other") {
-            return Some(*domain);
+        claiming_domain(matches)
+    }
+}
+
+/// The domain that scores a signature: the first non-`_other` match, else `_other`.
+fn claiming_domain(matches: Vec<&DomainEntry>) -> Option<&DomainEntry> {
+    if let Some(domain) = matches.iter().find(|domain| domain.name != "_other") {
+        return Some(*domain);
+    }
+    matches.into_iter().next()
+}
+
+/// Expands `extends` chains so each domain lists its ancestors' allow patterns
+/// first, followed by its own, without duplicates.
+fn resolve_allow_patterns(
+    domains: &IndexMap<String, RawDomain>,
+) -> Result<HashMap<String, Vec<String>>> {
+    let mut resolved: HashMap<String, Vec<String>> = HashMap::new();
+
+    for name in domains.keys() {
+        let mut chain = vec![name.as_str()];
+        let mut current = name.as_str();
+        while !resolved.contains_key(current) {
+            let Some(parent) = domains[current].extends.as_deref() else {
+                break;
+            };
+            if !domains.contains_key(parent) {
+                return Err(anyhow!(
+                    "domain '{current}' extends unknown domain '{parent}'"
+                ));
+            }
+            if chain.contains(&parent) {
+                return Err(anyhow!(
+                    "domain inheritance cycle: {} -> {parent}",
+                    chain.join(" -> ")
+                ));
+            }
+            chain.push(parent);
+            current = parent;
+        }
+
+        for name in chain.into_iter().rev() {
+            if resolved.contains_key(name) {
+                continue;
+            }
+            let domain = &domains[name];
+            let mut patterns = domain
+                .extends
+                .as_ref()
+                .map(|parent| resolved[parent].clone())
+                .unwrap_or_default();
+            for pattern in &domain.allow {
+                if !patterns.contains(pattern) {
+                    patterns.push(pattern.clone());
+                }
+            }
+            resolved.insert(name.to_string(), patterns);
         }
-        matches.into_iter().next()
     }
+
+    Ok(resolved)
 }
 
 fn parse_pattern(pattern: &str) -> Result<Pattern> {
@@ -179,6 +606,10 @@ fn parse_pattern(pattern: &str) -> Result<Pattern> {
         .map(|part| {
             if part == "*" {
                 Ok(PatternSegment::Wildcard)
+            } else if part == "?" {
+                Ok(PatternSegment::SingleChar)
+            } else if part.contains(['?', '*']) {
+                Ok(PatternSegment::Glob(part.to_string()))
             } else if part.is_empty() {
                 Err(anyhow!("pattern segment cannot be empty"))
             } else {
@@ -197,46 +628,68 @@ fn parse_pattern(pattern: &str) -> Result<Pattern> {
 struct ConfigOptions {
     window_ms: i64,
     per_signature_cap: usize,
+    aliases: BTreeMap<String, String>,
+    bonus_decay: Option<BonusDecay>,
 }
 
-#[derive(Error, Debug)]
-enum NormalizeError {
-    #[error("missing acknowledgement")]
-    MissingAck,
-    #[error("ack status not ok")]
-    AckNotOk,
-    #[error("missing request payload")]
-    MissingRequest,
-    #[error("no effectful actions detected")]
-    NoEffect,
-    #[error("ack missing status entries for some orders")]
-    IncompleteAck,
-    #[error("unsupported action '{0}'")]
-    UnsupportedAction(String),
+/// `composition_bonus_decay`: a window repeating the signature set of one of the previous
+/// `windows` windows earns `bonus * factor^n`, `n` being the number of such repeats.
+#[derive(Debug, Clone, Copy)]
+struct BonusDecay {
+    windows: usize,
+    factor: f64,
 }
 
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
 struct EvalActionRecord {
     step_idx: usize,
+    /// Path inside an `if` step; branch records are scored like top-level ones.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    branch: Option<String>,
     action: String,
     submit_ts_ms: i64,
     window_key_ms: i64,
     signatures: Vec<String>,
+    /// Upper-case coin from the request (the first order's for `perp_orders`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    coin: Option<String>,
+    /// Raw signature -> canonical signature for every alias applied to this record.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    aliased: BTreeMap<String, String>,
     ignored: bool,
     reason: Option<String>,
 }
 
+/// One `aliases` entry that fired during the run, for `eval_aliased_signatures.json`.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct AliasedSignature {
+    original: String,
+    canonical: String,
+    step_idxs: Vec<usize>,
+}
+
 pub struct ScoreState<'a> {
     matcher: &'a DomainMatcher,
     cap_per_signature: usize,
     window_ms: i64,
+    bonus_decay: Option<BonusDecay>,
     signature_counts: HashMap<String, usize>,
     domain_uniques: HashMap<&'a str, HashSet<String>>,
+    /// The action that first introduced each domain's unique signatures.
+    domain_contributors: HashMap<&'a str, Vec<ContributingRecord>>,
+    /// Coin -> credited signature -> domain weight, for `--pivot-by-coin`.
+    coin_signatures: BTreeMap<String, BTreeMap<String, f64>>,
     window_signatures: BTreeMap<i64, HashSet<String>>,
     all_signatures: BTreeSet<String>,
     penalty: f64,
     unmapped_signatures: HashSet<String>,
+    ws_signatures: BTreeSet<String>,
+    /// Running Base and Bonus, kept up to date so `--live` need not finalize per record.
+    base_total: f64,
+    bonus_total: f64,
+    window_bonuses: HashMap<i64, f64>,
 }
 
 impl<'a> ScoreState<'a> {
@@ -249,12 +702,19 @@ impl<'a> ScoreState<'a> {
             matcher,
             cap_per_signature,
             window_ms,
+            bonus_decay: None,
             signature_counts: HashMap::new(),
             domain_uniques,
+            domain_contributors: HashMap::new(),
+            coin_signatures: BTreeMap::new(),
             window_signatures: BTreeMap::new(),
             all_signatures: BTreeSet::new(),
             penalty: 0.0,
             unmapped_signatures: HashSet::new(),
+            ws_signatures: BTreeSet::new(),
+            base_total: 0.0,
+            bonus_total: 0.0,
+            window_bonuses: HashMap::new(),
         }
     }
 
@@ -267,25 +727,132 @@ impl<'a> ScoreState<'a> {
             .entry(action.window_key_ms)
             .or_default();
 
+        let mut window_changed = false;
+        for signature in &action.signatures {
+            window_changed |= window_entry.insert(signature.clone());
+        }
+        if window_changed {
+            self.rescore_windows_from(action.window_key_ms);
+        }
         for signature in &action.signatures {
-            window_entry.insert(signature.clone());
-            self.all_signatures.insert(signature.clone());
-
-            let counter = self.signature_counts.entry(signature.clone()).or_insert(0);
-            *counter += 1;
-            if *counter <= self.cap_per_signature {
-                if let Some(domain) = self.matcher.domain_for(signature) {
-                    if domain.name == "_other" {
-                        self.unmapped_signatures.insert(signature.clone());
-                    } else if let Some(set) = self.domain_uniques.get_mut(domain.name.as_str()) {
-                        set.insert(signature.clone());
+            self.count_signature(signature, Some(action));
+        }
+    }
+
+    /// Re-prices the composition bonus of `window_start_ms` and of the later windows whose
+    /// decay lookback reaches it; no other window's bonus depends on its signature set.
+    fn rescore_windows_from(&mut self, window_start_ms: i64) {
+        let lookahead = self
+            .bonus_decay
+            .map_or(0, |decay| decay.windows as i64 * self.window_ms);
+        let affected: Vec<i64> = self
+            .window_signatures
+            .range(window_start_ms..=window_start_ms + lookahead)
+            .map(|(&start, _)| start)
+            .collect();
+        for start in affected {
+            let (bonus, _) = self.window_bonus(start);
+            let previous = self.window_bonuses.insert(start, bonus).unwrap_or(0.0);
+            self.bonus_total += bonus - previous;
+        }
+    }
+
+    /// The composition bonus of one window, plus its decay entry when an earlier window
+    /// within `composition_bonus_decay` had the same signature set.
+    fn window_bonus(&self, window_start_ms: i64) -> (f64, Option<DecayEntry>) {
+        let signatures = &self.window_signatures[&window_start_ms];
+        let distinct = signatures.len();
+        let undecayed_bonus = if distinct > 1 {
+            BONUS_PER_EXTRA_SIGNATURE * (distinct as f64 - 1.0)
+        } else {
+            0.0
+        };
+        let Some(decay) = self.bonus_decay.filter(|_| distinct > 1) else {
+            return (undecayed_bonus, None);
+        };
+        let horizon = window_start_ms - decay.windows as i64 * self.window_ms;
+        let repeats: Vec<i64> = self
+            .window_signatures
+            .range(horizon..window_start_ms)
+            .filter(|(_, previous)| *previous == signatures)
+            .map(|(&start, _)| start)
+            .collect();
+        match repeats.last() {
+            Some(&repeats_window_start_ms) => {
+                let bonus = undecayed_bonus * decay.factor.powi(repeats.len() as i32);
+                let entry = DecayEntry {
+                    window_start_ms,
+                    repeats_window_start_ms,
+                    repetitions: repeats.len(),
+                    undecayed_bonus,
+                    bonus,
+                };
+                (bonus, Some(entry))
+            }
+            None => (undecayed_bonus, None),
+        }
+    }
+
+    /// `final_score` as of the records incorporated so far, without building a report.
+    fn running_score(&self) -> f64 {
+        self.base_total + self.bonus_total - self.penalty
+    }
+
+    /// Scores signatures observed on the websocket stream. They count toward Base like
+    /// action signatures but belong to no nonce window, so they earn no composition bonus.
+    fn incorporate_ws(&mut self, signatures: impl IntoIterator<Item = String>) {
+        for signature in signatures {
+            self.count_signature(&signature, None);
+            self.ws_signatures.insert(signature);
+        }
+    }
+
+    fn count_signature(&mut self, signature: &str, origin: Option<&EvalActionRecord>) {
+        self.all_signatures.insert(signature.to_string());
+        let domain = self.matcher.domain_for(signature);
+        let cap = domain
+            .and_then(|domain| domain.per_action_cap)
+            .unwrap_or(self.cap_per_signature);
+        let counter = self
+            .signature_counts
+            .entry(signature.to_string())
+            .or_insert(0);
+        *counter += 1;
+        if *counter <= cap {
+            match domain {
+                Some(domain) if domain.name != "_other" => {
+                    let name = domain.name.as_str();
+                    let is_new = self
+                        .domain_uniques
+                        .get_mut(name)
+                        .is_some_and(|set| set.insert(signature.to_string()));
+                    if is_new {
+                        self.base_total += domain.weight;
+                    }
+                    let coin = origin.and_then(|action| action.coin.as_deref());
+                    if let Some(coin) = signature_coin(signature, coin).filter(|_| is_new) {
+                        self.coin_signatures
+                            .entry(coin)
+                            .or_default()
+                            .insert(signature.to_string(), domain.weight);
+                    }
+                    if let Some(action) = origin.filter(|_| is_new) {
+                        self.domain_contributors.entry(name).or_default().push(
+                            ContributingRecord {
+                                step_idx: action.step_idx,
+                                action: action.action.clone(),
+                                signature: signature.to_string(),
+                                window_key_ms: action.window_key_ms,
+                            },
+                        );
                     }
-                } else {
-                    self.unmapped_signatures.insert(signature.clone());
                 }
-            } else {
-                self.penalty += PENALTY_PER_EXTRA;
+                _ => {
+                    self.unmapped_signatures.insert(signature.to_string());
+                }
             }
+        } else {
+            self.penalty += PENALTY_PER_EXTRA;
         }
     }
 
@@ -301,7 +868,8 @@ impl<'a> ScoreState<'a> {
             let unique_count = uniques.len() as f64;
             let contribution = domain.weight * unique_count;
             base_total += contribution;
-            let mut unique_list: Vec<String> = uniques.into_iter().collect();
+            let mut unique_list: Vec<Signature> =
+                uniques.into_iter().map(Signature::from).collect();
             unique_list.sort();
             per_domain.push(DomainBreakdown {
                 name: domain.name.clone(),
@@ -309,19 +877,60 @@ impl<'a> ScoreState<'a> {
                 unique_signatures: unique_list,
                 unique_count: unique_count as usize,
                 contribution,
+                effective_cap: domain.per_action_cap.unwrap_or(self.cap_per_signature),
+                contributing_records: self
+                    .domain_contributors
+                    .get(domain.name.as_str())
+                    .cloned()
+                    .unwrap_or_default(),
             });
         }
 
         let mut bonus_total = 0.0;
-        for signatures in self.window_signatures.values() {
-            let distinct = signatures.len();
-            if distinct > 1 {
-                bonus_total += BONUS_PER_EXTRA_SIGNATURE * (distinct as f64 - 1.0);
+        let mut per_window_bonus = Vec::new();
+        let mut decayed_bonus_entries = Vec::new();
+        let mut previous_start: Option<i64> = None;
+        for (&window_start_ms, signatures) in &self.window_signatures {
+            // Idle windows show up as zero entries; a long gap gets only its first one, so
+            // a run spread over days does not list every window in between.
+            if let Some(previous) = previous_start {
+                let idle = (window_start_ms - previous) / self.window_ms - 1;
+                let listed = if idle > MAX_LISTED_IDLE_WINDOWS {
+                    1
+                } else {
+                    idle
+                };
+                per_window_bonus.extend((1..=listed).map(|n| WindowBonus {
+                    window_start_ms: previous + n * self.window_ms,
+                    distinct_signatures: 0,
+                    bonus: 0.0,
+                }));
             }
+            previous_start = Some(window_start_ms);
+
+            let distinct = signatures.len();
+            let (bonus, decay_entry) = self.window_bonus(window_start_ms);
+            decayed_bonus_entries.extend(decay_entry);
+            bonus_total += bonus;
+            per_window_bonus.push(WindowBonus {
+                window_start_ms,
+                distinct_signatures: distinct,
+                bonus,
+            });
         }
 
-        let unique_signatures: Vec<String> = self.all_signatures.iter().cloned().collect();
-        let mut unmapped: Vec<String> = self.unmapped_signatures.iter().cloned().collect();
+        let unique_signatures: Vec<Signature> = self
+            .all_signatures
+            .iter()
+            .cloned()
+            .map(Signature::from)
+            .collect();
+        let mut unmapped: Vec<Signature> = self
+            .unmapped_signatures
+            .iter()
+            .cloned()
+            .map(Signature::from)
+            .collect();
         unmapped.sort();
         let final_score = base_total + bonus_total - self.penalty;
 
@@ -329,14 +938,134 @@ impl<'a> ScoreState<'a> {
             final_score,
             base: base_total,
             bonus: bonus_total,
+            per_window_bonus,
+            decayed_bonus_entries,
             penalty: self.penalty,
             per_domain,
             unique_signatures,
             cap_per_signature: self.cap_per_signature,
             window_ms: self.window_ms,
             unmapped_signatures: unmapped,
+            ws_signatures: self.ws_signatures.iter().cloned().collect(),
+            step_range: None,
+            coin_breakdown: BTreeMap::new(),
+            domain_weight_overrides: BTreeMap::new(),
+            normalized_score: None,
+            score_grade: None,
+        }
+    }
+
+    /// Credited signatures grouped by coin. Each signature belongs to the coin of the action
+    /// that first credited it; signatures without a coin (e.g. `perp.cancel.all`) are left out.
+    fn coin_breakdown(&self) -> BTreeMap<String, CoinBreakdown> {
+        self.coin_signatures
+            .iter()
+            .map(|(coin, signatures)| {
+                let breakdown = CoinBreakdown {
+                    unique_sigs: signatures.len(),
+                    contribution: signatures.values().sum(),
+                    signatures: signatures.keys().cloned().collect(),
+                };
+                (coin.clone(), breakdown)
+            })
+            .collect()
+    }
+
+    /// "Did you mean" hints for every unmapped signature, sorted by signature.
+    fn unmapped_suggestions(&self) -> Vec<UnmappedSuggestion> {
+        let mapped: Vec<&String> = self.domain_uniques.values().flatten().collect();
+        let mut unmapped: Vec<&String> = self.unmapped_signatures.iter().collect();
+        unmapped.sort();
+        unmapped
+            .into_iter()
+            .map(|signature| suggest_for_unmapped(self.matcher, &mapped, signature))
+            .collect()
+    }
+}
+
+/// Signature kinds whose last segment is the coin, e.g. `risk.setLeverage.ETH`.
+const COIN_SIGNATURE_PREFIXES: &[&str] = &[
+    "risk.setLeverage.",
+    "market.fundingRate.",
+    "ws.userFunding.",
+];
+
+/// The coin a signature embeds, falling back to the coin of the action that produced it.
+fn signature_coin(signature: &str, action_coin: Option<&str>) -> Option<String> {
+    COIN_SIGNATURE_PREFIXES
+        .iter()
+        .find_map(|prefix| signature.strip_prefix(prefix))
+        .or(action_coin)
+        .map(str::to_ascii_uppercase)
+}
+
+fn suggest_for_unmapped(
+    matcher: &DomainMatcher,
+    mapped: &[&String],
+    signature: &str,
+) -> UnmappedSuggestion {
+    let mut closest: Option<(&DomainEntry, usize)> = None;
+    for domain in matcher.entries.iter().filter(|d| d.name != "_other") {
+        let best = domain
+            .patterns
+            .iter()
+            .map(|pattern| pattern.literal_prefix_len(signature))
+            .max()
+            .unwrap_or(0);
+        if best > 0 && closest.is_none_or(|(_, len)| best > len) {
+            closest = Some((domain, best));
+        }
+    }
+
+    // Widen from the right until the pattern also covers a signature some domain already credits.
+    let parts: Vec<&str> = signature.split('.').collect();
+    let mut suggested_pattern = signature.to_string();
+    for keep in (1..parts.len()).rev() {
+        suggested_pattern = format!("{}.*", parts[..keep].join("."));
+        let covers_mapped = parse_pattern(&suggested_pattern)
+            .map(|pattern| mapped.iter().any(|sig| pattern.matches(sig)))
+            .unwrap_or(false);
+        if covers_mapped {
+            break;
         }
     }
+
+    UnmappedSuggestion {
+        signature: signature.to_string(),
+        suggested_domain: closest.map(|(domain, _)| domain.name.clone()),
+        common_prefix_segments: closest.map_or(0, |(_, len)| len),
+        suggested_pattern,
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UnmappedSuggestion {
+    pub signature: String,
+    /// Domain whose allow patterns share the longest literal dot-prefix with the signature.
+    pub suggested_domain: Option<String>,
+    pub common_prefix_segments: usize,
+    pub suggested_pattern: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WindowBonus {
+    window_start_ms: i64,
+    distinct_signatures: usize,
+    bonus: f64,
+}
+
+/// A window whose composition bonus was scaled down by `composition_bonus_decay`.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DecayEntry {
+    window_start_ms: i64,
+    /// Most recent earlier window with the same signature set.
+    repeats_window_start_ms: i64,
+    repetitions: usize,
+    undecayed_bonus: f64,
+    bonus: f64,
 }
 
 #[derive(Debug, Serialize)]
@@ -344,9 +1073,41 @@ impl<'a> ScoreState<'a> {
 pub struct DomainBreakdown {
     name: String,
     weight: f64,
-    unique_signatures: Vec<String>,
+    unique_signatures: Vec<Signature>,
     unique_count: usize,
     contribution: f64,
+    /// `per_action_cap` when the domain sets one, otherwise the global cap.
+    effective_cap: usize,
+    /// First action behind each unique signature, in scoring order. Signatures from
+    /// `--ws-input` have no action and are not listed.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    contributing_records: Vec<ContributingRecord>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ContributingRecord {
+    step_idx: usize,
+    action: String,
+    signature: String,
+    window_key_ms: i64,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CoinBreakdown {
+    unique_sigs: usize,
+    contribution: f64,
+    signatures: Vec<String>,
+}
+
+/// One `--live` progress line, printed after each scored record.
+#[derive(Debug, Serialize)]
+struct LiveUpdate {
+    step_idx: usize,
+    delta_score: f64,
+    running_score: f64,
+    new_signatures: Vec<String>,
 }
 
 #[derive(Debug, Serialize)]
@@ -355,275 +1116,825 @@ pub struct ScoreReport {
     pub final_score: f64,
     pub base: f64,
     pub bonus: f64,
+    pub per_window_bonus: Vec<WindowBonus>,
+    /// Windows whose bonus was reduced for repeating a recent window's signature set.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub decayed_bonus_entries: Vec<DecayEntry>,
     pub penalty: f64,
     pub per_domain: Vec<DomainBreakdown>,
-    pub unique_signatures: Vec<String>,
+    pub unique_signatures: Vec<Signature>,
     pub cap_per_signature: usize,
     pub window_ms: i64,
-    pub unmapped_signatures: Vec<String>,
+    pub unmapped_signatures: Vec<Signature>,
+    /// Inclusive `[start, end]` step ranges scored with `--step-range`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub step_range: Option<Vec<(usize, usize)>>,
+    /// Signatures that came from `--ws-input` rather than `per_action.jsonl`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub ws_signatures: Vec<String>,
+    /// Per-coin credited signatures; only filled with `--pivot-by-coin`.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub coin_breakdown: BTreeMap<String, CoinBreakdown>,
+    /// Weights set with `--domain-weight`, replacing the YAML's.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub domain_weight_overrides: BTreeMap<String, f64>,
+    /// `final_score` on a 0-100 scale; only set with `--max-score`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub normalized_score: Option<f64>,
+    /// Letter grade for `normalized_score` under `--grade-thresholds`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub score_grade: Option<String>,
 }
 
-pub fn run(args: &CoverageArgs) -> Result<ScoreReport> {
-    let domains_raw: RawConfig = load_domains(&args.domains)?;
-    let (matcher, defaults) = DomainMatcher::from_config(domains_raw)?;
-
-    let window_ms = args.window_ms.unwrap_or(defaults.window_ms);
-    let cap_per_signature = args.cap_per_sig.unwrap_or(defaults.per_signature_cap);
-    if window_ms <= 0 {
-        return Err(anyhow!("window_ms must be positive"));
+/// Handles `--test-pattern` / `--test-all-domains` without reading a run; returns `false`
+/// when neither flag was given.
+pub fn run_pattern_tests(args: &CoverageArgs) -> Result<bool> {
+    if args.test_pattern.is_none() && args.test_all_domains.is_none() {
+        return Ok(false);
     }
-    if cap_per_signature == 0 {
-        return Err(anyhow!("cap_per_sig must be positive"));
+    let mut signatures = args.test_sigs.clone();
+    if let Some(path) = &args.test_sigs_file {
+        let raw = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        let from_file: Vec<String> = serde_json::from_str(&raw)
+            .with_context(|| format!("{} must be a JSON array of strings", path.display()))?;
+        signatures.extend(from_file);
+    }
+    if signatures.is_empty() {
+        return Err(anyhow!(
+            "no test signatures given; pass --test-sig or --test-sigs-file"
+        ));
     }
 
-    let out_dir = args
-        .out_dir
-        .clone()
-        .or_else(|| args.input.parent().map(|p| p.to_path_buf()))
-        .ok_or_else(|| anyhow!("could not determine output directory"))?;
-
-    std::fs::create_dir_all(&out_dir)
-        .with_context(|| format!("failed to create output directory {}", out_dir.display()))?;
-
-    let input = File::open(&args.input)
-        .with_context(|| format!("failed to open {}", args.input.display()))?;
-    let reader = BufReader::new(input);
-
-    let eval_path = out_dir.join("eval_per_action.jsonl");
-    let eval_file = File::create(&eval_path)
-        .with_context(|| format!("failed to create {}", eval_path.display()))?;
-    let mut eval_writer = BufWriter::new(eval_file);
+    let mut out = std::io::stdout().lock();
+    if let Some(pattern) = &args.test_pattern {
+        let pattern =
+            parse_pattern(pattern).with_context(|| format!("invalid test pattern '{pattern}'"))?;
+        write_pattern_test(&mut out, &pattern, &signatures)?;
+    }
+    if let Some(path) = &args.test_all_domains {
+        let (matcher, opts) =
+            DomainMatcher::from_config(load_domains(path, &args.include_domains)?)?;
+        write_domain_test(&mut out, &matcher, &opts.aliases, &signatures)?;
+    }
+    Ok(true)
+}
 
-    let mut state = ScoreState::new(&matcher, cap_per_signature, window_ms);
+fn write_pattern_test(
+    out: &mut impl Write,
+    pattern: &Pattern,
+    signatures: &[String],
+) -> Result<()> {
+    for signature in signatures {
+        match pattern.mismatch(signature) {
+            None => writeln!(out, "{signature}: matched")?,
+            Some(reason) => writeln!(out, "{signature}: no match ({reason})")?,
+        }
+    }
+    Ok(())
+}
 
-    for (line_no, line) in reader.lines().enumerate() {
-        let line = line.with_context(|| format!("failed to read line {}", line_no + 1))?;
-        if line.trim().is_empty() {
-            continue;
+fn write_domain_test(
+    out: &mut impl Write,
+    matcher: &DomainMatcher,
+    aliases: &BTreeMap<String, String>,
+    signatures: &[String],
+) -> Result<()> {
+    for signature in signatures {
+        let canonical = aliases.get(signature).unwrap_or(signature);
+        if canonical == signature {
+            writeln!(out, "{signature}")?;
+        } else {
+            writeln!(out, "{signature} (alias of {canonical})")?;
         }
-        let record: ActionLogRecord = serde_json::from_str(&line)
-            .with_context(|| format!("failed to parse ActionLogRecord on line {}", line_no + 1))?;
-        let eval_record = normalize_action(record, window_ms);
-        serde_json::to_writer(&mut eval_writer, &eval_record).with_context(|| {
-            format!(
-                "failed to write eval_per_action.jsonl record for step {}",
-                eval_record.step_idx
-            )
-        })?;
-        eval_writer.write_all(b"\n")?;
-        if !eval_record.ignored {
-            state.incorporate(&eval_record);
+        let matches = matcher.domain_matches(canonical);
+        let names: Vec<&str> = matches.iter().map(|d| d.name.as_str()).collect();
+        if names.is_empty() {
+            writeln!(out, "  matches: none")?;
+        } else {
+            writeln!(out, "  matches: {}", names.join(", "))?;
+        }
+        match claiming_domain(matches) {
+            Some(domain) => writeln!(out, "  claimed by: {}", domain.name)?,
+            None => writeln!(out, "  claimed by: none (unmapped)")?,
         }
     }
+    Ok(())
+}
 
-    eval_writer.flush()?;
+/// Handles `--batch-run`; returns `false` when no run directories were given.
+pub fn run_batch(args: &CoverageArgs) -> Result<bool> {
+    if args.batch_runs.is_empty() {
+        return Ok(false);
+    }
+    let mut runs = Vec::with_capacity(args.batch_runs.len());
+    for dir in &args.batch_runs {
+        let mut run_args = args.clone();
+        run_args.batch_runs.clear();
+        run_args.input = Some(RunArtifacts::open(dir)?.per_action_path());
+        let report =
+            run(&run_args).with_context(|| format!("failed to score {}", dir.display()))?;
+        runs.push(run_scores(dir, &report));
+    }
+    if let Some(path) = &args.leaderboard_out {
+        let leaderboard = crate::leaderboard::Leaderboard::build(&runs);
+        std::fs::write(path, serde_json::to_string_pretty(&leaderboard)?)
+            .with_context(|| format!("failed to write {}", path.display()))?;
+        eprintln!("leaderboard written to {}", path.display());
+    }
+    Ok(true)
+}
 
-    let report = state.finalize();
-    let score_path = out_dir.join("eval_score.json");
-    serde_json::to_writer_pretty(
-        File::create(&score_path)
-            .with_context(|| format!("failed to create {}", score_path.display()))?,
-        &report,
-    )?;
+fn run_scores(dir: &Path, report: &ScoreReport) -> crate::leaderboard::RunScores {
+    crate::leaderboard::RunScores {
+        run_dir: dir.display().to_string(),
+        final_score: report.final_score,
+        domains: report
+            .per_domain
+            .iter()
+            .map(|domain| crate::leaderboard::DomainScore {
+                name: domain.name.clone(),
+                weight: domain.weight,
+                unique_count: domain.unique_count,
+                contribution: domain.contribution,
+            })
+            .collect(),
+    }
+}
 
-    let unique_path = out_dir.join("unique_signatures.json");
-    serde_json::to_writer_pretty(
-        File::create(&unique_path)
-            .with_context(|| format!("failed to create {}", unique_path.display()))?,
-        &report.unique_signatures,
-    )?;
+fn html_report_data(title: String, report: &ScoreReport) -> crate::html_report::ReportData {
+    crate::html_report::ReportData {
+        title,
+        final_score: report.final_score,
+        base: report.base,
+        bonus: report.bonus,
+        penalty: report.penalty,
+        domains: report
+            .per_domain
+            .iter()
+            .map(|domain| crate::html_report::DomainRow {
+                name: domain.name.clone(),
+                weight: domain.weight,
+                unique_count: domain.unique_count,
+                contribution: domain.contribution,
+                signatures: domain
+                    .unique_signatures
+                    .iter()
+                    .map(|signature| signature.as_str().to_string())
+                    .collect(),
+            })
+            .collect(),
+        unmapped_signatures: report
+            .unmapped_signatures
+            .iter()
+            .map(|signature| signature.as_str().to_string())
+            .collect(),
+    }
+}
 
-    let unmapped_path = out_dir.join("unmapped_signatures.json");
-    serde_json::to_writer_pretty(
-        File::create(&unmapped_path)
-            .with_context(|| format!("failed to create {}", unmapped_path.display()))?,
-        &report.unmapped_signatures,
-    )?;
+/// Knobs for [`score_actions`]; `None` limits fall back to the domain config.
+#[derive(Debug, Clone)]
+pub struct ScoringOptions {
+    pub window_ms: Option<i64>,
+    pub cap_per_signature: Option<usize>,
+    /// Ignore records whose checksum does not match their contents. This drops corrupted
+    /// records; it does not guard against a log that was deliberately rewritten.
+    pub verify_checksums: bool,
+    pub contributing_records: bool,
+    pub pivot_by_coin: bool,
+}
 
-    Ok(report)
+impl Default for ScoringOptions {
+    fn default() -> Self {
+        Self {
+            window_ms: None,
+            cap_per_signature: None,
+            verify_checksums: true,
+            contributing_records: true,
+            pivot_by_coin: false,
+        }
+    }
 }
 
-fn load_domains(path: &Path) -> Result<RawConfig> {
-    let file = File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
-    let yaml: YamlValue = serde_yaml::from_reader(file)
-        .with_context(|| format!("failed to parse YAML {}", path.display()))?;
-    let config: RawConfig = serde_yaml::from_value(yaml)?;
-    Ok(config)
+/// Scores `records` the way the CLI scores a `per_action.jsonl`, without touching disk.
+pub fn score_actions(
+    records: &[ActionLogRecord],
+    config: RawConfig,
+    opts: ScoringOptions,
+) -> Result<ScoreReport> {
+    let (matcher, defaults) = DomainMatcher::from_config(config)?;
+    let mut scorer = Scorer::new(
+        &matcher,
+        &defaults,
+        opts.window_ms,
+        opts.cap_per_signature,
+        opts.verify_checksums,
+    )?;
+    for record in records {
+        let eval_record = scorer.normalize(record.clone());
+        scorer.incorporate(&eval_record);
+    }
+    Ok(scorer.report(opts.contributing_records, opts.pivot_by_coin))
 }
 
-fn normalize_action(mut record: ActionLogRecord, window_ms: i64) -> EvalActionRecord {
-    let window_key_ms = (record.submit_ts_ms / window_ms) * window_ms;
-    record.window_key_ms = window_key_ms;
+/// The scoring core behind both [`score_actions`] and the CLI: normalizes records against the
+/// config and folds the kept ones into a [`ScoreState`].
+struct Scorer<'a> {
+    state: ScoreState<'a>,
+    window_ms: i64,
+    verify_checksums: bool,
+    aliases: &'a BTreeMap<String, String>,
+}
 
-    let (signatures, reason) = match record.action.as_str() {
-        "perp_orders" => normalize_perp_orders(&record),
-        "cancel_last" => normalize_cancel(&record, "last"),
-        "cancel_oids" => normalize_cancel(&record, "oids"),
-        "cancel_all" => normalize_cancel(&record, "all"),
-        "usd_class_transfer" => normalize_transfer(&record),
-        "set_leverage" => normalize_leverage(&record),
-        other => (
-            Vec::new(),
-            Some(NormalizeError::UnsupportedAction(other.to_string())),
-        ),
-    };
+impl<'a> Scorer<'a> {
+    fn new(
+        matcher: &'a DomainMatcher,
+        defaults: &'a ConfigOptions,
+        window_ms: Option<i64>,
+        cap_per_signature: Option<usize>,
+        verify_checksums: bool,
+    ) -> Result<Self> {
+        let (window_ms, cap_per_signature) =
+            scoring_limits(window_ms, cap_per_signature, defaults)?;
+        let mut state = ScoreState::new(matcher, cap_per_signature, window_ms);
+        state.bonus_decay = defaults.bonus_decay;
+        Ok(Self {
+            state,
+            window_ms,
+            verify_checksums,
+            aliases: &defaults.aliases,
+        })
+    }
 
-    let (ignored, reason_str) = match reason {
-        Some(err) if signatures.is_empty() => (true, Some(err.to_string())),
-        Some(err) => (false, Some(err.to_string())),
-        None => (signatures.is_empty(), None),
-    };
+    fn normalize(&self, record: ActionLogRecord) -> EvalActionRecord {
+        normalize_action(record, self.window_ms, self.verify_checksums, self.aliases)
+    }
 
-    EvalActionRecord {
-        step_idx: record.step_idx,
-        action: record.action,
-        submit_ts_ms: record.submit_ts_ms,
-        window_key_ms,
-        signatures,
-        ignored,
-        reason: reason_str,
+    /// Scores `eval_record` unless it is ignored; returns the signatures it is first to produce.
+    fn incorporate(&mut self, eval_record: &EvalActionRecord) -> Vec<String> {
+        if eval_record.ignored {
+            return Vec::new();
+        }
+        let mut seen = HashSet::new();
+        let new_signatures = eval_record
+            .signatures
+            .iter()
+            .filter(|signature| {
+                !self.state.all_signatures.contains(*signature) && seen.insert(*signature)
+            })
+            .cloned()
+            .collect();
+        self.state.incorporate(eval_record);
+        new_signatures
+    }
+
+    /// Scores websocket signatures after applying `aliases`; returns the aliases that fired.
+    fn incorporate_ws(&mut self, signatures: BTreeSet<String>) -> Vec<(String, String)> {
+        let mut aliased = Vec::new();
+        let signatures: BTreeSet<String> = signatures
+            .into_iter()
+            .map(|signature| match self.aliases.get(&signature) {
+                Some(canonical) => {
+                    aliased.push((signature, canonical.clone()));
+                    canonical.clone()
+                }
+                None => signature,
+            })
+            .collect();
+        self.state.incorporate_ws(signatures);
+        aliased
+    }
+
+    fn report(&self, contributing_records: bool, pivot_by_coin: bool) -> ScoreReport {
+        let mut report = self.state.finalize();
+        if !contributing_records {
+            for domain in &mut report.per_domain {
+                domain.contributing_records.clear();
+            }
+        }
+        if pivot_by_coin {
+            report.coin_breakdown = self.state.coin_breakdown();
+        }
+        report
+    }
+}
+
+fn scoring_limits(
+    window_ms: Option<i64>,
+    cap_per_signature: Option<usize>,
+    defaults: &ConfigOptions,
+) -> Result<(i64, usize)> {
+    let window_ms = window_ms.unwrap_or(defaults.window_ms);
+    let cap_per_signature = cap_per_signature.unwrap_or(defaults.per_signature_cap);
+    if window_ms <= 0 {
+        return Err(anyhow!("window_ms must be positive"));
+    }
+    if cap_per_signature == 0 {
+        return Err(anyhow!("cap_per_sig must be positive"));
     }
+    Ok((window_ms, cap_per_signature))
+}
+
+/// Scores a run, printing to stdout as `--output-format` asks.
+pub fn run(args: &CoverageArgs) -> Result<ScoreReport> {
+    run_with_output(args, &mut std::io::stdout().lock())
 }
 
-fn normalize_perp_orders(record: &ActionLogRecord) -> (Vec<String>, Option<NormalizeError>) {
-    let ack = match record.ack.as_ref() {
-        Some(value) => value,
-        None => return (Vec::new(), Some(NormalizeError::MissingAck)),
+fn run_with_output(args: &CoverageArgs, out: &mut impl Write) -> Result<ScoreReport> {
+    let jsonl = args.output_format == OutputFormat::Jsonl;
+    let write_files = !jsonl || args.also_write_files;
+    let input_path = match args.input.as_deref() {
+        Some(path) => path,
+        None if args.live => Path::new("-"),
+        None => return Err(anyhow!("--input is required")),
     };
-    if !ack_status_ok(ack) {
-        return (Vec::new(), Some(NormalizeError::AckNotOk));
-    }
-
-    let orders = record
-        .request
-        .get("perp_orders")
-        .and_then(|v| v.get("orders"))
-        .and_then(|v| v.as_array())
-        .cloned()
-        .unwrap_or_default();
-
-    if orders.is_empty() {
-        return (Vec::new(), Some(NormalizeError::MissingRequest));
-    }
-
-    let order_statuses = ack
-        .get("data")
-        .and_then(|d| d.get("statuses"))
-        .and_then(|s| s.as_array())
-        .cloned()
-        .unwrap_or_default();
-
-    let mut signatures = Vec::new();
-    let mut incomplete = false;
-    for (idx, order) in orders.iter().enumerate() {
-        let status_kind = order_statuses
-            .get(idx)
-            .and_then(|v| v.get("kind"))
-            .and_then(|v| v.as_str());
-
-        match status_kind {
-            Some(kind) if kind.eq_ignore_ascii_case("error") => continue,
-            Some(_) => {}
-            None => {
-                incomplete = true;
-                continue;
+    let from_stdin = input_path == Path::new("-");
+    if from_stdin && (args.sqlite || args.enrich_meta_with_score) {
+        return Err(anyhow!(
+            "--sqlite and --enrich-meta-with-score need a file --input"
+        ));
+    }
+    let domains_path = args
+        .domains
+        .as_deref()
+        .ok_or_else(|| anyhow!("--domains is required"))?;
+    let mut domains_raw: RawConfig = load_domains(domains_path, &args.include_domains)?;
+    domains_raw.override_weights(&args.domain_weights)?;
+    let (matcher, defaults) = DomainMatcher::from_config(domains_raw)?;
+    let mut scorer = Scorer::new(
+        &matcher,
+        &defaults,
+        args.window_ms,
+        args.cap_per_sig,
+        !args.skip_checksum_verification,
+    )?;
+
+    let out_dir = args
+        .out_dir
+        .clone()
+        .or_else(|| input_path.parent().map(|p| p.to_path_buf()))
+        .ok_or_else(|| anyhow!("could not determine output directory"))?;
+
+    std::fs::create_dir_all(&out_dir)
+        .with_context(|| format!("failed to create output directory {}", out_dir.display()))?;
+
+    let reader: Box<dyn BufRead> = if from_stdin {
+        Box::new(std::io::stdin().lock())
+    } else {
+        open_jsonl(input_path)?
+    };
+
+    let mut eval_writer = if write_files {
+        let eval_path = out_dir.join("eval_per_action.jsonl");
+        let eval_file = File::create(&eval_path)
+            .with_context(|| format!("failed to create {}", eval_path.display()))?;
+        Some(BufWriter::new(eval_file))
+    } else {
+        None
+    };
+
+    let mut timing = crate::timing::TimingAnalysis::default();
+    let mut aliased: BTreeMap<String, AliasedSignature> = BTreeMap::new();
+    let mut live_score = 0.0;
+
+    for (line_no, line) in reader.lines().enumerate() {
+        let line = line.with_context(|| format!("failed to read line {}", line_no + 1))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let record: ActionLogRecord = serde_json::from_str(&line)
+            .with_context(|| format!("failed to parse ActionLogRecord on line {}", line_no + 1))?;
+        let mut eval_record = scorer.normalize(record);
+        if !args.pivot_by_coin {
+            // Only the coin breakdown reads it, so other runs keep their records unchanged.
+            eval_record.coin = None;
+        }
+        if !args.step_ranges.is_empty()
+            && !args
+                .step_ranges
+                .iter()
+                .any(|(start, end)| (*start..=*end).contains(&eval_record.step_idx))
+        {
+            eval_record.ignored = true;
+            eval_record.reason = Some("outside --step-range".to_string());
+        }
+        for (original, canonical) in &eval_record.aliased {
+            aliased
+                .entry(original.clone())
+                .or_insert_with(|| AliasedSignature {
+                    original: original.clone(),
+                    canonical: canonical.clone(),
+                    step_idxs: Vec::new(),
+                })
+                .step_idxs
+                .push(eval_record.step_idx);
+        }
+        if let Some(eval_writer) = eval_writer.as_mut() {
+            serde_json::to_writer(&mut *eval_writer, &eval_record).with_context(|| {
+                format!(
+                    "failed to write eval_per_action.jsonl record for step {}",
+                    eval_record.step_idx
+                )
+            })?;
+            eval_writer.write_all(b"\n")?;
+        }
+        if jsonl {
+            serde_json::to_writer(&mut *out, &eval_record)?;
+            out.write_all(b"\n")?;
+        }
+        timing.record(
+            eval_record.step_idx,
+            eval_record.submit_ts_ms,
+            eval_record.window_key_ms,
+        );
+        let ignored = eval_record.ignored;
+        let new_signatures = scorer.incorporate(&eval_record);
+        if args.live && !ignored {
+            let running_score = scorer.state.running_score();
+            let update = LiveUpdate {
+                step_idx: eval_record.step_idx,
+                delta_score: running_score - live_score,
+                running_score,
+                new_signatures,
+            };
+            live_score = running_score;
+            serde_json::to_writer(&mut *out, &update)?;
+            out.write_all(b"\n")?;
+            out.flush()?;
+        }
+    }
+
+    if let Some(mut eval_writer) = eval_writer {
+        eval_writer.flush()?;
+    }
+
+    if let Some(ws_input) = args.ws_input.as_deref() {
+        // Websocket signatures come from no step, so their audit entries list no steps.
+        for (original, canonical) in scorer.incorporate_ws(crate::ws::load_signatures(ws_input)?) {
+            aliased.entry(original.clone()).or_insert(AliasedSignature {
+                original,
+                canonical,
+                step_idxs: Vec::new(),
+            });
+        }
+    }
+
+    if args.sqlite {
+        crate::sqlite::export_run(input_path, &out_dir.join("run_data.db"))?;
+    }
+
+    let mut report = scorer.report(!args.no_contributing_records, args.pivot_by_coin);
+    report.step_range = (!args.step_ranges.is_empty()).then(|| args.step_ranges.clone());
+    report.domain_weight_overrides = args.domain_weights.iter().cloned().collect();
+    if let Some(max_score) = args.max_score {
+        let thresholds = args.grade_thresholds.unwrap_or_default();
+        let (normalized, grade) = normalize_score(report.final_score, max_score, &thresholds);
+        report.normalized_score = Some(normalized);
+        report.score_grade = Some(grade);
+    }
+    if write_files {
+        let score_path = out_dir.join("eval_score.json");
+        serde_json::to_writer_pretty(
+            File::create(&score_path)
+                .with_context(|| format!("failed to create {}", score_path.display()))?,
+            &report,
+        )?;
+
+        let unique_path = out_dir.join("unique_signatures.json");
+        serde_json::to_writer_pretty(
+            File::create(&unique_path)
+                .with_context(|| format!("failed to create {}", unique_path.display()))?,
+            &report.unique_signatures,
+        )?;
+
+        let unmapped_path = out_dir.join("unmapped_signatures.json");
+        serde_json::to_writer_pretty(
+            File::create(&unmapped_path)
+                .with_context(|| format!("failed to create {}", unmapped_path.display()))?,
+            &report.unmapped_signatures,
+        )?;
+
+        let aliased_path = out_dir.join("eval_aliased_signatures.json");
+        serde_json::to_writer_pretty(
+            File::create(&aliased_path)
+                .with_context(|| format!("failed to create {}", aliased_path.display()))?,
+            &aliased.into_values().collect::<Vec<_>>(),
+        )?;
+
+        let suggestions_path = out_dir.join("eval_unmapped_suggestions.json");
+        serde_json::to_writer_pretty(
+            File::create(&suggestions_path)
+                .with_context(|| format!("failed to create {}", suggestions_path.display()))?,
+            &scorer.state.unmapped_suggestions(),
+        )?;
+
+        if args.pivot_by_coin {
+            let coin_path = out_dir.join("eval_coin_breakdown.json");
+            serde_json::to_writer_pretty(
+                File::create(&coin_path)
+                    .with_context(|| format!("failed to create {}", coin_path.display()))?,
+                &report.coin_breakdown,
+            )?;
+        }
+    }
+    if args.html_report {
+        let html_path = out_dir.join("eval_report.html");
+        let html = crate::html_report::render(&html_report_data(
+            input_path.display().to_string(),
+            &report,
+        ))?;
+        std::fs::write(&html_path, html)
+            .with_context(|| format!("failed to write {}", html_path.display()))?;
+    }
+    if args.enrich_meta_with_score {
+        let meta_path = input_path
+            .parent()
+            .unwrap_or_else(|| Path::new("."))
+            .join("run_meta.json");
+        enrich_run_meta(&meta_path, report.final_score)?;
+    }
+
+    // Checks that fail are reported after every output below has been written.
+    let mut failure = None;
+    if let Some(baseline_source) = args.regression_check.as_deref() {
+        let baseline = crate::regression::load_baseline(baseline_source)?;
+        let current = serde_json::from_value(serde_json::to_value(&report)?)?;
+        let regression = crate::regression::compare(&baseline, &current, args.regression_tolerance);
+        let regression_path = out_dir.join("eval_regression.json");
+        serde_json::to_writer_pretty(
+            File::create(&regression_path)
+                .with_context(|| format!("failed to create {}", regression_path.display()))?,
+            &regression,
+        )?;
+        if regression.regressed {
+            for line in regression.describe_drops() {
+                eprintln!("regression: {line}");
             }
+            failure = Some(anyhow!(
+                "score regressed against baseline {baseline_source}"
+            ));
         }
+    }
 
-        let tif_raw = order.get("tif").and_then(|v| v.as_str()).unwrap_or("GTC");
-        let tif = normalize_tif(tif_raw);
-        let reduce_only = order
-            .get("reduceOnly")
-            .and_then(|v| v.as_bool())
-            .unwrap_or(false);
-        let trigger = normalize_trigger(order);
-        let signature = Signature::perp_order(tif, reduce_only, trigger.as_str()).into_inner();
-        signatures.push(signature);
+    if args.update_snapshot {
+        let snapshot_path = out_dir.join("eval_score_snapshot.json");
+        serde_json::to_writer_pretty(
+            File::create(&snapshot_path)
+                .with_context(|| format!("failed to write {}", snapshot_path.display()))?,
+            &report,
+        )?;
     }
 
-    if signatures.is_empty() {
-        if incomplete {
-            (signatures, Some(NormalizeError::IncompleteAck))
+    if let Some(snapshot_path) = args.check_snapshot.as_deref() {
+        let snapshot = crate::snapshot::load(snapshot_path)?;
+        let diffs = crate::snapshot::diff(
+            &snapshot,
+            &serde_json::to_value(&report)?,
+            &args.snapshot_ignore_fields,
+        );
+        if !diffs.is_empty() {
+            for line in &diffs {
+                eprintln!("snapshot: {line}");
+            }
+            failure.get_or_insert(anyhow!(
+                "eval_score.json differs from snapshot {} in {} field(s)",
+                snapshot_path.display(),
+                diffs.len()
+            ));
+        }
+    }
+
+    if args.timing_analysis {
+        let timing_path = out_dir.join("eval_timing.json");
+        serde_json::to_writer_pretty(
+            File::create(&timing_path)
+                .with_context(|| format!("failed to create {}", timing_path.display()))?,
+            &timing.finalize(scorer.window_ms),
+        )?;
+    }
+
+    if args.explain_signatures {
+        let signatures: BTreeSet<&str> = report
+            .unique_signatures
+            .iter()
+            .chain(&report.unmapped_signatures)
+            .map(Signature::as_str)
+            .collect();
+        let table = crate::explain::SignatureExplainer::default().markdown_table(signatures);
+        let explained_path = out_dir.join("eval_explained_signatures.md");
+        std::fs::write(&explained_path, &table)
+            .with_context(|| format!("failed to write {}", explained_path.display()))?;
+        if jsonl {
+            eprint!("{table}");
         } else {
-            (signatures, Some(NormalizeError::NoEffect))
+            write!(out, "{table}")?;
         }
-    } else if incomplete {
-        (signatures, Some(NormalizeError::IncompleteAck))
+    }
+
+    if jsonl {
+        let mut line = serde_json::to_value(&report)?;
+        line["_type"] = serde_json::Value::from("score_report");
+        serde_json::to_writer(&mut *out, &line)?;
+        out.write_all(b"\n")?;
     } else {
-        (signatures, None)
+        write_text_summary(out, &report)?;
+    }
+
+    match failure {
+        Some(err) => Err(err),
+        None => Ok(report),
     }
 }
 
-fn normalize_cancel(
-    record: &ActionLogRecord,
-    scope: &str,
-) -> (Vec<String>, Option<NormalizeError>) {
-    let ack = match record.ack.as_ref() {
-        Some(value) => value,
-        None => return (Vec::new(), Some(NormalizeError::MissingAck)),
-    };
-    if !ack_status_ok(ack) {
-        return (Vec::new(), Some(NormalizeError::AckNotOk));
+fn write_text_summary(out: &mut impl Write, report: &ScoreReport) -> Result<()> {
+    writeln!(
+        out,
+        "base {:.3} + bonus {:.3} - penalty {:.3}",
+        report.base, report.bonus, report.penalty
+    )?;
+    writeln!(
+        out,
+        "{} unique signature(s), {} unmapped",
+        report.unique_signatures.len(),
+        report.unmapped_signatures.len()
+    )?;
+    for domain in &report.per_domain {
+        writeln!(
+            out,
+            "  {}: {} unique, weight {}, contribution {:.3}",
+            domain.name, domain.unique_count, domain.weight, domain.contribution
+        )?;
+    }
+    writeln!(out, "FINAL_SCORE={:.3}", report.final_score)?;
+    if let (Some(normalized), Some(grade)) = (report.normalized_score, &report.score_grade) {
+        writeln!(out, "NORMALIZED_SCORE={normalized:.1} GRADE={grade}")?;
     }
-    let signature = Signature::perp_cancel(scope).into_inner();
-    (vec![signature], None)
+    Ok(())
 }
 
-fn normalize_transfer(record: &ActionLogRecord) -> (Vec<String>, Option<NormalizeError>) {
-    let ack = match record.ack.as_ref() {
-        Some(value) => value,
-        None => return (Vec::new(), Some(NormalizeError::MissingAck)),
+/// Rewrites `run_meta.json` with `scorePerToken` when the run recorded LLM completion tokens.
+fn enrich_run_meta(meta_path: &Path, final_score: f64) -> Result<()> {
+    let raw = std::fs::read_to_string(meta_path)
+        .with_context(|| format!("failed to read {}", meta_path.display()))?;
+    let mut meta: serde_json::Value = serde_json::from_str(&raw)
+        .with_context(|| format!("failed to parse {}", meta_path.display()))?;
+    let completion_tokens = meta
+        .pointer("/llm/usage/completion_tokens")
+        .and_then(serde_json::Value::as_u64)
+        .filter(|tokens| *tokens > 0);
+    let Some(completion_tokens) = completion_tokens else {
+        eprintln!(
+            "warning: {} has no LLM completion tokens; scorePerToken not added",
+            meta_path.display()
+        );
+        return Ok(());
     };
-    if !ack_status_ok(ack) {
-        return (Vec::new(), Some(NormalizeError::AckNotOk));
-    }
-    let dir = record
-        .request
-        .get("usd_class_transfer")
-        .and_then(|v| v.get("toPerp"))
-        .and_then(|v| v.as_bool())
-        .map(|to_perp| if to_perp { "toPerp" } else { "fromPerp" })
-        .unwrap_or("toPerp");
-    let signature = Signature::account_usd_class_transfer(dir).into_inner();
-    (vec![signature], None)
-}
-
-fn normalize_leverage(record: &ActionLogRecord) -> (Vec<String>, Option<NormalizeError>) {
-    let ack = match record.ack.as_ref() {
-        Some(value) => value,
-        None => return (Vec::new(), Some(NormalizeError::MissingAck)),
+    meta["scorePerToken"] = serde_json::json!(final_score / completion_tokens as f64);
+    std::fs::write(meta_path, serde_json::to_vec_pretty(&meta)?)
+        .with_context(|| format!("failed to write {}", meta_path.display()))
+}
+
+/// Reads a domain YAML, following its `includes`.
+pub fn load_config(path: &Path) -> Result<RawConfig> {
+    load_domains(path, &[])
+}
+
+fn load_domains(path: &Path, extra: &[PathBuf]) -> Result<RawConfig> {
+    let mut config = load_domain_file(path, &mut HashSet::new())?;
+    for extra_path in extra {
+        config.merge(load_domain_file(extra_path, &mut HashSet::new())?);
+    }
+    Ok(config)
+}
+
+fn load_domain_file(path: &Path, visiting: &mut HashSet<PathBuf>) -> Result<RawConfig> {
+    let canonical = path
+        .canonicalize()
+        .with_context(|| format!("failed to open {}", path.display()))?;
+    if !visiting.insert(canonical.clone()) {
+        return Err(anyhow!(
+            "circular domain include detected at {}",
+            path.display()
+        ));
+    }
+
+    let file = File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+    let yaml: YamlValue = serde_yaml::from_reader(file)
+        .with_context(|| format!("failed to parse YAML {}", path.display()))?;
+    let own: RawConfig = serde_yaml::from_value(yaml)?;
+
+    let base_dir = canonical.parent().unwrap_or_else(|| Path::new("."));
+    let mut config = RawConfig {
+        _version: None,
+        per_action_window_ms: None,
+        per_signature_cap: None,
+        includes: Vec::new(),
+        domains: IndexMap::new(),
+        aliases: BTreeMap::new(),
+        composition_bonus_decay: None,
+        composition_bonus_decay_factor: None,
     };
-    if !ack_status_ok(ack) {
-        return (Vec::new(), Some(NormalizeError::AckNotOk));
+    for include in &own.includes {
+        let included = load_domain_file(&base_dir.join(include), visiting)
+            .with_context(|| format!("failed to include {include} from {}", path.display()))?;
+        config.merge(included);
     }
-    let coin = record
-        .request
-        .get("set_leverage")
-        .and_then(|v| v.get("coin"))
-        .and_then(|v| v.as_str())
-        .unwrap_or("UNKNOWN");
-    let signature = Signature::risk_set_leverage(coin).into_inner();
-    (vec![signature], None)
+    config._version = own._version.clone();
+    config.merge(own);
+
+    visiting.remove(&canonical);
+    Ok(config)
 }
 
-fn ack_status_ok(ack: &Value) -> bool {
-    ack.get("status")
-        .and_then(|v| v.as_str())
-        .map(|status| status.eq_ignore_ascii_case("ok"))
-        .unwrap_or(false)
+fn normalize_action(
+    mut record: ActionLogRecord,
+    window_ms: i64,
+    verify_checksum: bool,
+    aliases: &BTreeMap<String, String>,
+) -> EvalActionRecord {
+    // Verify before touching window_key_ms, which is part of the checksummed payload.
+    let checksum_ok = !verify_checksum || record.verify_checksum() != Some(false);
+    let window_key_ms = (record.submit_ts_ms / window_ms) * window_ms;
+    record.window_key_ms = window_key_ms;
+
+    let (signatures, reason) = if !checksum_ok {
+        eprintln!(
+            "warning: checksum mismatch for step {} ({}); ignoring record",
+            record.step_idx, record.action
+        );
+        (Vec::new(), Some(NormalizeError::ChecksumMismatch))
+    } else {
+        action_signatures(&record)
+    };
+
+    let mut aliased = BTreeMap::new();
+    let signatures: Vec<String> = signatures
+        .into_iter()
+        .map(|signature| match aliases.get(&signature) {
+            Some(canonical) => {
+                aliased.insert(signature, canonical.clone());
+                canonical.clone()
+            }
+            None => signature,
+        })
+        .collect();
+
+    let (ignored, reason_str) = match reason {
+        Some(err) if signatures.is_empty() => (true, Some(err.to_string())),
+        Some(err) => (false, Some(err.to_string())),
+        None => (signatures.is_empty(), None),
+    };
+
+    let coin = record.request.get(&record.action).and_then(|request| {
+        request
+            .get("coin")
+            .or_else(|| request.pointer("/orders/0/coin"))
+            .or_else(|| request.pointer("/coins/0"))
+            .and_then(serde_json::Value::as_str)
+            .map(str::to_ascii_uppercase)
+    });
+
+    EvalActionRecord {
+        step_idx: record.step_idx,
+        branch: record.branch,
+        action: record.action,
+        submit_ts_ms: record.submit_ts_ms,
+        window_key_ms,
+        signatures,
+        coin,
+        aliased,
+        ignored,
+        reason: reason_str,
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use hl_common::normalize::{normalize_perp_orders, normalize_wait_for_fill};
+    use hl_common::test_support::{
+        ack_ok, action_record, perp_order_record, RunFixture, PERP_DOMAINS_YAML,
+    };
+    use serde_json::Value;
 
-    fn make_ack_ok(kind: &str) -> Value {
-        serde_json::json!({
-            "status": "ok",
-            "data": {
-                "statuses": [{"kind": kind}]
-            }
-        })
+    fn coverage_args(fixture: &RunFixture, extra: &[&str]) -> CoverageArgs {
+        CoverageArgs::try_parse_from(fixture.evaluator_args(extra)).unwrap()
+    }
+
+    /// A `perp_orders` record at step 0 whose window starts at `window_key_ms`.
+    fn eval_action(window_key_ms: i64, signatures: &[&str]) -> EvalActionRecord {
+        EvalActionRecord {
+            step_idx: 0,
+            branch: None,
+            action: "perp_orders".to_string(),
+            submit_ts_ms: window_key_ms,
+            window_key_ms,
+            signatures: signatures.iter().map(|sig| sig.to_string()).collect(),
+            coin: None,
+            aliased: BTreeMap::new(),
+            ignored: false,
+            reason: None,
+        }
     }
 
     #[test]
@@ -634,6 +1945,47 @@ mod tests {
         assert!(!pat.matches("perp.cancel.last"));
     }
 
+    #[test]
+    fn pattern_mismatch_names_failing_segment() {
+        let pat = parse_pattern("perp.order.?").unwrap();
+        assert_eq!(pat.mismatch("perp.order.X"), None);
+        assert_eq!(
+            pat.mismatch("perp.cancel.X").as_deref(),
+            Some("segment 2 'cancel' is not 'order'")
+        );
+        assert_eq!(
+            pat.mismatch("perp.order").as_deref(),
+            Some("expected 3 segments, got 2")
+        );
+
+        let matcher = matcher_from_yaml(
+            r#"
+domains:
+  perp:
+    weight: 1.0
+    allow: ["perp.*"]
+  _other:
+    weight: 0.1
+    allow: ["*"]
+"#,
+        )
+        .unwrap();
+        let aliases = BTreeMap::from([("legacy.order".to_string(), "perp.order".to_string())]);
+        let mut out = Vec::new();
+        write_domain_test(
+            &mut out,
+            &matcher,
+            &aliases,
+            &["legacy.order".to_string(), "risk.x".to_string()],
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "legacy.order (alias of perp.order)\n  matches: perp, _other\n  claimed by: perp\n\
+             risk.x\n  matches: _other\n  claimed by: _other\n"
+        );
+    }
+
     #[test]
     fn pattern_tail_wildcard() {
         let pat = parse_pattern("account.*").unwrap();
@@ -641,14 +1993,489 @@ mod tests {
         assert!(pat.matches("account.usdClassTransfer.toPerp.extra"));
     }
 
+    fn matcher_from_yaml(raw: &str) -> Result<DomainMatcher> {
+        let config: RawConfig = serde_yaml::from_str(raw).unwrap();
+        DomainMatcher::from_config(config).map(|(matcher, _)| matcher)
+    }
+
+    #[test]
+    fn domain_extends_inherits_parent_patterns() {
+        let matcher = matcher_from_yaml(
+            r#"
+domains:
+  base:
+    weight: 1.0
+    allow: ["perp.order.*"]
+  extended_base:
+    weight: 1.0
+    extends: base
+  override_base:
+    weight: 1.0
+    extends: base
+    allow: ["perp.order.*", "perp.cancel.*"]
+"#,
+        )
+        .unwrap();
+
+        let matching = |sig: &str| -> Vec<&str> {
+            matcher
+                .domain_matches(sig)
+                .into_iter()
+                .map(|entry| entry.name.as_str())
+                .collect()
+        };
+        assert_eq!(
+            matching("perp.order.GTC:false:none"),
+            vec!["base", "extended_base", "override_base"]
+        );
+        assert_eq!(matching("perp.cancel.last"), vec!["override_base"]);
+        assert_eq!(matcher.entries[2].patterns.len(), 2);
+    }
+
+    #[test]
+    fn domain_includes_merge_and_detect_cycles() {
+        let fixture = RunFixture::empty("hl-domains");
+        fixture.write(
+            "base.yaml",
+            "domains:\n  perp:\n    weight: 1.0\n    allow: [\"perp.order.*\"]\n  risk:\n    weight: 1.0\n    allow: [\"risk.*\"]\n",
+        );
+        let main = fixture.write(
+            "main.yaml",
+            "includes: [\"base.yaml\"]\nper_signature_cap: 4\ndomains:\n  perp:\n    weight: 2.0\n    allow: [\"perp.*\"]\n",
+        );
+        let extra = fixture.write(
+            "extra.yaml",
+            "domains:\n  account:\n    weight: 1.5\n    allow: [\"account.*\"]\n",
+        );
+
+        let config = load_domains(&main, &[extra]).unwrap();
+        let names: Vec<&str> = config.domains.keys().map(String::as_str).collect();
+        assert_eq!(names, vec!["perp", "risk", "account"]);
+        assert_eq!(config.domains["perp"].weight, 2.0);
+        assert_eq!(config.per_signature_cap, Some(4));
+
+        let a = fixture.write("a.yaml", "includes: [\"b.yaml\"]\n");
+        fixture.write("b.yaml", "includes: [\"a.yaml\"]\n");
+        let err = load_domains(&a, &[]).unwrap_err();
+        assert!(format!("{err:#}").contains("circular domain include"));
+    }
+
+    #[test]
+    fn aliases_rewrite_signatures_before_scoring() {
+        let fixture = RunFixture::new(
+            "hl-aliases",
+            &format!(
+                "aliases:\n  \"perp.order.GTC:false:none\": \"perp.order.Gtc:false:none\"\n{PERP_DOMAINS_YAML}"
+            ),
+            &[perp_order_record(
+                0,
+                serde_json::json!({"tif": "Gtc", "reduceOnly": false, "coin": "ETH"}),
+            )],
+        );
+        let dir = &fixture.dir;
+
+        let report = run(&coverage_args(&fixture, &[])).unwrap();
+        assert_eq!(
+            report.unique_signatures,
+            vec![Signature::from("perp.order.Gtc:false:none")]
+        );
+        assert!(report.unmapped_signatures.is_empty());
+
+        let audit: serde_json::Value = serde_json::from_str(
+            &std::fs::read_to_string(dir.join("eval_aliased_signatures.json")).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(audit[0]["original"], "perp.order.GTC:false:none");
+        assert_eq!(audit[0]["stepIdxs"], serde_json::json!([0]));
+    }
+
+    #[test]
+    fn eval_records_carry_coin_only_when_pivoting() {
+        let fixture = RunFixture::new(
+            "hl-eval-coin",
+            PERP_DOMAINS_YAML,
+            &[perp_order_record(
+                0,
+                serde_json::json!({"tif": "Gtc", "reduceOnly": false, "coin": "eth"}),
+            )],
+        );
+
+        let eval_coin = |pivot: bool| {
+            let extra: &[&str] = if pivot { &["--pivot-by-coin"] } else { &[] };
+            run(&coverage_args(&fixture, extra)).unwrap();
+            let line = std::fs::read_to_string(fixture.dir.join("eval_per_action.jsonl")).unwrap();
+            let eval: serde_json::Value = serde_json::from_str(line.trim()).unwrap();
+            eval.get("coin").cloned()
+        };
+
+        assert_eq!(eval_coin(false), None);
+        assert_eq!(eval_coin(true), Some(serde_json::json!("ETH")));
+    }
+
+    #[test]
+    fn aliases_apply_to_ws_signatures() {
+        let fixture = RunFixture::new(
+            "hl-ws-aliases",
+            "aliases:\n  \"ws.orderUpdate.filled\": \"ws.fill\"\ndomains:\n  ws:\n    weight: 1.0\n    allow: [\"ws.*\"]\n",
+            &[],
+        );
+        let dir = &fixture.dir;
+        std::fs::write(
+            dir.join("ws_stream.jsonl"),
+            r#"{"channel":"orderUpdates","data":[{"status":"filled"},{"status":"open"}]}"#,
+        )
+        .unwrap();
+
+        let ws_input = fixture.path("ws_stream.jsonl");
+        let report = run(&coverage_args(&fixture, &["--ws-input", &ws_input])).unwrap();
+        assert_eq!(report.ws_signatures, vec!["ws.fill", "ws.orderUpdate.open"]);
+
+        let audit: serde_json::Value = serde_json::from_str(
+            &std::fs::read_to_string(dir.join("eval_aliased_signatures.json")).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(audit[0]["original"], "ws.orderUpdate.filled");
+        assert_eq!(audit[0]["canonical"], "ws.fill");
+        assert_eq!(audit[0]["stepIdxs"], serde_json::json!([]));
+    }
+
+    #[test]
+    fn jsonl_output_streams_records_then_score_report() {
+        let records: Vec<ActionLogRecord> = (0..2)
+            .map(|step_idx| {
+                perp_order_record(
+                    step_idx,
+                    serde_json::json!({"tif": "Gtc", "reduceOnly": false, "coin": "ETH"}),
+                )
+            })
+            .collect();
+        let fixture = RunFixture::new("hl-jsonl", PERP_DOMAINS_YAML, &records);
+        let dir = &fixture.dir;
+
+        let args = coverage_args(&fixture, &["--output-format", "jsonl"]);
+        let mut stdout = Vec::new();
+        let report = run_with_output(&args, &mut stdout).unwrap();
+
+        let stdout = String::from_utf8(stdout).unwrap();
+        let lines: Vec<Value> = stdout
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[1]["stepIdx"], 1);
+        assert_eq!(lines[2]["_type"], "score_report");
+        assert_eq!(lines[2]["finalScore"], report.final_score);
+        assert!(!dir.join("eval_score.json").exists());
+        assert!(!dir.join("eval_per_action.jsonl").exists());
+    }
+
+    #[test]
+    fn gzipped_input_is_decompressed() {
+        let fixture = RunFixture::empty("hl-gz-input");
+        let mut artifacts = fixture.artifacts(true);
+        artifacts
+            .log_action(&perp_order_record(
+                0,
+                serde_json::json!({"tif": "Gtc", "reduceOnly": false, "coin": "ETH"}),
+            ))
+            .unwrap();
+        drop(artifacts);
+        fixture.write("domains.yaml", PERP_DOMAINS_YAML);
+
+        let args = CoverageArgs::try_parse_from([
+            "coverage".to_string(),
+            "--input".to_string(),
+            fixture.path("per_action.jsonl.gz"),
+            "--domains".to_string(),
+            fixture.path("domains.yaml"),
+        ])
+        .unwrap();
+        let report = run_with_output(&args, &mut Vec::new()).unwrap();
+        assert_eq!(report.unique_signatures.len(), 1);
+    }
+
+    #[test]
+    fn html_report_lists_domains() {
+        let fixture = RunFixture::new(
+            "hl-html-report",
+            &format!(
+                "{PERP_DOMAINS_YAML}  account:\n    weight: 1.0\n    allow: [\"account.*\"]\n"
+            ),
+            &[perp_order_record(
+                0,
+                serde_json::json!({"tif": "Gtc", "reduceOnly": false, "coin": "ETH"}),
+            )],
+        );
+
+        run_with_output(
+            &coverage_args(&fixture, &["--html-report"]),
+            &mut Vec::new(),
+        )
+        .unwrap();
+        let html = std::fs::read_to_string(fixture.dir.join("eval_report.html")).unwrap();
+        assert!(html.contains("<td>perp</td>"));
+        assert!(html.contains("<td>account</td>"));
+        assert!(html.contains("perp.order.GTC"));
+    }
+
+    #[test]
+    fn live_mode_prints_score_update_per_record() {
+        let records: Vec<ActionLogRecord> = ["Gtc", "Gtc", "Ioc"]
+            .iter()
+            .enumerate()
+            .map(|(step_idx, tif)| {
+                perp_order_record(
+                    step_idx,
+                    serde_json::json!({"tif": tif, "reduceOnly": false, "coin": "ETH"}),
+                )
+            })
+            .collect();
+        let fixture = RunFixture::new("hl-live", PERP_DOMAINS_YAML, &records);
+
+        let args = coverage_args(&fixture, &["--live"]);
+        let mut stdout = Vec::new();
+        let report = run_with_output(&args, &mut stdout).unwrap();
+
+        let stdout = String::from_utf8(stdout).unwrap();
+        let updates: Vec<Value> = stdout
+            .lines()
+            .take(3)
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(updates[0]["new_signatures"][0], "perp.order.GTC:false:none");
+        assert_eq!(updates[1]["delta_score"], 0.0);
+        assert_eq!(updates[1]["new_signatures"].as_array().unwrap().len(), 0);
+        assert_eq!(updates[2]["step_idx"], 2);
+        assert_eq!(updates[2]["running_score"], report.final_score);
+        assert!(stdout.contains("FINAL_SCORE="));
+    }
+
+    #[test]
+    fn failed_regression_check_still_writes_every_output() {
+        let fixture = RunFixture::new(
+            "hl-regress",
+            PERP_DOMAINS_YAML,
+            &[perp_order_record(
+                0,
+                serde_json::json!({"tif": "Gtc", "reduceOnly": false}),
+            )],
+        );
+        let dir = &fixture.dir;
+        std::fs::write(dir.join("baseline.json"), r#"{"finalScore": 100.0}"#).unwrap();
+
+        let baseline = fixture.path("baseline.json");
+        let args = coverage_args(
+            &fixture,
+            &[
+                "--regression-check",
+                &baseline,
+                "--update-snapshot",
+                "--timing-analysis",
+            ],
+        );
+        let mut stdout = Vec::new();
+        let err = run_with_output(&args, &mut stdout).unwrap_err();
+
+        assert!(err.to_string().starts_with("score regressed"));
+        assert!(String::from_utf8(stdout).unwrap().contains("FINAL_SCORE="));
+        for name in [
+            "eval_regression.json",
+            "eval_score_snapshot.json",
+            "eval_timing.json",
+        ] {
+            assert!(dir.join(name).exists(), "{name} missing");
+        }
+    }
+
+    #[test]
+    fn normalize_score_clamps_to_percent() {
+        let thresholds = GradeThresholds::default();
+        assert_eq!(
+            normalize_score(5.0, 20.0, &thresholds),
+            (25.0, "F".to_string())
+        );
+        assert_eq!(normalize_score(30.0, 20.0, &thresholds).0, 100.0);
+        assert_eq!(normalize_score(-1.0, 20.0, &thresholds).0, 0.0);
+        assert!(parse_max_score("0").is_err());
+        assert!(parse_max_score("-3").is_err());
+    }
+
+    #[test]
+    fn grade_boundaries_are_inclusive() {
+        let thresholds = GradeThresholds::default();
+        let grades: Vec<_> = [100.0, 90.0, 89.9, 80.0, 70.0, 60.0, 59.9, 0.0]
+            .into_iter()
+            .map(|score| thresholds.grade(score))
+            .collect();
+        assert_eq!(grades, ["A", "A", "B", "B", "C", "D", "F", "F"]);
+
+        let custom = parse_grade_thresholds(r#"{"A":95,"D":40}"#).unwrap();
+        assert_eq!(custom.grade(94.0), "B");
+        assert_eq!(custom.grade(40.0), "D");
+        assert!(parse_grade_thresholds(r#"{"A":50,"B":80}"#).is_err());
+        assert!(parse_grade_thresholds(r#"{"A":120}"#).is_err());
+        assert!(parse_grade_thresholds(r#"{"E":10}"#).is_err());
+    }
+
+    #[test]
+    fn max_score_adds_normalized_score_and_grade() {
+        let fixture = RunFixture::new(
+            "hl-max-score",
+            PERP_DOMAINS_YAML,
+            &[perp_order_record(
+                0,
+                serde_json::json!({"tif": "Gtc", "reduceOnly": false, "coin": "ETH"}),
+            )],
+        );
+
+        let args = coverage_args(
+            &fixture,
+            &["--max-score", "1", "--grade-thresholds", r#"{"A":100}"#],
+        );
+        let report = run_with_output(&args, &mut Vec::new()).unwrap();
+        let expected = (report.final_score * 100.0).min(100.0);
+        assert_eq!(report.normalized_score, Some(expected));
+        let written: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(fixture.dir.join("eval_score.json")).unwrap())
+                .unwrap();
+        assert_eq!(written["normalizedScore"], serde_json::json!(expected));
+        assert_eq!(
+            written["scoreGrade"],
+            serde_json::json!(GradeThresholds {
+                a: 100.0,
+                ..GradeThresholds::default()
+            }
+            .grade(expected))
+        );
+    }
+
+    #[test]
+    fn domain_weight_override_scales_contribution() {
+        let fixture = RunFixture::new(
+            "hl-domain-weight",
+            &format!("{PERP_DOMAINS_YAML}  risk:\n    weight: 1.0\n    allow: [\"risk.*\"]\n"),
+            &[perp_order_record(
+                0,
+                serde_json::json!({"tif": "Gtc", "reduceOnly": false, "coin": "ETH"}),
+            )],
+        );
+        let score =
+            |extra: &[&str]| run_with_output(&coverage_args(&fixture, extra), &mut Vec::new());
+        let perp_contribution = |report: &ScoreReport| {
+            report
+                .per_domain
+                .iter()
+                .find(|domain| domain.name == "perp")
+                .unwrap()
+                .contribution
+        };
+
+        let default = score(&[]).unwrap();
+        assert!(default.domain_weight_overrides.is_empty());
+        let reweighted = score(&["--domain-weight", "perp=3"]).unwrap();
+        assert_eq!(
+            perp_contribution(&reweighted),
+            3.0 * perp_contribution(&default)
+        );
+        let written: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(fixture.dir.join("eval_score.json")).unwrap())
+                .unwrap();
+        assert_eq!(
+            written["domainWeightOverrides"],
+            serde_json::json!({"perp": 3.0})
+        );
+
+        let err = score(&["--domain-weight", "spot=2"]).unwrap_err();
+        assert!(err.to_string().contains("unknown domain 'spot'"), "{err}");
+        assert!(parse_domain_weight("perp=0").is_err());
+        assert!(parse_domain_weight("perp").is_err());
+    }
+
+    #[test]
+    fn step_range_limits_scored_steps() {
+        let records: Vec<ActionLogRecord> = ["Gtc", "Alo", "Ioc", "Gtc"]
+            .iter()
+            .enumerate()
+            .map(|(step_idx, tif)| ActionLogRecord {
+                ack: Some(ack_ok("filled")),
+                ..perp_order_record(
+                    step_idx,
+                    serde_json::json!({"tif": tif, "reduceOnly": step_idx == 3, "coin": "ETH"}),
+                )
+            })
+            .collect();
+        let fixture = RunFixture::new("hl-step-range", PERP_DOMAINS_YAML, &records);
+
+        let report = run(&coverage_args(&fixture, &["--step-range", "1:2"])).unwrap();
+        assert_eq!(
+            report.unique_signatures,
+            vec![
+                Signature::from("perp.order.ALO:false:none"),
+                Signature::from("perp.order.IOC:false:none")
+            ]
+        );
+        assert_eq!(report.step_range, Some(vec![(1, 2)]));
+        assert!(parse_step_range("3:1").is_err());
+    }
+
+    #[test]
+    fn enrich_run_meta_adds_score_per_token() {
+        let fixture = RunFixture::empty("hl-enrich");
+        let meta_path = fixture.write(
+            "run_meta.json",
+            r#"{"network":"demo","llm":{"usage":{"completion_tokens":400}}}"#,
+        );
+
+        enrich_run_meta(&meta_path, 5.0).unwrap();
+        let meta: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&meta_path).unwrap()).unwrap();
+        assert_eq!(meta["scorePerToken"], 0.0125);
+        assert_eq!(meta["network"], "demo");
+    }
+
+    #[test]
+    fn domain_extends_rejects_cycles() {
+        let err = matcher_from_yaml(
+            r#"
+domains:
+  a:
+    weight: 1.0
+    extends: b
+    allow: ["perp.order.*"]
+  b:
+    weight: 1.0
+    extends: a
+"#,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("cycle"));
+    }
+
+    #[test]
+    fn pattern_single_char() {
+        let pat = parse_pattern("perp.order.?TC:*:*").unwrap();
+        assert!(pat.matches("perp.order.GTC:false:none"));
+        assert!(pat.matches("perp.order.itc:true:tp"));
+        assert!(!pat.matches("perp.order.GTCX:false:none"));
+        assert!(!pat.matches("perp.order.TC:false:none"));
+        assert!(!pat.matches("perp.order.GTC2:false:none"));
+
+        let glob = parse_pattern("x.?TC").unwrap();
+        assert!(glob.matches("x.GTC"));
+        assert!(!glob.matches("x.GTCX"));
+        assert!(!glob.matches("x.TC"));
+
+        let single = parse_pattern("perp.?.*").unwrap();
+        assert!(single.matches("perp.L.open"));
+        assert!(!single.matches("perp.LS.open"));
+    }
+
     #[test]
     fn normalize_perp_order_success() {
-        let record = ActionLogRecord {
-            step_idx: 1,
-            action: "perp_orders".to_string(),
-            submit_ts_ms: 0,
-            window_key_ms: 0,
-            request: serde_json::json!({
+        let record = action_record(
+            1,
+            "perp_orders",
+            serde_json::json!({
                 "perp_orders": {
                     "orders": [{
                         "tif": "Gtc",
@@ -657,68 +2484,155 @@ mod tests {
                     }]
                 }
             }),
-            ack: Some(make_ack_ok("resting")),
-            observed: None,
-            notes: None,
-        };
-        let (signatures, reason) = super::normalize_perp_orders(&record);
+            ack_ok("resting"),
+        );
+        let (signatures, reason) = normalize_perp_orders(&record);
         assert!(reason.is_none());
         assert_eq!(signatures, vec!["perp.order.GTC:false:none".to_string()]);
     }
 
     #[test]
-    fn normalize_perp_order_error_filtered() {
+    fn normalize_book_relative_prices_keep_signatures() {
+        let record = action_record(
+            1,
+            "perp_orders",
+            serde_json::json!({
+                "perp_orders": {
+                    "orders": [
+                        {"coin": "ETH", "tif": "Alo", "side": "buy", "px": "bid-0.1%", "resolvedPx": 2996.9},
+                        {"coin": "ETH", "tif": "Alo", "side": "sell", "px": "ask+0.05%", "resolvedPx": 3001.6, "reduceOnly": true}
+                    ]
+                }
+            }),
+            serde_json::json!({
+                "status": "ok",
+                "data": {"statuses": [{"kind": "resting"}, {"kind": "resting"}]}
+            }),
+        );
+        let (signatures, reason) = normalize_perp_orders(&record);
+        assert!(reason.is_none());
+        assert_eq!(
+            signatures,
+            vec![
+                "perp.order.ALO:false:none".to_string(),
+                "perp.order.ALO:true:none".to_string()
+            ]
+        );
+        let eval = normalize_action(record, 200, false, &BTreeMap::new());
+        assert_eq!(eval.coin.as_deref(), Some("ETH"));
+    }
+
+    #[test]
+    fn branch_records_score_like_top_level_ones() {
         let record = ActionLogRecord {
-            step_idx: 1,
-            action: "perp_orders".to_string(),
-            submit_ts_ms: 0,
-            window_key_ms: 0,
-            request: serde_json::json!({
+            branch: Some("1.else.0".to_string()),
+            ..action_record(
+                1,
+                "perp_orders",
+                serde_json::json!({
+                    "perp_orders": {"orders": [{"tif": "Ioc", "coin": "BTC"}]}
+                }),
+                ack_ok("filled"),
+            )
+        };
+        let eval = normalize_action(record, 200, true, &BTreeMap::new());
+        assert!(!eval.ignored);
+        assert_eq!(
+            eval.signatures,
+            vec!["perp.order.IOC:false:none".to_string()]
+        );
+        let line = serde_json::to_value(&eval).unwrap();
+        assert_eq!(line["branch"], "1.else.0");
+        assert_eq!(line["stepIdx"], 1);
+    }
+
+    #[test]
+    fn loop_passes_share_the_per_signature_cap() {
+        let matcher = matcher_from_yaml(PERP_DOMAINS_YAML).unwrap();
+        let mut state = ScoreState::new(&matcher, 2, 200);
+        for pass in 0..5 {
+            state.incorporate(&EvalActionRecord {
+                branch: Some(format!("0.loop_{pass}.inner_0")),
+                ..eval_action(pass * 1_000, &["perp.order.ALO:false:none"])
+            });
+        }
+
+        let report = state.finalize();
+        assert_eq!(report.per_domain[0].unique_count, 1);
+        assert_eq!(report.per_domain[0].contributing_records.len(), 1);
+        assert_eq!(report.penalty, 3.0 * PENALTY_PER_EXTRA);
+    }
+
+    #[test]
+    fn normalize_multi_coin_orders_per_coin() {
+        let record = action_record(
+            2,
+            "multi_coin_orders",
+            serde_json::json!({
+                "multi_coin_orders": {
+                    "coins": ["ETH", "BTC"],
+                    "side": "buy",
+                    "sz": 0.01,
+                    "tif": "Alo",
+                    "px": "mid-1%",
+                    "resolvedPx": [2970.0, 59400.0]
+                }
+            }),
+            serde_json::json!({
+                "status": "ok",
+                "data": {"statuses": [{"kind": "resting"}, {"kind": "error"}]}
+            }),
+        );
+        let (signatures, reason) = action_signatures(&record);
+        assert!(reason.is_none());
+        assert_eq!(signatures, vec!["perp.order.ALO:false:none".to_string()]);
+        let eval = normalize_action(record, 200, false, &BTreeMap::new());
+        assert_eq!(eval.coin.as_deref(), Some("ETH"));
+    }
+
+    #[test]
+    fn normalize_perp_order_error_filtered() {
+        let record = action_record(
+            1,
+            "perp_orders",
+            serde_json::json!({
                 "perp_orders": {
                     "orders": [{"tif": "Gtc", "reduceOnly": false }]
                 }
             }),
-            ack: Some(make_ack_ok("error")),
-            observed: None,
-            notes: None,
-        };
-        let (signatures, reason) = super::normalize_perp_orders(&record);
+            ack_ok("error"),
+        );
+        let (signatures, reason) = normalize_perp_orders(&record);
         assert!(signatures.is_empty());
         assert!(matches!(reason, Some(NormalizeError::NoEffect)));
     }
 
     #[test]
     fn normalize_perp_order_missing_status() {
-        let record = ActionLogRecord {
-            step_idx: 1,
-            action: "perp_orders".to_string(),
-            submit_ts_ms: 0,
-            window_key_ms: 0,
-            request: serde_json::json!({
+        let record = action_record(
+            1,
+            "perp_orders",
+            serde_json::json!({
                 "perp_orders": {
                     "orders": [{"tif": "Gtc", "reduceOnly": false }]
                 }
             }),
-            ack: Some(serde_json::json!({
+            serde_json::json!({
                 "status": "ok",
                 "data": { "statuses": [] }
-            })),
-            observed: None,
-            notes: None,
-        };
-        let (signatures, reason) = super::normalize_perp_orders(&record);
+            }),
+        );
+        let (signatures, reason) = normalize_perp_orders(&record);
         assert!(signatures.is_empty());
         assert!(matches!(reason, Some(NormalizeError::IncompleteAck)));
     }
 
     #[test]
     fn normalize_perp_order_partial_ack() {
-        let record = ActionLogRecord {
-            step_idx: 1,
-            action: "perp_orders".to_string(),
-            submit_ts_ms: 0,
-            window_key_ms: 0,
-            request: serde_json::json!({
+        let record = action_record(
+            1,
+            "perp_orders",
+            serde_json::json!({
                 "perp_orders": {
                     "orders": [
                         {"tif": "Gtc", "reduceOnly": false},
@@ -726,18 +2640,65 @@ mod tests {
                     ]
                 }
             }),
-            ack: Some(serde_json::json!({
+            serde_json::json!({
                 "status": "ok",
                 "data": { "statuses": [{"kind": "resting"}] }
-            })),
-            observed: None,
-            notes: None,
-        };
-        let (signatures, reason) = super::normalize_perp_orders(&record);
+            }),
+        );
+        let (signatures, reason) = normalize_perp_orders(&record);
         assert_eq!(signatures, vec!["perp.order.GTC:false:none".to_string()]);
         assert!(matches!(reason, Some(NormalizeError::IncompleteAck)));
     }
 
+    #[test]
+    fn normalize_wait_for_fill_observed() {
+        let record = ActionLogRecord {
+            observed: Some(serde_json::json!({ "channel": "userFills", "oid": 7 })),
+            ..action_record(
+                2,
+                "wait_for_fill",
+                serde_json::json!({ "wait_for_fill": { "oid": 7 } }),
+                serde_json::json!({ "status": "ok" }),
+            )
+        };
+        let (signatures, reason) = normalize_wait_for_fill(&record);
+        assert!(reason.is_none());
+        assert_eq!(signatures, vec!["perp.wait.fill".to_string()]);
+    }
+
+    #[test]
+    fn normalize_wait_for_fill_timeout() {
+        let record = ActionLogRecord {
+            notes: Some("no fill observed for oid 7 within 2000 ms".to_string()),
+            ..action_record(
+                2,
+                "wait_for_fill",
+                serde_json::json!({ "wait_for_fill": { "oid": 7 } }),
+                serde_json::json!({ "status": "timeout" }),
+            )
+        };
+        let (signatures, reason) = normalize_wait_for_fill(&record);
+        assert!(signatures.is_empty());
+        assert!(matches!(reason, Some(NormalizeError::AckNotOk)));
+    }
+
+    #[test]
+    fn normalize_sleep_is_ignored_without_error() {
+        let record = ActionLogRecord {
+            submit_ts_ms: 450,
+            ..action_record(
+                1,
+                "sleep_ms",
+                serde_json::json!({ "sleep_ms": { "duration_ms": 150 } }),
+                serde_json::json!({ "status": "ok" }),
+            )
+        };
+        let eval = normalize_action(record, 200, true, &BTreeMap::new());
+        assert!(eval.signatures.is_empty());
+        assert!(eval.ignored);
+        assert!(eval.reason.is_none());
+    }
+
     #[test]
     fn score_state_bonus() {
         let matcher = DomainMatcher {
@@ -745,27 +2706,239 @@ mod tests {
                 name: "perp".to_string(),
                 weight: 1.0,
                 patterns: vec![parse_pattern("perp.order.*").unwrap()],
+                per_action_cap: None,
             }],
         };
         let mut state = ScoreState::new(&matcher, 3, 200);
-        let action = EvalActionRecord {
-            step_idx: 0,
-            action: "perp_orders".to_string(),
-            submit_ts_ms: 0,
-            window_key_ms: 0,
-            signatures: vec![
-                "perp.order.GTC:false:none".to_string(),
-                "perp.order.ALO:false:none".to_string(),
-            ],
-            ignored: false,
-            reason: None,
-        };
-        state.incorporate(&action);
+        state.incorporate(&eval_action(
+            0,
+            &["perp.order.GTC:false:none", "perp.order.ALO:false:none"],
+        ));
         let report = state.finalize();
         assert_eq!(report.bonus, BONUS_PER_EXTRA_SIGNATURE);
         assert!((report.final_score - (2.0 + BONUS_PER_EXTRA_SIGNATURE)).abs() < 1e-6);
     }
 
+    #[test]
+    fn contributing_records_trace_first_occurrence() {
+        let matcher = matcher_from_yaml(PERP_DOMAINS_YAML).unwrap();
+        let mut state = ScoreState::new(&matcher, 3, 200);
+        let action = |step_idx: usize, tif: &str| EvalActionRecord {
+            step_idx,
+            ..eval_action(
+                step_idx as i64 * 1_000,
+                &[&format!("perp.order.{tif}:false:none")],
+            )
+        };
+        state.incorporate(&action(0, "GTC"));
+        state.incorporate(&action(1, "GTC"));
+        state.incorporate(&action(2, "ALO"));
+        state.incorporate_ws(["perp.order.IOC:false:none".to_string()]);
+
+        let report = state.finalize();
+        let records = &report.per_domain[0].contributing_records;
+        assert_eq!(report.per_domain[0].unique_count, 3);
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].step_idx, 0);
+        assert_eq!(records[0].signature, "perp.order.GTC:false:none");
+        assert_eq!(records[1].step_idx, 2);
+        assert_eq!(records[1].window_key_ms, 2_000);
+    }
+
+    #[test]
+    fn coin_breakdown_groups_credited_signatures_by_coin() {
+        let matcher = matcher_from_yaml(
+            "domains:\n  perp:\n    weight: 1.0\n    allow: [\"perp.order.*\"]\n  risk:\n    weight: 2.0\n    allow: [\"risk.setLeverage.*\"]\n",
+        )
+        .unwrap();
+        let mut state = ScoreState::new(&matcher, 3, 200);
+        let action = |step_idx: usize, coin: Option<&str>, signature: &str| EvalActionRecord {
+            step_idx,
+            coin: coin.map(String::from),
+            ..eval_action(step_idx as i64 * 1_000, &[signature])
+        };
+        state.incorporate(&action(0, Some("ETH"), "perp.order.GTC:false:none"));
+        state.incorporate(&action(1, Some("BTC"), "perp.order.GTC:false:none"));
+        state.incorporate(&action(2, Some("BTC"), "perp.order.ALO:false:none"));
+        state.incorporate(&action(3, None, "risk.setLeverage.SOL"));
+
+        let breakdown = state.coin_breakdown();
+        assert_eq!(
+            breakdown.keys().collect::<Vec<_>>(),
+            vec!["BTC", "ETH", "SOL"]
+        );
+        assert_eq!(
+            breakdown["ETH"].signatures,
+            vec!["perp.order.GTC:false:none"]
+        );
+        assert_eq!(breakdown["BTC"].unique_sigs, 1);
+        assert_eq!(breakdown["SOL"].contribution, 2.0);
+        assert!(state.finalize().coin_breakdown.is_empty());
+    }
+
+    #[test]
+    fn domain_per_action_cap_overrides_global_cap() {
+        let raw: RawConfig = serde_yaml::from_str(
+            "domains:\n  perp:\n    weight: 1.0\n    allow: [\"perp.order.*\"]\n    per_action_cap: 1\n  cancel:\n    weight: 1.0\n    allow: [\"perp.cancel.*\"]\n",
+        )
+        .unwrap();
+        let (matcher, _) = DomainMatcher::from_config(raw).unwrap();
+        let mut state = ScoreState::new(&matcher, 3, 200);
+        for window_key_ms in [0, 200, 400] {
+            state.incorporate(&eval_action(
+                window_key_ms,
+                &["perp.order.GTC:false:none", "perp.cancel.last"],
+            ));
+        }
+        let report = state.finalize();
+        assert_eq!(report.penalty, 2.0 * PENALTY_PER_EXTRA);
+        let caps: Vec<(&str, usize)> = report
+            .per_domain
+            .iter()
+            .map(|d| (d.name.as_str(), d.effective_cap))
+            .collect();
+        assert_eq!(caps, vec![("perp", 1), ("cancel", 3)]);
+
+        let zero: RawConfig = serde_yaml::from_str(
+            "domains:\n  perp:\n    weight: 1.0\n    allow: [\"perp.*\"]\n    per_action_cap: 0\n",
+        )
+        .unwrap();
+        assert!(DomainMatcher::from_config(zero).is_err());
+    }
+
+    #[test]
+    fn ws_signatures_score_without_window_bonus() {
+        let matcher = DomainMatcher {
+            entries: vec![DomainEntry {
+                name: "ws".to_string(),
+                weight: 0.5,
+                patterns: vec![parse_pattern("ws.*").unwrap()],
+                per_action_cap: None,
+            }],
+        };
+        let mut state = ScoreState::new(&matcher, 3, 200);
+        state.incorporate_ws([
+            "ws.orderUpdate.filled".to_string(),
+            "ws.userFunding.ETH".to_string(),
+        ]);
+        let report = state.finalize();
+        assert_eq!(report.base, 1.0);
+        assert_eq!(report.bonus, 0.0);
+        assert_eq!(
+            report.ws_signatures,
+            vec!["ws.orderUpdate.filled", "ws.userFunding.ETH"]
+        );
+        let ws_signatures: Vec<Signature> = report
+            .ws_signatures
+            .iter()
+            .cloned()
+            .map(Signature::from)
+            .collect();
+        assert_eq!(report.unique_signatures, ws_signatures);
+    }
+
+    #[test]
+    fn repeated_window_sets_decay_bonus() {
+        let raw: RawConfig = serde_yaml::from_str(
+            "composition_bonus_decay: 2\ndomains:\n  perp:\n    weight: 1.0\n    allow: [\"perp.order.*\"]\n",
+        )
+        .unwrap();
+        let (matcher, defaults) = DomainMatcher::from_config(raw).unwrap();
+        let mut state = ScoreState::new(&matcher, 10, 200);
+        state.bonus_decay = defaults.bonus_decay;
+        let pair = ["perp.order.GTC:false:none", "perp.order.ALO:false:none"];
+        for (window_key_ms, signatures) in [(0, pair), (200, pair), (400, pair), (1_000, pair)] {
+            state.incorporate(&eval_action(window_key_ms, &signatures));
+        }
+
+        let report = state.finalize();
+        let decayed: Vec<_> = report
+            .decayed_bonus_entries
+            .iter()
+            .map(|entry| (entry.window_start_ms, entry.repetitions, entry.bonus))
+            .collect();
+        // The window at 1000ms is three windows after the last repeat, so it keeps full bonus.
+        assert_eq!(decayed, vec![(200, 1, 0.125), (400, 2, 0.0625)]);
+        assert!((report.bonus - (0.25 + 0.125 + 0.0625 + 0.25)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn running_score_tracks_finalize() {
+        let raw: RawConfig = serde_yaml::from_str(
+            "composition_bonus_decay: 2\ndomains:\n  perp:\n    weight: 1.0\n    allow: [\"perp.order.*\"]\n",
+        )
+        .unwrap();
+        let (matcher, defaults) = DomainMatcher::from_config(raw).unwrap();
+        let mut state = ScoreState::new(&matcher, 2, 200);
+        state.bonus_decay = defaults.bonus_decay;
+        let gtc = "perp.order.GTC:false:none";
+        let alo = "perp.order.ALO:false:none";
+        // The last record widens the 0ms window, which changes the decay of the later ones.
+        let records = [
+            (0, vec![gtc]),
+            (200, vec![gtc, alo]),
+            (400, vec![gtc, alo]),
+            (400, vec![gtc]),
+            (0, vec![alo]),
+        ];
+        for (window_key_ms, signatures) in records {
+            state.incorporate(&eval_action(window_key_ms, &signatures));
+            assert!((state.running_score() - state.finalize().final_score).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn per_window_bonus_includes_empty_windows() {
+        let matcher = DomainMatcher {
+            entries: vec![DomainEntry {
+                name: "perp".to_string(),
+                weight: 1.0,
+                patterns: vec![parse_pattern("perp.*").unwrap()],
+                per_action_cap: None,
+            }],
+        };
+        let mut state = ScoreState::new(&matcher, 3, 200);
+        for (window_key_ms, signatures) in [
+            (0, vec!["perp.order.GTC:false:none", "perp.cancel.last"]),
+            (600, vec!["perp.cancel.all"]),
+        ] {
+            state.incorporate(&eval_action(window_key_ms, &signatures));
+        }
+        let report = state.finalize();
+        let windows: Vec<(i64, usize)> = report
+            .per_window_bonus
+            .iter()
+            .map(|w| (w.window_start_ms, w.distinct_signatures))
+            .collect();
+        assert_eq!(windows, vec![(0, 2), (200, 0), (400, 0), (600, 1)]);
+        assert_eq!(report.bonus, BONUS_PER_EXTRA_SIGNATURE);
+    }
+
+    #[test]
+    fn long_idle_gaps_get_one_zero_window() {
+        let matcher = DomainMatcher {
+            entries: vec![DomainEntry {
+                name: "perp".to_string(),
+                weight: 1.0,
+                patterns: vec![parse_pattern("perp.*").unwrap()],
+                per_action_cap: None,
+            }],
+        };
+        let mut state = ScoreState::new(&matcher, 3, 200);
+        // About two days of 200ms windows between the two records.
+        let later = 200 * 1_000_000_000;
+        for window_key_ms in [0, later] {
+            state.incorporate(&eval_action(window_key_ms, &["perp.order.GTC:false:none"]));
+        }
+        let report = state.finalize();
+        let windows: Vec<(i64, usize)> = report
+            .per_window_bonus
+            .iter()
+            .map(|w| (w.window_start_ms, w.distinct_signatures))
+            .collect();
+        assert_eq!(windows, vec![(0, 1), (200, 0), (later, 1)]);
+    }
+
     #[test]
     fn unmapped_signatures_recorded() {
         let matcher = DomainMatcher {
@@ -773,23 +2946,51 @@ mod tests {
                 name: "perp".to_string(),
                 weight: 1.0,
                 patterns: vec![parse_pattern("perp.order.*").unwrap()],
+                per_action_cap: None,
             }],
         };
         let mut state = ScoreState::new(&matcher, 3, 200);
-        let action = EvalActionRecord {
-            step_idx: 0,
+        state.incorporate(&EvalActionRecord {
             action: "unknown".to_string(),
-            submit_ts_ms: 0,
-            window_key_ms: 0,
-            signatures: vec!["account.someNewAction".to_string()],
-            ignored: false,
-            reason: None,
-        };
-        state.incorporate(&action);
+            ..eval_action(0, &["account.someNewAction"])
+        });
         let report = state.finalize();
         assert_eq!(
             report.unmapped_signatures,
-            vec!["account.someNewAction".to_string()]
+            vec![Signature::from("account.someNewAction")]
         );
     }
+
+    #[test]
+    fn unmapped_signature_suggests_widened_pattern() {
+        let matcher = DomainMatcher {
+            entries: vec![
+                DomainEntry {
+                    name: "perp".to_string(),
+                    weight: 1.0,
+                    patterns: vec![parse_pattern("perp.order.GTC:*:*").unwrap()],
+                    per_action_cap: None,
+                },
+                DomainEntry {
+                    name: "cancel".to_string(),
+                    weight: 1.0,
+                    patterns: vec![parse_pattern("perp.cancel.*").unwrap()],
+                    per_action_cap: None,
+                },
+            ],
+        };
+        let mut state = ScoreState::new(&matcher, 3, 200);
+        state.incorporate(&eval_action(
+            0,
+            &["perp.order.GTC:false:none", "perp.order.FOK:false:none"],
+        ));
+
+        let suggestions = state.unmapped_suggestions();
+        assert_eq!(suggestions.len(), 1);
+        let suggestion = &suggestions[0];
+        assert_eq!(suggestion.signature, "perp.order.FOK:false:none");
+        assert_eq!(suggestion.suggested_pattern, "perp.order.*");
+        assert_eq!(suggestion.suggested_domain.as_deref(), Some("perp"));
+        assert_eq!(suggestion.common_prefix_segments, 2);
+    }
 }
@@ -0,0 +1,187 @@
+use std::{collections::BTreeSet, fs, path::PathBuf, process::ExitCode};
+
+use anyhow::{Context, Result};
+use clap::Args;
+use hl_common::Signature;
+use serde::Serialize;
+
+use crate::regression::{compare, load_baseline, DomainDelta, ScoreSnapshot};
+
+/// `hl-evaluator diff`: compares two `eval_score.json` files.
+#[derive(Args, Debug, Clone)]
+pub struct DiffArgs {
+    /// eval_score.json of the reference run
+    #[arg(long)]
+    pub baseline: PathBuf,
+    /// eval_score.json of the run being compared
+    #[arg(long)]
+    pub candidate: PathBuf,
+    /// Write the diff here instead of stdout
+    #[arg(long)]
+    pub out: Option<PathBuf>,
+}
+
+/// Candidate minus baseline; `perDomain[].current` is the candidate's contribution.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScoreDiff {
+    pub baseline_score: f64,
+    pub candidate_score: f64,
+    pub final_score_delta: f64,
+    pub base_delta: f64,
+    pub bonus_delta: f64,
+    pub penalty_delta: f64,
+    /// In the candidate but not the baseline.
+    pub signatures_gained: Vec<Signature>,
+    /// In the baseline but not the candidate.
+    pub signatures_lost: Vec<Signature>,
+    pub signatures_unchanged: usize,
+    pub per_domain: Vec<DomainDelta>,
+}
+
+pub fn diff(baseline: &ScoreSnapshot, candidate: &ScoreSnapshot) -> ScoreDiff {
+    let before: BTreeSet<&Signature> = baseline.unique_signatures.iter().collect();
+    let after: BTreeSet<&Signature> = candidate.unique_signatures.iter().collect();
+    ScoreDiff {
+        baseline_score: baseline.final_score,
+        candidate_score: candidate.final_score,
+        final_score_delta: candidate.final_score - baseline.final_score,
+        base_delta: candidate.base - baseline.base,
+        bonus_delta: candidate.bonus - baseline.bonus,
+        penalty_delta: candidate.penalty - baseline.penalty,
+        signatures_gained: after
+            .difference(&before)
+            .map(|sig| (*sig).clone())
+            .collect(),
+        signatures_lost: before
+            .difference(&after)
+            .map(|sig| (*sig).clone())
+            .collect(),
+        signatures_unchanged: before.intersection(&after).count(),
+        per_domain: compare(baseline, candidate, 0.0).per_domain,
+    }
+}
+
+fn load(path: &std::path::Path) -> Result<ScoreSnapshot> {
+    load_baseline(&path.display().to_string())
+}
+
+/// Prints (or writes) the diff; the exit code is 1 when the candidate scored lower.
+pub fn run(args: &DiffArgs) -> Result<ExitCode> {
+    let score_diff = diff(&load(&args.baseline)?, &load(&args.candidate)?);
+    let json = serde_json::to_string_pretty(&score_diff)?;
+    match &args.out {
+        Some(path) => {
+            fs::write(path, json).with_context(|| format!("failed to write {}", path.display()))?
+        }
+        None => println!("{json}"),
+    }
+    if score_diff.final_score_delta < 0.0 {
+        return Ok(ExitCode::FAILURE);
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hl_common::test_support::RunFixture;
+
+    #[test]
+    fn reports_gained_lost_and_unchanged_signatures() {
+        let fixture = RunFixture::empty("hl-diff");
+        let baseline = fixture.write(
+            "baseline.json",
+            serde_json::json!({
+                "finalScore": 3.0, "base": 2.5, "bonus": 0.5, "penalty": 0.0,
+                "perDomain": [
+                    {"name": "perp", "contribution": 2.0},
+                    {"name": "risk", "contribution": 1.0}
+                ],
+                "uniqueSignatures": ["perp.order.GTC:false:none", "risk.setLeverage.ETH", "perp.cancel.all"]
+            })
+            .to_string(),
+        );
+        let candidate = fixture.write(
+            "candidate.json",
+            serde_json::json!({
+                "finalScore": 3.5, "base": 3.0, "bonus": 0.75, "penalty": 0.25,
+                "perDomain": [
+                    {"name": "perp", "contribution": 3.0},
+                    {"name": "spot", "contribution": 0.5}
+                ],
+                "uniqueSignatures": ["perp.order.GTC:false:none", "perp.cancel.all", "perp.order.ALO:false:none", "spot.order.IOC"]
+            })
+            .to_string(),
+        );
+
+        let score_diff = diff(&load(&baseline).unwrap(), &load(&candidate).unwrap());
+        assert_eq!(
+            score_diff.signatures_gained,
+            vec![
+                Signature::from("perp.order.ALO:false:none"),
+                Signature::from("spot.order.IOC")
+            ]
+        );
+        assert_eq!(
+            score_diff.signatures_lost,
+            vec![Signature::from("risk.setLeverage.ETH")]
+        );
+        assert_eq!(score_diff.signatures_unchanged, 2);
+        assert_eq!(score_diff.final_score_delta, 0.5);
+        assert_eq!(
+            (
+                score_diff.base_delta,
+                score_diff.bonus_delta,
+                score_diff.penalty_delta
+            ),
+            (0.5, 0.25, 0.25)
+        );
+        let deltas: Vec<_> = score_diff
+            .per_domain
+            .iter()
+            .map(|domain| (domain.name.as_str(), domain.delta))
+            .collect();
+        assert_eq!(deltas, vec![("perp", 1.0), ("risk", -1.0), ("spot", 0.5)]);
+    }
+
+    #[test]
+    fn identical_runs_have_empty_diff() {
+        let fixture = RunFixture::empty("hl-diff");
+        let path = fixture.write(
+            "same.json",
+            serde_json::json!({"finalScore": 1.0, "uniqueSignatures": ["perp.cancel.all"]})
+                .to_string(),
+        );
+        let snapshot = load(&path).unwrap();
+        let score_diff = diff(&snapshot, &snapshot);
+        assert!(score_diff.signatures_gained.is_empty() && score_diff.signatures_lost.is_empty());
+        assert_eq!(score_diff.signatures_unchanged, 1);
+        assert_eq!(score_diff.final_score_delta, 0.0);
+    }
+
+    #[test]
+    fn lower_candidate_scores_fail_the_exit_code() {
+        let fixture = RunFixture::empty("hl-diff");
+        let higher = fixture.write(
+            "higher.json",
+            serde_json::json!({"finalScore": 2.0}).to_string(),
+        );
+        let lower = fixture.write(
+            "lower.json",
+            serde_json::json!({"finalScore": 1.0}).to_string(),
+        );
+        let out = fixture.dir.join("diff.json");
+        let exit_code = |baseline: &PathBuf, candidate: &PathBuf| {
+            run(&DiffArgs {
+                baseline: baseline.clone(),
+                candidate: candidate.clone(),
+                out: Some(out.clone()),
+            })
+            .unwrap()
+        };
+
+        assert_eq!(exit_code(&higher, &lower), ExitCode::FAILURE);
+        assert_eq!(exit_code(&lower, &higher), ExitCode::SUCCESS);
+    }
+}
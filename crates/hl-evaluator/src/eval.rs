@@ -0,0 +1,18 @@
+//! Score action records in-process instead of through the CLI:
+//!
+//! ```no_run
+//! use hl_evaluator::eval::{load_config, score_actions, ScoringOptions};
+//!
+//! # fn main() -> anyhow::Result<()> {
+//! let config = load_config("dataset/domains-hl.yaml".as_ref())?;
+//! let records = Vec::new(); // e.g. RunArtifacts::open(dir)?.iter_action_records()
+//! let report = score_actions(&records, config, ScoringOptions::default())?;
+//! println!("{}", report.final_score);
+//! # Ok(())
+//! # }
+//! ```
+
+pub use crate::coverage::{
+    load_config, score_actions, CoinBreakdown, ContributingRecord, DecayEntry, DomainBreakdown,
+    RawConfig, ScoreReport, ScoringOptions, WindowBonus,
+};
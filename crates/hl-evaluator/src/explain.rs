@@ -0,0 +1,123 @@
+use std::collections::BTreeMap;
+
+/// Rule-based plain-English descriptions for coverage signatures, keyed by the
+/// first two dot-segments (lowercased, mirroring `Pattern::matches`).
+pub struct SignatureExplainer {
+    rules: BTreeMap<String, &'static str>,
+}
+
+impl Default for SignatureExplainer {
+    fn default() -> Self {
+        let mut rules = BTreeMap::new();
+        rules.insert("perp.order".to_string(), "Perpetual order");
+        rules.insert("perp.cancel".to_string(), "Perpetual cancel");
+        rules.insert("perp.wait".to_string(), "Wait on perpetual order");
+        rules.insert("account.usdclasstransfer".to_string(), "USD class transfer");
+        rules.insert(
+            "account.subaccounttransfer".to_string(),
+            "Sub-account USDC transfer",
+        );
+        rules.insert("risk.setleverage".to_string(), "Leverage update");
+        rules.insert("market.fundingrate".to_string(), "Funding rate query");
+        rules.insert(
+            "account.assertbalance".to_string(),
+            "Perp balance assertion passed",
+        );
+        Self { rules }
+    }
+}
+
+impl SignatureExplainer {
+    /// Returns the interpretation of `signature`, or `None` when no rule covers it.
+    pub fn explain(&self, signature: &str) -> Option<String> {
+        let parts: Vec<&str> = signature.split('.').collect();
+        if parts.len() < 2 {
+            return None;
+        }
+        let key = format!("{}.{}", parts[0], parts[1]).to_ascii_lowercase();
+        let base = *self.rules.get(&key)?;
+        let rest = parts[2..].join(".");
+
+        let text = match key.as_str() {
+            "perp.order" => explain_perp_order(&rest)?,
+            "perp.cancel" => match rest.to_ascii_lowercase().as_str() {
+                "last" => "Perpetual cancel of the most recently placed order".to_string(),
+                "oids" => "Perpetual cancel of explicit order ids".to_string(),
+                "all" => "Perpetual cancel of all open orders".to_string(),
+                _ => format!("{base}, scope {rest}"),
+            },
+            "perp.wait" if rest.eq_ignore_ascii_case("fill") => {
+                "Wait until a perpetual order is filled".to_string()
+            }
+            "account.usdclasstransfer" => match rest.to_ascii_lowercase().as_str() {
+                "toperp" => "USD class transfer from spot to perp".to_string(),
+                "fromperp" => "USD class transfer from perp to spot".to_string(),
+                _ => format!("{base}, direction {rest}"),
+            },
+            "risk.setleverage" | "market.fundingrate" if !rest.is_empty() => {
+                format!("{base} on {rest}")
+            }
+            _ if rest.is_empty() => base.to_string(),
+            _ => format!("{base} ({rest})"),
+        };
+        Some(text)
+    }
+
+    /// Renders a Markdown table with one row per signature.
+    pub fn markdown_table<'a>(&self, signatures: impl IntoIterator<Item = &'a str>) -> String {
+        let mut out = String::from("| Signature | Interpretation |\n|---|---|\n");
+        for signature in signatures {
+            let text = self
+                .explain(signature)
+                .unwrap_or_else(|| "(unrecognized pattern)".to_string());
+            out.push_str(&format!("| `{signature}` | {text} |\n"));
+        }
+        out
+    }
+}
+
+/// Interprets the `<TIF>:<reduce_only>:<trigger>` tail of a `perp.order` signature.
+fn explain_perp_order(tail: &str) -> Option<String> {
+    let mut fields = tail.split(':');
+    let tif = fields.next().filter(|s| !s.is_empty())?;
+    let reduce_only = match fields.next()? {
+        "true" => "reduce-only",
+        "false" => "non-reduce-only",
+        _ => return None,
+    };
+    let trigger = match fields.next()? {
+        "none" => "no trigger".to_string(),
+        kind => format!("{kind} trigger"),
+    };
+    if fields.next().is_some() {
+        return None;
+    }
+    Some(format!(
+        "Perpetual {} order, {reduce_only}, {trigger}",
+        tif.to_ascii_uppercase()
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn explains_known_and_unknown_signatures() {
+        let explainer = SignatureExplainer::default();
+        assert_eq!(
+            explainer.explain("perp.order.GTC:false:none").as_deref(),
+            Some("Perpetual GTC order, non-reduce-only, no trigger")
+        );
+        assert_eq!(
+            explainer.explain("risk.setLeverage.ETH").as_deref(),
+            Some("Leverage update on ETH")
+        );
+        assert_eq!(explainer.explain("perp.order.GTC"), None);
+        assert_eq!(explainer.explain("spot.swap.x"), None);
+
+        let table = explainer.markdown_table(["perp.cancel.all", "spot.swap.x"]);
+        assert!(table.contains("| `perp.cancel.all` | Perpetual cancel of all open orders |"));
+        assert!(table.contains("| `spot.swap.x` | (unrecognized pattern) |"));
+    }
+}
@@ -1,13 +1,14 @@
 use std::{
     collections::BTreeMap,
     fs::File,
-    io::{BufRead, BufReader},
+    io::BufRead,
     path::{Path, PathBuf},
+    process::ExitCode,
 };
 
 use anyhow::{anyhow, Context, Result};
-use clap::Parser;
-use hl_common::ActionLogRecord;
+use clap::Args;
+use hl_common::{artifacts::open_jsonl, ActionLogRecord};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
@@ -18,8 +19,8 @@ const DEFAULT_PX_TOL_PCT: f64 = 0.2;
 const DEFAULT_SZ_TOL_PCT: f64 = 0.5;
 const CONTEXT_RADIUS: usize = 3;
 
-#[derive(Parser, Debug, Clone)]
-#[command(about = "Validate Haystack-in-a-Needle ground truth against runner artifacts")]
+/// `hl-evaluator hian`: validates Haystack-in-a-Needle ground truth against runner artifacts.
+#[derive(Args, Debug, Clone)]
 pub struct HianArgs {
     #[arg(long)]
     pub ground: PathBuf,
@@ -39,6 +40,9 @@ pub struct HianArgs {
     pub px_tol_pct: Option<f64>,
     #[arg(long)]
     pub sz_tol_pct: Option<f64>,
+    /// Fail the case when more than N logged actions matched no expected step
+    #[arg(long)]
+    pub fail_on_extra: Option<usize>,
 }
 
 #[derive(Debug, Serialize)]
@@ -47,6 +51,23 @@ pub struct HianOutput {
     pub out_dir: PathBuf,
 }
 
+/// Runs the validator and prints `PASS`/`FAIL`; the exit code is 2 on `FAIL`.
+pub fn run_command(args: &HianArgs) -> Result<ExitCode> {
+    let output = run(args)?;
+    let result_path = output.out_dir.join("eval_hian.json");
+    if output.result.pass {
+        println!("PASS ({})", result_path.display());
+        return Ok(ExitCode::SUCCESS);
+    }
+    println!(
+        "FAIL: {} missing, {} extra (see {})",
+        output.result.missing.len(),
+        output.result.extra_count,
+        output.out_dir.join("eval_hian_diff.txt").display()
+    );
+    Ok(ExitCode::from(2))
+}
+
 pub fn run(args: &HianArgs) -> Result<HianOutput> {
     let ground = load_ground_truth(&args.ground)?;
     let per_actions = load_action_log(&args.per_action)?;
@@ -109,6 +130,15 @@ pub fn run(args: &HianArgs) -> Result<HianOutput> {
             }
         }
 
+        let strict = ground.strict_ordering || step.strict_ordering;
+        if let Some((idx, _)) = found.as_ref().filter(|_| strict) {
+            let gap = idx - start_idx;
+            if gap > 0 {
+                failure_reason = format!("strict ordering violated: gap of {gap} actions");
+                found = None;
+            }
+        }
+
         if let Some((idx, detail)) = found {
             cursor = idx as isize;
             last_ts = Some(per_actions[idx].submit_ts_ms);
@@ -128,7 +158,17 @@ pub fn run(args: &HianArgs) -> Result<HianOutput> {
         }
     }
 
-    let pass = missing.is_empty();
+    let matched_at: std::collections::BTreeSet<usize> =
+        matched.iter().map(|m| m.matched_at).collect();
+    let extra: Vec<ExtraActionSerial> = per_actions
+        .iter()
+        .enumerate()
+        .filter(|(idx, _)| !matched_at.contains(idx))
+        .map(|(_, action)| ExtraActionSerial::from_action(action))
+        .collect();
+
+    let too_many_extra = args.fail_on_extra.is_some_and(|limit| extra.len() > limit);
+    let pass = missing.is_empty() && !too_many_extra;
     let result = EvalHian {
         pass,
         case_id: ground.case_id.clone(),
@@ -137,7 +177,8 @@ pub fn run(args: &HianArgs) -> Result<HianOutput> {
             .map(|m| m.detail.to_serializable(m.expect_idx, m.matched_at))
             .collect(),
         missing: missing.iter().map(|m| m.to_serializable()).collect(),
-        extra: Vec::new(),
+        extra_count: extra.len(),
+        extra,
         metrics: Metrics {
             latency_ms: latency,
             window_ms: settings.window_ms,
@@ -150,10 +191,13 @@ pub fn run(args: &HianArgs) -> Result<HianOutput> {
         .with_context(|| format!("failed to write {}", json_path.display()))?;
 
     if !result.pass {
-        let diff = build_diff(&ground, &per_actions, &missing);
+        let diff = build_diff(&ground, &per_actions, &matched, &missing, &result.extra);
         let diff_path = out_dir.join("eval_hian_diff.txt");
-        std::fs::write(&diff_path, diff)
+        std::fs::write(&diff_path, build_text_diff(&diff, &per_actions))
             .with_context(|| format!("failed to write {}", diff_path.display()))?;
+        let json_diff_path = out_dir.join("eval_hian_diff.json");
+        std::fs::write(&json_diff_path, build_json_diff(&diff)?)
+            .with_context(|| format!("failed to write {}", json_diff_path.display()))?;
     }
 
     Ok(HianOutput { result, out_dir })
@@ -166,7 +210,9 @@ pub struct EvalHian {
     pub case_id: Option<String>,
     pub matched: Vec<MatchedStepSerial>,
     pub missing: Vec<MissingStepSerial>,
-    pub extra: Vec<Value>,
+    /// Logged actions that no expected step matched.
+    pub extra: Vec<ExtraActionSerial>,
+    pub extra_count: usize,
     pub metrics: Metrics,
     pub settings: SettingsUsed,
 }
@@ -200,6 +246,7 @@ struct MatchDetail {
     oid: Option<u64>,
     fill: Option<FillInfo>,
     latency_ms: Option<i64>,
+    matched_alternative: Option<usize>,
 }
 
 impl MatchDetail {
@@ -211,6 +258,7 @@ impl MatchDetail {
             ts_ms: self.ts_ms,
             oid: self.oid,
             fill: self.fill.clone(),
+            matched_alternative: self.matched_alternative,
         }
     }
 }
@@ -242,6 +290,9 @@ pub struct MatchedStepSerial {
     pub oid: Option<u64>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub fill: Option<FillInfo>,
+    /// Index into `perpOrderMulti` of the alternative that matched.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub matched_alternative: Option<usize>,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -251,6 +302,38 @@ pub struct MissingStepSerial {
     pub reason: String,
 }
 
+#[derive(Debug, Clone, Serialize)]
+pub struct ExtraActionSerial {
+    pub step_idx: usize,
+    pub action: String,
+    pub submit_ts_ms: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub coin: Option<String>,
+}
+
+impl ExtraActionSerial {
+    fn from_action(action: &ActionLogRecord) -> Self {
+        let request = action.request.get(&action.action);
+        let coin = request
+            .and_then(|req| req.get("coin"))
+            .or_else(|| {
+                request
+                    .and_then(|req| req.get("orders"))
+                    .and_then(Value::as_array)
+                    .and_then(|orders| orders.first())
+                    .and_then(|order| order.get("coin"))
+            })
+            .and_then(Value::as_str)
+            .map(str::to_string);
+        Self {
+            step_idx: action.step_idx,
+            action: action.action.clone(),
+            submit_ts_ms: action.submit_ts_ms,
+            coin,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize)]
 pub struct FillInfo {
     pub px: Option<String>,
@@ -261,6 +344,8 @@ pub struct FillInfo {
 enum MatchKind {
     UsdClassTransfer,
     PerpOrder,
+    FundingRate,
+    SetLeverage,
 }
 
 impl std::fmt::Display for MatchKind {
@@ -268,11 +353,14 @@ impl std::fmt::Display for MatchKind {
         match self {
             MatchKind::UsdClassTransfer => write!(f, "usd_class_transfer"),
             MatchKind::PerpOrder => write!(f, "perp_order"),
+            MatchKind::FundingRate => write!(f, "fetch_funding_rate"),
+            MatchKind::SetLeverage => write!(f, "set_leverage"),
         }
     }
 }
 
 #[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
 struct GroundTruth {
     #[serde(default)]
     case_id: Option<String>,
@@ -280,6 +368,9 @@ struct GroundTruth {
     within_ms: Option<i64>,
     #[serde(default)]
     window_ms: Option<i64>,
+    /// Every step must match the action right after the previous match (no gaps).
+    #[serde(default, alias = "strict_ordering")]
+    strict_ordering: bool,
     steps: Vec<ExpectedStep>,
 }
 
@@ -289,6 +380,16 @@ struct ExpectedStep {
     usd_class_transfer: Option<ExpectedTransfer>,
     #[serde(rename = "perpOrder")]
     perp_order: Option<ExpectedPerpOrder>,
+    /// Any one of these orders satisfies the step.
+    #[serde(rename = "perpOrderMulti")]
+    perp_order_multi: Option<Vec<ExpectedPerpOrder>>,
+    #[serde(rename = "fetchFundingRate")]
+    fetch_funding_rate: Option<ExpectedFundingRate>,
+    #[serde(rename = "setLeverage")]
+    set_leverage: Option<ExpectedSetLeverage>,
+    /// This step must match the action right after the previous match.
+    #[serde(default, rename = "strictOrdering", alias = "strict_ordering")]
+    strict_ordering: bool,
 }
 
 impl ExpectedStep {
@@ -297,6 +398,12 @@ impl ExpectedStep {
             StepKind::UsdClassTransfer(t.clone())
         } else if let Some(p) = &self.perp_order {
             StepKind::PerpOrder(p.clone())
+        } else if let Some(alternatives) = &self.perp_order_multi {
+            StepKind::PerpOrderMulti(alternatives.clone())
+        } else if let Some(f) = &self.fetch_funding_rate {
+            StepKind::FundingRate(f.clone())
+        } else if let Some(l) = &self.set_leverage {
+            StepKind::SetLeverage(l.clone())
         } else {
             StepKind::Unsupported
         }
@@ -312,6 +419,22 @@ impl ExpectedStep {
                 "perp_order {{ coin: {:?}, side: {:?}, tif: {:?}, reduceOnly: {:?} }}",
                 p.coin, p.side, p.tif, p.reduce_only
             ),
+            StepKind::PerpOrderMulti(alternatives) => format!(
+                "perpOrderMulti({})",
+                alternatives
+                    .iter()
+                    .map(|alt| alt.coin.as_deref().unwrap_or("*"))
+                    .collect::<Vec<_>>()
+                    .join("|")
+            ),
+            StepKind::FundingRate(f) => format!(
+                "fetch_funding_rate {{ coin: {:?}, funding_rate: {:?} }}",
+                f.coin, f.funding_rate
+            ),
+            StepKind::SetLeverage(l) => format!(
+                "set_leverage {{ coin: {:?}, leverage: {:?}, cross: {:?} }}",
+                l.coin, l.leverage, l.cross
+            ),
             StepKind::Unsupported => "unsupported step".to_string(),
         }
     }
@@ -321,10 +444,14 @@ impl ExpectedStep {
 enum StepKind {
     UsdClassTransfer(ExpectedTransfer),
     PerpOrder(ExpectedPerpOrder),
+    PerpOrderMulti(Vec<ExpectedPerpOrder>),
+    FundingRate(ExpectedFundingRate),
+    SetLeverage(ExpectedSetLeverage),
     Unsupported,
 }
 
 #[derive(Debug, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
 struct ExpectedTransfer {
     to_perp: bool,
     #[serde(default)]
@@ -332,6 +459,25 @@ struct ExpectedTransfer {
 }
 
 #[derive(Debug, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct ExpectedFundingRate {
+    coin: String,
+    #[serde(default, alias = "funding_rate")]
+    funding_rate: Option<NumMatcher>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+struct ExpectedSetLeverage {
+    #[serde(default)]
+    coin: Option<String>,
+    #[serde(default)]
+    leverage: Option<u32>,
+    #[serde(default)]
+    cross: Option<bool>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
 struct ExpectedPerpOrder {
     coin: Option<String>,
     side: Option<String>,
@@ -471,10 +617,150 @@ fn match_step(
             match_transfer(&expected, action, ws_events, settings)
         }
         StepKind::PerpOrder(expected) => match_perp_order(&expected, action, ws_events, settings),
+        StepKind::PerpOrderMulti(alternatives) => {
+            match_perp_order_multi(&alternatives, action, ws_events, settings)
+        }
+        StepKind::FundingRate(expected) => match_funding_rate(&expected, action, settings),
+        StepKind::SetLeverage(expected) => match_leverage(&expected, action),
         StepKind::Unsupported => Err("unsupported step kind".to_string()),
     }
 }
 
+fn match_perp_order_multi(
+    alternatives: &[ExpectedPerpOrder],
+    action: &ActionLogRecord,
+    ws_events: &[WsEvent],
+    settings: &SettingsUsed,
+) -> Result<MatchDetail, String> {
+    let mut failures = Vec::with_capacity(alternatives.len());
+    for (alt_idx, expected) in alternatives.iter().enumerate() {
+        match match_perp_order(expected, action, ws_events, settings) {
+            Ok(mut detail) => {
+                detail.matched_alternative = Some(alt_idx);
+                return Ok(detail);
+            }
+            Err(reason) => failures.push(format!(
+                "[{alt_idx}] {}: {reason}",
+                expected.coin.as_deref().unwrap_or("*")
+            )),
+        }
+    }
+    Err(format!(
+        "no alternative matched:\n    {}",
+        failures.join("\n    ")
+    ))
+}
+
+fn match_funding_rate(
+    expected: &ExpectedFundingRate,
+    action: &ActionLogRecord,
+    settings: &SettingsUsed,
+) -> Result<MatchDetail, String> {
+    if action.action != "fetch_funding_rate" {
+        return Err("action kind mismatch".to_string());
+    }
+
+    let coin = action
+        .request
+        .get("fetch_funding_rate")
+        .and_then(|v| v.get("coin"))
+        .and_then(Value::as_str)
+        .ok_or_else(|| "missing coin in request".to_string())?;
+    if !coin.eq_ignore_ascii_case(&expected.coin) {
+        return Err(format!(
+            "coin mismatch (expected {}, got {})",
+            expected.coin, coin
+        ));
+    }
+
+    let rate = action
+        .observed
+        .as_ref()
+        .and_then(|v| v.get("fundingRate"))
+        .and_then(Value::as_f64)
+        .ok_or_else(|| "no observed funding rate".to_string())?;
+    if let Some(matcher) = &expected.funding_rate {
+        matcher.matches_amount(rate, settings)?;
+    }
+
+    Ok(MatchDetail {
+        kind: MatchKind::FundingRate,
+        ts_ms: action.submit_ts_ms,
+        oid: None,
+        fill: None,
+        latency_ms: None,
+        matched_alternative: None,
+    })
+}
+
+fn match_leverage(
+    expected: &ExpectedSetLeverage,
+    action: &ActionLogRecord,
+) -> Result<MatchDetail, String> {
+    if action.action != "set_leverage" {
+        return Err("action kind mismatch".to_string());
+    }
+    let ack_status = action
+        .ack
+        .as_ref()
+        .and_then(|ack| ack.get("status"))
+        .and_then(Value::as_str);
+    if ack_status != Some("ok") {
+        return Err(format!(
+            "leverage update not acknowledged (status {})",
+            ack_status.unwrap_or("missing")
+        ));
+    }
+
+    let request = action
+        .request
+        .get("set_leverage")
+        .ok_or_else(|| "missing set_leverage request".to_string())?;
+    if let Some(expected_coin) = &expected.coin {
+        let coin = request
+            .get("coin")
+            .and_then(Value::as_str)
+            .ok_or_else(|| "missing coin in request".to_string())?;
+        if !coin.eq_ignore_ascii_case(expected_coin) {
+            return Err(format!(
+                "coin mismatch (expected {expected_coin}, got {coin})"
+            ));
+        }
+    }
+    if let Some(expected_leverage) = expected.leverage {
+        let leverage = request
+            .get("leverage")
+            .and_then(Value::as_u64)
+            .ok_or_else(|| "missing leverage in request".to_string())?;
+        if leverage != u64::from(expected_leverage) {
+            return Err(format!(
+                "leverage mismatch (expected {expected_leverage}, got {leverage})"
+            ));
+        }
+    }
+    if let Some(expected_cross) = expected.cross {
+        // The runner always logs `cross`; older logs without it were isolated updates.
+        let cross = request
+            .get("cross")
+            .and_then(Value::as_bool)
+            .unwrap_or(false);
+        if cross != expected_cross {
+            return Err(format!(
+                "cross mismatch (expected {expected_cross}, got {cross})"
+            ));
+        }
+    }
+
+    Ok(MatchDetail {
+        kind: MatchKind::SetLeverage,
+        ts_ms: action.submit_ts_ms,
+        oid: None,
+        fill: None,
+        latency_ms: action.duration_ms,
+        matched_alternative: None,
+    })
+}
+
 fn match_transfer(
     expected: &ExpectedTransfer,
     action: &ActionLogRecord,
@@ -525,6 +811,7 @@ fn match_transfer(
         oid: None,
         fill: None,
         latency_ms,
+        matched_alternative: None,
     })
 }
 
@@ -654,6 +941,7 @@ fn match_perp_order(
             oid,
             fill,
             latency_ms,
+            matched_alternative: None,
         });
     }
 
@@ -813,8 +1101,7 @@ fn load_ground_truth(path: &Path) -> Result<GroundTruth> {
 }
 
 fn load_action_log(path: &Path) -> Result<Vec<ActionLogRecord>> {
-    let file = File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
-    let reader = BufReader::new(file);
+    let reader = open_jsonl(path)?;
     let mut records = Vec::new();
     for (idx, line) in reader.lines().enumerate() {
         let line = line.with_context(|| format!("failed to read per_action line {}", idx + 1))?;
@@ -836,8 +1123,7 @@ fn load_ws_events(path: Option<PathBuf>) -> Result<Vec<WsEvent>> {
     if !path.exists() {
         return Ok(Vec::new());
     }
-    let file = File::open(&path).with_context(|| format!("failed to open {}", path.display()))?;
-    let reader = BufReader::new(file);
+    let reader = open_jsonl(&path)?;
     let mut events = Vec::new();
     for (idx, line) in reader.lines().enumerate() {
         let line = line.with_context(|| format!("failed to read ws_stream line {}", idx + 1))?;
@@ -855,31 +1141,116 @@ fn load_ws_events(path: Option<PathBuf>) -> Result<Vec<WsEvent>> {
     Ok(events)
 }
 
+/// Per-step outcome shared by the text and JSON diff renderings.
+#[derive(Debug, Serialize)]
+struct DiffResult {
+    case_id: String,
+    total_expected: usize,
+    passed: usize,
+    failed: usize,
+    steps: Vec<DiffStep>,
+    extra: Vec<ExtraActionSerial>,
+}
+
+#[derive(Debug, Serialize)]
+struct DiffStep {
+    expect_idx: usize,
+    description: String,
+    status: DiffStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    matched_at: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    matched_action: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum DiffStatus {
+    Pass,
+    Fail,
+}
+
 fn build_diff(
     ground: &GroundTruth,
     actions: &[ActionLogRecord],
+    matched: &[MatchedStepRecord],
     missing: &[MissingStepRecord],
-) -> String {
+    extra: &[ExtraActionSerial],
+) -> DiffResult {
+    let mut steps: Vec<DiffStep> = matched
+        .iter()
+        .map(|m| DiffStep {
+            expect_idx: m.expect_idx,
+            description: ground.steps[m.expect_idx].describe(),
+            status: DiffStatus::Pass,
+            reason: None,
+            matched_at: Some(m.matched_at),
+            matched_action: actions.get(m.matched_at).map(action_summary),
+        })
+        .chain(missing.iter().map(|miss| DiffStep {
+            expect_idx: miss.expect_idx,
+            description: miss.description.clone(),
+            status: DiffStatus::Fail,
+            reason: Some(miss.reason.clone()),
+            matched_at: None,
+            matched_action: None,
+        }))
+        .collect();
+    steps.sort_by_key(|step| step.expect_idx);
+
+    DiffResult {
+        case_id: ground
+            .case_id
+            .clone()
+            .unwrap_or_else(|| "unknown-case".to_string()),
+        total_expected: ground.steps.len(),
+        passed: matched.len(),
+        failed: missing.len(),
+        steps,
+        extra: extra.to_vec(),
+    }
+}
+
+fn build_text_diff(diff: &DiffResult, actions: &[ActionLogRecord]) -> String {
     let mut out = String::new();
-    let case = ground
-        .case_id
-        .clone()
-        .unwrap_or_else(|| "unknown-case".to_string());
     use std::fmt::Write as _;
-    let _ = writeln!(out, "HiaN FAIL (case {case})");
-    for miss in missing {
+    let _ = writeln!(out, "HiaN FAIL (case {})", diff.case_id);
+    for step in diff.steps.iter().filter(|s| s.status == DiffStatus::Fail) {
         let _ = writeln!(
             out,
             "\nStep {} expected: {}\n  ✗ {}",
-            miss.expect_idx, miss.description, miss.reason
+            step.expect_idx,
+            step.description,
+            step.reason.as_deref().unwrap_or_default()
         );
         for summary in context_actions(actions, CONTEXT_RADIUS) {
             let _ = writeln!(out, "    {summary}");
         }
     }
+    if !diff.extra.is_empty() {
+        let _ = writeln!(out, "\nExtra actions ({}):", diff.extra.len());
+        for extra in &diff.extra {
+            let summary = actions
+                .iter()
+                .find(|action| action.step_idx == extra.step_idx)
+                .map(action_summary)
+                .unwrap_or_else(|| extra.action.clone());
+            let _ = writeln!(
+                out,
+                "    #{} {summary} @{}",
+                extra.step_idx, extra.submit_ts_ms
+            );
+        }
+    }
     out
 }
 
+fn build_json_diff(diff: &DiffResult) -> Result<String> {
+    Ok(serde_json::to_string_pretty(diff)?)
+}
+
 fn context_actions(actions: &[ActionLogRecord], radius: usize) -> Vec<String> {
     actions
         .iter()
@@ -937,34 +1308,20 @@ fn action_summary(action: &ActionLogRecord) -> String {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::io::Write;
-    use uuid::Uuid;
-
-    fn tmp_dir() -> PathBuf {
-        let mut dir = std::env::temp_dir();
-        dir.push(format!("hian-test-{}", Uuid::new_v4()));
-        std::fs::create_dir_all(&dir).unwrap();
-        dir
-    }
-
-    fn write_file(path: &Path, contents: &str) {
-        let mut file = File::create(path).unwrap();
-        file.write_all(contents.as_bytes()).unwrap();
-    }
+    use hl_common::test_support::RunFixture;
 
     #[test]
     fn hian_pass_minimal() {
-        let dir = tmp_dir();
-        let per_action_path = dir.join("per_action.jsonl");
-        write_file(
-            &per_action_path,
+        let fixture = RunFixture::empty("hian-test");
+        let dir = &fixture.dir;
+        let per_action_path = fixture.write(
+            "per_action.jsonl",
             r#"{"stepIdx":0,"action":"usd_class_transfer","submitTsMs":1000,"windowKeyMs":1000,"request":{"usd_class_transfer":{"toPerp":true,"usdc":25.0}},"ack":{"status":"ok"},"observed":{"channel":"accountClassTransfer","toPerp":true,"usdc":25.0,"time":1010}}
 {"stepIdx":1,"action":"perp_orders","submitTsMs":1200,"windowKeyMs":1200,"request":{"perp_orders":{"orders":[{"coin":"ETH","side":"sell","tif":"IOC","reduceOnly":true,"sz":0.01}]}},"ack":{"status":"ok","data":{"statuses":[{"kind":"filled","oid":1,"avgPx":"3875.1","totalSz":"0.01","statusTimestamp":1210}]}},"observed":[{"channel":"userFills","fills":[{"px":"3875.1","sz":"0.01","time":1210,"oid":1}]}]}"#,
         );
 
-        let ground_path = dir.join("ground_truth.json");
-        write_file(
-            &ground_path,
+        let ground_path = fixture.write(
+            "ground_truth.json",
             r#"{
   "caseId": "sample",
   "steps": [
@@ -984,24 +1341,249 @@ mod tests {
             amount_tol: None,
             px_tol_pct: None,
             sz_tol_pct: None,
+            fail_on_extra: None,
         };
 
         let output = run(&args).unwrap();
         assert!(output.result.pass);
         assert!(output.out_dir.join("eval_hian.json").exists());
+        assert!(!output.out_dir.join("eval_hian_diff.json").exists());
+    }
+
+    #[test]
+    fn dataset_ground_truth_parses() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("../../dataset/hian/case_128k/ground_truth.json");
+        let ground = load_ground_truth(&path).unwrap();
+        assert_eq!(ground.case_id.as_deref(), Some("case_128k"));
+        assert_eq!(ground.within_ms, Some(5000));
+        let kinds: Vec<String> = ground.steps.iter().map(ExpectedStep::describe).collect();
+        assert!(kinds[0].starts_with("usd_class_transfer { toPerp: true"));
+        assert!(kinds[1].starts_with("perp_order { coin: Some(\"ETH\")"));
+    }
+
+    #[test]
+    fn perp_order_multi_records_matched_alternative() {
+        let step: ExpectedStep = serde_json::from_str(
+            r#"{"perpOrderMulti": [{"coin": "ETH"}, {"coin": "SOL", "side": "buy"}, {"coin": "BTC"}]}"#,
+        )
+        .unwrap();
+        assert_eq!(step.describe(), "perpOrderMulti(ETH|SOL|BTC)");
+
+        let action: ActionLogRecord = serde_json::from_str(
+            r#"{"stepIdx":0,"action":"perp_orders","submitTsMs":1000,"windowKeyMs":1000,"request":{"perp_orders":{"orders":[{"coin":"SOL","side":"buy","tif":"GTC","sz":1.0}]}},"ack":{"status":"ok","data":{"statuses":[{"kind":"resting","oid":9}]}}}"#,
+        )
+        .unwrap();
+        let settings = SettingsUsed {
+            within_ms: DEFAULT_WITHIN_MS,
+            window_ms: DEFAULT_WINDOW_MS,
+            amount_tolerance: DEFAULT_AMOUNT_TOL,
+            px_tolerance_pct: DEFAULT_PX_TOL_PCT,
+            sz_tolerance_pct: DEFAULT_SZ_TOL_PCT,
+        };
+
+        let detail = match_step(&step, &action, &[], &settings).unwrap();
+        assert_eq!(detail.matched_alternative, Some(1));
+        assert_eq!(detail.oid, Some(9));
+
+        let miss: ExpectedStep =
+            serde_json::from_str(r#"{"perpOrderMulti": [{"coin": "ETH"}, {"coin": "BTC"}]}"#)
+                .unwrap();
+        let reason = match_step(&miss, &action, &[], &settings).unwrap_err();
+        assert!(reason.contains("[0] ETH"));
+        assert!(reason.contains("[1] BTC"));
+
+        // Alternatives use the same camelCase keys as perpOrder.
+        let reduce_only: ExpectedStep = serde_json::from_str(
+            r#"{"perpOrderMulti": [{"coin": "SOL", "reduceOnly": true}, {"coin": "SOL", "tif": "GTC"}]}"#,
+        )
+        .unwrap();
+        let detail = match_step(&reduce_only, &action, &[], &settings).unwrap();
+        assert_eq!(detail.matched_alternative, Some(1));
+    }
+
+    #[test]
+    fn fetch_funding_rate_checks_observed_range() {
+        let step: ExpectedStep = serde_json::from_str(
+            r#"{"fetchFundingRate": {"coin": "ETH", "funding_rate": {"ge": 0.0, "le": 0.0005}}}"#,
+        )
+        .unwrap();
+        let action: ActionLogRecord = serde_json::from_str(
+            r#"{"stepIdx":0,"action":"fetch_funding_rate","submitTsMs":1000,"windowKeyMs":1000,"request":{"fetch_funding_rate":{"coin":"ETH"}},"ack":{"status":"ok"},"observed":{"fundingRate":0.0001,"premium":0.0}}"#,
+        )
+        .unwrap();
+        let settings = SettingsUsed {
+            within_ms: DEFAULT_WITHIN_MS,
+            window_ms: DEFAULT_WINDOW_MS,
+            amount_tolerance: DEFAULT_AMOUNT_TOL,
+            px_tolerance_pct: DEFAULT_PX_TOL_PCT,
+            sz_tolerance_pct: DEFAULT_SZ_TOL_PCT,
+        };
+
+        let detail = match_step(&step, &action, &[], &settings).unwrap();
+        assert_eq!(detail.kind.to_string(), "fetch_funding_rate");
+
+        let negative: ExpectedStep = serde_json::from_str(
+            r#"{"fetchFundingRate": {"coin": "ETH", "funding_rate": {"le": 0.0}}}"#,
+        )
+        .unwrap();
+        assert!(match_step(&negative, &action, &[], &settings).is_err());
+        let other_coin: ExpectedStep =
+            serde_json::from_str(r#"{"fetchFundingRate": {"coin": "SOL"}}"#).unwrap();
+        assert!(match_step(&other_coin, &action, &[], &settings)
+            .unwrap_err()
+            .contains("coin mismatch"));
+    }
+
+    #[test]
+    fn strict_ordering_rejects_gaps() {
+        let fixture = RunFixture::empty("hian-test");
+        let dir = &fixture.dir;
+        let per_action_path = fixture.write(
+            "per_action.jsonl",
+            r#"{"stepIdx":0,"action":"perp_orders","submitTsMs":1000,"windowKeyMs":1000,"request":{"perp_orders":{"orders":[{"coin":"ETH","side":"buy","tif":"GTC","sz":0.01}]}},"ack":{"status":"ok","data":{"statuses":[{"kind":"resting","oid":1}]}}}
+{"stepIdx":1,"action":"sleep_ms","submitTsMs":1100,"windowKeyMs":1000,"request":{"sleep_ms":{"duration_ms":50}},"ack":{"status":"ok"}}
+{"stepIdx":2,"action":"perp_orders","submitTsMs":1200,"windowKeyMs":1200,"request":{"perp_orders":{"orders":[{"coin":"BTC","side":"buy","tif":"GTC","sz":0.001}]}},"ack":{"status":"ok","data":{"statuses":[{"kind":"resting","oid":2}]}}}"#,
+        );
+        let steps = r#"[{"perpOrder": {"coin": "ETH"}}, {"perpOrder": {"coin": "BTC"}}]"#;
+        let args_for = |ground: &str| {
+            let ground_path = dir.join(format!("{ground}.json"));
+            HianArgs {
+                ground: ground_path,
+                per_action: per_action_path.clone(),
+                ws_stream: None,
+                out_dir: Some(dir.clone()),
+                within_ms: None,
+                window_ms: None,
+                amount_tol: None,
+                px_tol_pct: None,
+                sz_tol_pct: None,
+                fail_on_extra: None,
+            }
+        };
+
+        fixture.write("loose.json", format!(r#"{{"steps": {steps}}}"#));
+        assert!(run(&args_for("loose")).unwrap().result.pass);
+        assert_eq!(run_command(&args_for("loose")).unwrap(), ExitCode::SUCCESS);
+
+        fixture.write(
+            "strict.json",
+            format!(r#"{{"strict_ordering": true, "steps": {steps}}}"#),
+        );
+        let strict = run(&args_for("strict")).unwrap().result;
+        assert!(!strict.pass);
+        assert_eq!(strict.missing.len(), 1);
+        assert_eq!(strict.missing[0].expect_idx, 1);
+        assert_eq!(
+            strict.missing[0].reason,
+            "strict ordering violated: gap of 1 actions"
+        );
+        assert_eq!(run_command(&args_for("strict")).unwrap(), ExitCode::from(2));
+    }
+
+    #[test]
+    fn unmatched_actions_are_reported_as_extra() {
+        let fixture = RunFixture::empty("hian-test");
+        let dir = &fixture.dir;
+        let per_action_path = fixture.write(
+            "per_action.jsonl",
+            r#"{"stepIdx":0,"action":"perp_orders","submitTsMs":1000,"windowKeyMs":1000,"request":{"perp_orders":{"orders":[{"coin":"BTC","side":"buy","tif":"GTC","sz":0.001}]}},"ack":{"status":"ok","data":{"statuses":[{"kind":"resting","oid":1}]}}}
+{"stepIdx":1,"action":"perp_orders","submitTsMs":1100,"windowKeyMs":1000,"request":{"perp_orders":{"orders":[{"coin":"ETH","side":"buy","tif":"GTC","sz":0.01}]}},"ack":{"status":"ok","data":{"statuses":[{"kind":"resting","oid":2}]}}}
+{"stepIdx":2,"action":"cancel_last","submitTsMs":1200,"windowKeyMs":1200,"request":{"cancel_last":{"coin":"ETH"}},"ack":{"status":"ok"}}"#,
+        );
+        let ground_path = fixture.write(
+            "ground_truth.json",
+            r#"{"steps":[{"perpOrder":{"coin":"ETH"}}]}"#,
+        );
+        let mut args = HianArgs {
+            ground: ground_path,
+            per_action: per_action_path,
+            ws_stream: None,
+            out_dir: Some(dir.clone()),
+            within_ms: None,
+            window_ms: None,
+            amount_tol: None,
+            px_tol_pct: None,
+            sz_tol_pct: None,
+            fail_on_extra: None,
+        };
+
+        let result = run(&args).unwrap().result;
+        assert!(result.pass);
+        assert_eq!(result.extra_count, 2);
+        assert_eq!(result.extra[0].step_idx, 0);
+        assert_eq!(result.extra[0].coin.as_deref(), Some("BTC"));
+        assert_eq!(result.extra[1].action, "cancel_last");
+        assert_eq!(result.extra[1].coin.as_deref(), Some("ETH"));
+
+        args.fail_on_extra = Some(1);
+        let output = run(&args).unwrap();
+        assert!(!output.result.pass);
+        let diff = std::fs::read_to_string(output.out_dir.join("eval_hian_diff.txt")).unwrap();
+        assert!(diff.contains("Extra actions (2):"));
+        assert!(diff.contains("#2 cancel_last @1200"));
+    }
+
+    #[test]
+    fn set_leverage_step_matches_request() {
+        let fixture = RunFixture::empty("hian-test");
+        let dir = &fixture.dir;
+        let per_action_path = fixture.write(
+            "per_action.jsonl",
+            r#"{"stepIdx":0,"action":"set_leverage","submitTsMs":1000,"windowKeyMs":1000,"request":{"set_leverage":{"coin":"ETH","leverage":5,"cross":true}},"ack":{"status":"ok"},"durationMs":42}"#,
+        );
+        let args_for = |name: &str, ground: &str| {
+            let ground_path = fixture.write(format!("{name}.json"), ground);
+            HianArgs {
+                ground: ground_path,
+                per_action: per_action_path.clone(),
+                ws_stream: None,
+                out_dir: Some(dir.join(name)),
+                within_ms: None,
+                window_ms: None,
+                amount_tol: None,
+                px_tol_pct: None,
+                sz_tol_pct: None,
+                fail_on_extra: None,
+            }
+        };
+
+        let output = run(&args_for(
+            "match",
+            r#"{"steps":[{"setLeverage":{"coin":"eth","leverage":5,"cross":true}}]}"#,
+        ))
+        .unwrap();
+        assert!(output.result.pass);
+        assert_eq!(output.result.matched[0].kind, "set_leverage");
+        assert_eq!(output.result.metrics.latency_ms["0"], Some(42));
+
+        let output = run(&args_for(
+            "mismatch",
+            r#"{"steps":[{"setLeverage":{"coin":"ETH","leverage":10}}]}"#,
+        ))
+        .unwrap();
+        assert!(!output.result.pass);
+        assert_eq!(
+            output.result.missing[0].reason,
+            "leverage mismatch (expected 10, got 5)"
+        );
+        let json_diff: Value = serde_json::from_str(
+            &std::fs::read_to_string(output.out_dir.join("eval_hian_diff.json")).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(json_diff["steps"][0]["status"], "fail");
     }
 
     #[test]
     fn hian_fail_amount() {
-        let dir = tmp_dir();
-        let per_action_path = dir.join("per_action.jsonl");
-        write_file(
-            &per_action_path,
+        let fixture = RunFixture::empty("hian-test");
+        let dir = &fixture.dir;
+        let per_action_path = fixture.write(
+            "per_action.jsonl",
             r#"{"stepIdx":0,"action":"usd_class_transfer","submitTsMs":1000,"windowKeyMs":1000,"request":{"usd_class_transfer":{"toPerp":true,"usdc":24.9}},"ack":{"status":"ok"},"observed":{"channel":"accountClassTransfer","toPerp":true,"usdc":24.9,"time":1010}}"#,
         );
-        let ground_path = dir.join("ground_truth.json");
-        write_file(
-            &ground_path,
+        let ground_path = fixture.write(
+            "ground_truth.json",
             r#"{"steps":[{"usdClassTransfer":{"toPerp":true,"usdc":{"eq":25.0,"tol":0.01}}}]}"#,
         );
         let args = HianArgs {
@@ -1014,9 +1596,17 @@ mod tests {
             amount_tol: None,
             px_tol_pct: None,
             sz_tol_pct: None,
+            fail_on_extra: None,
         };
         let output = run(&args).unwrap();
         assert!(!output.result.pass);
         assert!(output.out_dir.join("eval_hian_diff.txt").exists());
+        let json_diff: Value = serde_json::from_str(
+            &std::fs::read_to_string(output.out_dir.join("eval_hian_diff.json")).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(json_diff["passed"], 0);
+        assert_eq!(json_diff["failed"], 1);
+        assert_eq!(json_diff["steps"][0]["status"], "fail");
     }
 }
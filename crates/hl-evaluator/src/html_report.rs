@@ -0,0 +1,142 @@
+use anyhow::{Context, Result};
+use minijinja::{context, Environment};
+use serde::Serialize;
+
+const TEMPLATE: &str = include_str!("../templates/report.html");
+
+/// Bar chart geometry, in SVG user units.
+const BAR_HEIGHT: f64 = 22.0;
+const BAR_GAP: f64 = 8.0;
+const LABEL_WIDTH: f64 = 180.0;
+const CHART_WIDTH: f64 = 420.0;
+
+/// Everything `--html-report` shows, built from the score report.
+#[derive(Debug, Clone)]
+pub struct ReportData {
+    pub title: String,
+    pub final_score: f64,
+    pub base: f64,
+    pub bonus: f64,
+    pub penalty: f64,
+    pub domains: Vec<DomainRow>,
+    pub unmapped_signatures: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DomainRow {
+    pub name: String,
+    pub weight: f64,
+    pub unique_count: usize,
+    pub contribution: f64,
+    pub signatures: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct Bar<'a> {
+    name: &'a str,
+    contribution: f64,
+    y: f64,
+    width: f64,
+}
+
+#[derive(Debug, Serialize)]
+struct SignatureRow<'a> {
+    signature: &'a str,
+    domain: &'a str,
+}
+
+/// Renders a self-contained page: inline CSS, SVG and a few lines of JS for sorting.
+pub fn render(data: &ReportData) -> Result<String> {
+    let max_contribution = data
+        .domains
+        .iter()
+        .map(|domain| domain.contribution)
+        .fold(0.0, f64::max);
+    let bars: Vec<Bar> = data
+        .domains
+        .iter()
+        .enumerate()
+        .map(|(idx, domain)| Bar {
+            name: &domain.name,
+            contribution: domain.contribution,
+            y: idx as f64 * (BAR_HEIGHT + BAR_GAP),
+            width: if max_contribution > 0.0 {
+                domain.contribution / max_contribution * CHART_WIDTH
+            } else {
+                0.0
+            },
+        })
+        .collect();
+    let mut signatures: Vec<SignatureRow> = data
+        .domains
+        .iter()
+        .flat_map(|domain| {
+            domain.signatures.iter().map(|signature| SignatureRow {
+                signature,
+                domain: &domain.name,
+            })
+        })
+        .collect();
+    signatures.sort_by(|a, b| a.signature.cmp(b.signature));
+
+    let mut env = Environment::new();
+    env.add_template("report.html", TEMPLATE)?;
+    env.get_template("report.html")?
+        .render(context! {
+            title => data.title,
+            final_score => data.final_score,
+            base => data.base,
+            bonus => data.bonus,
+            penalty => data.penalty,
+            domains => data.domains,
+            bars => bars,
+            bar_height => BAR_HEIGHT,
+            label_width => LABEL_WIDTH,
+            chart_width => LABEL_WIDTH + CHART_WIDTH + 80.0,
+            chart_height => (data.domains.len().max(1) as f64) * (BAR_HEIGHT + BAR_GAP),
+            signatures => signatures,
+            unmapped_signatures => data.unmapped_signatures,
+        })
+        .context("failed to render HTML report")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes_names_and_scales_bars() {
+        let html = render(&ReportData {
+            title: "run <1>".to_string(),
+            final_score: 2.5,
+            base: 2.0,
+            bonus: 0.5,
+            penalty: 0.0,
+            domains: vec![
+                DomainRow {
+                    name: "perp".to_string(),
+                    weight: 1.0,
+                    unique_count: 2,
+                    contribution: 2.0,
+                    signatures: vec!["perp.order.GTC:false:none".to_string()],
+                },
+                DomainRow {
+                    name: "risk".to_string(),
+                    weight: 1.0,
+                    unique_count: 0,
+                    contribution: 0.0,
+                    signatures: Vec::new(),
+                },
+            ],
+            unmapped_signatures: vec!["odd.sig".to_string()],
+        })
+        .unwrap();
+        assert!(html.contains("run &lt;1&gt;"));
+        assert!(
+            html.contains(r#"width="420.0""#),
+            "longest bar spans the chart"
+        );
+        assert!(html.contains("odd.sig"));
+        assert!(!html.contains("http://") && !html.contains("https://"));
+    }
+}
@@ -0,0 +1,157 @@
+use std::cmp::Ordering;
+
+use indexmap::IndexMap;
+use serde::Serialize;
+
+/// One `--batch-run` directory's scores, as fed to the leaderboard.
+#[derive(Debug, Clone)]
+pub struct RunScores {
+    pub run_dir: String,
+    pub final_score: f64,
+    pub domains: Vec<DomainScore>,
+}
+
+#[derive(Debug, Clone)]
+pub struct DomainScore {
+    pub name: String,
+    pub weight: f64,
+    pub unique_count: usize,
+    pub contribution: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Leaderboard {
+    /// Runs by final score, best first.
+    pub leaderboard: Vec<RunRank>,
+    /// Domains in YAML order, each ranking the runs by contribution.
+    pub domains: Vec<DomainLeaderboard>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RunRank {
+    pub run_dir: String,
+    pub final_score: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DomainLeaderboard {
+    pub domain_name: String,
+    pub weight: f64,
+    pub best_run: Option<String>,
+    pub best_unique_count: usize,
+    pub best_contribution: f64,
+    pub rankings: Vec<DomainRank>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DomainRank {
+    pub run_dir: String,
+    pub unique_count: usize,
+    pub contribution: f64,
+}
+
+impl Leaderboard {
+    pub fn build(runs: &[RunScores]) -> Self {
+        let mut leaderboard: Vec<RunRank> = runs
+            .iter()
+            .map(|run| RunRank {
+                run_dir: run.run_dir.clone(),
+                final_score: run.final_score,
+            })
+            .collect();
+        leaderboard.sort_by(|a, b| {
+            descending(a.final_score, b.final_score).then_with(|| a.run_dir.cmp(&b.run_dir))
+        });
+
+        let mut by_domain: IndexMap<&str, (f64, Vec<DomainRank>)> = IndexMap::new();
+        for run in runs {
+            for domain in &run.domains {
+                by_domain
+                    .entry(domain.name.as_str())
+                    .or_insert_with(|| (domain.weight, Vec::new()))
+                    .1
+                    .push(DomainRank {
+                        run_dir: run.run_dir.clone(),
+                        unique_count: domain.unique_count,
+                        contribution: domain.contribution,
+                    });
+            }
+        }
+
+        let domains = by_domain
+            .into_iter()
+            .map(|(name, (weight, mut rankings))| {
+                rankings.sort_by(|a, b| {
+                    descending(a.contribution, b.contribution)
+                        .then_with(|| b.unique_count.cmp(&a.unique_count))
+                        .then_with(|| a.run_dir.cmp(&b.run_dir))
+                });
+                let best = rankings.first();
+                DomainLeaderboard {
+                    domain_name: name.to_string(),
+                    weight,
+                    best_run: best.map(|rank| rank.run_dir.clone()),
+                    best_unique_count: best.map_or(0, |rank| rank.unique_count),
+                    best_contribution: best.map_or(0.0, |rank| rank.contribution),
+                    rankings,
+                }
+            })
+            .collect();
+
+        Self {
+            leaderboard,
+            domains,
+        }
+    }
+}
+
+fn descending(a: f64, b: f64) -> Ordering {
+    b.partial_cmp(&a).unwrap_or(Ordering::Equal)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(run_dir: &str, final_score: f64, perp: (usize, f64), risk: (usize, f64)) -> RunScores {
+        let domain = |name: &str, (unique_count, contribution): (usize, f64)| DomainScore {
+            name: name.to_string(),
+            weight: 1.0,
+            unique_count,
+            contribution,
+        };
+        RunScores {
+            run_dir: run_dir.to_string(),
+            final_score,
+            domains: vec![domain("perp", perp), domain("risk", risk)],
+        }
+    }
+
+    #[test]
+    fn ranks_runs_overall_and_per_domain() {
+        let board = Leaderboard::build(&[
+            run("runs/a", 3.0, (2, 2.0), (1, 1.0)),
+            run("runs/b", 4.5, (4, 4.0), (0, 0.0)),
+            run("runs/c", 1.0, (1, 1.0), (1, 1.0)),
+        ]);
+
+        let overall: Vec<&str> = board
+            .leaderboard
+            .iter()
+            .map(|rank| rank.run_dir.as_str())
+            .collect();
+        assert_eq!(overall, vec!["runs/b", "runs/a", "runs/c"]);
+
+        assert_eq!(board.domains[0].domain_name, "perp");
+        assert_eq!(board.domains[0].best_run.as_deref(), Some("runs/b"));
+        assert_eq!(board.domains[0].best_unique_count, 4);
+
+        let risk: Vec<&str> = board.domains[1]
+            .rankings
+            .iter()
+            .map(|rank| rank.run_dir.as_str())
+            .collect();
+        assert_eq!(risk, vec!["runs/a", "runs/c", "runs/b"]);
+        assert_eq!(board.domains[1].best_contribution, 1.0);
+    }
+}
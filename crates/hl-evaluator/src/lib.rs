@@ -0,0 +1,41 @@
+//! Scoring for HyperLiquidBench runs. [`eval`] is the library API; [`run_cli`] is what the
+//! `hl-evaluator` binary runs.
+
+mod coverage;
+mod diff;
+pub mod eval;
+mod explain;
+mod hian;
+mod html_report;
+mod leaderboard;
+mod regression;
+mod snapshot;
+mod sqlite;
+mod timing;
+mod trend;
+mod ws;
+
+use std::process::ExitCode;
+
+use anyhow::Result;
+use clap::Parser;
+
+/// Parses the command line and runs the requested evaluation. Failures that are verdicts
+/// rather than errors (a `diff` regression, a `hian` FAIL) come back as the exit code.
+pub fn run_cli() -> Result<ExitCode> {
+    let coverage_args = coverage::CoverageArgs::parse();
+    match &coverage_args.command {
+        Some(coverage::Command::Diff(args)) => return diff::run(args),
+        Some(coverage::Command::Trend(args)) => {
+            trend::run(args)?;
+            return Ok(ExitCode::SUCCESS);
+        }
+        Some(coverage::Command::Hian(args)) => return hian::run_command(args),
+        None => {}
+    }
+    if coverage::run_pattern_tests(&coverage_args)? || coverage::run_batch(&coverage_args)? {
+        return Ok(ExitCode::SUCCESS);
+    }
+    coverage::run(&coverage_args)?;
+    Ok(ExitCode::SUCCESS)
+}
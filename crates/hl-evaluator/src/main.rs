@@ -1,13 +1,8 @@
-mod coverage;
+use std::process::ExitCode;
 
 use anyhow::Result;
-use clap::Parser;
 
-fn main() -> Result<()> {
+fn main() -> Result<ExitCode> {
     dotenvy::dotenv().ok();
-
-    let coverage_args = coverage::CoverageArgs::parse();
-    let report = coverage::run(&coverage_args)?;
-    println!("FINAL_SCORE={:.3}", report.final_score);
-    Ok(())
+    hl_evaluator::run_cli()
 }
@@ -0,0 +1,176 @@
+use anyhow::{Context, Result};
+use hl_common::Signature;
+use serde::{Deserialize, Serialize};
+
+/// The subset of `eval_score.json` needed to compare two runs.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScoreSnapshot {
+    pub final_score: f64,
+    #[serde(default)]
+    pub base: f64,
+    #[serde(default)]
+    pub bonus: f64,
+    #[serde(default)]
+    pub penalty: f64,
+    #[serde(default)]
+    pub per_domain: Vec<DomainSnapshot>,
+    #[serde(default)]
+    pub unique_signatures: Vec<Signature>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DomainSnapshot {
+    pub name: String,
+    pub contribution: f64,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RegressionReport {
+    pub baseline_score: f64,
+    pub current_score: f64,
+    pub score_delta: f64,
+    pub tolerance: f64,
+    pub baseline_unique_signatures: usize,
+    pub current_unique_signatures: usize,
+    pub unique_signatures_delta: i64,
+    pub per_domain: Vec<DomainDelta>,
+    pub regressed: bool,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DomainDelta {
+    pub name: String,
+    pub baseline: f64,
+    pub current: f64,
+    pub delta: f64,
+}
+
+/// Reads a baseline `eval_score.json` from a local path or an http(s) URL.
+pub fn load_baseline(source: &str) -> Result<ScoreSnapshot> {
+    let raw = if source.starts_with("http://") || source.starts_with("https://") {
+        reqwest::blocking::get(source)
+            .and_then(|resp| resp.error_for_status())
+            .and_then(|resp| resp.text())
+            .with_context(|| format!("failed to fetch baseline {source}"))?
+    } else {
+        std::fs::read_to_string(source)
+            .with_context(|| format!("failed to read baseline {source}"))?
+    };
+    serde_json::from_str(&raw).with_context(|| format!("failed to parse baseline {source}"))
+}
+
+pub fn compare(
+    baseline: &ScoreSnapshot,
+    current: &ScoreSnapshot,
+    tolerance: f64,
+) -> RegressionReport {
+    let mut per_domain: Vec<DomainDelta> = baseline
+        .per_domain
+        .iter()
+        .map(|base| {
+            let current = current
+                .per_domain
+                .iter()
+                .find(|d| d.name == base.name)
+                .map_or(0.0, |d| d.contribution);
+            DomainDelta {
+                name: base.name.clone(),
+                baseline: base.contribution,
+                current,
+                delta: current - base.contribution,
+            }
+        })
+        .collect();
+    for domain in &current.per_domain {
+        if !baseline.per_domain.iter().any(|d| d.name == domain.name) {
+            per_domain.push(DomainDelta {
+                name: domain.name.clone(),
+                baseline: 0.0,
+                current: domain.contribution,
+                delta: domain.contribution,
+            });
+        }
+    }
+
+    let baseline_unique = baseline.unique_signatures.len();
+    let current_unique = current.unique_signatures.len();
+    let regressed =
+        current.final_score < baseline.final_score - tolerance || current_unique < baseline_unique;
+
+    RegressionReport {
+        baseline_score: baseline.final_score,
+        current_score: current.final_score,
+        score_delta: current.final_score - baseline.final_score,
+        tolerance,
+        baseline_unique_signatures: baseline_unique,
+        current_unique_signatures: current_unique,
+        unique_signatures_delta: current_unique as i64 - baseline_unique as i64,
+        per_domain,
+        regressed,
+    }
+}
+
+impl RegressionReport {
+    /// Human-readable reasons, one line per dropped metric.
+    pub fn describe_drops(&self) -> Vec<String> {
+        let mut lines = vec![format!(
+            "final score {:.3} -> {:.3} ({:+.3}, tolerance {:.3})",
+            self.baseline_score, self.current_score, self.score_delta, self.tolerance
+        )];
+        if self.unique_signatures_delta < 0 {
+            lines.push(format!(
+                "unique signatures {} -> {}",
+                self.baseline_unique_signatures, self.current_unique_signatures
+            ));
+        }
+        for domain in self.per_domain.iter().filter(|d| d.delta < 0.0) {
+            lines.push(format!(
+                "domain '{}' contribution {:.3} -> {:.3} ({:+.3})",
+                domain.name, domain.baseline, domain.current, domain.delta
+            ));
+        }
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(raw: serde_json::Value) -> ScoreSnapshot {
+        serde_json::from_value(raw).unwrap()
+    }
+
+    #[test]
+    fn detects_score_and_domain_regression() {
+        let baseline = snapshot(serde_json::json!({
+            "finalScore": 5.0,
+            "perDomain": [
+                {"name": "perp", "contribution": 3.0},
+                {"name": "risk", "contribution": 2.0}
+            ],
+            "uniqueSignatures": ["a", "b", "c"]
+        }));
+        let current = snapshot(serde_json::json!({
+            "finalScore": 4.0,
+            "perDomain": [
+                {"name": "perp", "contribution": 3.0},
+                {"name": "risk", "contribution": 1.0}
+            ],
+            "uniqueSignatures": ["a", "b", "c"]
+        }));
+
+        let report = compare(&baseline, &current, 0.0);
+        assert!(report.regressed);
+        assert_eq!(report.score_delta, -1.0);
+        let drops = report.describe_drops();
+        assert!(drops.iter().any(|line| line.contains("domain 'risk'")));
+        assert!(!drops.iter().any(|line| line.contains("domain 'perp'")));
+
+        assert!(!compare(&baseline, &current, 1.5).regressed);
+    }
+}
@@ -0,0 +1,185 @@
+use std::{collections::BTreeSet, path::Path};
+
+use anyhow::{Context, Result};
+use serde_json::Value;
+
+/// Largest numeric difference still treated as equal.
+const FLOAT_TOLERANCE: f64 = 1e-9;
+
+/// Reads a golden `eval_score.json` written by `--update-snapshot`.
+pub fn load(path: &Path) -> Result<Value> {
+    let raw = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read snapshot {}", path.display()))?;
+    serde_json::from_str(&raw)
+        .with_context(|| format!("failed to parse snapshot {}", path.display()))
+}
+
+/// Compares a score report against its snapshot field by field, one line per difference.
+///
+/// Numbers match within `FLOAT_TOLERANCE`, string arrays (signature lists) compare as
+/// sets, and arrays of objects with a `name` (per-domain rows) are matched by name.
+/// A field listed in `ignore` is skipped by key or by dotted path (`perDomain.weight`).
+pub fn diff(snapshot: &Value, current: &Value, ignore: &[String]) -> Vec<String> {
+    let mut diffs = Vec::new();
+    diff_value("", snapshot, current, ignore, &mut diffs);
+    diffs
+}
+
+fn diff_value(
+    path: &str,
+    expected: &Value,
+    actual: &Value,
+    ignore: &[String],
+    out: &mut Vec<String>,
+) {
+    match (expected, actual) {
+        (Value::Object(expected), Value::Object(actual)) => {
+            let keys: BTreeSet<&String> = expected.keys().chain(actual.keys()).collect();
+            for key in keys {
+                let child = join(path, key);
+                if ignore
+                    .iter()
+                    .any(|field| field == key || *field == strip_names(&child))
+                {
+                    continue;
+                }
+                match (expected.get(key), actual.get(key)) {
+                    (Some(e), Some(a)) => diff_value(&child, e, a, ignore, out),
+                    (Some(e), None) => out.push(format!("{child}: {e} -> (missing)")),
+                    (None, Some(a)) => out.push(format!("{child}: (missing) -> {a}")),
+                    (None, None) => {}
+                }
+            }
+        }
+        (Value::Array(expected), Value::Array(actual)) if all_strings(expected, actual) => {
+            let removed: Vec<&str> = strings(expected)
+                .filter(|s| !actual.iter().any(|a| a.as_str() == Some(s)))
+                .collect();
+            let added: Vec<&str> = strings(actual)
+                .filter(|s| !expected.iter().any(|e| e.as_str() == Some(s)))
+                .collect();
+            if !removed.is_empty() {
+                out.push(format!("{path}: removed {}", removed.join(", ")));
+            }
+            if !added.is_empty() {
+                out.push(format!("{path}: added {}", added.join(", ")));
+            }
+        }
+        (Value::Array(expected), Value::Array(actual)) if all_named(expected, actual) => {
+            for e in expected {
+                let name = e["name"].as_str().unwrap_or_default();
+                let child = format!("{path}[{name}]");
+                match actual.iter().find(|a| a["name"] == e["name"]) {
+                    Some(a) => diff_value(&child, e, a, ignore, out),
+                    None => out.push(format!("{child}: removed")),
+                }
+            }
+            for a in actual {
+                if !expected.iter().any(|e| e["name"] == a["name"]) {
+                    let name = a["name"].as_str().unwrap_or_default();
+                    out.push(format!("{path}[{name}]: added"));
+                }
+            }
+        }
+        (Value::Array(expected), Value::Array(actual)) => {
+            if expected.len() != actual.len() {
+                out.push(format!(
+                    "{path}: length {} -> {}",
+                    expected.len(),
+                    actual.len()
+                ));
+            }
+            for (idx, (e, a)) in expected.iter().zip(actual).enumerate() {
+                diff_value(&format!("{path}[{idx}]"), e, a, ignore, out);
+            }
+        }
+        (Value::Number(e), Value::Number(a)) => {
+            let (e, a) = (e.as_f64().unwrap_or(0.0), a.as_f64().unwrap_or(0.0));
+            if (e - a).abs() > FLOAT_TOLERANCE {
+                out.push(format!("{path}: {e} -> {a}"));
+            }
+        }
+        (e, a) if e != a => out.push(format!("{path}: {e} -> {a}")),
+        _ => {}
+    }
+}
+
+fn join(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_string()
+    } else {
+        format!("{path}.{key}")
+    }
+}
+
+/// Drops `[...]` selectors so `perDomain[perp].weight` matches the ignore entry `perDomain.weight`.
+fn strip_names(path: &str) -> String {
+    let mut out = String::with_capacity(path.len());
+    let mut depth = 0;
+    for ch in path.chars() {
+        match ch {
+            '[' => depth += 1,
+            ']' => depth -= 1,
+            _ if depth == 0 => out.push(ch),
+            _ => {}
+        }
+    }
+    out
+}
+
+fn strings(values: &[Value]) -> impl Iterator<Item = &str> {
+    values.iter().filter_map(Value::as_str)
+}
+
+fn all_strings(expected: &[Value], actual: &[Value]) -> bool {
+    expected.iter().chain(actual).all(Value::is_string)
+        && !(expected.is_empty() && actual.is_empty())
+}
+
+fn all_named(expected: &[Value], actual: &[Value]) -> bool {
+    expected
+        .iter()
+        .chain(actual)
+        .all(|v| v.get("name").is_some_and(Value::is_string))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn reports_score_signature_and_domain_changes() {
+        let snapshot = json!({
+            "finalScore": 3.0,
+            "generatedAtMs": 1,
+            "uniqueSignatures": ["a", "b"],
+            "perDomain": [{"name": "perp", "uniqueCount": 2, "weight": 1.0}]
+        });
+        let same = json!({
+            "finalScore": 3.0 + 1e-12,
+            "generatedAtMs": 1,
+            "uniqueSignatures": ["b", "a"],
+            "perDomain": [{"name": "perp", "uniqueCount": 2, "weight": 1.0}]
+        });
+        assert!(diff(&snapshot, &same, &[]).is_empty());
+
+        let changed = json!({
+            "finalScore": 2.5,
+            "generatedAtMs": 2,
+            "uniqueSignatures": ["a", "c"],
+            "perDomain": [{"name": "perp", "uniqueCount": 1, "weight": 2.0}]
+        });
+        let ignore = vec!["generatedAtMs".to_string(), "perDomain.weight".to_string()];
+        let diffs = diff(&snapshot, &changed, &ignore);
+        assert_eq!(
+            diffs,
+            vec![
+                "finalScore: 3 -> 2.5",
+                "perDomain[perp].uniqueCount: 2 -> 1",
+                "uniqueSignatures: removed b",
+                "uniqueSignatures: added c",
+            ]
+        );
+    }
+}
@@ -0,0 +1,148 @@
+use std::{io::BufRead, path::Path};
+
+use anyhow::{Context, Result};
+use hl_common::{artifact_db::ArtifactDb, artifacts::open_jsonl, ActionLogRecord, RunArtifacts};
+
+/// Imports the action records at `per_action` and the run's routed orders (from whichever
+/// artifact backend wrote them) into a fresh `run_artifacts.db`-schema database at
+/// `db_path`.
+pub fn export_run(per_action: &Path, db_path: &Path) -> Result<()> {
+    if db_path.exists() {
+        std::fs::remove_file(db_path)
+            .with_context(|| format!("failed to replace {}", db_path.display()))?;
+    }
+    let mut db = ArtifactDb::open(db_path)?;
+    db.insert_actions(&read_actions(per_action)?)
+        .context("failed to import action records")?;
+
+    let run_dir = per_action
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    for order in RunArtifacts::open(run_dir)?.routed_orders()? {
+        db.insert_routed_order(&order, None)
+            .context("failed to import routed orders")?;
+    }
+    Ok(())
+}
+
+fn read_actions(path: &Path) -> Result<Vec<ActionLogRecord>> {
+    let mut records = Vec::new();
+    for (line_no, line) in open_jsonl(path)?.lines().enumerate() {
+        let line = line.with_context(|| format!("failed to read line {}", line_no + 1))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        records.push(
+            serde_json::from_str(&line).with_context(|| {
+                format!("failed to parse ActionLogRecord on line {}", line_no + 1)
+            })?,
+        );
+    }
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hl_common::{
+        test_support::{ack_ok, action_record, perp_order_record, RunFixture, PERP_DOMAINS_YAML},
+        RoutedOrderRecord,
+    };
+    use rusqlite::Connection;
+
+    fn routed(oid: u64, coin: &str, spot: bool) -> RoutedOrderRecord {
+        RoutedOrderRecord {
+            ts_ms: 1_000,
+            oid: Some(oid),
+            coin: coin.to_string(),
+            side: "buy".to_string(),
+            px: 3465.0,
+            sz: 0.01,
+            tif: "Gtc".to_string(),
+            spot,
+            ..Default::default()
+        }
+    }
+
+    fn query<T: rusqlite::types::FromSql>(db_path: &Path, sql: &str) -> Vec<T> {
+        let conn = Connection::open(db_path).unwrap();
+        let mut stmt = conn.prepare(sql).unwrap();
+        stmt.query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn legacy_csv_columns_are_mapped_by_header() {
+        let fixture = RunFixture::new(
+            "hl-sqlite-csv",
+            PERP_DOMAINS_YAML,
+            &[
+                perp_order_record(0, serde_json::json!({"tif": "Gtc", "coin": "ETH"})),
+                ActionLogRecord {
+                    notes: Some("n".to_string()),
+                    ..action_record(1, "cancel_last", serde_json::json!({}), ack_ok("success"))
+                },
+            ],
+        );
+        // Written before the spot column existed, with the duplicate header old runners
+        // emitted.
+        std::fs::write(
+            fixture.dir.join("orders_routed.csv"),
+            "ts,oid,coin,side,px,sz,tif,reduceOnly,builderCode\n\
+             tsMs,oid,coin,side,px,sz,tif,reduceOnly,builderCode\n\
+             1000,1,ETH,buy,3465.0,0.01,Gtc,false,\n\
+             1000,2,BTC,buy,59400.0,0.01,Alo,false,code\n\
+             1000,3,ETH,sell,3535.0,0.01,Gtc,true,\n",
+        )
+        .unwrap();
+
+        let db_path = fixture.dir.join("run_data.db");
+        export_run(&fixture.dir.join("per_action.jsonl"), &db_path).unwrap();
+
+        let eth: Vec<i64> = query(
+            &db_path,
+            "SELECT oid FROM routed_orders WHERE coin = 'ETH' AND spot = 0 ORDER BY oid",
+        );
+        assert_eq!(eth, vec![1, 3]);
+        let builder: Vec<String> = query(
+            &db_path,
+            "SELECT builder_code FROM routed_orders WHERE builder_code IS NOT NULL",
+        );
+        assert_eq!(builder, vec!["code"]);
+        let notes: Vec<String> = query(&db_path, "SELECT notes FROM per_action WHERE step_idx = 1");
+        assert_eq!(notes, vec!["n"]);
+    }
+
+    #[test]
+    fn parquet_runs_export_their_routed_orders() {
+        let fixture = RunFixture::empty("hl-sqlite-parquet");
+        let mut artifacts = fixture.artifacts(false);
+        artifacts
+            .log_action(&perp_order_record(
+                0,
+                serde_json::json!({"tif": "Gtc", "coin": "ETH"}),
+            ))
+            .unwrap();
+        artifacts.buffer_routed_orders();
+        artifacts.disable_routed_csv().unwrap();
+        artifacts
+            .log_routed_order(&routed(7, "ETH", false))
+            .unwrap();
+        artifacts
+            .log_routed_order(&routed(8, "PURR", true))
+            .unwrap();
+        artifacts.write_routed_parquet().unwrap();
+        drop(artifacts);
+
+        let db_path = fixture.dir.join("run_data.db");
+        export_run(&fixture.dir.join("per_action.jsonl"), &db_path).unwrap();
+
+        let spot: Vec<String> = query(&db_path, "SELECT coin FROM routed_orders WHERE spot = 1");
+        assert_eq!(spot, vec!["PURR"]);
+        let actions: Vec<i64> = query(&db_path, "SELECT COUNT(*) FROM per_action");
+        assert_eq!(actions, vec![1]);
+    }
+}
@@ -0,0 +1,161 @@
+use std::collections::BTreeMap;
+
+use hl_common::window_count;
+use serde::Serialize;
+
+/// Share of windows below which a run whose busiest windows hold most steps is bursty.
+const BURSTY_WINDOW_SHARE: f64 = 0.2;
+/// Share of occupied windows at or above which a run counts as spread.
+const SPREAD_WINDOW_SHARE: f64 = 0.5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TimingQuality {
+    Bursty,
+    Spread,
+    Uniform,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SlowStep {
+    pub step_idx: usize,
+    pub gap_ms: i64,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TimingReport {
+    pub step_count: usize,
+    pub mean_interval_ms: f64,
+    pub stddev_interval_ms: f64,
+    pub min_interval_ms: i64,
+    pub max_interval_ms: i64,
+    /// Steps submitted more than `mean + 2 * stddev` after the previous step.
+    pub slow_steps: Vec<SlowStep>,
+    pub occupied_windows: usize,
+    pub total_windows: u64,
+    pub timing_quality: TimingQuality,
+}
+
+/// Collects `(step_idx, submit_ts_ms, window_key_ms)` in log order.
+#[derive(Debug, Default)]
+pub struct TimingAnalysis {
+    steps: Vec<(usize, i64, i64)>,
+}
+
+impl TimingAnalysis {
+    pub fn record(&mut self, step_idx: usize, submit_ts_ms: i64, window_key_ms: i64) {
+        self.steps.push((step_idx, submit_ts_ms, window_key_ms));
+    }
+
+    pub fn finalize(&self, window_ms: i64) -> TimingReport {
+        let gaps: Vec<(usize, i64)> = self
+            .steps
+            .windows(2)
+            .map(|pair| (pair[1].0, pair[1].1 - pair[0].1))
+            .collect();
+
+        let (mean, stddev) = if gaps.is_empty() {
+            (0.0, 0.0)
+        } else {
+            let n = gaps.len() as f64;
+            let mean = gaps.iter().map(|(_, gap)| *gap as f64).sum::<f64>() / n;
+            let variance = gaps
+                .iter()
+                .map(|(_, gap)| (*gap as f64 - mean).powi(2))
+                .sum::<f64>()
+                / n;
+            (mean, variance.sqrt())
+        };
+        let threshold = mean + 2.0 * stddev;
+        let slow_steps = gaps
+            .iter()
+            .filter(|(_, gap)| *gap as f64 > threshold)
+            .map(|&(step_idx, gap_ms)| SlowStep { step_idx, gap_ms })
+            .collect();
+
+        let mut per_window: BTreeMap<i64, usize> = BTreeMap::new();
+        for (_, _, window_key_ms) in &self.steps {
+            *per_window.entry(*window_key_ms).or_default() += 1;
+        }
+        let total_windows = match (per_window.keys().next(), per_window.keys().next_back()) {
+            (Some(first), Some(last)) => window_count(*first, last + window_ms, window_ms),
+            _ => 0,
+        };
+
+        TimingReport {
+            step_count: self.steps.len(),
+            mean_interval_ms: mean,
+            stddev_interval_ms: stddev,
+            min_interval_ms: gaps.iter().map(|(_, gap)| *gap).min().unwrap_or(0),
+            max_interval_ms: gaps.iter().map(|(_, gap)| *gap).max().unwrap_or(0),
+            slow_steps,
+            occupied_windows: per_window.len(),
+            total_windows,
+            timing_quality: classify(&per_window, total_windows),
+        }
+    }
+}
+
+fn classify(per_window: &BTreeMap<i64, usize>, total_windows: u64) -> TimingQuality {
+    if total_windows == 0 {
+        return TimingQuality::Uniform;
+    }
+    let total_steps: usize = per_window.values().sum();
+    let mut counts: Vec<usize> = per_window.values().copied().collect();
+    counts.sort_unstable_by(|a, b| b.cmp(a));
+
+    // Fewest windows that together hold a majority of the steps.
+    let mut covered = 0;
+    let mut busiest = 0;
+    for count in counts {
+        covered += count;
+        busiest += 1;
+        if covered * 2 > total_steps {
+            break;
+        }
+    }
+
+    let total = total_windows as f64;
+    if (busiest as f64) < BURSTY_WINDOW_SHARE * total {
+        TimingQuality::Bursty
+    } else if per_window.len() as f64 >= SPREAD_WINDOW_SHARE * total {
+        TimingQuality::Spread
+    } else {
+        TimingQuality::Uniform
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flags_slow_steps_and_bursts() {
+        let mut analysis = TimingAnalysis::default();
+        let mut ts = 0;
+        for step_idx in 0..10 {
+            analysis.record(step_idx, ts, (ts / 200) * 200);
+            ts += 100;
+        }
+        ts += 4_900;
+        analysis.record(10, ts, (ts / 200) * 200);
+
+        let report = analysis.finalize(200);
+        assert_eq!(report.min_interval_ms, 100);
+        assert_eq!(report.max_interval_ms, 5_000);
+        assert_eq!(report.slow_steps.len(), 1);
+        assert_eq!(report.slow_steps[0].step_idx, 10);
+        assert_eq!(report.timing_quality, TimingQuality::Bursty);
+
+        let mut even = TimingAnalysis::default();
+        for step_idx in 0..5 {
+            let ts = step_idx as i64 * 200;
+            even.record(step_idx, ts, ts);
+        }
+        let report = even.finalize(200);
+        assert!(report.slow_steps.is_empty());
+        assert_eq!(report.timing_quality, TimingQuality::Spread);
+    }
+}
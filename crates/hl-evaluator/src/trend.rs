@@ -0,0 +1,296 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use clap::Args;
+use serde::Serialize;
+use serde_json::Value;
+
+/// Lowest to highest score.
+const SPARK_LEVELS: &[u8] = b"_.-~=+*#";
+
+/// `hl-evaluator trend`: score history across a directory of scored runs.
+#[derive(Args, Debug, Clone)]
+pub struct TrendArgs {
+    /// Directory to scan, typically runs/
+    #[arg(long)]
+    pub dir: PathBuf,
+    /// Glob, relative to --dir, selecting the score files
+    #[arg(long, default_value = "**/eval_score.json")]
+    pub glob: String,
+    /// Runs in each moving average / standard deviation
+    #[arg(long, default_value_t = 5, value_parser = clap::value_parser!(u64).range(1..))]
+    pub window: u64,
+    /// Where score_trend.json and score_trend.csv go (default: --dir)
+    #[arg(long)]
+    pub out_dir: Option<PathBuf>,
+}
+
+/// One scored run, oldest first, with statistics over the `window` runs ending here.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrendPoint {
+    pub path: PathBuf,
+    pub timestamp: DateTime<Utc>,
+    /// `evalScore`, `runMeta` or `mtime`: where `timestamp` came from.
+    pub timestamp_source: &'static str,
+    pub final_score: f64,
+    pub moving_average: f64,
+    pub std_dev: f64,
+    /// Change from the previous run; `None` for the first.
+    pub delta: Option<f64>,
+    /// Consecutive improvements ending at this run.
+    pub streak: usize,
+}
+
+/// Written to `score_trend.json`.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScoreTrend {
+    pub window: usize,
+    pub runs: usize,
+    pub current_streak: usize,
+    pub longest_streak: usize,
+    pub sparkline: String,
+    pub points: Vec<TrendPoint>,
+}
+
+/// Writes `score_trend.json` and `score_trend.csv`, then prints the sparkline.
+pub fn run(args: &TrendArgs) -> Result<()> {
+    let out_dir = args.out_dir.as_deref().unwrap_or(&args.dir);
+    let trend = build_trend(&args.dir, &args.glob, args.window as usize)?;
+    fs::create_dir_all(out_dir)
+        .with_context(|| format!("failed to create {}", out_dir.display()))?;
+    write_trend(out_dir, &trend)?;
+    if trend.runs == 0 {
+        println!(
+            "no score files matched {} under {}",
+            args.glob,
+            args.dir.display()
+        );
+    } else {
+        let last = &trend.points[trend.runs - 1];
+        println!(
+            "{} {:.3} (avg {:.3}, {} run(s), streak {})",
+            trend.sparkline,
+            last.final_score,
+            last.moving_average,
+            trend.runs,
+            trend.current_streak
+        );
+    }
+    Ok(())
+}
+
+pub fn build_trend(dir: &Path, pattern: &str, window: usize) -> Result<ScoreTrend> {
+    if !dir.is_dir() {
+        return Err(anyhow!("{} is not a directory", dir.display()));
+    }
+    let full_pattern = dir.join(pattern);
+    let matches = glob::glob(&full_pattern.to_string_lossy())
+        .with_context(|| format!("invalid glob {pattern}"))?;
+    let mut runs = Vec::new();
+    for path in matches {
+        let path = path?;
+        let raw = fs::read_to_string(&path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        let score: Value = serde_json::from_str(&raw)
+            .with_context(|| format!("failed to parse {}", path.display()))?;
+        let final_score = score
+            .get("finalScore")
+            .and_then(Value::as_f64)
+            .ok_or_else(|| anyhow!("{} has no finalScore", path.display()))?;
+        let (timestamp, source) = run_timestamp(&path, &score)?;
+        runs.push((timestamp, source, path, final_score));
+    }
+    runs.sort_by(|a, b| a.0.cmp(&b.0).then_with(|| a.2.cmp(&b.2)));
+
+    let scores: Vec<f64> = runs.iter().map(|run| run.3).collect();
+    let mut points: Vec<TrendPoint> = Vec::with_capacity(runs.len());
+    for (idx, (timestamp, timestamp_source, path, final_score)) in runs.into_iter().enumerate() {
+        let recent = &scores[(idx + 1).saturating_sub(window)..=idx];
+        let mean = recent.iter().sum::<f64>() / recent.len() as f64;
+        let variance = recent
+            .iter()
+            .map(|score| (score - mean).powi(2))
+            .sum::<f64>()
+            / recent.len() as f64;
+        let previous = idx.checked_sub(1).map(|prev| &points[prev]);
+        let streak = match previous {
+            Some(prev) if final_score > prev.final_score => prev.streak + 1,
+            _ => 0,
+        };
+        points.push(TrendPoint {
+            path,
+            timestamp,
+            timestamp_source,
+            final_score,
+            moving_average: mean,
+            std_dev: variance.sqrt(),
+            delta: previous.map(|prev| final_score - prev.final_score),
+            streak,
+        });
+    }
+
+    Ok(ScoreTrend {
+        window,
+        runs: points.len(),
+        current_streak: points.last().map_or(0, |point| point.streak),
+        longest_streak: points.iter().map(|point| point.streak).max().unwrap_or(0),
+        sparkline: sparkline(&scores),
+        points,
+    })
+}
+
+/// `timestamp` from the score file, else from the run's `run_meta.json`, else the file's mtime.
+fn run_timestamp(path: &Path, score: &Value) -> Result<(DateTime<Utc>, &'static str)> {
+    if let Some(timestamp) = score.get("timestamp").and_then(parse_timestamp) {
+        return Ok((timestamp, "evalScore"));
+    }
+    let meta_path = path.with_file_name("run_meta.json");
+    let meta_timestamp = fs::read_to_string(meta_path)
+        .ok()
+        .and_then(|raw| serde_json::from_str::<Value>(&raw).ok())
+        .and_then(|meta| meta.get("timestamp").and_then(parse_timestamp));
+    if let Some(timestamp) = meta_timestamp {
+        return Ok((timestamp, "runMeta"));
+    }
+    let modified = fs::metadata(path)
+        .and_then(|meta| meta.modified())
+        .with_context(|| format!("failed to read mtime of {}", path.display()))?;
+    Ok((modified.into(), "mtime"))
+}
+
+/// RFC 3339, or the runner's `%Y%m%d-%H%M%S` run timestamp (UTC).
+fn parse_timestamp(value: &Value) -> Option<DateTime<Utc>> {
+    let raw = value.as_str()?;
+    DateTime::parse_from_rfc3339(raw)
+        .map(|timestamp| timestamp.with_timezone(&Utc))
+        .ok()
+        .or_else(|| {
+            NaiveDateTime::parse_from_str(raw, "%Y%m%d-%H%M%S")
+                .ok()
+                .map(|naive| naive.and_utc())
+        })
+}
+
+fn sparkline(scores: &[f64]) -> String {
+    let min = scores.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = scores.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let top = SPARK_LEVELS.len() - 1;
+    scores
+        .iter()
+        .map(|score| {
+            let level = if max > min {
+                ((score - min) / (max - min) * top as f64).round() as usize
+            } else {
+                top / 2
+            };
+            SPARK_LEVELS[level] as char
+        })
+        .collect()
+}
+
+fn write_trend(out_dir: &Path, trend: &ScoreTrend) -> Result<()> {
+    let json_path = out_dir.join("score_trend.json");
+    fs::write(&json_path, serde_json::to_vec_pretty(trend)?)
+        .with_context(|| format!("failed to write {}", json_path.display()))?;
+
+    let csv_path = out_dir.join("score_trend.csv");
+    let mut writer = csv::Writer::from_path(&csv_path)
+        .with_context(|| format!("failed to create {}", csv_path.display()))?;
+    writer.write_record([
+        "timestamp",
+        "path",
+        "finalScore",
+        "movingAverage",
+        "stdDev",
+        "delta",
+        "streak",
+    ])?;
+    for point in &trend.points {
+        writer.write_record([
+            point.timestamp.to_rfc3339(),
+            point.path.display().to_string(),
+            point.final_score.to_string(),
+            point.moving_average.to_string(),
+            point.std_dev.to_string(),
+            point
+                .delta
+                .map(|delta| delta.to_string())
+                .unwrap_or_default(),
+            point.streak.to_string(),
+        ])?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hl_common::test_support::RunFixture;
+
+    fn runs_dir(scores: &[f64]) -> RunFixture {
+        let fixture = RunFixture::empty("hl-trend");
+        for (idx, score) in scores.iter().enumerate() {
+            fixture.write(
+                format!("run-{idx}/eval_score.json"),
+                serde_json::json!({ "finalScore": score }).to_string(),
+            );
+            // Directory names sort opposite to time, so ordering must come from run_meta.
+            fixture.write(
+                format!("run-{idx}/run_meta.json"),
+                serde_json::json!({ "timestamp": format!("2025010{}-000000", 9 - idx) })
+                    .to_string(),
+            );
+        }
+        fixture
+    }
+
+    #[test]
+    fn empty_directory_yields_empty_trend() {
+        let runs = runs_dir(&[]);
+        let dir = &runs.dir;
+        let trend = build_trend(dir, "**/eval_score.json", 3).unwrap();
+        assert_eq!(trend.runs, 0);
+        assert_eq!(trend.sparkline, "");
+        assert_eq!(trend.current_streak, 0);
+        write_trend(dir, &trend).unwrap();
+        let csv = fs::read_to_string(dir.join("score_trend.csv")).unwrap();
+        assert_eq!(csv.lines().count(), 1);
+    }
+
+    #[test]
+    fn single_run_has_no_delta_or_spread() {
+        let runs = runs_dir(&[2.5]);
+        let dir = &runs.dir;
+        let trend = build_trend(dir, "**/eval_score.json", 3).unwrap();
+        assert_eq!(trend.runs, 1);
+        let point = &trend.points[0];
+        assert_eq!(point.timestamp_source, "runMeta");
+        assert_eq!((point.moving_average, point.std_dev), (2.5, 0.0));
+        assert_eq!(point.delta, None);
+        assert_eq!(trend.sparkline.len(), 1);
+    }
+
+    #[test]
+    fn non_monotone_scores_reset_streaks() {
+        // Written newest first: chronological order is 1, 3, 2, 4, 5.
+        let runs = runs_dir(&[5.0, 4.0, 2.0, 3.0, 1.0]);
+        let dir = &runs.dir;
+        let trend = build_trend(dir, "**/eval_score.json", 2).unwrap();
+        let scores: Vec<f64> = trend.points.iter().map(|p| p.final_score).collect();
+        assert_eq!(scores, vec![1.0, 3.0, 2.0, 4.0, 5.0]);
+        let streaks: Vec<usize> = trend.points.iter().map(|p| p.streak).collect();
+        assert_eq!(streaks, vec![0, 1, 0, 1, 2]);
+        assert_eq!((trend.current_streak, trend.longest_streak), (2, 2));
+        assert_eq!(trend.points[2].moving_average, 2.5);
+        assert_eq!(trend.points[2].std_dev, 0.5);
+        assert_eq!(trend.points[2].delta, Some(-1.0));
+        assert_eq!(trend.sparkline, "_=-+#");
+    }
+}
@@ -0,0 +1,135 @@
+use std::{collections::BTreeSet, io::BufRead, path::Path};
+
+use anyhow::{Context, Result};
+use hl_common::artifacts::open_jsonl;
+use serde_json::Value;
+
+/// Distinct `ws.*` signatures observed in a runner's `ws_stream.jsonl`.
+pub fn load_signatures(path: &Path) -> Result<BTreeSet<String>> {
+    let mut signatures = BTreeSet::new();
+    for (line_no, line) in open_jsonl(path)?.lines().enumerate() {
+        let line = line.with_context(|| format!("failed to read line {}", line_no + 1))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let event: Value = serde_json::from_str(&line).with_context(|| {
+            format!(
+                "failed to parse ws event on line {} of {}",
+                line_no + 1,
+                path.display()
+            )
+        })?;
+        signatures.extend(event_signatures(&event));
+    }
+    Ok(signatures)
+}
+
+/// Maps one logged websocket message to `ws.orderUpdate.<status>`, `ws.userFunding.<coin>`
+/// and `ws.ledger.<kind>` signatures. Other channels yield nothing.
+pub fn event_signatures(event: &Value) -> Vec<String> {
+    match event.get("channel").and_then(Value::as_str) {
+        Some("orderUpdates") => entries(event, &["data"])
+            .filter_map(|update| {
+                let status = update.get("status")?.as_str()?;
+                Some(format!("ws.orderUpdate.{status}"))
+            })
+            .collect(),
+        Some("userFundings") => entries(event, &["fundings", "data"])
+            .filter_map(|funding| {
+                let coin = funding.get("coin")?.as_str()?;
+                Some(format!("ws.userFunding.{}", coin.to_ascii_uppercase()))
+            })
+            .collect(),
+        Some("userNonFundingLedgerUpdates") => entries(event, &["updates", "data"])
+            .map(|update| format!("ws.ledger.{}", ledger_kind(update)))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Items of the first array found under `keys` (also looking one level into an object
+/// payload, as in `data.fundings`), or the event itself for flattened demo records.
+fn entries<'a>(event: &'a Value, keys: &[&str]) -> Box<dyn Iterator<Item = &'a Value> + 'a> {
+    let nested = event.get("data").filter(|data| data.is_object());
+    for key in keys {
+        if let Some(items) = event
+            .get(*key)
+            .or_else(|| nested.and_then(|data| data.get(*key)))
+            .and_then(Value::as_array)
+        {
+            return Box::new(items.iter());
+        }
+    }
+    Box::new(std::iter::once(event))
+}
+
+fn ledger_kind(update: &Value) -> String {
+    if let Some(kind) = update.get("kind").and_then(Value::as_str) {
+        // Runner logs the SDK's Debug output, e.g. `Deposit(DepositData { .. })`.
+        let ident: String = kind
+            .chars()
+            .take_while(|ch| ch.is_ascii_alphanumeric())
+            .collect();
+        let mut chars = ident.chars();
+        return match chars.next() {
+            Some(first) => first.to_ascii_lowercase().to_string() + chars.as_str(),
+            None => "unknown".to_string(),
+        };
+    }
+    if let Some(kind) = update
+        .pointer("/delta/type")
+        .and_then(Value::as_str)
+        .or_else(|| {
+            update
+                .get("channel")
+                .and_then(Value::as_str)
+                .filter(|channel| *channel != "userNonFundingLedgerUpdates")
+        })
+    {
+        return kind.to_string();
+    }
+    if update.get("toPerp").is_some() {
+        return "accountClassTransfer".to_string();
+    }
+    "unknown".to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn maps_runner_ws_messages_to_signatures() {
+        let orders = json!({"channel": "orderUpdates", "data": [
+            {"channel": "orderUpdates", "oid": 1, "status": "open"},
+            {"channel": "orderUpdates", "oid": 1, "status": "filled"}
+        ]});
+        assert_eq!(
+            event_signatures(&orders),
+            vec!["ws.orderUpdate.open", "ws.orderUpdate.filled"]
+        );
+
+        let demo_cancel = json!({"channel": "orderUpdates", "oid": 2, "status": "canceled"});
+        assert_eq!(
+            event_signatures(&demo_cancel),
+            vec!["ws.orderUpdate.canceled"]
+        );
+
+        let fundings = json!({"channel": "userFundings", "data": {"fundings": [
+            {"coin": "eth", "usdc": "-0.01"}
+        ]}});
+        assert_eq!(event_signatures(&fundings), vec!["ws.userFunding.ETH"]);
+
+        let ledger = json!({"channel": "userNonFundingLedgerUpdates", "updates": [
+            {"channel": "accountClassTransfer", "usdc": 5.0, "toPerp": true},
+            {"channel": "ledger", "kind": "Deposit(DepositData { usdc: \"1\" })"}
+        ]});
+        assert_eq!(
+            event_signatures(&ledger),
+            vec!["ws.ledger.accountClassTransfer", "ws.ledger.deposit"]
+        );
+
+        assert!(event_signatures(&json!({"channel": "userFills", "fills": []})).is_empty());
+    }
+}
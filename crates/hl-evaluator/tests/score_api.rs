@@ -0,0 +1,97 @@
+use hl_common::{test_support::action_record, ActionLogRecord, Signature};
+use hl_evaluator::eval::{score_actions, RawConfig, ScoringOptions};
+use serde_json::{json, Value};
+
+const DOMAINS: &str = r#"
+per_action_window_ms: 200
+domains:
+  perp:
+    weight: 1.0
+    allow: ["perp.order.*"]
+  risk:
+    weight: 2.0
+    allow: ["risk.*"]
+"#;
+
+fn record(step_idx: usize, action: &str, submit_ts_ms: i64, request: Value) -> ActionLogRecord {
+    ActionLogRecord {
+        submit_ts_ms,
+        observed: Some(json!({"channel": "userFills", "oid": 1})),
+        ..action_record(
+            step_idx,
+            action,
+            request,
+            json!({"status": "ok", "data": {"statuses": [{"kind": "resting", "oid": 1}]}}),
+        )
+    }
+}
+
+fn fixture_records() -> Vec<ActionLogRecord> {
+    vec![
+        record(
+            0,
+            "perp_orders",
+            0,
+            json!({"perp_orders": {"orders": [{"coin": "ETH", "tif": "Gtc", "reduceOnly": false}]}}),
+        ),
+        record(
+            1,
+            "set_leverage",
+            50,
+            json!({"set_leverage": {"coin": "ETH", "leverage": 5}}),
+        ),
+        record(2, "wait_for_fill", 1_000, json!({"wait_for_fill": {}})),
+    ]
+}
+
+fn config() -> RawConfig {
+    serde_yaml::from_str(DOMAINS).unwrap()
+}
+
+#[test]
+fn scores_records_without_the_cli() {
+    let report = score_actions(&fixture_records(), config(), ScoringOptions::default()).unwrap();
+
+    // perp (1.0 x 1) + risk (2.0 x 1), plus 0.25 for two signatures in the first window.
+    assert_eq!(report.base, 3.0);
+    assert_eq!(report.bonus, 0.25);
+    assert_eq!(report.penalty, 0.0);
+    assert_eq!(report.final_score, 3.25);
+    assert_eq!(
+        report.unmapped_signatures,
+        vec![Signature::perp_wait_fill()]
+    );
+}
+
+#[test]
+fn options_override_the_config_window() {
+    let opts = ScoringOptions {
+        window_ms: Some(10),
+        ..ScoringOptions::default()
+    };
+    let report = score_actions(&fixture_records(), config(), opts).unwrap();
+    // 0 and 50 ms now fall in different windows, so no composition bonus.
+    assert_eq!(report.bonus, 0.0);
+    assert_eq!(report.final_score, 3.0);
+
+    let bad = ScoringOptions {
+        cap_per_signature: Some(0),
+        ..ScoringOptions::default()
+    };
+    assert!(score_actions(&fixture_records(), config(), bad).is_err());
+}
+
+#[test]
+fn weight_overrides_apply_to_library_scoring() {
+    let mut config = config();
+    config
+        .override_weights(&[("risk".to_string(), 4.0)])
+        .unwrap();
+    let report = score_actions(&fixture_records(), config, ScoringOptions::default()).unwrap();
+    assert_eq!(report.base, 5.0);
+
+    let mut unknown = self::config();
+    assert!(unknown
+        .override_weights(&[("spot".to_string(), 1.0)])
+        .is_err());
+}
@@ -0,0 +1,195 @@
+use std::{fs, path::Path};
+
+use anyhow::{anyhow, Context, Result};
+use hl_common::plan::{is_plan_url, is_stdin_plan_spec, plan_spec_path, split_plan_spec};
+use serde::Serialize;
+use tracing::{error, info};
+
+use crate::{llm::LlmPlanSpec, prune_sibling_runs, run_once, Cli, FinalOutput};
+
+/// A `--plan-batch` line whose run failed.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchFailure {
+    pub line: usize,
+    pub error: String,
+}
+
+/// Written to `<out>/batch_summary.json` and printed to stdout. Line numbers are 1-based,
+/// as in `--plan file.jsonl:N`, and name each run's `<out>/<line>` directory.
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchSummary {
+    pub plans: usize,
+    pub succeeded: Vec<usize>,
+    pub failed: Vec<BatchFailure>,
+    /// Lines never run because `--stop-on-error` aborted the batch.
+    pub skipped: Vec<usize>,
+}
+
+/// Runs every non-blank line of the JSONL `--plan` as its own plan, one after another.
+pub async fn run_batch(cli: &Cli, timestamp: &str, out_dir: &Path) -> Result<()> {
+    let summary = batch_plans(cli, timestamp, out_dir).await?;
+    let summary_path = out_dir.join("batch_summary.json");
+    fs::write(&summary_path, serde_json::to_vec_pretty(&summary)?)
+        .with_context(|| format!("failed to write {}", summary_path.display()))?;
+    info!(
+        "batch: {}/{} plan(s) succeeded, summary at {}",
+        summary.succeeded.len(),
+        summary.plans,
+        summary_path.display()
+    );
+
+    if let Some(max_runs) = cli.max_runs {
+        prune_sibling_runs(cli, out_dir, max_runs)?;
+    }
+
+    println!("{}", serde_json::to_string(&summary)?);
+    if !summary.failed.is_empty() {
+        return Err(anyhow!(
+            "{} of {} batch plans failed",
+            summary.failed.len(),
+            summary.plans
+        ));
+    }
+    Ok(())
+}
+
+async fn batch_plans(cli: &Cli, timestamp: &str, out_dir: &Path) -> Result<BatchSummary> {
+    let spec = cli.plan_spec();
+    if LlmPlanSpec::parse(spec).is_some() || is_plan_url(spec) || is_stdin_plan_spec(spec) {
+        return Err(anyhow!("--plan-batch needs a local JSONL plan file"));
+    }
+    if split_plan_spec(spec).1.is_some() {
+        return Err(anyhow!(
+            "--plan-batch runs every line; drop the :N selector from {spec}"
+        ));
+    }
+    let path = plan_spec_path(spec)?;
+    let source = fs::read_to_string(&path)
+        .with_context(|| format!("failed to read plan file {}", path.display()))?;
+    let lines: Vec<usize> = source
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(idx, _)| idx + 1)
+        .collect();
+    if lines.is_empty() {
+        return Err(anyhow!("{} has no plans", path.display()));
+    }
+
+    fs::create_dir_all(out_dir)
+        .with_context(|| format!("failed to create {}", out_dir.display()))?;
+    info!(
+        "running {} plan(s) from {} under {}",
+        lines.len(),
+        path.display(),
+        out_dir.display()
+    );
+
+    let mut summary = BatchSummary {
+        plans: lines.len(),
+        ..BatchSummary::default()
+    };
+    for (pos, &line) in lines.iter().enumerate() {
+        let run_out = out_dir.join(line.to_string());
+        let mut run_cli = cli.clone();
+        run_cli.plan = vec![format!("{}:{line}", path.display())];
+        run_cli.plan_batch = false;
+        run_cli.max_runs = None;
+        run_cli.profile = cli
+            .profile
+            .as_ref()
+            .and_then(|profile| profile.file_name())
+            .map(|name| run_out.join(name));
+
+        let mut output = FinalOutput::new(&run_out);
+        match run_once(&run_cli, timestamp, &run_out, &mut output).await {
+            Ok(()) => summary.succeeded.push(line),
+            Err(err) => {
+                error!("batch plan on line {line} failed: {err:#}");
+                summary.failed.push(BatchFailure {
+                    line,
+                    error: format!("{err:#}"),
+                });
+                if cli.stop_on_error {
+                    summary.skipped = lines[pos + 1..].to_vec();
+                    break;
+                }
+            }
+        }
+    }
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+    use hl_common::test_support::RunFixture;
+
+    fn batch_cli(plans: &str, fixture: &RunFixture, extra: &[&str]) -> Cli {
+        let plan_path = fixture.write("plans.jsonl", plans);
+        let mut args = vec![
+            "hl-runner".to_string(),
+            "--plan".to_string(),
+            plan_path.display().to_string(),
+            "--plan-batch".to_string(),
+            "--demo".to_string(),
+        ];
+        args.extend(extra.iter().map(|arg| arg.to_string()));
+        Cli::try_parse_from(args).unwrap()
+    }
+
+    // Paused so the demo runner's 5s settle sleep is skipped.
+    #[tokio::test(start_paused = true)]
+    async fn demo_batch_writes_one_run_per_line() {
+        let fixture = RunFixture::empty("hl-runner-batch");
+        let out_dir = fixture.dir.join("runs");
+        let cli = batch_cli(
+            concat!(
+                "{\"steps\":[{\"sleep_ms\":{\"duration_ms\":1}}]}\n",
+                "{\"steps\":[{\"perp_orders\":{\"orders\":[{\"coin\":\"ETH\",\"side\":\"buy\",\"sz\":0.01,\"px\":3000}]}}]}\n",
+                "\n",
+                "{\"steps\":[{\"cancel_all\":{\"coin\":\"ETH\"}}]}\n",
+            ),
+            &fixture,
+            &[],
+        );
+
+        run_batch(&cli, "20250101-000000", &out_dir).await.unwrap();
+        for line in ["1", "2", "4"] {
+            assert!(
+                out_dir.join(line).join("per_action.jsonl").exists(),
+                "{line}"
+            );
+        }
+        assert!(!out_dir.join("3").exists());
+        let summary: serde_json::Value =
+            serde_json::from_slice(&fs::read(out_dir.join("batch_summary.json")).unwrap()).unwrap();
+        assert_eq!(summary["plans"], 3);
+        assert_eq!(summary["succeeded"], serde_json::json!([1, 2, 4]));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn stop_on_error_skips_remaining_lines() {
+        let plans = concat!(
+            "{\"steps\":[{\"sleep_ms\":{\"duration_ms\":1}}]}\n",
+            "{\"steps\":[{\"no_such_step\":{}}]}\n",
+            "{\"steps\":[{\"sleep_ms\":{\"duration_ms\":1}}]}\n",
+        );
+        let fixture = RunFixture::empty("hl-runner-batch");
+        let out_dir = fixture.dir.join("runs");
+
+        let cli = batch_cli(plans, &fixture, &[]);
+        let summary = batch_plans(&cli, "ts", &out_dir).await.unwrap();
+        assert_eq!(summary.succeeded, vec![1, 3]);
+        assert_eq!(summary.failed[0].line, 2);
+        assert!(summary.skipped.is_empty());
+
+        let cli = batch_cli(plans, &fixture, &["--stop-on-error"]);
+        let summary = batch_plans(&cli, "ts", &out_dir).await.unwrap();
+        assert_eq!(summary.succeeded, vec![1]);
+        assert_eq!(summary.skipped, vec![3]);
+    }
+}
@@ -0,0 +1,44 @@
+use anyhow::{bail, Result};
+use clap::Parser;
+use hl_common::plan::{check_wallet, load_plan_from_spec, validate};
+
+#[derive(Parser, Debug)]
+#[command(
+    name = "hl-validate",
+    about = "Statically validate a HyperLiquidBench plan"
+)]
+struct Args {
+    /// Plan spec (JSON file, JSONL with optional :line suffix, http(s) URL; - reads stdin)
+    #[arg(long, allow_hyphen_values = true)]
+    plan: String,
+
+    /// Comma-separated coins the plan may trade
+    #[arg(long, value_delimiter = ',')]
+    allowed_coins: Option<Vec<String>>,
+
+    /// Primary wallet address; warns about sub-account transfers addressed to it
+    #[arg(long)]
+    wallet: Option<String>,
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+    let plan = load_plan_from_spec(&args.plan)?;
+    let mut result = validate(&plan, args.allowed_coins.as_deref());
+    if let Some(wallet) = args.wallet.as_deref() {
+        check_wallet(&plan, wallet, &mut result);
+    }
+
+    for warning in &result.warnings {
+        println!("warning: {warning}");
+    }
+    for error in &result.errors {
+        println!("error: {error}");
+    }
+
+    if !result.is_valid() {
+        bail!("plan has {} validation error(s)", result.errors.len());
+    }
+    println!("plan OK ({} steps)", plan.steps.len());
+    Ok(())
+}
@@ -0,0 +1,197 @@
+use std::{
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process::{Child, ChildStdin, Command, Stdio},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::{Context, Result};
+use tracing::{info, warn};
+
+/// How long [`LiveEvaluator::finish`] gives the evaluator to print its final report.
+const FINISH_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// `--live-eval`: an `hl-evaluator --live` child fed every action record as it is logged.
+/// [`LiveEvaluator::finish`] closes the pipe and waits for the final report; dropping it
+/// unfinished kills the child instead of blocking.
+pub struct LiveEvaluator {
+    child: Child,
+    stdin: Arc<Mutex<Option<ChildStdin>>>,
+    reaped: bool,
+}
+
+impl LiveEvaluator {
+    pub fn spawn(
+        bin: &Path,
+        domains: &Path,
+        out_dir: &Path,
+        stdout_to_stderr: bool,
+    ) -> Result<Self> {
+        let mut command = Command::new(bin);
+        command
+            .arg("--live")
+            .arg("--domains")
+            .arg(domains)
+            .arg("--out-dir")
+            .arg(out_dir)
+            .stdin(Stdio::piped());
+        if stdout_to_stderr {
+            command.stdout(io::stderr());
+        }
+        let mut child = command
+            .spawn()
+            .with_context(|| format!("failed to spawn live evaluator {}", bin.display()))?;
+        let stdin = child.stdin.take();
+        info!("live evaluation via {}", bin.display());
+        Ok(Self {
+            child,
+            stdin: Arc::new(Mutex::new(stdin)),
+            reaped: false,
+        })
+    }
+
+    /// Closes the pipe and waits (without blocking the runtime) for the evaluator to exit,
+    /// killing it after [`FINISH_TIMEOUT`].
+    pub async fn finish(mut self) {
+        self.close_pipe();
+        let deadline = tokio::time::Instant::now() + FINISH_TIMEOUT;
+        loop {
+            match self.child.try_wait() {
+                Ok(Some(status)) => {
+                    self.reaped = true;
+                    if !status.success() {
+                        warn!("live evaluator exited with {status}");
+                    }
+                    return;
+                }
+                Ok(None) if tokio::time::Instant::now() < deadline => {
+                    tokio::time::sleep(Duration::from_millis(20)).await;
+                }
+                Ok(None) => {
+                    warn!(
+                        "live evaluator still running after {}s; killing it",
+                        FINISH_TIMEOUT.as_secs()
+                    );
+                    return;
+                }
+                Err(err) => {
+                    warn!("failed to wait for live evaluator: {err}");
+                    return;
+                }
+            }
+        }
+    }
+
+    fn close_pipe(&self) {
+        if let Ok(mut stdin) = self.stdin.lock() {
+            stdin.take();
+        }
+    }
+
+    /// Writer for `RunArtifacts::tee_actions`; fails with `BrokenPipe` once closed.
+    pub fn sink(&self) -> Box<dyn Write + Send> {
+        Box::new(PipeWriter(self.stdin.clone()))
+    }
+}
+
+impl Drop for LiveEvaluator {
+    fn drop(&mut self) {
+        if self.reaped {
+            return;
+        }
+        self.close_pipe();
+        // Never block here: kill, then reap if it has already gone.
+        self.child.kill().ok();
+        self.child.try_wait().ok();
+    }
+}
+
+/// `hl-evaluator` next to the running binary (as built by cargo), else from `PATH`.
+pub fn default_evaluator_bin() -> PathBuf {
+    let name = format!("hl-evaluator{}", std::env::consts::EXE_SUFFIX);
+    std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(|dir| dir.join(&name)))
+        .filter(|path| path.is_file())
+        .unwrap_or_else(|| PathBuf::from(name))
+}
+
+struct PipeWriter(Arc<Mutex<Option<ChildStdin>>>);
+
+impl Write for PipeWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut stdin = self
+            .0
+            .lock()
+            .map_err(|_| io::Error::other("live evaluator pipe poisoned"))?;
+        match stdin.as_mut() {
+            Some(stdin) => stdin.write(buf),
+            None => Err(io::ErrorKind::BrokenPipe.into()),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.0.lock() {
+            Ok(mut stdin) => stdin.as_mut().map_or(Ok(()), Write::flush),
+            Err(_) => Err(io::Error::other("live evaluator pipe poisoned")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hl_common::test_support::RunFixture;
+
+    #[tokio::test]
+    async fn sink_reaches_child_and_breaks_after_close() {
+        let fixture = RunFixture::empty("hl-live-eval");
+        let dir = &fixture.dir;
+        // Stand-in evaluator copying stdin to `<--out-dir>/received.jsonl` ($5).
+        let script = fixture.write("fake-evaluator", "#!/bin/sh\ncat > \"$5/received.jsonl\"\n");
+        std::process::Command::new("chmod")
+            .arg("+x")
+            .arg(&script)
+            .status()
+            .unwrap();
+
+        let evaluator = LiveEvaluator::spawn(&script, Path::new("d.yaml"), dir, true).unwrap();
+        let mut sink = evaluator.sink();
+        sink.write_all(b"{\"stepIdx\":0}\n").unwrap();
+        sink.flush().unwrap();
+        evaluator.finish().await;
+
+        assert_eq!(
+            std::fs::read_to_string(dir.join("received.jsonl")).unwrap(),
+            "{\"stepIdx\":0}\n"
+        );
+        assert_eq!(
+            sink.write(b"late").unwrap_err().kind(),
+            io::ErrorKind::BrokenPipe
+        );
+    }
+
+    #[test]
+    fn drop_kills_an_unfinished_evaluator() {
+        let fixture = RunFixture::empty("hl-live-eval");
+        let dir = &fixture.dir;
+        // Ignores EOF on stdin, so only a kill ends it.
+        let script = fixture.write(
+            "stuck-evaluator",
+            "#!/bin/sh
+exec sleep 30
+",
+        );
+        std::process::Command::new("chmod")
+            .arg("+x")
+            .arg(&script)
+            .status()
+            .unwrap();
+
+        let evaluator = LiveEvaluator::spawn(&script, Path::new("d.yaml"), dir, true).unwrap();
+        let started = std::time::Instant::now();
+        drop(evaluator);
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+}
@@ -3,13 +3,17 @@ mod plan_decode;
 mod prompts;
 
 use std::{
+    collections::{BTreeMap, HashMap, HashSet},
     env,
     fs::{self, File},
-    path::PathBuf,
+    future::Future,
+    path::{Path, PathBuf},
+    sync::Arc,
 };
 
 use anyhow::{anyhow, Context, Result};
-use hl_common::plan::{ActionStep, Plan};
+use hl_common::plan::{is_eth_address, ActionStep, PerpOrdersStep, Plan, MAX_ORDER_SIZE};
+use hyperliquid_rust_sdk::BaseUrl;
 use openrouter::{
     build_cached_payload, cache_filename, hash_prompt, parse_cached_payload, OpenRouter,
     OpenRouterConfig,
@@ -17,17 +21,25 @@ use openrouter::{
 use prompts::{coverage_prompts, hian_prompts, CoveragePrompt, HianPrompt};
 use serde::Serialize;
 use serde_json::Value;
+use tracing::{info, warn};
+
+use crate::{net_trace::NetworkTrace, tls::TlsConfig};
 
 const OPENROUTER_ENDPOINT: &str = "https://openrouter.ai/api/v1/chat/completions";
 const USER_AGENT: &str = "HyperLiquidBenchRunner/0.1";
 const LLM_TITLE: &str = "HyperLiquidBench";
-const MAX_ORDER_SIZE: f64 = 1.0;
 const MIN_ORDER_SIZE: f64 = 0.0001;
 const MAX_LEVERAGE: u32 = 20;
+/// Plan regenerations allowed after refreshing a stale allowed-coin list.
+const MAX_COIN_REFRESHES: u32 = 2;
+/// Default `--llm-max-loop-count`.
+pub const DEFAULT_MAX_LOOP_COUNT: u32 = 20;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum LlmPlanSpec {
     Coverage,
+    /// Coverage prompt sampled N times, with the resulting plans merged.
+    MultiCoverage(u32),
     Hian(PathBuf),
 }
 
@@ -36,6 +48,12 @@ impl LlmPlanSpec {
         if let Some(rest) = spec.strip_prefix("llm:") {
             if rest.eq_ignore_ascii_case("coverage") {
                 Some(LlmPlanSpec::Coverage)
+            } else if let Some(count) = rest.strip_prefix("coverage:") {
+                count
+                    .parse::<u32>()
+                    .ok()
+                    .filter(|n| *n > 0)
+                    .map(LlmPlanSpec::MultiCoverage)
             } else {
                 rest.strip_prefix("hian:")
                     .map(|path| LlmPlanSpec::Hian(PathBuf::from(path)))
@@ -54,12 +72,76 @@ pub struct LlmOptions {
     pub top_p: f32,
     pub max_output_tokens: u32,
     pub max_steps: u32,
+    pub seed: Option<u64>,
     pub allowed_coins: Vec<String>,
     pub default_builder_code: Option<String>,
     pub cache_dir: Option<PathBuf>,
     pub dry_run: bool,
+    pub demo: bool,
+    pub network_trace: Option<Arc<NetworkTrace>>,
+    /// Re-discover `allowed_coins` from `coin_source` and regenerate when a plan uses a coin outside it.
+    pub refresh_coins_on_retry: bool,
+    /// Network discovery parameters; `None` when coins were given explicitly.
+    pub coin_source: Option<CoinSource>,
+    /// Orders kept per coin across all steps; later ones are trimmed.
+    pub max_orders_per_coin: Option<u32>,
+    /// Orders kept across the whole plan; later ones are trimmed.
+    pub max_orders_total: Option<u32>,
+    /// Loop counts above this are lowered to it.
+    pub max_loop_count: u32,
+    /// Checked against reference mids, since live prices are unknown at generation time.
+    pub notional_limits: crate::NotionalLimits,
+    pub tls: TlsConfig,
+}
+
+impl LlmOptions {
+    /// Options for applying plan checks with no model call behind them.
+    fn offline(allowed_coins: Vec<String>, notional_limits: crate::NotionalLimits) -> Self {
+        Self {
+            api_key: String::new(),
+            model: String::new(),
+            temperature: 0.0,
+            top_p: 1.0,
+            max_output_tokens: 0,
+            max_steps: 0,
+            seed: None,
+            allowed_coins,
+            default_builder_code: None,
+            cache_dir: None,
+            dry_run: true,
+            demo: false,
+            network_trace: None,
+            refresh_coins_on_retry: false,
+            coin_source: None,
+            max_orders_per_coin: None,
+            max_orders_total: None,
+            max_loop_count: DEFAULT_MAX_LOOP_COUNT,
+            notional_limits,
+            tls: TlsConfig::default(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct CoinSource {
+    pub base_url: BaseUrl,
+    pub http: reqwest::Client,
+    pub max_coins: usize,
+    pub exclude: Vec<String>,
+}
+
+/// Raised by `sanitize_plan` so `generate_plan` can tell stale coin lists from other failures.
+#[derive(Debug)]
+struct CoinNotAllowed(String);
+
+impl std::fmt::Display for CoinNotAllowed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "coin {} not allowed", self.0)
+    }
 }
 
+impl std::error::Error for CoinNotAllowed {}
+
 #[derive(Debug, Serialize, Clone)]
 pub struct LlmMeta {
     pub model: String,
@@ -67,6 +149,8 @@ pub struct LlmMeta {
     pub top_p: f32,
     pub max_output_tokens: u32,
     pub max_steps: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
     pub allowed_coins: Vec<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub default_builder_code: Option<String>,
@@ -74,6 +158,16 @@ pub struct LlmMeta {
     pub cached: bool,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub usage: Option<openrouter::Usage>,
+    /// Sanitized plan steps per completion token.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token_efficiency: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub multi_sample_count: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub multi_sample_deduped: Option<usize>,
+    /// Orders dropped per coin by the order-count limits.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub orders_trimmed: Option<BTreeMap<String, usize>>,
 }
 
 pub struct PlanResult {
@@ -82,9 +176,62 @@ pub struct PlanResult {
     pub meta: LlmMeta,
 }
 
-pub async fn generate_plan(spec: LlmPlanSpec, opts: &LlmOptions) -> Result<PlanResult> {
-    let system_user = match spec {
-        LlmPlanSpec::Coverage => {
+pub async fn generate_plan(spec: LlmPlanSpec, opts: &mut LlmOptions) -> Result<PlanResult> {
+    retry_with_fresh_coins(
+        opts,
+        |opts| {
+            let spec = spec.clone();
+            async move { generate_plan_once(spec, &opts).await }
+        },
+        |source| async move {
+            crate::fetch_allowed_coins_from_network(
+                source.base_url,
+                source.http,
+                source.max_coins,
+                &source.exclude,
+            )
+            .await
+        },
+    )
+    .await
+}
+
+/// Runs `attempt` until it stops failing with [`CoinNotAllowed`], re-fetching the allowed
+/// coins through `refresh` before each retry when `refresh_coins_on_retry` allows it.
+async fn retry_with_fresh_coins<T, A, AF, R, RF>(
+    opts: &mut LlmOptions,
+    mut attempt: A,
+    mut refresh: R,
+) -> Result<T>
+where
+    A: FnMut(LlmOptions) -> AF,
+    AF: Future<Output = Result<T>>,
+    R: FnMut(CoinSource) -> RF,
+    RF: Future<Output = Result<Vec<String>>>,
+{
+    let mut refreshes = 0;
+    loop {
+        let err = match attempt(opts.clone()).await {
+            Err(err) if err.downcast_ref::<CoinNotAllowed>().is_some() => err,
+            result => return result,
+        };
+        let source = match opts.coin_source.as_ref() {
+            Some(source) if opts.refresh_coins_on_retry && refreshes < MAX_COIN_REFRESHES => source,
+            _ => return Err(err),
+        };
+        refreshes += 1;
+        let refreshed = refresh(source.clone()).await?;
+        info!(
+            "{err}; refreshed allowed coins {:?} -> {:?} (retry {refreshes}/{MAX_COIN_REFRESHES})",
+            opts.allowed_coins, refreshed
+        );
+        opts.allowed_coins = refreshed;
+    }
+}
+
+async fn generate_plan_once(spec: LlmPlanSpec, opts: &LlmOptions) -> Result<PlanResult> {
+    let (system, user) = match spec {
+        LlmPlanSpec::Coverage | LlmPlanSpec::MultiCoverage(_) => {
             let ctx = CoveragePrompt {
                 max_steps: opts.max_steps,
                 allowed_coins: &opts.allowed_coins,
@@ -106,16 +253,68 @@ pub async fn generate_plan(spec: LlmPlanSpec, opts: &LlmOptions) -> Result<PlanR
         }
     };
 
-    let (system, user) = system_user;
-    let prompt_hash = hash_prompt(&opts.model, &system, &user, opts.temperature, opts.top_p);
+    if let Some(seed) = opts.seed {
+        info!("requesting LLM plan with seed {seed}");
+    }
+    let prompt_hash = hash_prompt(
+        &opts.model,
+        &system,
+        &user,
+        opts.temperature,
+        opts.top_p,
+        opts.seed,
+    );
+
+    if let LlmPlanSpec::MultiCoverage(count) = spec {
+        return generate_merged_plan(count, system, user, prompt_hash, opts).await;
+    }
+
+    let sample = sample_plan(&system, &user, &prompt_hash, opts).await?;
+    let meta = build_meta(
+        opts,
+        prompt_hash,
+        sample.cached,
+        sample.completion.usage.clone(),
+        sample.plan.steps.len(),
+    );
+    let meta = LlmMeta {
+        orders_trimmed: (!sample.orders_trimmed.is_empty()).then_some(sample.orders_trimmed),
+        ..meta
+    };
 
+    Ok(PlanResult {
+        plan: sample.plan,
+        raw: sample.completion.content.trim().to_string(),
+        meta,
+    })
+}
+
+struct PlanSample {
+    plan: Plan,
+    completion: openrouter::Completion,
+    cached: bool,
+    orders_trimmed: BTreeMap<String, usize>,
+}
+
+async fn sample_plan(
+    system: &str,
+    user: &str,
+    prompt_hash: &str,
+    opts: &LlmOptions,
+) -> Result<PlanSample> {
     let mut was_cached = false;
     let completion = if let Some(ref cache_dir) = opts.cache_dir {
         fs::create_dir_all(cache_dir)
             .with_context(|| format!("failed to create cache directory {}", cache_dir.display()))?;
-        let cache_path = cache_dir.join(cache_filename(&prompt_hash));
+        let cache_path = cache_dir.join(cache_filename(prompt_hash));
         if cache_path.exists() {
             was_cached = true;
+            if opts.network_trace.is_some() {
+                warn!(
+                    "--record-network-trace: completion served from {}, so no request is recorded",
+                    cache_path.display()
+                );
+            }
             let cached_value: Value =
                 serde_json::from_reader(File::open(&cache_path).with_context(|| {
                     format!("failed to open cache file {}", cache_path.display())
@@ -123,43 +322,171 @@ pub async fn generate_plan(spec: LlmPlanSpec, opts: &LlmOptions) -> Result<PlanR
                 .context("failed to read cached completion")?;
             parse_cached_payload(cached_value).context("failed to parse cached completion")?
         } else {
-            let completion = request_completion(&system, &user, opts).await?;
+            let completion = request_completion(system, user, opts).await?;
             let payload = build_cached_payload(&completion.content, completion.usage.as_ref());
-            serde_json::to_writer_pretty(
-                File::create(&cache_path).with_context(|| {
-                    format!("failed to create cache file {}", cache_path.display())
-                })?,
-                &payload,
-            )
-            .context("failed to persist cached completion")?;
+            write_cache_file(&cache_path, &payload)?;
             completion
         }
     } else {
-        request_completion(&system, &user, opts).await?
+        request_completion(system, user, opts).await?
     };
 
     let plan = plan_decode::decode_plan(&completion.content, opts.max_steps)?;
     let mut plan = plan;
-    sanitize_plan(&mut plan, opts)?;
+    let orders_trimmed = sanitize_plan(&mut plan, opts)?;
 
-    let meta = LlmMeta {
+    Ok(PlanSample {
+        plan,
+        completion,
+        cached: was_cached,
+        orders_trimmed,
+    })
+}
+
+/// Writes `payload` next to `cache_path` and renames it into place, so concurrent samples
+/// sharing a prompt hash never leave (or read) a half-written cache file.
+fn write_cache_file(cache_path: &Path, payload: &Value) -> Result<()> {
+    let tmp_path = cache_path.with_extension(format!("{}.tmp", uuid::Uuid::new_v4()));
+    let written = File::create(&tmp_path)
+        .with_context(|| format!("failed to create cache file {}", tmp_path.display()))
+        .and_then(|file| {
+            serde_json::to_writer_pretty(file, payload)
+                .context("failed to persist cached completion")
+        })
+        .and_then(|_| {
+            fs::rename(&tmp_path, cache_path)
+                .with_context(|| format!("failed to move cache file into {}", cache_path.display()))
+        });
+    if written.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+    }
+    written
+}
+
+async fn generate_merged_plan(
+    count: u32,
+    system: String,
+    user: String,
+    prompt_hash: String,
+    opts: &LlmOptions,
+) -> Result<PlanResult> {
+    let mut handles = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let (system, user, prompt_hash, opts) = (
+            system.clone(),
+            user.clone(),
+            prompt_hash.clone(),
+            opts.clone(),
+        );
+        handles.push(tokio::task::spawn(async move {
+            sample_plan(&system, &user, &prompt_hash, &opts).await
+        }));
+    }
+
+    let mut samples = Vec::with_capacity(handles.len());
+    for handle in handles {
+        samples.push(
+            handle
+                .await
+                .context("LLM sampling task failed to complete")??,
+        );
+    }
+
+    let (mut plan, deduped) = merge_plans(
+        samples.iter().map(|sample| &sample.plan),
+        opts.max_steps.saturating_mul(count) as usize,
+    );
+    // Each sample respects the limits on its own; the merged plan may not.
+    let mut orders_trimmed = trim_orders(&mut plan, opts);
+    for sample in &samples {
+        for (coin, trimmed) in &sample.orders_trimmed {
+            *orders_trimmed.entry(coin.clone()).or_default() += trimmed;
+        }
+    }
+    let cached = samples.iter().all(|sample| sample.cached);
+    let usage = sum_usage(
+        samples
+            .iter()
+            .filter_map(|sample| sample.completion.usage.as_ref()),
+    );
+    let raw = samples
+        .iter()
+        .map(|sample| sample.completion.content.trim())
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let mut meta = build_meta(opts, prompt_hash, cached, usage, plan.steps.len());
+    meta.multi_sample_count = Some(count);
+    meta.multi_sample_deduped = Some(deduped);
+    meta.orders_trimmed = (!orders_trimmed.is_empty()).then_some(orders_trimmed);
+
+    Ok(PlanResult { plan, raw, meta })
+}
+
+/// Concatenates plan steps in sample order, dropping steps whose JSON matches
+/// an earlier one. Returns the merged plan and how many duplicates were removed.
+fn merge_plans<'a>(plans: impl IntoIterator<Item = &'a Plan>, max_steps: usize) -> (Plan, usize) {
+    let mut seen = HashSet::new();
+    let mut steps = Vec::new();
+    let mut deduped = 0;
+    for plan in plans {
+        for step in &plan.steps {
+            let key = serde_json::to_string(step).expect("plan step must serialize");
+            if !seen.insert(key) {
+                deduped += 1;
+                continue;
+            }
+            steps.push(step.clone());
+        }
+    }
+    steps.truncate(max_steps);
+    (Plan { steps }, deduped)
+}
+
+fn sum_usage<'a>(usages: impl Iterator<Item = &'a openrouter::Usage>) -> Option<openrouter::Usage> {
+    fn add(total: Option<u32>, value: Option<u32>) -> Option<u32> {
+        match (total, value) {
+            (Some(a), Some(b)) => Some(a + b),
+            (a, b) => a.or(b),
+        }
+    }
+
+    usages.cloned().reduce(|total, usage| openrouter::Usage {
+        prompt_tokens: add(total.prompt_tokens, usage.prompt_tokens),
+        completion_tokens: add(total.completion_tokens, usage.completion_tokens),
+        total_tokens: add(total.total_tokens, usage.total_tokens),
+    })
+}
+
+fn build_meta(
+    opts: &LlmOptions,
+    prompt_hash: String,
+    cached: bool,
+    usage: Option<openrouter::Usage>,
+    step_count: usize,
+) -> LlmMeta {
+    let token_efficiency = usage
+        .as_ref()
+        .and_then(|usage| usage.completion_tokens)
+        .filter(|tokens| *tokens > 0)
+        .map(|tokens| step_count as f64 / f64::from(tokens));
+    LlmMeta {
         model: opts.model.clone(),
         temperature: opts.temperature,
         top_p: opts.top_p,
         max_output_tokens: opts.max_output_tokens,
         max_steps: opts.max_steps,
+        seed: opts.seed,
         allowed_coins: opts.allowed_coins.clone(),
         default_builder_code: opts.default_builder_code.clone(),
         prompt_hash,
-        cached: was_cached,
-        usage: completion.usage.clone(),
-    };
-
-    Ok(PlanResult {
-        plan,
-        raw: completion.content.trim().to_string(),
-        meta,
-    })
+        cached,
+        usage,
+        token_efficiency,
+        multi_sample_count: None,
+        multi_sample_deduped: None,
+        orders_trimmed: None,
+    }
 }
 
 async fn request_completion(
@@ -174,65 +501,301 @@ async fn request_completion(
         temperature: opts.temperature,
         top_p: opts.top_p,
         max_tokens: opts.max_output_tokens,
+        seed: opts.seed,
         title: LLM_TITLE.to_string(),
         user_agent: USER_AGENT.to_string(),
+        tls: opts.tls.clone(),
     };
-    let client = OpenRouter::new(config)?;
+    let client = OpenRouter::new(config, opts.network_trace.clone())?;
     client.complete(system, user).await
 }
 
-fn sanitize_plan(plan: &mut Plan, opts: &LlmOptions) -> Result<()> {
+/// `sanitize_plan`'s step checks applied to a hand-written plan: the first rejection of
+/// each top-level step, rather than stopping at the first one overall.
+pub fn sanitize_errors(
+    plan: &Plan,
+    allowed_coins: Vec<String>,
+    notional_limits: crate::NotionalLimits,
+) -> Vec<(usize, String)> {
+    let opts = LlmOptions::offline(allowed_coins, notional_limits);
+    plan.steps
+        .iter()
+        .enumerate()
+        .filter_map(|(idx, step)| {
+            sanitize_step(&mut step.clone(), &opts)
+                .err()
+                .map(|err| (idx, format!("{err:#}")))
+        })
+        .collect()
+}
+
+/// Normalizes and checks an LLM plan, then applies the order-count limits.
+/// Returns how many orders were trimmed per coin.
+fn sanitize_plan(plan: &mut Plan, opts: &LlmOptions) -> Result<BTreeMap<String, usize>> {
     for step in &mut plan.steps {
-        match step {
-            ActionStep::PerpOrders { perp_orders } => {
-                if perp_orders.builder_code.is_none() {
-                    if let Some(default) = opts.default_builder_code.as_ref() {
-                        perp_orders.builder_code = Some(default.clone());
-                    }
+        sanitize_step(step, opts)?;
+    }
+    Ok(trim_orders(plan, opts))
+}
+
+fn sanitize_step(step: &mut ActionStep, opts: &LlmOptions) -> Result<()> {
+    match step {
+        ActionStep::PerpOrders { perp_orders } => {
+            // LLM plans must state TIF per order.
+            perp_orders.time_in_force_override = None;
+            if perp_orders.builder_code.is_none() {
+                if let Some(default) = opts.default_builder_code.as_ref() {
+                    perp_orders.builder_code = Some(default.clone());
                 }
-                for order in &mut perp_orders.orders {
-                    if order.sz <= 0.0 {
-                        return Err(anyhow!("order size must be positive"));
-                    }
-                    if order.sz > MAX_ORDER_SIZE || order.sz < MIN_ORDER_SIZE {
-                        return Err(anyhow!(
-                            "order size {} must be between {} and {}",
-                            order.sz,
-                            MIN_ORDER_SIZE,
-                            MAX_ORDER_SIZE
-                        ));
-                    }
-                    if let Some(default) = opts.default_builder_code.as_ref() {
-                        if order.builder_code.is_none() {
-                            order.builder_code = Some(default.clone());
-                        }
-                    }
-                    order.trigger = None;
-                    if !opts
-                        .allowed_coins
-                        .iter()
-                        .any(|coin| coin.eq_ignore_ascii_case(&order.coin))
-                    {
-                        return Err(anyhow!("coin {} not allowed", order.coin));
+            }
+            for order in &mut perp_orders.orders {
+                check_order_size(order.sz)?;
+                if let Some(default) = opts.default_builder_code.as_ref() {
+                    if order.builder_code.is_none() {
+                        order.builder_code = Some(default.clone());
                     }
-                    order.coin = order.coin.to_uppercase();
                 }
+                order.trigger = None;
+                order.coin = allowed_coin(&order.coin, opts)?;
             }
-            ActionStep::SetLeverage { set_leverage } => {
-                if set_leverage.leverage == 0 || set_leverage.leverage > MAX_LEVERAGE {
-                    return Err(anyhow!(
-                        "leverage {} must be between 1 and {}",
-                        set_leverage.leverage,
-                        MAX_LEVERAGE
-                    ));
-                }
+            check_notional(perp_orders, opts)?;
+        }
+        ActionStep::MultiCoinOrders { multi_coin_orders } => {
+            if multi_coin_orders.builder_code.is_none() {
+                multi_coin_orders.builder_code = opts.default_builder_code.clone();
+            }
+            check_order_size(multi_coin_orders.sz)?;
+            for coin in &mut multi_coin_orders.coins {
+                *coin = allowed_coin(coin, opts)?;
+            }
+            check_notional(&multi_coin_orders.to_perp_orders(), opts)?;
+        }
+        ActionStep::SpotOrders { spot_orders } => {
+            if spot_orders.builder_code.is_none() {
+                spot_orders.builder_code = opts.default_builder_code.clone();
+            }
+            for order in &mut spot_orders.orders {
+                check_order_size(order.sz)?;
+                order.coin = allowed_coin(&order.coin, opts)?;
+            }
+            check_notional(&spot_orders.to_perp_orders(), opts)?;
+        }
+        ActionStep::SetLeverage { set_leverage }
+            if set_leverage.leverage == 0 || set_leverage.leverage > MAX_LEVERAGE =>
+        {
+            return Err(anyhow!(
+                "leverage {} must be between 1 and {}",
+                set_leverage.leverage,
+                MAX_LEVERAGE
+            ));
+        }
+        ActionStep::SetLeverage { set_leverage } if opts.demo && set_leverage.fail_if_position => {
+            // Demo runs hold no real positions, so the pre-checks are meaningless.
+            set_leverage.fail_if_position = false;
+            set_leverage.dry_set_leverage = false;
+        }
+        ActionStep::SubAccountTransfer {
+            sub_account_transfer,
+        } => {
+            if !is_eth_address(&sub_account_transfer.to_address) {
+                return Err(anyhow!(
+                    "sub-account address '{}' is not 0x followed by 40 hex digits",
+                    sub_account_transfer.to_address
+                ));
+            }
+            if sub_account_transfer.amount <= 0.0 {
+                return Err(anyhow!(
+                    "sub-account transfer amount {} must be positive",
+                    sub_account_transfer.amount
+                ));
             }
-            _ => {}
         }
+        ActionStep::If { if_step } => {
+            for child in if_step.then_steps.iter_mut().chain(&mut if_step.else_steps) {
+                sanitize_step(child, opts)?;
+            }
+        }
+        ActionStep::Loop { loop_step } => {
+            if loop_step.count > opts.max_loop_count {
+                warn!(
+                    "lowering LLM loop count {} to {}",
+                    loop_step.count, opts.max_loop_count
+                );
+                loop_step.count = opts.max_loop_count;
+            }
+            for child in &mut loop_step.steps {
+                sanitize_step(child, opts)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+fn check_order_size(sz: f64) -> Result<()> {
+    if sz <= 0.0 {
+        return Err(anyhow!("order size must be positive"));
+    }
+    if !(MIN_ORDER_SIZE..=MAX_ORDER_SIZE).contains(&sz) {
+        return Err(anyhow!(
+            "order size {} must be between {} and {}",
+            sz,
+            MIN_ORDER_SIZE,
+            MAX_ORDER_SIZE
+        ));
     }
     Ok(())
 }
 
+/// Upper-cased `coin`, or `CoinNotAllowed` when it is outside `--allowed-coins`.
+fn allowed_coin(coin: &str, opts: &LlmOptions) -> Result<String> {
+    if !opts
+        .allowed_coins
+        .iter()
+        .any(|allowed| allowed.eq_ignore_ascii_case(coin))
+    {
+        return Err(CoinNotAllowed(coin.to_string()).into());
+    }
+    Ok(coin.to_uppercase())
+}
+
+fn check_notional(step: &PerpOrdersStep, opts: &LlmOptions) -> Result<()> {
+    let notionals = step.orders.iter().map(|order| {
+        let mid = crate::demo_mid_for_coin(&order.coin);
+        (
+            order.coin.as_str(),
+            order.px.resolve_with_mid(mid),
+            order.sz,
+        )
+    });
+    match opts.notional_limits.violation(notionals) {
+        Some(reason) => Err(anyhow!("{reason} (estimated from reference mids)")),
+        None => Ok(()),
+    }
+}
+
+/// Keeps the first `max_orders_per_coin` orders per coin and the first `max_orders_total`
+/// overall, dropping order steps (and loops) left empty. Orders inside `if` branches count
+/// as if either branch may run; orders inside loops count once per pass.
+fn trim_orders(plan: &mut Plan, opts: &LlmOptions) -> BTreeMap<String, usize> {
+    let per_coin_limit = opts.max_orders_per_coin.map_or(usize::MAX, |n| n as usize);
+    let total_limit = opts.max_orders_total.map_or(usize::MAX, |n| n as usize);
+    let mut per_coin: HashMap<String, usize> = HashMap::new();
+    let mut total: usize = 0;
+    let mut trimmed: BTreeMap<String, usize> = BTreeMap::new();
+
+    let mut keep = |coin: &str, passes: usize| {
+        let count = per_coin.entry(coin.to_ascii_uppercase()).or_default();
+        if count.saturating_add(passes) > per_coin_limit
+            || total.saturating_add(passes) > total_limit
+        {
+            *trimmed.entry(coin.to_ascii_uppercase()).or_default() += 1;
+            return false;
+        }
+        *count += passes;
+        total += passes;
+        true
+    };
+    trim_steps(&mut plan.steps, 1, &mut keep, &mut Vec::new());
+
+    for (coin, count) in &trimmed {
+        warn!("trimmed {count} {coin} order(s) from the LLM plan to respect order limits");
+    }
+    trimmed
+}
+
+/// [`trim_orders`] over `steps`, each of which runs `passes` times.
+///
+/// `placed` holds one kept/trimmed flag per order placed so far, in plan order, so that
+/// `wait_for_fill.oidIndex` can be moved to the same order's position in the trimmed plan;
+/// a wait on a trimmed order is dropped. Loops replay their body's flags once per pass, and
+/// steps after an `if` are placed as if `thenSteps` ran.
+fn trim_steps(
+    steps: &mut Vec<ActionStep>,
+    passes: usize,
+    keep: &mut impl FnMut(&str, usize) -> bool,
+    placed: &mut Vec<bool>,
+) {
+    let record = |kept: bool, placed: &mut Vec<bool>| {
+        placed.push(kept);
+        kept
+    };
+    steps.retain_mut(|step| match step {
+        ActionStep::PerpOrders { perp_orders } => {
+            perp_orders
+                .orders
+                .retain(|order| record(keep(&order.coin, passes), placed));
+            !perp_orders.orders.is_empty()
+        }
+        ActionStep::MultiCoinOrders { multi_coin_orders } => {
+            multi_coin_orders
+                .coins
+                .retain(|coin| record(keep(coin, passes), placed));
+            !multi_coin_orders.coins.is_empty()
+        }
+        ActionStep::SpotOrders { spot_orders } => {
+            spot_orders
+                .orders
+                .retain(|order| record(keep(&order.coin, passes), placed));
+            !spot_orders.orders.is_empty()
+        }
+        ActionStep::WaitForFill { wait_for_fill } => match wait_for_fill.oid_index {
+            Some(index) => match remap_oid_index(index, placed) {
+                Some(index) => {
+                    wait_for_fill.oid_index = Some(index);
+                    true
+                }
+                None => {
+                    warn!("dropped a wait_for_fill on oidIndex {index}: that order was trimmed");
+                    false
+                }
+            },
+            None => true,
+        },
+        ActionStep::If { if_step } => {
+            let mut else_placed = placed.clone();
+            trim_steps(&mut if_step.then_steps, passes, keep, placed);
+            trim_steps(&mut if_step.else_steps, passes, keep, &mut else_placed);
+            true
+        }
+        ActionStep::Loop { loop_step } => {
+            let inner = passes.saturating_mul(loop_step.count as usize);
+            let start = placed.len();
+            trim_steps(&mut loop_step.steps, inner, keep, placed);
+            let pass: Vec<bool> = placed[start..].to_vec();
+            for _ in 1..loop_step.count {
+                placed.extend_from_slice(&pass);
+            }
+            !loop_step.steps.is_empty()
+        }
+        _ => true,
+    });
+}
+
+/// Where the order at `index` (as resolved against `placed`) sits once trimmed orders are
+/// gone, keeping the sign convention; `None` when that order was trimmed. Indices past the
+/// orders placed so far are left alone.
+fn remap_oid_index(index: i32, placed: &[bool]) -> Option<i32> {
+    let pos = if index < 0 {
+        placed.len().checked_sub(index.unsigned_abs() as usize)
+    } else {
+        Some(index as usize)
+    };
+    let Some(pos) = pos.filter(|pos| *pos < placed.len()) else {
+        return Some(index);
+    };
+    if !placed[pos] {
+        return None;
+    }
+    let kept_before = placed[..pos].iter().filter(|kept| **kept).count() as i32;
+    if index < 0 {
+        let kept = placed.iter().filter(|kept| **kept).count() as i32;
+        Some(kept_before - kept)
+    } else {
+        Some(kept_before)
+    }
+}
+
 pub fn parse_allowed_coins(raw: &str) -> Vec<String> {
     raw.split(',')
         .filter_map(|part| {
@@ -253,3 +816,373 @@ pub fn discover_cache_dir() -> Option<PathBuf> {
 pub fn dry_run_enabled() -> bool {
     env::var("HL_LLM_DRYRUN").is_ok()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hl_common::test_support::RunFixture;
+
+    #[test]
+    fn parses_multi_coverage_spec() {
+        assert!(matches!(
+            LlmPlanSpec::parse("llm:coverage:5"),
+            Some(LlmPlanSpec::MultiCoverage(5))
+        ));
+        assert!(LlmPlanSpec::parse("llm:coverage:0").is_none());
+        assert!(matches!(
+            LlmPlanSpec::parse("llm:coverage"),
+            Some(LlmPlanSpec::Coverage)
+        ));
+    }
+
+    #[test]
+    fn cache_file_is_replaced_whole() {
+        let fixture = RunFixture::empty("hl-llm-cache");
+        let path = fixture.dir.join(cache_filename("abc"));
+        std::thread::scope(|scope| {
+            for n in 0..8 {
+                let path = &path;
+                scope.spawn(move || {
+                    write_cache_file(path, &build_cached_payload(&"x".repeat(n * 1000), None))
+                        .unwrap()
+                });
+            }
+        });
+
+        let cached: Value = serde_json::from_reader(File::open(&path).unwrap()).unwrap();
+        assert!(parse_cached_payload(cached).is_ok());
+        let leftovers: Vec<_> = fs::read_dir(&fixture.dir).unwrap().collect();
+        assert_eq!(leftovers.len(), 1);
+    }
+
+    #[test]
+    fn seed_changes_prompt_hash() {
+        let unseeded = hash_prompt("m", "sys", "user", 0.2, 1.0, None);
+        assert_eq!(unseeded, hash_prompt("m", "sys", "user", 0.2, 1.0, None));
+        assert_ne!(unseeded, hash_prompt("m", "sys", "user", 0.2, 1.0, Some(7)));
+        assert_ne!(
+            hash_prompt("m", "sys", "user", 0.2, 1.0, Some(7)),
+            hash_prompt("m", "sys", "user", 0.2, 1.0, Some(8))
+        );
+    }
+
+    fn test_options() -> LlmOptions {
+        LlmOptions {
+            api_key: String::new(),
+            model: "m".to_string(),
+            temperature: 0.2,
+            top_p: 1.0,
+            max_output_tokens: 100,
+            max_steps: 5,
+            seed: None,
+            allowed_coins: vec!["ETH".to_string()],
+            default_builder_code: None,
+            cache_dir: None,
+            dry_run: false,
+            demo: true,
+            network_trace: None,
+            refresh_coins_on_retry: true,
+            coin_source: None,
+            max_orders_per_coin: Some(3),
+            max_orders_total: None,
+            max_loop_count: DEFAULT_MAX_LOOP_COUNT,
+            notional_limits: crate::NotionalLimits::default(),
+            tls: TlsConfig::default(),
+        }
+    }
+
+    #[test]
+    fn token_efficiency_divides_steps_by_completion_tokens() {
+        let usage = |completion_tokens| openrouter::Usage {
+            prompt_tokens: Some(50),
+            completion_tokens,
+            total_tokens: None,
+        };
+        let opts = test_options();
+        let meta = build_meta(&opts, "h".to_string(), false, Some(usage(Some(200))), 4);
+        assert_eq!(meta.token_efficiency, Some(0.02));
+        let meta = build_meta(&opts, "h".to_string(), false, Some(usage(None)), 4);
+        assert_eq!(meta.token_efficiency, None);
+    }
+
+    #[test]
+    fn disallowed_coin_is_a_typed_error() {
+        let mut plan: Plan = serde_json::from_str(
+            r#"{"steps":[{"perp_orders":{"orders":[{"coin":"doge","side":"buy","sz":0.01,"px":"mid-1%","tif":"ALO"}]}}]}"#,
+        )
+        .unwrap();
+        let opts = test_options();
+
+        let err = sanitize_plan(&mut plan, &opts).unwrap_err();
+        assert_eq!(err.to_string(), "coin doge not allowed");
+        assert!(err.downcast_ref::<CoinNotAllowed>().is_some());
+
+        let mut refreshed = opts.clone();
+        refreshed.allowed_coins.push("DOGE".to_string());
+        sanitize_plan(&mut plan, &refreshed).unwrap();
+    }
+
+    #[tokio::test]
+    async fn stale_coin_failures_retry_with_refreshed_coins() {
+        let mut opts = test_options();
+        opts.coin_source = Some(CoinSource {
+            base_url: BaseUrl::Testnet,
+            http: reqwest::Client::new(),
+            max_coins: 5,
+            exclude: Vec::new(),
+        });
+        let attempts = std::cell::Cell::new(0);
+        let refreshes = std::cell::Cell::new(0);
+        let attempt = |opts: LlmOptions| {
+            attempts.set(attempts.get() + 1);
+            async move {
+                if opts.allowed_coins.iter().any(|coin| coin == "DOGE") {
+                    Ok(opts.allowed_coins)
+                } else {
+                    Err(anyhow::Error::new(CoinNotAllowed("doge".to_string())))
+                }
+            }
+        };
+        let refresh = |_source: CoinSource| {
+            refreshes.set(refreshes.get() + 1);
+            async { Ok(vec!["ETH".to_string(), "DOGE".to_string()]) }
+        };
+
+        let used = retry_with_fresh_coins(&mut opts, attempt, refresh)
+            .await
+            .unwrap();
+        assert_eq!(used, vec!["ETH", "DOGE"]);
+        assert_eq!(opts.allowed_coins, used);
+        assert_eq!((attempts.get(), refreshes.get()), (2, 1));
+
+        // Without refreshing, the first failure is final.
+        opts.allowed_coins = vec!["ETH".to_string()];
+        opts.refresh_coins_on_retry = false;
+        attempts.set(0);
+        refreshes.set(0);
+        let err = retry_with_fresh_coins(&mut opts, attempt, refresh)
+            .await
+            .unwrap_err();
+        assert!(err.downcast_ref::<CoinNotAllowed>().is_some());
+        assert_eq!((attempts.get(), refreshes.get()), (1, 0));
+    }
+
+    #[tokio::test]
+    async fn coin_refreshes_are_bounded() {
+        let mut opts = test_options();
+        opts.coin_source = Some(CoinSource {
+            base_url: BaseUrl::Testnet,
+            http: reqwest::Client::new(),
+            max_coins: 5,
+            exclude: Vec::new(),
+        });
+        let attempts = std::cell::Cell::new(0);
+        let err = retry_with_fresh_coins(
+            &mut opts,
+            |_opts| {
+                attempts.set(attempts.get() + 1);
+                async { Err::<(), _>(anyhow::Error::new(CoinNotAllowed("doge".to_string()))) }
+            },
+            |_source| async { Ok(vec!["ETH".to_string()]) },
+        )
+        .await
+        .unwrap_err();
+        assert!(err.downcast_ref::<CoinNotAllowed>().is_some());
+        assert_eq!(attempts.get(), MAX_COIN_REFRESHES + 1);
+    }
+
+    #[test]
+    fn order_limits_trim_excess_orders_per_coin_and_total() {
+        let order = |coin: &str| {
+            format!(r#"{{"coin":"{coin}","side":"buy","sz":0.01,"px":"mid-1%","tif":"ALO"}}"#)
+        };
+        let eth: Vec<String> = (0..4).map(|_| order("ETH")).collect();
+        let raw = format!(
+            r#"{{"steps":[{{"perp_orders":{{"orders":[{}]}}}},{{"perp_orders":{{"orders":[{},{}]}}}}]}}"#,
+            eth.join(","),
+            order("ETH"),
+            order("BTC")
+        );
+        let mut opts = test_options();
+        opts.allowed_coins.push("BTC".to_string());
+
+        let mut plan: Plan = serde_json::from_str(&raw).unwrap();
+        let trimmed = sanitize_plan(&mut plan, &opts).unwrap();
+        assert_eq!(trimmed, BTreeMap::from([("ETH".to_string(), 2)]));
+        let counts: Vec<usize> = plan
+            .steps
+            .iter()
+            .filter_map(ActionStep::as_perp_orders)
+            .map(|step| step.orders.len())
+            .collect();
+        assert_eq!(counts, vec![3, 1]);
+
+        opts.max_orders_total = Some(2);
+        let mut plan: Plan = serde_json::from_str(&raw).unwrap();
+        let trimmed = sanitize_plan(&mut plan, &opts).unwrap();
+        assert_eq!(
+            trimmed,
+            BTreeMap::from([("BTC".to_string(), 1), ("ETH".to_string(), 3)])
+        );
+        assert_eq!(plan.steps.len(), 1);
+    }
+
+    #[test]
+    fn order_limits_reach_into_branches_and_loop_passes() {
+        let raw = r#"{"steps":[
+            {"if":{"condition":"previousStepSucceeded","thenSteps":[
+                {"perp_orders":{"orders":[
+                    {"coin":"ETH","side":"buy","sz":0.01,"px":"mid-1%","tif":"ALO"},
+                    {"coin":"ETH","side":"buy","sz":0.01,"px":"mid-2%","tif":"ALO"}
+                ]}}
+            ],"elseSteps":[
+                {"spot_orders":{"orders":[{"coin":"BTC","side":"buy","sz":0.01,"px":"mid-1%"}]}}
+            ]}},
+            {"loop":{"count":2,"steps":[
+                {"perp_orders":{"orders":[{"coin":"ETH","side":"buy","sz":0.01,"px":"mid-1%","tif":"ALO"}]}},
+                {"perp_orders":{"orders":[{"coin":"BTC","side":"buy","sz":0.01,"px":"mid-1%","tif":"ALO"}]}}
+            ]}}
+        ]}"#;
+        let mut opts = test_options();
+        opts.allowed_coins.push("BTC".to_string());
+
+        let mut plan: Plan = serde_json::from_str(raw).unwrap();
+        let trimmed = sanitize_plan(&mut plan, &opts).unwrap();
+        // Two ETH orders in the branch plus two loop passes would make four.
+        assert_eq!(trimmed, BTreeMap::from([("ETH".to_string(), 1)]));
+        let ActionStep::If { if_step } = &plan.steps[0] else {
+            panic!("expected if step");
+        };
+        assert_eq!(if_step.then_steps.len(), 1);
+        assert_eq!(if_step.else_steps.len(), 1);
+        let ActionStep::Loop { loop_step } = &plan.steps[1] else {
+            panic!("expected loop step");
+        };
+        assert_eq!(loop_step.steps.len(), 1);
+        assert_eq!(
+            loop_step.steps[0].as_perp_orders().unwrap().orders[0].coin,
+            "BTC"
+        );
+
+        opts.max_orders_total = Some(2);
+        let mut plan: Plan = serde_json::from_str(raw).unwrap();
+        sanitize_plan(&mut plan, &opts).unwrap();
+        // The branch used up the budget, so the emptied loop goes too.
+        assert_eq!(plan.steps.len(), 1);
+    }
+
+    #[test]
+    fn demo_plans_drop_set_leverage_position_checks() {
+        let raw = r#"{"steps":[
+            {"set_leverage":{"coin":"ETH","leverage":5,"failIfPosition":true,"drySetLeverage":true}}
+        ]}"#;
+        let flags = |demo: bool| {
+            let mut opts = test_options();
+            opts.demo = demo;
+            let mut plan: Plan = serde_json::from_str(raw).unwrap();
+            sanitize_plan(&mut plan, &opts).unwrap();
+            match &plan.steps[0] {
+                ActionStep::SetLeverage { set_leverage } => {
+                    (set_leverage.fail_if_position, set_leverage.dry_set_leverage)
+                }
+                other => panic!("unexpected {}", other.kind()),
+            }
+        };
+
+        assert_eq!(flags(true), (false, false));
+        assert_eq!(flags(false), (true, true));
+    }
+
+    #[test]
+    fn oid_indices_follow_their_orders_through_trimming() {
+        let raw = r#"{"steps":[
+            {"perp_orders":{"orders":[
+                {"coin":"ETH","side":"buy","sz":0.01,"px":"mid-1%","tif":"ALO"},
+                {"coin":"ETH","side":"buy","sz":0.01,"px":"mid-2%","tif":"ALO"},
+                {"coin":"BTC","side":"buy","sz":0.01,"px":"mid-1%","tif":"ALO"}
+            ]}},
+            {"wait_for_fill":{"oidIndex":2}},
+            {"wait_for_fill":{"oidIndex":-1}},
+            {"wait_for_fill":{"oidIndex":-2}},
+            {"wait_for_fill":{"oidIndex":1}}
+        ]}"#;
+        let mut opts = test_options();
+        opts.allowed_coins.push("BTC".to_string());
+        opts.max_orders_per_coin = Some(1);
+
+        let mut plan: Plan = serde_json::from_str(raw).unwrap();
+        sanitize_plan(&mut plan, &opts).unwrap();
+        let indices: Vec<Option<i32>> = plan.steps[1..]
+            .iter()
+            .map(|step| match step {
+                ActionStep::WaitForFill { wait_for_fill } => wait_for_fill.oid_index,
+                other => panic!("unexpected {}", other.kind()),
+            })
+            .collect();
+        // BTC moves from 2 to 1 (still -1); the waits on the trimmed second ETH order go.
+        assert_eq!(indices, vec![Some(1), Some(-1)]);
+
+        let placed = [true, false, true, true];
+        assert_eq!(remap_oid_index(0, &placed), Some(0));
+        assert_eq!(remap_oid_index(3, &placed), Some(2));
+        assert_eq!(remap_oid_index(-4, &placed), Some(-3));
+        assert_eq!(remap_oid_index(-3, &placed), None);
+        assert_eq!(remap_oid_index(7, &placed), Some(7));
+    }
+
+    #[test]
+    fn multi_coin_orders_are_checked_per_coin() {
+        let raw = r#"{"steps":[{"multi_coin_orders":{"coins":["eth","btc"],"side":"buy","sz":0.01,"px":"mid-1%","tif":"ALO"}}]}"#;
+        let mut opts = test_options();
+        let mut plan: Plan = serde_json::from_str(raw).unwrap();
+        let err = sanitize_plan(&mut plan, &opts).unwrap_err();
+        assert!(err.downcast_ref::<CoinNotAllowed>().is_some());
+
+        opts.allowed_coins.push("BTC".to_string());
+        opts.max_orders_total = Some(1);
+        let mut plan: Plan = serde_json::from_str(raw).unwrap();
+        let trimmed = sanitize_plan(&mut plan, &opts).unwrap();
+        assert_eq!(trimmed, BTreeMap::from([("BTC".to_string(), 1)]));
+        let ActionStep::MultiCoinOrders { multi_coin_orders } = &plan.steps[0] else {
+            panic!("expected multi_coin_orders");
+        };
+        assert_eq!(multi_coin_orders.coins, vec!["ETH"]);
+    }
+
+    #[test]
+    fn loop_counts_are_capped_and_bodies_sanitized() {
+        let raw = r#"{"steps":[{"loop":{"count":500,"steps":[
+            {"perp_orders":{"orders":[{"coin":"eth","side":"buy","sz":0.01,"px":"mid-1%"}]}}
+        ]}}]}"#;
+        let mut opts = test_options();
+        // Each pass counts against the order limits; those are covered elsewhere.
+        opts.max_orders_per_coin = None;
+        let mut plan: Plan = serde_json::from_str(raw).unwrap();
+        sanitize_plan(&mut plan, &opts).unwrap();
+        let ActionStep::Loop { loop_step } = &plan.steps[0] else {
+            panic!("expected loop");
+        };
+        assert_eq!(loop_step.count, DEFAULT_MAX_LOOP_COUNT);
+        let perp = loop_step.steps[0].as_perp_orders().unwrap();
+        assert_eq!(perp.orders[0].coin, "ETH");
+    }
+
+    #[test]
+    fn merge_plans_dedupes_identical_steps() {
+        let a: Plan = serde_json::from_str(
+            r#"{"steps":[{"cancel_all":{"coin":"ETH"}},{"sleep_ms":{"duration_ms":100}}]}"#,
+        )
+        .unwrap();
+        let b: Plan = serde_json::from_str(
+            r#"{"steps":[{"sleep_ms":{"duration_ms":100}},{"cancel_last":{}}]}"#,
+        )
+        .unwrap();
+
+        let (merged, deduped) = merge_plans([&a, &b], 10);
+        assert_eq!(merged.steps.len(), 3);
+        assert_eq!(deduped, 1);
+
+        let (capped, _) = merge_plans([&a, &b], 2);
+        assert_eq!(capped.steps.len(), 2);
+    }
+}
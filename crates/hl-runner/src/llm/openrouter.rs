@@ -1,10 +1,13 @@
-use std::time::Duration;
+use std::{sync::Arc, time::Duration};
 
 use anyhow::{anyhow, Context, Result};
 use reqwest::{Client, StatusCode};
+use reqwest_middleware::{ClientBuilder, ClientWithMiddleware};
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 
+use crate::{net_trace::NetworkTrace, tls::TlsConfig};
+
 pub struct OpenRouterConfig {
     pub endpoint: String,
     pub api_key: String,
@@ -12,26 +15,36 @@ pub struct OpenRouterConfig {
     pub temperature: f32,
     pub top_p: f32,
     pub max_tokens: u32,
+    pub seed: Option<u64>,
     pub title: String,
     pub user_agent: String,
+    pub tls: TlsConfig,
 }
 
 pub struct OpenRouter {
-    client: Client,
+    client: ClientWithMiddleware,
     config: OpenRouterConfig,
 }
 
 impl OpenRouter {
-    pub fn new(config: OpenRouterConfig) -> Result<Self> {
-        let client = Client::builder()
-            .timeout(Duration::from_secs(30))
+    pub fn new(config: OpenRouterConfig, trace: Option<Arc<NetworkTrace>>) -> Result<Self> {
+        let client = config
+            .tls
+            .apply(Client::builder().timeout(Duration::from_secs(30)))
             .build()
             .context("failed to build reqwest client")?;
-        Ok(Self { client, config })
+        let mut builder = ClientBuilder::new(client);
+        if let Some(trace) = trace {
+            builder = builder.with_arc(trace);
+        }
+        Ok(Self {
+            client: builder.build(),
+            config,
+        })
     }
 
     pub async fn complete(&self, system: &str, user: &str) -> Result<Completion> {
-        let body = json!({
+        let mut body = json!({
             "model": self.config.model,
             "temperature": self.config.temperature,
             "top_p": self.config.top_p,
@@ -41,6 +54,9 @@ impl OpenRouter {
                 {"role": "user", "content": user}
             ]
         });
+        if let Some(seed) = self.config.seed {
+            body["seed"] = json!(seed);
+        }
 
         let response = self
             .client
@@ -116,12 +132,24 @@ struct ChoiceMessage {
     content: Option<String>,
 }
 
-pub fn hash_prompt(model: &str, system: &str, user: &str, temperature: f32, top_p: f32) -> String {
+pub fn hash_prompt(
+    model: &str,
+    system: &str,
+    user: &str,
+    temperature: f32,
+    top_p: f32,
+    seed: Option<u64>,
+) -> String {
     use sha2::{Digest, Sha256};
     let mut hasher = Sha256::new();
     hasher.update(model.as_bytes());
     hasher.update(temperature.to_le_bytes());
     hasher.update(top_p.to_le_bytes());
+    // Unseeded requests keep their original cache keys.
+    if let Some(seed) = seed {
+        hasher.update(b"seed");
+        hasher.update(seed.to_le_bytes());
+    }
     hasher.update(system.as_bytes());
     hasher.update(user.as_bytes());
     format!("{:x}", hasher.finalize())
@@ -146,6 +174,7 @@ pub fn parse_cached_payload(value: Value) -> Result<Completion> {
         .to_string();
     let usage = value
         .get("usage")
+        .filter(|usage| !usage.is_null())
         .cloned()
         .map(serde_json::from_value)
         .transpose()
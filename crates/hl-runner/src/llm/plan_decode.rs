@@ -2,8 +2,12 @@ use anyhow::{anyhow, Context, Result};
 use hl_common::plan::Plan;
 use serde_json::{json, Value};
 
+/// Tags some reasoning models wrap their chain of thought in before the JSON answer.
+const REASONING_TAGS: &[&str] = &["think", "thinking", "reasoning"];
+
 pub fn decode_plan(raw: &str, max_steps: u32) -> Result<Plan> {
-    let raw = raw.trim();
+    let stripped = strip_reasoning_blocks(raw);
+    let raw = stripped.trim();
     if raw.is_empty() {
         return Err(anyhow!("LLM response was empty"));
     }
@@ -62,6 +66,27 @@ fn parse_plan_candidate(candidate: &str, max_steps: u32) -> Result<Plan> {
     serde_json::from_value::<Plan>(root).with_context(|| "failed to deserialize plan".to_string())
 }
 
+/// Removes every closed `<tag>...</tag>` reasoning block (tag names matched case-insensitively).
+fn strip_reasoning_blocks(raw: &str) -> String {
+    let mut text = raw.to_string();
+    for tag in REASONING_TAGS {
+        let open = format!("<{tag}>");
+        let close = format!("</{tag}>");
+        loop {
+            // ASCII lowercasing keeps byte offsets aligned with `text`.
+            let lower = text.to_ascii_lowercase();
+            let Some(start) = lower.find(&open) else {
+                break;
+            };
+            let Some(end) = lower[start..].find(&close) else {
+                break;
+            };
+            text.replace_range(start..start + end + close.len(), "");
+        }
+    }
+    text
+}
+
 fn generate_candidates(raw: &str) -> Vec<String> {
     let mut out = Vec::new();
     out.push(raw.trim().to_string());
@@ -77,9 +102,109 @@ fn generate_candidates(raw: &str) -> Vec<String> {
         out.push(raw[idx..].trim().to_string());
     }
 
-    out.into_iter()
-        .filter(|candidate| candidate.starts_with('{') || candidate.starts_with('['))
-        .collect()
+    let candidates = out
+        .into_iter()
+        .filter(|candidate| candidate.starts_with('{') || candidate.starts_with('['));
+    #[cfg(feature = "repair-json")]
+    let candidates = candidates.map(|candidate| repair_json(&candidate));
+    candidates.collect()
+}
+
+/// Patches common LLM JSON slips outside string literals: `undefined` becomes `null`,
+/// single-quoted strings become double-quoted, bare `key:` identifiers get quoted and
+/// commas before `}` / `]` are dropped. Valid JSON passes through unchanged, so the
+/// repair is idempotent.
+#[cfg(feature = "repair-json")]
+fn repair_json(input: &str) -> String {
+    let chars: Vec<char> = input.chars().collect();
+    let mut out = String::with_capacity(input.len());
+    let mut i = 0;
+    while i < chars.len() {
+        let ch = chars[i];
+        match ch {
+            '"' => {
+                let end = string_end(&chars, i, '"');
+                out.extend(&chars[i..end]);
+                i = end;
+            }
+            '\'' => {
+                let end = string_end(&chars, i, '\'');
+                out.push('"');
+                let mut j = i + 1;
+                while j < end {
+                    match chars[j] {
+                        '\\' if chars.get(j + 1) == Some(&'\'') => {
+                            out.push('\'');
+                            j += 2;
+                            continue;
+                        }
+                        '\\' => {
+                            out.extend(chars.get(j..(j + 2).min(end)).unwrap_or_default());
+                            j += 2;
+                            continue;
+                        }
+                        '"' => out.push_str("\\\""),
+                        '\'' if j + 1 == end => {}
+                        other => out.push(other),
+                    }
+                    j += 1;
+                }
+                out.push('"');
+                i = end;
+            }
+            ',' => {
+                let next = chars[i + 1..]
+                    .iter()
+                    .find(|ch| !ch.is_whitespace() && **ch != ',');
+                if !matches!(next, Some('}' | ']')) {
+                    out.push(',');
+                }
+                i += 1;
+            }
+            ch if ch.is_ascii_alphabetic() || ch == '_' => {
+                let mut end = i + 1;
+                while end < chars.len() && (chars[end].is_ascii_alphanumeric() || chars[end] == '_')
+                {
+                    end += 1;
+                }
+                let ident: String = chars[i..end].iter().collect();
+                let is_key = chars[end..]
+                    .iter()
+                    .find(|ch| !ch.is_whitespace())
+                    .is_some_and(|ch| *ch == ':');
+                let after_number = i > 0 && chars[i - 1].is_ascii_digit();
+                if is_key && !after_number {
+                    out.push('"');
+                    out.push_str(&ident);
+                    out.push('"');
+                } else if ident == "undefined" && !after_number {
+                    out.push_str("null");
+                } else {
+                    out.push_str(&ident);
+                }
+                i = end;
+            }
+            other => {
+                out.push(other);
+                i += 1;
+            }
+        }
+    }
+    out
+}
+
+/// Index just past the string literal opened by `quote` at `start` (or the input's end).
+#[cfg(feature = "repair-json")]
+fn string_end(chars: &[char], start: usize, quote: char) -> usize {
+    let mut j = start + 1;
+    while j < chars.len() {
+        match chars[j] {
+            '\\' => j += 2,
+            ch if ch == quote => return j + 1,
+            _ => j += 1,
+        }
+    }
+    chars.len()
 }
 
 fn extract_code_blocks(raw: &str) -> Option<Vec<String>> {
@@ -140,6 +265,49 @@ mod tests {
         assert_eq!(plan.steps.len(), 1);
     }
 
+    #[test]
+    fn parse_after_think_block() {
+        let raw = "<think>Maybe {\"steps\": [{\"sleep_ms\": {\"duration_ms\": 999}}]} first?</think>\n<Reasoning>use [brackets] too</Reasoning>\n{\"steps\": [{\"sleep_ms\": {\"duration_ms\": 100}}]}";
+        let plan = parse(raw).unwrap();
+        assert_eq!(plan.steps.len(), 1);
+        let json = plan.as_json().to_string();
+        assert!(json.contains("100"));
+        assert!(!json.contains("999"));
+    }
+
+    #[cfg(feature = "repair-json")]
+    #[test]
+    fn repair_json_patterns() {
+        let cases = [
+            (r#"{"a": undefined}"#, r#"{"a": null}"#),
+            (r#"{'a': 'it\'s "x"'}"#, r#"{"a": "it's \"x\""}"#),
+            (
+                r#"{"a": [1, 2,], "b": {"c": 1 ,}, }"#,
+                r#"{"a": [1, 2], "b": {"c": 1 } }"#,
+            ),
+            (
+                r#"{steps: [{sleep_ms: {duration_ms: 1e3}}]}"#,
+                r#"{"steps": [{"sleep_ms": {"duration_ms": 1e3}}]}"#,
+            ),
+            (
+                r#"{"note": "keep: undefined, 'quotes',]"}"#,
+                r#"{"note": "keep: undefined, 'quotes',]"}"#,
+            ),
+        ];
+        for (input, expected) in cases {
+            let repaired = repair_json(input);
+            assert_eq!(repaired, expected, "repairing {input}");
+            assert_eq!(repair_json(&repaired), repaired, "idempotent for {input}");
+        }
+    }
+
+    #[cfg(feature = "repair-json")]
+    #[test]
+    fn parse_repaired_llm_output() {
+        let plan = parse("```json\n{steps: [{'sleep_ms': {duration_ms: 100,},},]}\n```").unwrap();
+        assert_eq!(plan.steps.len(), 1);
+    }
+
     #[test]
     fn reject_too_many_steps() {
         let err = decode_plan(
@@ -1,56 +1,103 @@
+mod batch;
+mod live_eval;
 mod llm;
+mod net_trace;
+mod parallel;
+mod plan_validate;
+mod profile;
+mod sub_account;
+mod tls;
+mod watch;
 
 use std::{
-    collections::{HashMap, VecDeque},
+    collections::{BTreeSet, HashMap, HashSet, VecDeque},
     env,
     path::{Path, PathBuf},
     str::FromStr,
     sync::Arc,
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 use anyhow::{anyhow, Context, Result};
 use chrono::Utc;
-use clap::{Parser, ValueEnum};
-use ethers::signers::{LocalWallet, Signer};
+use clap::{Parser, Subcommand, ValueEnum};
+use ethers::{
+    signers::{LocalWallet, Signer},
+    types::H160,
+};
+use futures::future::join_all;
 use hl_common::{
-    load_plan_from_spec,
+    normalize::unique_signatures_from_log,
     plan::{
-        ActionStep, CancelAllStep, CancelLastStep, CancelOidsStep, OrderPrice, PerpOrder,
-        PerpOrdersStep, Plan, SetLeverageStep, UsdClassTransferStep,
+        is_plan_url, load_plan_with_vars, ActionStep, AssertBalanceStep, CancelAllStep,
+        CancelLastStep, CancelOidsStep, FetchFundingRateStep, IfStep, LoopStep,
+        MultiCoinOrdersStep, OrderPrice, PerpOrder, PerpOrdersStep, Plan, SetLeverageStep,
+        SleepMsStep, SpotOrdersStep, SubAccountTransferStep, UsdClassTransferStep, WaitForFillStep,
     },
+    plan_url::fetch_plan,
     time::timestamp_ms,
-    RoutedOrderRecord, RunArtifacts,
+    ActionLogRecord, L2Snapshot, RoutedOrderRecord, RunArtifacts,
 };
 use hyperliquid_rust_sdk::{
-    BaseUrl, BuilderInfo, ClientCancelRequest, ClientLimit, ClientOrder, ClientOrderRequest,
-    ExchangeClient, ExchangeDataStatus, ExchangeResponseStatus, InfoClient, LedgerUpdate,
-    LedgerUpdateData, Message, Subscription,
+    ActiveAssetCtxData, AssetCtx, AssetPosition, BaseUrl, BuilderInfo, ClientCancelRequest,
+    ClientLimit, ClientOrder, ClientOrderRequest, ExchangeClient, ExchangeDataStatus,
+    ExchangeResponseStatus, FundingHistoryResponse, InfoClient, LedgerUpdate, LedgerUpdateData,
+    Message, Subscription,
+};
+use live_eval::LiveEvaluator;
+use llm::{
+    generate_plan as llm_generate_plan, parse_allowed_coins, CoinSource, LlmOptions, LlmPlanSpec,
 };
-use llm::{generate_plan as llm_generate_plan, parse_allowed_coins, LlmOptions, LlmPlanSpec};
+use net_trace::NetworkTrace;
+use profile::StepTrace;
+use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
+use tls::TlsConfig;
 use tokio::{
     sync::{broadcast, mpsc, Mutex},
     time::timeout,
 };
-use tracing::{error, info, warn};
+use tracing::{debug, error, info, warn};
 use uuid::Uuid;
 
-#[derive(Parser, Debug)]
+#[derive(Parser, Debug, Clone)]
 #[command(
     author,
     version,
-    about = "Execute HyperLiquidBench plans against Hyperliquid APIs"
+    about = "Execute HyperLiquidBench plans against Hyperliquid APIs",
+    subcommand_negates_reqs = true
 )]
 struct Cli {
-    /// Plan specification: a JSON file or JSONL file with :line selector (1-based)
-    #[arg(long)]
-    plan: String,
+    /// Plan specification: a JSON file or JSONL file with :line selector (1-based), an
+    /// http(s) URL to one, or - for stdin. Repeatable with --parallel to run a different plan per task
+    #[arg(
+        long,
+        required_unless_present = "coinlist_from_meta",
+        allow_hyphen_values = true
+    )]
+    plan: Vec<String>,
+
+    /// Timeout for fetching a --plan given as an http(s) URL
+    #[arg(long, value_name = "SECS", default_value_t = 30)]
+    plan_timeout_secs: u64,
+
+    /// Value for a `{{KEY}}` placeholder in the --plan file (repeatable)
+    #[arg(long = "plan-var", value_name = "KEY=VALUE", value_parser = parse_key_value)]
+    plan_vars: Vec<(String, String)>,
+
+    /// Print every tradeable coin from the network's meta (sorted) and exit
+    #[arg(long, default_value_t = false)]
+    coinlist_from_meta: bool,
 
     /// Output directory. Defaults to runs/<timestamp>
     #[arg(long)]
     out: Option<PathBuf>,
 
+    /// Keep only the N most recent timestamped run directories under runs/ (or under --out
+    /// with --watch), this run included
+    #[arg(long, value_parser = parse_max_runs)]
+    max_runs: Option<usize>,
+
     /// Network to target (mainnet, testnet, local)
     #[arg(long, value_enum, default_value = "testnet")]
     network: Network,
@@ -59,6 +106,11 @@ struct Cli {
     #[arg(long)]
     builder_code: Option<String>,
 
+    /// Per-coin builder codes, e.g. ETH:0xabc,BTC:0xdef; they beat step and global codes
+    /// but not a code set on the order itself
+    #[arg(long, value_name = "COIN:CODE,...")]
+    builder_code_per_coin: Option<String>,
+
     /// Hex-encoded private key for the trading wallet (env: HL_PRIVATE_KEY)
     #[arg(long, env = "HL_PRIVATE_KEY")]
     private_key: Option<String>,
@@ -67,10 +119,191 @@ struct Cli {
     #[arg(long, default_value_t = false)]
     demo: bool,
 
+    /// Resolve order prices against live mids and log what would be sent, without submitting
+    #[arg(long, default_value_t = false, conflicts_with = "demo")]
+    dry_run_with_prices: bool,
+
     /// Max time (ms) to wait for websocket confirmation effects
     #[arg(long, default_value_t = 2_000)]
     effect_timeout_ms: u64,
 
+    /// JSON file of mid prices, e.g. {"BTC": 60000.0, "ETH": 3500.0}, used instead of the
+    /// all_mids API (and instead of the built-in demo mids with --demo); coins missing from it are errors
+    #[arg(long, alias = "demo-prices", value_name = "PATH")]
+    coinprice_source: Option<PathBuf>,
+
+    /// Max age (ms) of a cached mid price before it is re-fetched for mid-relative orders
+    #[arg(long, default_value_t = 5_000)]
+    mid_cache_ttl_ms: u64,
+
+    /// Subscribe to activeAssetCtx for every coin the plan orders and price mid-relative
+    /// orders from those pushes when under 100 ms old, falling back to all_mids
+    #[arg(long, default_value_t = false, conflicts_with_all = ["demo", "coinprice_source"])]
+    use_ws_pricing: bool,
+
+    /// Log L2 order book snapshots for each coin right before every perp_orders step
+    #[arg(long, default_value_t = false)]
+    capture_l2: bool,
+
+    /// Number of bid/ask levels kept per snapshot with --capture-l2
+    #[arg(long, default_value_t = 5)]
+    l2_depth: usize,
+
+    /// Idle keep-alive connections per host in the HTTP client shared by the info and exchange clients
+    #[arg(long, value_name = "N", default_value_t = 4)]
+    http_pool_size: usize,
+
+    /// Abort the run when a wait_for_fill step times out instead of noting it and continuing
+    #[arg(long, default_value_t = false)]
+    abort_on_timeout: bool,
+
+    /// Skip any perp_orders step whose summed notional (resolved px * sz) exceeds this many USDC
+    #[arg(long, value_name = "USDC")]
+    max_notional_per_step: Option<f64>,
+
+    /// Skip any perp_orders step with a single order whose notional exceeds this many USDC
+    #[arg(long, value_name = "USDC")]
+    max_notional_per_order: Option<f64>,
+
+    /// Refuse to run when withdrawable perp margin (USDC) is below this amount
+    #[arg(long)]
+    min_perp_balance: Option<f64>,
+
+    /// Refuse to run when free spot USDC is below this amount
+    #[arg(long)]
+    min_spot_balance: Option<f64>,
+
+    /// Log balance shortfalls as warnings and continue instead of aborting
+    #[arg(long, default_value_t = false)]
+    warn_only_balance: bool,
+
+    /// Comma-separated signatures that must appear in the run, checked after execution
+    #[arg(long)]
+    assert_signatures: Option<String>,
+
+    /// Minimum number of unique signatures the run must produce
+    #[arg(long)]
+    assert_min_unique_sigs: Option<usize>,
+
+    /// Exit with code 4 if any logged step has a rejected or failed ack (ignored in demo mode)
+    #[arg(long, default_value_t = false)]
+    assert_no_errors: bool,
+
+    /// Exit with code 4 if any logged step carries notes, e.g. websocket timeouts (implies
+    /// --assert-no-errors; ignored in demo mode)
+    #[arg(long, default_value_t = false)]
+    assert_no_notes: bool,
+
+    /// Output format: text logs, or a single JSON summary line on stdout (logs move to stderr)
+    #[arg(long, value_enum, default_value = "text")]
+    output_format: OutputFormat,
+
+    /// Store action records, websocket events, routed orders and meta as files or in one
+    /// run_artifacts.db SQLite database. The --assert-* checks read per_action.jsonl, so
+    /// they need `files`.
+    #[arg(
+        long,
+        value_enum,
+        default_value = "files",
+        conflicts_with_all = ["assert_signatures", "assert_min_unique_sigs", "assert_no_errors", "assert_no_notes"]
+    )]
+    artifact_backend: ArtifactBackend,
+
+    /// Write per_action and ws_stream gzipped, as .jsonl.gz
+    #[arg(long, default_value_t = false)]
+    compress_artifacts: bool,
+
+    /// Routed order log format: orders_routed.csv, routed_orders.parquet, or both
+    #[arg(long, value_enum, default_value = "csv")]
+    routed_format: RoutedFormat,
+
+    /// Re-run the plan whenever the plan file changes (file plans only)
+    #[arg(long, default_value_t = false)]
+    watch: bool,
+
+    /// Run N copies of the plan (or the N --plan values) concurrently, each on its own
+    /// connections and writing to <out>/run-<i>
+    #[arg(long, value_name = "N", conflicts_with = "watch")]
+    parallel: Option<usize>,
+
+    /// Delay (ms) between the starts of consecutive --parallel tasks
+    #[arg(long, default_value_t = 0, requires = "parallel")]
+    parallel_stagger_ms: u64,
+
+    /// Run every line of the JSONL --plan as its own plan, in order, writing to <out>/<line>
+    #[arg(long, default_value_t = false, conflicts_with_all = ["watch", "parallel"])]
+    plan_batch: bool,
+
+    /// Abort --plan-batch at the first failed plan instead of running the rest
+    #[arg(long, default_value_t = false, requires = "plan_batch")]
+    stop_on_error: bool,
+
+    /// Pause (ms) after every step except the last, on top of any explicit sleep_ms steps
+    #[arg(long, value_name = "MS", default_value_t = 0)]
+    inject_sleep_between_steps: u64,
+
+    /// Write per-step wall-clock timings as a Chrome trace (chrome://tracing) to this path
+    #[arg(long)]
+    profile: Option<PathBuf>,
+
+    /// Save every HTTP request/response the runner issues itself (LLM calls) as numbered JSON files in this directory
+    #[arg(long)]
+    record_network_trace: Option<PathBuf>,
+
+    /// With --record-network-trace, record response status and headers but not bodies
+    #[arg(long, default_value_t = false, requires = "record_network_trace")]
+    no_trace_ack_bodies: bool,
+
+    /// Comma-separated websocket channels (e.g. userFills) left out of ws_stream.jsonl;
+    /// events still reach step confirmation
+    #[arg(long, value_delimiter = ',', value_name = "CHANNELS")]
+    event_filter: Vec<String>,
+
+    /// Delay before the first websocket reconnect attempt; doubles on each further attempt
+    #[arg(long, value_name = "MS", default_value_t = 500)]
+    ws_reconnect_delay_ms: u64,
+
+    /// Upper bound for the doubling websocket reconnect delay
+    #[arg(long, value_name = "MS", default_value_t = 30_000)]
+    ws_max_reconnect_delay_ms: u64,
+
+    /// Reconnect attempts after the websocket stream closes before giving up
+    #[arg(long, value_name = "N", default_value_t = 5)]
+    ws_max_reconnect_attempts: u32,
+
+    /// Write only the --event-filter channels to ws_stream.jsonl instead of skipping them
+    #[arg(long, default_value_t = false, requires = "event_filter")]
+    event_filter_invert: bool,
+
+    /// Shell command run via `sh -c` after the run finishes, whether or not it succeeded
+    #[arg(long, value_name = "COMMAND")]
+    post_run_hook: Option<String>,
+
+    /// Extra environment variable for --post-run-hook (repeatable)
+    #[arg(
+        long = "post-run-hook-env",
+        value_name = "KEY=VALUE",
+        value_parser = parse_key_value,
+        requires = "post_run_hook"
+    )]
+    post_run_hook_env: Vec<(String, String)>,
+
+    /// Seconds to wait for --post-run-hook before killing it
+    #[arg(long, default_value_t = 60, requires = "post_run_hook")]
+    hook_timeout_secs: u64,
+
+    /// Pipe each action record into an `hl-evaluator --live` child for running scores
+    #[arg(long, default_value_t = false)]
+    live_eval: bool,
+
+    /// Domains YAML for --live-eval
+    #[arg(long, value_name = "PATH", default_value = "dataset/domains-hl.yaml")]
+    live_eval_domains: PathBuf,
+
+    /// hl-evaluator binary for --live-eval (defaults to the one next to hl-runner, then PATH)
+    #[arg(long, value_name = "PATH", requires = "live_eval")]
+    live_eval_bin: Option<PathBuf>,
+
     /// LLM model to use when plan spec is llm:*
     #[arg(long, env = "LLM_MODEL")]
     llm_model: Option<String>,
@@ -83,6 +316,35 @@ struct Cli {
     #[arg(long)]
     llm_allowed_coins: Option<String>,
 
+    /// Maximum coins taken from network meta when --llm-allowed-coins is unset (0 = all)
+    #[arg(long, default_value_t = 5)]
+    max_coins: usize,
+
+    /// Comma-separated coins removed from the network-discovered coin list
+    #[arg(long)]
+    exclude_coins: Option<String>,
+
+    /// Exchange asset metadata (a `meta` response, or just its `universe` array) read
+    /// instead of querying the network for the coin list
+    #[arg(long, value_name = "PATH", conflicts_with = "demo")]
+    coinbook: Option<PathBuf>,
+
+    /// Re-fetch the network coin list and regenerate when the LLM plan uses a coin outside it
+    #[arg(long, default_value_t = false)]
+    llm_refresh_coins_on_retry: bool,
+
+    /// Orders kept per coin in LLM plans; later ones are trimmed (0 = no limit)
+    #[arg(long, value_name = "N", default_value_t = 3)]
+    llm_max_orders_per_coin: u32,
+
+    /// Orders kept across a whole LLM plan; later ones are trimmed
+    #[arg(long, value_name = "N")]
+    llm_max_orders_total: Option<u32>,
+
+    /// Highest `loop` count kept in LLM plans; larger counts are lowered to it
+    #[arg(long, value_name = "N", default_value_t = llm::DEFAULT_MAX_LOOP_COUNT)]
+    llm_max_loop_count: u32,
+
     /// Default builder code suggested to the LLM
     #[arg(long)]
     llm_builder_code: Option<String>,
@@ -98,6 +360,67 @@ struct Cli {
     /// Maximum output tokens for the LLM response
     #[arg(long, env = "LLM_MAX_OUTPUT_TOKENS", default_value_t = 800)]
     llm_max_output_tokens: u32,
+
+    /// Sampling seed forwarded to the LLM (models that ignore it only get a distinct cache key)
+    #[arg(long, env = "LLM_SEED")]
+    llm_seed: Option<u64>,
+
+    /// Accept any TLS certificate, including self-signed and intercepted ones
+    #[arg(long, default_value_t = false, help_heading = "Dangerous")]
+    skip_ssl_verify: bool,
+
+    /// Extra PEM CA certificate to trust, e.g. a corporate proxy's root
+    #[arg(long, value_name = "PATH", help_heading = "Dangerous")]
+    ca_cert: Option<PathBuf>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+enum Command {
+    /// Check a plan offline and print a JSON report; exits 1 when it is invalid
+    PlanValidate(plan_validate::PlanValidateArgs),
+}
+
+#[derive(Copy, Clone, Debug, ValueEnum)]
+enum OutputFormat {
+    Text,
+    Json,
+}
+
+impl OutputFormat {
+    /// Writes a line meant for people: to `stdout` for text, to `stderr` for JSON, whose
+    /// stdout carries only machine-readable lines.
+    fn write_human(
+        self,
+        line: &str,
+        stdout: &mut impl std::io::Write,
+        stderr: &mut impl std::io::Write,
+    ) -> std::io::Result<()> {
+        match self {
+            OutputFormat::Text => writeln!(stdout, "{line}"),
+            OutputFormat::Json => writeln!(stderr, "{line}"),
+        }
+    }
+
+    fn print_human(self, line: &str) {
+        // A closed pipe is not worth failing the run over.
+        let _ = self.write_human(line, &mut std::io::stdout(), &mut std::io::stderr());
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+enum ArtifactBackend {
+    Files,
+    Sqlite,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+enum RoutedFormat {
+    Csv,
+    Parquet,
+    Both,
 }
 
 #[derive(Copy, Clone, Debug, ValueEnum)]
@@ -125,21 +448,123 @@ impl Network {
     }
 }
 
-#[derive(Clone, Debug)]
-struct PlacedOrder {
-    coin: String,
-    oid: u64,
+/// Orders placed during a run, indexed by coin so coin-filtered cancels
+/// do not scan every tracked order.
+#[derive(Debug, Default)]
+struct OrderTracker {
+    by_coin: HashMap<String, VecDeque<u64>>,
+    insertion: Vec<(u64, String)>,
+}
+
+impl OrderTracker {
+    fn push(&mut self, coin: &str, oid: u64) {
+        self.by_coin
+            .entry(coin.to_string())
+            .or_default()
+            .push_back(oid);
+        self.insertion.push((oid, coin.to_string()));
+    }
+
+    fn last(&self, coin: &str) -> Option<u64> {
+        self.by_coin.get(coin).and_then(|oids| oids.back().copied())
+    }
+
+    fn last_any(&self) -> Option<(String, u64)> {
+        self.insertion
+            .last()
+            .map(|(oid, coin)| (coin.clone(), *oid))
+    }
+
+    /// The `index`-th tracked order, oldest first; negative indices count back from the newest.
+    fn nth(&self, index: i32) -> Option<(String, u64)> {
+        let pos = if index < 0 {
+            self.insertion
+                .len()
+                .checked_sub(index.unsigned_abs() as usize)?
+        } else {
+            index as usize
+        };
+        self.insertion
+            .get(pos)
+            .map(|(oid, coin)| (coin.clone(), *oid))
+    }
+
+    fn coin_of(&self, oid: u64) -> Option<&str> {
+        self.insertion
+            .iter()
+            .find(|(tracked, _)| *tracked == oid)
+            .map(|(_, coin)| coin.as_str())
+    }
+
+    fn pop_last(&mut self, coin: &str) -> Option<u64> {
+        let oid = self.by_coin.get_mut(coin)?.pop_back()?;
+        if let Some(pos) = self
+            .insertion
+            .iter()
+            .rposition(|(tracked, _)| *tracked == oid)
+        {
+            self.insertion.remove(pos);
+        }
+        Some(oid)
+    }
+
+    fn pop_last_any(&mut self) -> Option<(String, u64)> {
+        let (oid, coin) = self.insertion.pop()?;
+        if let Some(oids) = self.by_coin.get_mut(&coin) {
+            if let Some(pos) = oids.iter().rposition(|tracked| *tracked == oid) {
+                oids.remove(pos);
+            }
+        }
+        Some((coin, oid))
+    }
+
+    fn all_for_coin(&self, coin: &str) -> Vec<u64> {
+        self.by_coin
+            .get(coin)
+            .map(|oids| oids.iter().copied().collect())
+            .unwrap_or_default()
+    }
+
+    fn all(&self) -> Vec<(String, u64)> {
+        self.insertion
+            .iter()
+            .map(|(oid, coin)| (coin.clone(), *oid))
+            .collect()
+    }
+
+    /// Stops tracking `oid`, returning whether it was tracked.
+    fn remove(&mut self, oid: u64) -> bool {
+        let Some(pos) = self
+            .insertion
+            .iter()
+            .position(|(tracked, _)| *tracked == oid)
+        else {
+            return false;
+        };
+        let (_, coin) = self.insertion.remove(pos);
+        if let Some(oids) = self.by_coin.get_mut(&coin) {
+            oids.retain(|tracked| *tracked != oid);
+        }
+        true
+    }
+
+    fn remove_all(&mut self, oids: &[u64]) {
+        for oid in oids {
+            self.remove(*oid);
+        }
+    }
 }
 
 #[derive(Clone, Debug)]
 enum ObservedEvent {
     OrderUpdate {
         oid: u64,
-        _status: String,
+        status: String,
         payload: serde_json::Value,
     },
     UserFill {
         oid: u64,
+        coin: String,
         payload: serde_json::Value,
     },
     LedgerClassTransfer {
@@ -151,6 +576,8 @@ enum ObservedEvent {
         _channel: String,
         payload: serde_json::Value,
     },
+    /// The websocket stream closed and every reconnect attempt failed.
+    WsDisconnected { payload: serde_json::Value },
 }
 
 impl ObservedEvent {
@@ -159,48 +586,189 @@ impl ObservedEvent {
             ObservedEvent::OrderUpdate { payload, .. }
             | ObservedEvent::UserFill { payload, .. }
             | ObservedEvent::LedgerClassTransfer { payload, .. }
-            | ObservedEvent::Other { payload, .. } => payload,
+            | ObservedEvent::Other { payload, .. }
+            | ObservedEvent::WsDisconnected { payload } => payload,
         }
     }
+
+    /// The websocket is gone for good, so waiters can stop early.
+    fn is_ws_disconnect(&self) -> bool {
+        matches!(self, ObservedEvent::WsDisconnected { .. })
+    }
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
     dotenvy::dotenv().ok();
 
-    tracing_subscriber::fmt()
+    let cli = Cli::parse();
+
+    let subscriber = tracing_subscriber::fmt()
         .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
-        .with_target(false)
-        .init();
+        .with_target(false);
+    match (cli.output_format, &cli.command) {
+        (OutputFormat::Text, None) => subscriber.init(),
+        // Keep stdout for the single FinalOutput (or plan-validate report) line; logs go to stderr.
+        _ => subscriber.with_writer(std::io::stderr).init(),
+    }
 
-    let cli = Cli::parse();
+    if let Some(Command::PlanValidate(args)) = &cli.command {
+        // URL plans are fetched with a blocking client, which must not run on a runtime thread.
+        let report = tokio::task::block_in_place(|| plan_validate::run(args))?;
+        if !report.valid {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    check_network_trace(&cli)?;
+
+    if cli.skip_ssl_verify {
+        warn!("--skip-ssl-verify is set: TLS certificates are NOT verified; traffic can be intercepted");
+    }
+    if let Some(path) = cli.ca_cert.as_deref() {
+        warn!(
+            "trusting extra CA certificate {} (--ca-cert)",
+            path.display()
+        );
+    }
+
+    if cli.coinlist_from_meta {
+        return print_coinlist(cli.network.base_url(), http_client_for(&cli)?).await;
+    }
+
+    if cli.watch {
+        return watch::watch_plan(&cli).await;
+    }
+
+    let timestamp = Utc::now().format("%Y%m%d-%H%M%S").to_string();
+    let out_dir = cli
+        .out
+        .clone()
+        .unwrap_or_else(|| PathBuf::from(DEFAULT_RUNS_ROOT).join(&timestamp));
+
+    if let Some(tasks) = cli.parallel {
+        return parallel::run_parallel(&cli, tasks, &timestamp, &out_dir).await;
+    }
+    if cli.plan.len() > 1 {
+        return Err(anyhow!("multiple --plan values require --parallel"));
+    }
+    if cli.plan_batch {
+        return batch::run_batch(&cli, &timestamp, &out_dir).await;
+    }
+    let mut output = FinalOutput::new(&out_dir);
+    let result = run_once(&cli, &timestamp, &out_dir, &mut output).await;
+    run_post_run_hook(&cli, &output, result.is_ok()).await;
+    let exit_code = match &result {
+        Err(err) if err.is::<AckAssertionFailed>() => 4,
+        _ => 1,
+    };
+
+    match cli.output_format {
+        OutputFormat::Text => match result {
+            Err(err) if exit_code != 1 => {
+                eprintln!("Error: {err:#}");
+                std::process::exit(exit_code);
+            }
+            result => result,
+        },
+        OutputFormat::Json => {
+            output.error = result.err().map(|err| format!("{err:#}"));
+            output.success = output.error.is_none();
+            println!("{}", serde_json::to_string(&output)?);
+            if !output.success {
+                std::process::exit(exit_code);
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Single-line run summary printed to stdout with `--output-format json`.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FinalOutput {
+    success: bool,
+    out_dir: String,
+    step_count: usize,
+    error: Option<String>,
+    wallet: Option<String>,
+    /// Mean wall-clock time of the run's perp_orders steps.
+    avg_order_latency_ms: Option<f64>,
+}
+
+impl TlsConfig {
+    fn from_cli(cli: &Cli) -> Result<Self> {
+        TlsConfig::new(cli.skip_ssl_verify, cli.ca_cert.as_deref())
+    }
+}
+
+impl Cli {
+    /// The `--plan` value; clap only lets it be absent with `--coinlist-from-meta`.
+    fn plan_spec(&self) -> &str {
+        self.plan
+            .first()
+            .expect("--plan is required unless --coinlist-from-meta is set")
+    }
+}
+
+impl FinalOutput {
+    fn new(out_dir: &Path) -> Self {
+        Self {
+            success: false,
+            out_dir: out_dir.display().to_string(),
+            step_count: 0,
+            error: None,
+            wallet: None,
+            avg_order_latency_ms: None,
+        }
+    }
+}
+
+async fn run_once(
+    cli: &Cli,
+    timestamp: &str,
+    out_dir: &Path,
+    output: &mut FinalOutput,
+) -> Result<()> {
     let base_url = cli.network.base_url();
+    let builder_codes = BuilderCodes::from_cli(cli)?;
 
-    let plan_source = resolve_plan(&cli, base_url).await?;
+    let plan_source = resolve_plan(cli, base_url).await?;
     let PlanSource {
         plan,
         raw,
         llm_meta,
         dry_run,
     } = plan_source;
-
-    let timestamp = Utc::now().format("%Y%m%d-%H%M%S").to_string();
-    let out_dir = cli
-        .out
-        .clone()
-        .unwrap_or_else(|| PathBuf::from("runs").join(&timestamp));
+    output.step_count = plan.steps.len();
+    let estimated_duration_ms = plan.estimated_duration_ms();
+    info!("estimated plan duration: {estimated_duration_ms}ms");
 
     let plan_json = plan.as_json();
-    let artifacts = RunArtifacts::create(&out_dir, &plan_json, raw.as_deref(), None)?;
+    let mut artifacts = RunArtifacts::create(
+        out_dir,
+        &plan_json,
+        raw.as_deref(),
+        None,
+        cli.compress_artifacts,
+    )?;
+    if cli.artifact_backend == ArtifactBackend::Sqlite {
+        let db_path = artifacts.use_sqlite()?;
+        info!("writing run artifacts to {}", db_path.display());
+    } else if cli.routed_format != RoutedFormat::Csv {
+        artifacts.buffer_routed_orders();
+    }
     let artifacts = Arc::new(Mutex::new(artifacts));
 
     if dry_run {
         let window_ms = artifacts.lock().await.window_ms();
         let meta = build_run_meta(
-            &cli,
-            &timestamp,
-            &out_dir,
+            cli,
+            timestamp,
+            out_dir,
             &plan_json,
+            estimated_duration_ms,
             None,
             window_ms,
             llm_meta.as_ref(),
@@ -215,11 +783,161 @@ async fn main() -> Result<()> {
         return Ok(());
     }
 
-    let mut wallet_hex: Option<String> = None;
+    // Finished once the plan has run; an early return drops (and kills) it instead.
+    let live_eval = if cli.live_eval {
+        let bin = cli
+            .live_eval_bin
+            .clone()
+            .unwrap_or_else(live_eval::default_evaluator_bin);
+        let evaluator = LiveEvaluator::spawn(
+            &bin,
+            &cli.live_eval_domains,
+            out_dir,
+            matches!(cli.output_format, OutputFormat::Json),
+        )?;
+        artifacts.lock().await.tee_actions(evaluator.sink());
+        Some(evaluator)
+    } else {
+        None
+    };
+
+    let mut trace = StepTrace::default();
+    let executed = execute_mode(
+        cli,
+        plan,
+        artifacts.clone(),
+        &builder_codes,
+        output,
+        &mut trace,
+    )
+    .await;
+    // Written on failure too, so an aborted run still leaves its routed orders behind.
+    let routed = finish_routed_orders(cli, &artifacts).await;
+    if let Some(evaluator) = live_eval {
+        evaluator.finish().await;
+    }
+    executed?;
+    routed?;
+
+    output.avg_order_latency_ms = trace.mean_duration_ms("perp_orders");
+    if let Some(path) = cli.profile.as_ref() {
+        trace.write(path)?;
+        info!("step trace written to {}", path.display());
+    }
+
+    let window_ms = artifacts.lock().await.window_ms();
+    let mut meta = build_run_meta(
+        cli,
+        timestamp,
+        out_dir,
+        &plan_json,
+        estimated_duration_ms,
+        output.wallet.clone(),
+        window_ms,
+        llm_meta.as_ref(),
+        false,
+        cli.demo,
+    )?;
+    {
+        let mut artifacts = artifacts.lock().await;
+        meta["duplicateOidCount"] = json!(artifacts.duplicate_oid_count());
+        meta["tradingStats"] = json!(artifacts.trading_stats());
+        artifacts.write_meta(&meta)?;
+        // The --assert-* checks below read the logs back, gzipped ones included.
+        artifacts.close_logs()?;
+    }
+
+    info!("run artifacts stored under {}", out_dir.display());
+
+    if let Some(max_runs) = cli.max_runs {
+        prune_sibling_runs(cli, out_dir, max_runs)?;
+    }
+
+    if cli.assert_signatures.is_some() || cli.assert_min_unique_sigs.is_some() {
+        check_signature_assertions(cli, &RunArtifacts::open(out_dir)?.per_action_path())?;
+    }
+
+    if !cli.demo && (cli.assert_no_errors || cli.assert_no_notes) {
+        let records = RunArtifacts::open(out_dir)?
+            .iter_action_records()
+            .collect::<Result<Vec<_>>>()?;
+        let failures = ack_assertion_failures(&records, cli.assert_no_notes);
+        for failure in &failures {
+            eprintln!("{failure}");
+        }
+        if !failures.is_empty() {
+            return Err(AckAssertionFailed {
+                count: failures.len(),
+            }
+            .into());
+        }
+    }
+    Ok(())
+}
+
+/// Runs the plan in the mode `cli` selects: demo, dry run with prices, or live.
+async fn execute_mode(
+    cli: &Cli,
+    plan: Plan,
+    artifacts: Arc<Mutex<RunArtifacts>>,
+    builder_codes: &BuilderCodes,
+    output: &mut FinalOutput,
+    trace: &mut StepTrace,
+) -> Result<()> {
+    let base_url = cli.network.base_url();
+    let coinbook = cli.coinbook.as_deref().map(Coinbook::load).transpose()?;
+    let price_file = cli
+        .coinprice_source
+        .as_deref()
+        .map(|path| {
+            let file = PriceFile::load(path)?;
+            Ok::<_, anyhow::Error>(match &coinbook {
+                Some(coinbook) => file.with_coinbook(coinbook),
+                None => file,
+            })
+        })
+        .transpose()?;
 
     if cli.demo {
         info!("demo mode enabled — skipping network execution");
-        run_demo(plan.clone(), artifacts.clone(), cli.builder_code.clone()).await?;
+        run_demo(
+            plan.clone(),
+            artifacts.clone(),
+            builder_codes,
+            cli.capture_l2.then_some(cli.l2_depth),
+            price_file.as_ref(),
+            cli.inject_sleep_between_steps,
+            NotionalLimits::from_cli(cli),
+            trace,
+        )
+        .await?;
+    } else if cli.dry_run_with_prices {
+        info!("dry run with live prices — orders will not be submitted");
+        if let Some(price_file) = price_file {
+            run_dry_with_prices(
+                plan.clone(),
+                artifacts.clone(),
+                price_file,
+                cli.mid_cache_ttl_ms,
+                builder_codes,
+                trace,
+            )
+            .await?;
+        } else {
+            let http = http_client_for(cli)?;
+            let info_http = InfoClient::new(Some(http), Some(base_url))
+                .await
+                .context("failed to initialise info client")?;
+            run_dry_with_prices(
+                plan.clone(),
+                artifacts.clone(),
+                info_http,
+                cli.mid_cache_ttl_ms,
+                builder_codes,
+                trace,
+            )
+            .await?;
+        }
     } else {
         let private_key = cli.private_key.as_ref().ok_or_else(|| {
             anyhow!("--private-key or HL_PRIVATE_KEY must be provided unless --demo is set")
@@ -228,64 +946,365 @@ async fn main() -> Result<()> {
         let wallet = LocalWallet::from_str(private_key.trim())
             .map_err(|e| anyhow!("failed to parse wallet private key: {e}"))?;
         let wallet_address = wallet.address();
-        wallet_hex = Some(format!("0x{:x}", wallet_address));
+        output.wallet = Some(format!("0x{:x}", wallet_address));
 
-        let exchange = ExchangeClient::new(None, wallet.clone(), Some(base_url), None, None)
-            .await
-            .context("failed to initialise exchange client")?;
+        let http = http_client_for(cli)?;
+        let exchange = ExchangeClient::new(
+            Some(http.clone()),
+            wallet.clone(),
+            Some(base_url),
+            None,
+            None,
+        )
+        .await
+        .context("failed to initialise exchange client")?;
 
-        let info_http = InfoClient::new(None, Some(base_url))
+        let info_http = InfoClient::new(Some(http.clone()), Some(base_url))
             .await
             .context("failed to initialise info client")?;
-        let info_ws = InfoClient::with_reconnect(None, Some(base_url))
-            .await
-            .context("failed to initialise websocket info client")?;
+
+        check_balances(cli, &info_http, wallet_address).await?;
+
+        let ws_mids = cli.use_ws_pricing.then(WsMids::default);
 
         let (event_tx, _) = broadcast::channel::<ObservedEvent>(256);
-        spawn_ws_task(info_ws, wallet_address, artifacts.clone(), event_tx.clone());
+        spawn_ws_task(
+            InfoWsConnector {
+                http,
+                base_url,
+                wallet_address,
+                // `--use-ws-pricing`: the coins whose pushed mids land in `ws_mids`.
+                pricing_coins: if cli.use_ws_pricing {
+                    plan.coins_used()
+                } else {
+                    Vec::new()
+                },
+            },
+            WsReconnectConfig::from_cli(cli),
+            artifacts.clone(),
+            event_tx.clone(),
+            WsChannelFilter::from_cli(cli),
+            ws_mids.clone(),
+        );
 
         execute_plan(
             plan,
             artifacts.clone(),
             exchange,
             info_http,
+            wallet_address,
             event_tx.clone(),
-            cli.builder_code.clone(),
+            builder_codes,
             cli.effect_timeout_ms,
+            cli.abort_on_timeout,
+            MidCache::new(cli.mid_cache_ttl_ms).with_ws_mids(ws_mids),
+            price_file,
+            cli.capture_l2.then_some(cli.l2_depth),
+            cli.inject_sleep_between_steps,
+            NotionalLimits::from_cli(cli),
+            trace,
         )
         .await?;
     }
-
-    let window_ms = artifacts.lock().await.window_ms();
-    let meta = build_run_meta(
-        &cli,
-        &timestamp,
-        &out_dir,
-        &plan_json,
-        wallet_hex,
-        window_ms,
-        llm_meta.as_ref(),
-        false,
-        cli.demo,
-    )?;
-    artifacts.lock().await.write_meta(&meta)?;
-
-    info!("run artifacts stored under {}", out_dir.display());
     Ok(())
 }
 
-struct PlanSource {
-    plan: Plan,
-    raw: Option<String>,
-    llm_meta: Option<llm::LlmMeta>,
-    dry_run: bool,
+/// Writes `routed_orders.parquet` for `--routed-format parquet|both`. With `parquet` the
+/// CSV is kept until then, so a run that dies before this point still has its orders.
+async fn finish_routed_orders(cli: &Cli, artifacts: &Mutex<RunArtifacts>) -> Result<()> {
+    if cli.artifact_backend == ArtifactBackend::Sqlite || cli.routed_format == RoutedFormat::Csv {
+        return Ok(());
+    }
+    let mut artifacts = artifacts.lock().await;
+    let path = artifacts.write_routed_parquet()?;
+    info!("routed orders written to {}", path.display());
+    if cli.routed_format == RoutedFormat::Parquet {
+        artifacts.disable_routed_csv()?;
+    }
+    Ok(())
+}
+
+fn parse_key_value(raw: &str) -> Result<(String, String), String> {
+    match raw.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(format!("expected KEY=VALUE, got '{raw}'")),
+    }
+}
+
+fn parse_max_runs(raw: &str) -> Result<usize, String> {
+    match raw.parse::<usize>() {
+        Ok(0) => Err("--max-runs must be at least 1".to_string()),
+        Ok(max_runs) => Ok(max_runs),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+/// Runs `--post-run-hook` with the run's outcome in `HL_RUN_*` variables. A failing or
+/// timed-out hook only warns; it never changes the runner's exit code.
+async fn run_post_run_hook(cli: &Cli, output: &FinalOutput, success: bool) {
+    let Some(hook) = cli.post_run_hook.as_deref() else {
+        return;
+    };
+    let mut command = tokio::process::Command::new("sh");
+    command
+        .arg("-c")
+        .arg(hook)
+        .env("HL_RUN_OUT_DIR", &output.out_dir)
+        .env("HL_RUN_SUCCESS", if success { "1" } else { "0" })
+        .env("HL_RUN_STEP_COUNT", output.step_count.to_string())
+        .env(
+            "HL_RUN_WALLET",
+            output.wallet.as_deref().unwrap_or_default(),
+        )
+        .envs(
+            cli.post_run_hook_env
+                .iter()
+                .map(|(key, value)| (key, value)),
+        );
+    if matches!(cli.output_format, OutputFormat::Json) {
+        // Stdout is reserved for the FinalOutput line.
+        command.stdout(std::io::stderr());
+    }
+
+    match wait_for_hook(command, Duration::from_secs(cli.hook_timeout_secs)).await {
+        Ok(Some(status)) if status.success() => info!("post-run hook finished"),
+        Ok(Some(status)) => warn!("post-run hook exited with {status}"),
+        Ok(None) => warn!(
+            "post-run hook killed after {}s timeout",
+            cli.hook_timeout_secs
+        ),
+        Err(err) => warn!("post-run hook failed: {err:#}"),
+    }
+}
+
+/// Spawns `command` and waits up to `timeout`; `None` means it was killed on timeout. On
+/// unix the hook leads its own process group, so a timeout also kills whatever it started.
+async fn wait_for_hook(
+    mut command: tokio::process::Command,
+    timeout: Duration,
+) -> Result<Option<std::process::ExitStatus>> {
+    #[cfg(unix)]
+    command.process_group(0);
+    let mut child = command.spawn().context("failed to spawn post-run hook")?;
+    if let Ok(status) = tokio::time::timeout(timeout, child.wait()).await {
+        return status.map(Some).context("failed to wait for post-run hook");
+    }
+    #[cfg(unix)]
+    if let Some(pid) = child.id() {
+        // SAFETY: kill(2) has no memory-safety preconditions; a negative pid names the group.
+        unsafe {
+            libc::kill(-(pid as libc::pid_t), libc::SIGKILL);
+        }
+    }
+    child.kill().await.ok();
+    Ok(None)
+}
+
+/// `--record-network-trace` only sees the OpenRouter calls behind `llm:*` plans; with no
+/// such plan it would silently record nothing.
+fn check_network_trace(cli: &Cli) -> Result<()> {
+    if cli.record_network_trace.is_none()
+        || cli
+            .plan
+            .iter()
+            .any(|spec| LlmPlanSpec::parse(spec).is_some())
+    {
+        return Ok(());
+    }
+    Err(anyhow!(
+        "--record-network-trace only captures the OpenRouter requests of llm:* plans, \
+         and no --plan is one"
+    ))
+}
+
+const DEFAULT_RUNS_ROOT: &str = "runs";
+
+/// Where timestamped run directories live: `runs/`, or the `--out` base in watch mode.
+fn runs_root(cli: &Cli) -> PathBuf {
+    match &cli.out {
+        Some(base) if cli.watch => base.clone(),
+        _ => PathBuf::from(DEFAULT_RUNS_ROOT),
+    }
+}
+
+/// Applies `--max-runs` to the runs root, keeping `out_dir`. A run written anywhere else
+/// prunes nothing, so `--out /tmp/x` never touches unrelated directories in `/tmp`.
+fn prune_sibling_runs(cli: &Cli, out_dir: &Path, max_runs: usize) -> Result<()> {
+    let root = runs_root(cli);
+    let parent = out_dir
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or_else(|| Path::new("."));
+    let under_root = matches!(
+        (root.canonicalize(), parent.canonicalize()),
+        (Ok(root), Ok(parent)) if root == parent
+    );
+    if !under_root {
+        warn!(
+            "--max-runs only prunes runs directly under {}; {} is not one, nothing pruned",
+            root.display(),
+            out_dir.display()
+        );
+        return Ok(());
+    }
+    let pruned = RunArtifacts::prune_old_runs(&root, max_runs, out_dir)?;
+    if pruned > 0 {
+        info!("pruned {pruned} old run(s) from {}", root.display());
+    }
+    Ok(())
+}
+
+/// Pre-flight check against `--min-perp-balance` / `--min-spot-balance`.
+async fn check_balances(cli: &Cli, info_http: &InfoClient, wallet_address: H160) -> Result<()> {
+    let mut shortfalls = Vec::new();
+    if let Some(required) = cli.min_perp_balance {
+        let state = info_http
+            .user_state(wallet_address)
+            .await
+            .context("failed to fetch clearinghouse state for balance check")?;
+        let available = parse_balance(&state.withdrawable, "perp withdrawable balance")?;
+        shortfalls.extend(balance_shortfall("perp", available, required));
+    }
+    if let Some(required) = cli.min_spot_balance {
+        let balances = info_http
+            .user_token_balances(wallet_address)
+            .await
+            .context("failed to fetch spot balances for balance check")?;
+        let available = match balances.balances.iter().find(|b| b.coin == "USDC") {
+            Some(usdc) => {
+                parse_balance(&usdc.total, "spot USDC total")?
+                    - parse_balance(&usdc.hold, "spot USDC hold")?
+            }
+            None => 0.0,
+        };
+        shortfalls.extend(balance_shortfall("spot", available, required));
+    }
+
+    if shortfalls.is_empty() {
+        return Ok(());
+    }
+    if cli.warn_only_balance {
+        for shortfall in &shortfalls {
+            warn!("{shortfall}");
+        }
+        Ok(())
+    } else {
+        Err(anyhow!(shortfalls.join("; ")))
+    }
+}
+
+/// Parses a decimal string from the info API; a malformed one is an error, not a zero balance.
+fn parse_balance(raw: &str, what: &str) -> Result<f64> {
+    raw.parse::<f64>()
+        .with_context(|| format!("invalid {what} {raw:?} from the info API"))
+}
+
+fn balance_shortfall(kind: &str, available: f64, required: f64) -> Option<String> {
+    (available < required).then(|| {
+        format!("insufficient {kind} balance: {available:?} USDC < required {required:?} USDC")
+    })
+}
+
+/// Scores the run's action log inline and checks `--assert-*` expectations.
+fn check_signature_assertions(cli: &Cli, per_action_path: &Path) -> Result<()> {
+    let found = unique_signatures_from_log(per_action_path)?;
+    let required: Vec<String> = cli
+        .assert_signatures
+        .as_deref()
+        .map(|csv| {
+            csv.split(',')
+                .map(str::trim)
+                .filter(|sig| !sig.is_empty())
+                .map(String::from)
+                .collect()
+        })
+        .unwrap_or_default();
+    let failures = signature_assertion_failures(&found, &required, cli.assert_min_unique_sigs);
+
+    let summary = if failures.is_empty() {
+        format!("PASS: {} unique signatures", found.len())
+    } else {
+        format!("FAIL: {}", failures.join("; "))
+    };
+    cli.output_format.print_human(&summary);
+
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        Err(anyhow!("signature assertions failed"))
+    }
+}
+
+fn signature_assertion_failures(
+    found: &BTreeSet<String>,
+    required: &[String],
+    min_unique: Option<usize>,
+) -> Vec<String> {
+    let mut failures: Vec<String> = required
+        .iter()
+        .filter(|sig| !found.iter().any(|f| f.eq_ignore_ascii_case(sig)))
+        .map(|sig| format!("missing signature {sig}"))
+        .collect();
+    if let Some(min) = min_unique {
+        if found.len() < min {
+            failures.push(format!(
+                "{} unique signatures < required {min}",
+                found.len()
+            ));
+        }
+    }
+    failures
+}
+
+/// `--assert-no-errors` / `--assert-no-notes` found offending steps; the runner exits 4.
+#[derive(Debug)]
+struct AckAssertionFailed {
+    count: usize,
+}
+
+impl std::fmt::Display for AckAssertionFailed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} step(s) failed the ack assertions", self.count)
+    }
+}
+
+impl std::error::Error for AckAssertionFailed {}
+
+/// One line per record whose ack status is not `ok`/`dryRun`/`skipped` or reports an
+/// order-level error, plus (with `check_notes`) every record that has notes.
+fn ack_assertion_failures(records: &[ActionLogRecord], check_notes: bool) -> Vec<String> {
+    let mut failures = Vec::new();
+    for record in records {
+        if let Some(ack) = record.ack.as_ref().filter(|_| record.ack_failed()) {
+            failures.push(format!(
+                "step {} {}: ack {ack}",
+                record.step_idx, record.action
+            ));
+        }
+        if check_notes {
+            if let Some(notes) = &record.notes {
+                failures.push(format!(
+                    "step {} {}: notes {notes}",
+                    record.step_idx, record.action
+                ));
+            }
+        }
+    }
+    failures
+}
+
+struct PlanSource {
+    plan: Plan,
+    raw: Option<String>,
+    llm_meta: Option<llm::LlmMeta>,
+    dry_run: bool,
 }
 
 async fn resolve_plan(cli: &Cli, base_url: BaseUrl) -> Result<PlanSource> {
-    if let Some(spec) = LlmPlanSpec::parse(&cli.plan) {
+    if let Some(spec) = LlmPlanSpec::parse(cli.plan_spec()) {
         let allowed_coins = determine_allowed_coins(cli, base_url).await?;
-        let llm_opts = build_llm_options(cli, allowed_coins)?;
-        let llm_plan = llm_generate_plan(spec, &llm_opts).await?;
+        let mut llm_opts = build_llm_options(cli, allowed_coins, base_url)?;
+        let llm_plan = llm_generate_plan(spec, &mut llm_opts).await?;
         Ok(PlanSource {
             plan: llm_plan.plan,
             raw: Some(llm_plan.raw),
@@ -293,7 +1312,24 @@ async fn resolve_plan(cli: &Cli, base_url: BaseUrl) -> Result<PlanSource> {
             dry_run: llm_opts.dry_run,
         })
     } else {
-        let plan = load_plan_from_spec(&cli.plan)?;
+        let spec = cli.plan_spec();
+        let vars: HashMap<String, String> = cli.plan_vars.iter().cloned().collect();
+        let plan = if is_plan_url(spec) {
+            let timeout = Duration::from_secs(cli.plan_timeout_secs);
+            let tls = TlsConfig::from_cli(cli)?;
+            let spec = spec.to_string();
+            tokio::task::spawn_blocking(move || {
+                let http = tls
+                    .apply_blocking(reqwest::blocking::Client::builder())
+                    .build()
+                    .context("failed to build HTTP client")?;
+                fetch_plan(&spec, &http, timeout, &vars)
+            })
+            .await
+            .context("plan fetch task failed")??
+        } else {
+            load_plan_with_vars(spec, &vars)?
+        };
         Ok(PlanSource {
             plan,
             raw: None,
@@ -310,33 +1346,165 @@ async fn determine_allowed_coins(cli: &Cli, base_url: BaseUrl) -> Result<Vec<Str
             return Err(anyhow!("--llm-allowed-coins did not contain any symbols"));
         }
         Ok(coins)
+    } else if let Some(path) = cli.coinbook.as_deref() {
+        let coinbook = Coinbook::load(path)?;
+        info!(
+            "found {} coins in coinbook {}",
+            coinbook.universe.len(),
+            path.display()
+        );
+        Ok(coinbook.coins_used(cli.max_coins, &excluded_coins(cli)))
     } else {
-        fetch_allowed_coins_from_network(base_url).await
+        fetch_allowed_coins_from_network(
+            base_url,
+            http_client_for(cli)?,
+            cli.max_coins,
+            &excluded_coins(cli),
+        )
+        .await
     }
 }
 
-async fn fetch_allowed_coins_from_network(base_url: BaseUrl) -> Result<Vec<String>> {
-    let info = InfoClient::new(None, Some(base_url))
+fn excluded_coins(cli: &Cli) -> Vec<String> {
+    cli.exclude_coins
+        .as_deref()
+        .map(parse_allowed_coins)
+        .unwrap_or_default()
+}
+
+/// HTTP client shared by every SDK client of a run, so info and exchange calls reuse
+/// keep-alive connections instead of each holding its own pool.
+fn build_http_client(pool_size: usize, tls: &TlsConfig) -> Result<reqwest::Client> {
+    debug!("http client keeps up to {pool_size} idle connection(s) per host");
+    let builder = reqwest::Client::builder()
+        .connection_verbose(false)
+        .pool_max_idle_per_host(pool_size);
+    tls.apply(builder)
+        .build()
+        .context("failed to build HTTP client")
+}
+
+/// The shared HTTP client for this run, honouring `--http-pool-size` and the TLS flags.
+fn http_client_for(cli: &Cli) -> Result<reqwest::Client> {
+    build_http_client(cli.http_pool_size, &TlsConfig::from_cli(cli)?)
+}
+
+async fn fetch_meta_coins(base_url: BaseUrl, http: reqwest::Client) -> Result<Vec<String>> {
+    let info = InfoClient::new(Some(http), Some(base_url))
         .await
         .context("failed to initialise info client for coin discovery")?;
     let meta = info
         .meta()
         .await
         .context("failed to fetch meta for coin discovery")?;
-    let mut coins = meta
+    let coins = meta
         .universe
         .into_iter()
         .map(|asset| asset.name)
         .filter(|name| !name.is_empty())
-        .take(5)
+        .collect::<Vec<_>>();
+    info!("found {} coins in network meta", coins.len());
+    Ok(coins)
+}
+
+async fn print_coinlist(base_url: BaseUrl, http: reqwest::Client) -> Result<()> {
+    let mut coins = fetch_meta_coins(base_url, http).await?;
+    coins.sort();
+    for coin in coins {
+        println!("{coin}");
+    }
+    Ok(())
+}
+
+async fn fetch_allowed_coins_from_network(
+    base_url: BaseUrl,
+    http: reqwest::Client,
+    max_coins: usize,
+    exclude: &[String],
+) -> Result<Vec<String>> {
+    let coins = fetch_meta_coins(base_url, http).await?;
+    Ok(select_coins(coins, max_coins, exclude))
+}
+
+/// Drops excluded coins and keeps the first `max_coins` (0 = all), falling back to ETH.
+fn select_coins(coins: Vec<String>, max_coins: usize, exclude: &[String]) -> Vec<String> {
+    let limit = if max_coins == 0 {
+        usize::MAX
+    } else {
+        max_coins
+    };
+    let mut coins = coins
+        .into_iter()
+        .filter(|name| !exclude.iter().any(|ex| ex.eq_ignore_ascii_case(name)))
+        .take(limit)
         .collect::<Vec<_>>();
     if coins.is_empty() {
         coins.push("ETH".to_string());
     }
-    Ok(coins)
+    coins
+}
+
+/// Asset metadata loaded from a `--coinbook` file in the venue's `meta` format.
+#[derive(Debug, Clone)]
+struct Coinbook {
+    universe: Vec<CoinbookAsset>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct CoinbookAsset {
+    name: String,
+    #[serde(default, rename = "szDecimals")]
+    sz_decimals: Option<u32>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum CoinbookFile {
+    Meta { universe: Vec<CoinbookAsset> },
+    Universe(Vec<CoinbookAsset>),
 }
 
-fn build_llm_options(cli: &Cli, allowed_coins: Vec<String>) -> Result<LlmOptions> {
+impl Coinbook {
+    fn load(path: &Path) -> Result<Self> {
+        let raw = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read coinbook {}", path.display()))?;
+        let file: CoinbookFile = serde_json::from_str(&raw).with_context(|| {
+            format!(
+                "coinbook {} must be a meta object or an array of assets with a name",
+                path.display()
+            )
+        })?;
+        let universe = match file {
+            CoinbookFile::Meta { universe } | CoinbookFile::Universe(universe) => universe,
+        };
+        Ok(Self { universe })
+    }
+
+    /// `szDecimals` of every asset that lists it, for `snapToTick` without the network.
+    fn sz_decimals(&self) -> HashMap<String, u32> {
+        self.universe
+            .iter()
+            .filter_map(|asset| Some((asset.name.clone(), asset.sz_decimals?)))
+            .collect()
+    }
+
+    /// Allowed coins for LLM plans, chosen the same way as from the network meta.
+    fn coins_used(&self, max_coins: usize, exclude: &[String]) -> Vec<String> {
+        let names = self
+            .universe
+            .iter()
+            .map(|asset| asset.name.clone())
+            .filter(|name| !name.is_empty())
+            .collect();
+        select_coins(names, max_coins, exclude)
+    }
+}
+
+fn build_llm_options(
+    cli: &Cli,
+    allowed_coins: Vec<String>,
+    base_url: BaseUrl,
+) -> Result<LlmOptions> {
     if allowed_coins.is_empty() {
         return Err(anyhow!("allowed coin list is empty"));
     }
@@ -356,6 +1524,7 @@ fn build_llm_options(cli: &Cli, allowed_coins: Vec<String>) -> Result<LlmOptions
         top_p: cli.llm_top_p,
         max_output_tokens: cli.llm_max_output_tokens,
         max_steps: cli.llm_max_steps.max(1),
+        seed: cli.llm_seed,
         allowed_coins,
         default_builder_code: cli
             .llm_builder_code
@@ -363,6 +1532,29 @@ fn build_llm_options(cli: &Cli, allowed_coins: Vec<String>) -> Result<LlmOptions
             .or_else(|| cli.builder_code.clone()),
         cache_dir: llm::discover_cache_dir(),
         dry_run: llm::dry_run_enabled(),
+        demo: cli.demo,
+        network_trace: cli
+            .record_network_trace
+            .as_deref()
+            .map(|dir| NetworkTrace::create(dir, !cli.no_trace_ack_bodies).map(Arc::new))
+            .transpose()?,
+        refresh_coins_on_retry: cli.llm_refresh_coins_on_retry,
+        max_orders_per_coin: (cli.llm_max_orders_per_coin > 0)
+            .then_some(cli.llm_max_orders_per_coin),
+        max_orders_total: cli.llm_max_orders_total,
+        max_loop_count: cli.llm_max_loop_count,
+        notional_limits: NotionalLimits::from_cli(cli),
+        coin_source: (cli.llm_allowed_coins.is_none() && cli.coinbook.is_none())
+            .then(|| -> Result<CoinSource> {
+                Ok(CoinSource {
+                    base_url,
+                    http: http_client_for(cli)?,
+                    max_coins: cli.max_coins,
+                    exclude: excluded_coins(cli),
+                })
+            })
+            .transpose()?,
+        tls: TlsConfig::from_cli(cli)?,
     })
 }
 
@@ -372,6 +1564,7 @@ fn build_run_meta(
     timestamp: &str,
     out_dir: &Path,
     plan_json: &Value,
+    estimated_duration_ms: u64,
     wallet: Option<String>,
     window_ms: i64,
     llm_meta: Option<&llm::LlmMeta>,
@@ -383,13 +1576,16 @@ fn build_run_meta(
         "network": network_label,
         "builderCode": cli.builder_code,
         "plan": { "steps": plan_json["steps"].clone() },
+        "estimatedDurationMs": estimated_duration_ms,
         "wallet": wallet,
         "outDir": out_dir.display().to_string(),
         "effectTimeoutMs": cli.effect_timeout_ms,
+        "abortOnTimeout": cli.abort_on_timeout,
         "timestamp": timestamp,
         "windowMs": window_ms,
         "llmDryRun": dry_run,
         "demoMode": demo,
+        "dryRunWithPrices": cli.dry_run_with_prices,
     });
 
     if let Some(meta_obj) = llm_meta {
@@ -399,25 +1595,234 @@ fn build_run_meta(
     Ok(meta)
 }
 
+/// Walks the plan resolving order prices from live mids and logs each step as
+/// `dryRun` without touching the exchange.
+async fn run_dry_with_prices(
+    plan: Plan,
+    artifacts: Arc<Mutex<RunArtifacts>>,
+    mut mid_source: impl MidSource,
+    mid_cache_ttl_ms: u64,
+    builder_codes: &BuilderCodes,
+    trace: &mut StepTrace,
+) -> Result<()> {
+    let mut mid_cache = MidCache::new(mid_cache_ttl_ms);
+
+    let mut queue = StepQueue::new(&plan.steps);
+    while let Some(QueuedStep { idx, branch, step }) = queue.next() {
+        let started = trace.start();
+        let submit_ts = timestamp_ms();
+        let (request, ack) = match step {
+            ActionStep::If { if_step } => {
+                let artifacts = artifacts.lock().await;
+                queue.take_branch(idx, branch.as_deref(), if_step, &artifacts);
+                continue;
+            }
+            ActionStep::Loop { loop_step } => {
+                queue.take_loop(idx, branch.as_deref(), loop_step);
+                continue;
+            }
+            ActionStep::PerpOrders { perp_orders } => {
+                dry_run_orders(
+                    step.kind(),
+                    &perp_orders.with_tif_override(),
+                    &mut mid_source,
+                    &mut mid_cache,
+                    builder_codes,
+                )
+                .await?
+            }
+            ActionStep::MultiCoinOrders { multi_coin_orders } => {
+                dry_run_orders(
+                    step.kind(),
+                    &multi_coin_orders.to_perp_orders(),
+                    &mut mid_source,
+                    &mut mid_cache,
+                    builder_codes,
+                )
+                .await?
+            }
+            ActionStep::SpotOrders { spot_orders } => {
+                dry_run_orders(
+                    step.kind(),
+                    &spot_orders.to_perp_orders(),
+                    &mut mid_source,
+                    &mut mid_cache,
+                    builder_codes,
+                )
+                .await?
+            }
+            other => (serde_json::to_value(other)?, json!({ "status": "dryRun" })),
+        };
+
+        {
+            let mut artifacts = artifacts.lock().await;
+            artifacts.set_branch(branch);
+            let record = artifacts.make_action_record(
+                idx,
+                step.kind(),
+                submit_ts,
+                request,
+                Some(ack),
+                None,
+                Some("dry run with live prices: not submitted".to_string()),
+            );
+            artifacts.log_action(&record)?;
+        }
+        trace.record(idx, step, started);
+    }
+
+    Ok(())
+}
+
+/// Resolves every order's price for a `--dry-run-with-prices` record of an order step;
+/// returns the request (under `action`) and a `dryRun` ack carrying the resolved prices.
+async fn dry_run_orders(
+    action: &str,
+    perp_orders: &PerpOrdersStep,
+    mid_source: &mut impl MidSource,
+    mid_cache: &mut MidCache,
+    builder_codes: &BuilderCodes,
+) -> Result<(Value, Value)> {
+    let builder_code = builder_codes.step_code(perp_orders);
+    let mut request_orders = Vec::with_capacity(perp_orders.orders.len());
+    let mut statuses = Vec::with_capacity(perp_orders.orders.len());
+    for order in &perp_orders.orders {
+        let resolved_px = resolve_limit_price(order, mid_source, mid_cache).await?;
+        request_orders.push(json!({
+            "coin": order.coin,
+            "side": if order.is_buy() { "buy" } else { "sell" },
+            "sz": order.sz,
+            "tif": order.tif.as_sdk_str(),
+            "reduceOnly": order.reduce_only,
+            "builderCode": builder_codes.order_code(order, builder_code.as_deref()),
+            "px": order_price_label(&order.px),
+            "resolvedPx": resolved_px,
+            "trigger": "none",
+        }));
+        statuses.push(json!({ "status": "dryRun", "resolvedPx": resolved_px }));
+    }
+    let mut request = json!({ action: { "orders": request_orders } });
+    if let Some(code) = builder_code {
+        request[action]["builderCode"] = json!(code);
+    }
+    let ack = json!({ "status": "dryRun", "data": { "statuses": statuses } });
+    Ok((request, ack))
+}
+
+#[allow(clippy::too_many_arguments)]
 async fn run_demo(
     plan: Plan,
     artifacts: Arc<Mutex<RunArtifacts>>,
-    default_builder_code: Option<String>,
+    builder_codes: &BuilderCodes,
+    l2_depth: Option<usize>,
+    prices: Option<&PriceFile>,
+    inject_sleep_ms: u64,
+    limits: NotionalLimits,
+    trace: &mut StepTrace,
+) -> Result<()> {
+    run_demo_steps(
+        &plan,
+        artifacts,
+        builder_codes,
+        l2_depth,
+        prices,
+        inject_sleep_ms,
+        limits,
+        trace,
+    )
+    .await?;
+
+    tokio::time::sleep(Duration::from_secs(5)).await;
+
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn run_demo_steps(
+    plan: &Plan,
+    artifacts: Arc<Mutex<RunArtifacts>>,
+    builder_codes: &BuilderCodes,
+    l2_depth: Option<usize>,
+    prices: Option<&PriceFile>,
+    inject_sleep_ms: u64,
+    limits: NotionalLimits,
+    trace: &mut StepTrace,
 ) -> Result<()> {
-    let default_builder = default_builder_code.as_deref();
-    let mut placed_orders: VecDeque<PlacedOrder> = VecDeque::new();
+    let mut placed_orders = OrderTracker::default();
     let mut next_oid: u64 = 1;
 
-    for (idx, step) in plan.steps.iter().enumerate() {
+    let mut queue = StepQueue::new(&plan.steps);
+    while let Some(QueuedStep { idx, branch, step }) = queue.next() {
+        artifacts.lock().await.set_branch(branch.clone());
+        let started = trace.start();
         match step {
+            ActionStep::If { if_step } => {
+                let artifacts = artifacts.lock().await;
+                queue.take_branch(idx, branch.as_deref(), if_step, &artifacts);
+                continue;
+            }
+            ActionStep::Loop { loop_step } => {
+                queue.take_loop(idx, branch.as_deref(), loop_step);
+                continue;
+            }
             ActionStep::PerpOrders { perp_orders } => {
+                if let Some(depth) = l2_depth {
+                    let ts_ms = timestamp_ms();
+                    let mut artifacts = artifacts.lock().await;
+                    for coin in step_coins(perp_orders) {
+                        let mid = demo_mid(prices, &coin)?;
+                        artifacts
+                            .log_l2_snapshot(&demo_l2_snapshot(idx, &coin, mid, depth, ts_ms))?;
+                    }
+                }
+                run_demo_perp_orders(
+                    idx,
+                    perp_orders,
+                    OrderSource::Perp,
+                    &artifacts,
+                    builder_codes,
+                    prices,
+                    &mut placed_orders,
+                    &mut next_oid,
+                    limits,
+                )
+                .await?;
+            }
+            ActionStep::MultiCoinOrders { multi_coin_orders } => {
+                let perp_orders = &multi_coin_orders.to_perp_orders();
+                if let Some(depth) = l2_depth {
+                    let ts_ms = timestamp_ms();
+                    let mut artifacts = artifacts.lock().await;
+                    for coin in step_coins(perp_orders) {
+                        let mid = demo_mid(prices, &coin)?;
+                        artifacts
+                            .log_l2_snapshot(&demo_l2_snapshot(idx, &coin, mid, depth, ts_ms))?;
+                    }
+                }
                 run_demo_perp_orders(
                     idx,
                     perp_orders,
+                    OrderSource::MultiCoin(multi_coin_orders),
+                    &artifacts,
+                    builder_codes,
+                    prices,
+                    &mut placed_orders,
+                    &mut next_oid,
+                    limits,
+                )
+                .await?;
+            }
+            ActionStep::SpotOrders { spot_orders } => {
+                run_demo_perp_orders(
+                    idx,
+                    &spot_orders.to_perp_orders(),
+                    OrderSource::Spot(spot_orders),
                     &artifacts,
-                    default_builder,
+                    builder_codes,
+                    prices,
                     &mut placed_orders,
                     &mut next_oid,
+                    limits,
                 )
                 .await?;
             }
@@ -433,55 +1838,118 @@ async fn run_demo(
             ActionStep::UsdClassTransfer { usd_class_transfer } => {
                 run_demo_usd_transfer(idx, usd_class_transfer, &artifacts).await?;
             }
+            ActionStep::SubAccountTransfer {
+                sub_account_transfer,
+            } => {
+                run_demo_sub_account_transfer(idx, sub_account_transfer, &artifacts).await?;
+            }
             ActionStep::SetLeverage { set_leverage } => {
                 run_demo_set_leverage(idx, set_leverage, &artifacts).await?;
             }
-            ActionStep::Sleep { .. } => {
+            ActionStep::Sleep { sleep_ms } => {
                 // Skip real sleeping in demo mode to keep runs fast.
+                log_sleep(
+                    idx,
+                    sleep_ms,
+                    &artifacts,
+                    timestamp_ms(),
+                    Some("demo mode: sleep skipped".to_string()),
+                )
+                .await?;
+            }
+            ActionStep::WaitForFill { wait_for_fill } => {
+                run_demo_wait_for_fill(idx, wait_for_fill, &artifacts, &placed_orders).await?;
+            }
+            ActionStep::FetchFundingRate { fetch_funding_rate } => {
+                log_funding_rate(
+                    idx,
+                    fetch_funding_rate,
+                    &artifacts,
+                    timestamp_ms(),
+                    Some((DEMO_FUNDING_RATE, 0.0)),
+                    Some("demo mode synthetic funding rate".to_string()),
+                    None,
+                )
+                .await?;
+            }
+            ActionStep::AssertBalance { assert_balance } => {
+                log_assert_balance(
+                    idx,
+                    assert_balance,
+                    &artifacts,
+                    timestamp_ms(),
+                    DEMO_PERP_BALANCE,
+                    None,
+                    None,
+                )
+                .await?;
             }
         }
+        trace.record(idx, step, started);
+        inject_step_sleep(idx, plan.steps.len(), inject_sleep_ms).await;
     }
 
-    tokio::time::sleep(Duration::from_secs(5)).await;
-
     Ok(())
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn run_demo_perp_orders(
     step_idx: usize,
     step: &PerpOrdersStep,
+    source: OrderSource<'_>,
     artifacts: &Arc<Mutex<RunArtifacts>>,
-    default_builder: Option<&str>,
-    placed_orders: &mut VecDeque<PlacedOrder>,
+    builder_codes: &BuilderCodes,
+    prices: Option<&PriceFile>,
+    placed_orders: &mut OrderTracker,
     next_oid: &mut u64,
+    limits: NotionalLimits,
 ) -> Result<()> {
     if step.orders.is_empty() {
         return Ok(());
     }
+    let step = &step.with_tif_override();
 
     let submit_ts = timestamp_ms();
-    let builder_code = step
-        .builder_code
-        .clone()
-        .or_else(|| default_builder.map(|code| code.to_string()));
+    let builder_code = builder_codes.step_code(step);
+
+    let resolved_prices = step
+        .orders
+        .iter()
+        .map(|order| demo_limit_price(prices, order))
+        .collect::<Result<Vec<f64>>>()?;
+    let notionals = step
+        .orders
+        .iter()
+        .zip(&resolved_prices)
+        .map(|(order, px)| (order.coin.as_str(), *px, order.sz));
+    if let Some(reason) = limits.violation(notionals) {
+        let (action, request) =
+            orders_request(step, source, &resolved_prices, builder_code.as_deref());
+        return log_notional_skip(step_idx, action, artifacts, submit_ts, request, reason).await;
+    }
+
+    // Parallel sub-batches get their oids batch by batch, as the venue would number them.
+    let batches = if step.parallel {
+        coin_batches(step)
+    } else {
+        vec![(0..step.orders.len()).collect()]
+    };
+    let mut oids = vec![0; step.orders.len()];
+    for idx in batches.iter().flatten() {
+        oids[*idx] = *next_oid;
+        *next_oid += 1;
+    }
 
     let mut statuses = Vec::new();
     let mut observed = Vec::new();
     let mut request_orders = Vec::new();
     let mut routed = Vec::new();
 
-    for order in &step.orders {
+    for ((order, &resolved_px), &oid) in step.orders.iter().zip(&resolved_prices).zip(&oids) {
         if order.trigger.is_some() {
             return Err(anyhow!("demo mode does not yet support triggered orders"));
         }
-        let mid = demo_mid_for_coin(&order.coin);
-        let resolved_px = order.px.resolve_with_mid(mid);
-        let oid = *next_oid;
-        *next_oid += 1;
-        placed_orders.push_back(PlacedOrder {
-            coin: order.coin.clone(),
-            oid,
-        });
+        placed_orders.push(&order.coin, oid);
 
         statuses.push(json!({ "kind": "success", "oid": oid }));
         observed.push(json!({
@@ -507,7 +1975,7 @@ async fn run_demo_perp_orders(
         }
         request_orders.push(order_value);
 
-        let routed_builder = order.builder_code.clone().or_else(|| builder_code.clone());
+        let routed_builder = builder_codes.order_code(order, builder_code.as_deref());
         routed.push(RoutedOrderRecord {
             ts_ms: submit_ts,
             oid: Some(oid),
@@ -522,6 +1990,7 @@ async fn run_demo_perp_orders(
             tif: order.tif.as_sdk_str().to_string(),
             reduce_only: order.reduce_only,
             builder_code: routed_builder,
+            spot: source.is_spot(),
         });
     }
 
@@ -535,23 +2004,36 @@ async fn run_demo_perp_orders(
         Some(Value::Array(observed.clone()))
     };
 
-    let mut request_value = json!({
-        "perp_orders": { "orders": request_orders },
-    });
-    if let Some(code) = &builder_code {
-        request_value["perp_orders"]["builderCode"] = json!(code);
-    }
-
-    {
-        let mut artifacts = artifacts.lock().await;
+    let (action, request_value) = match source {
+        OrderSource::Perp => {
+            let mut request_value = json!({
+                "perp_orders": { "orders": request_orders },
+            });
+            if let Some(code) = &builder_code {
+                request_value["perp_orders"]["builderCode"] = json!(code);
+            }
+            ("perp_orders", request_value)
+        }
+        source => orders_request(step, source, &resolved_prices, builder_code.as_deref()),
+    };
+
+    {
+        let mut artifacts = artifacts.lock().await;
         let record = artifacts.make_action_record(
             step_idx,
-            "perp_orders",
+            action,
             submit_ts,
             request_value,
             Some(ack_value),
             observed_value.clone(),
-            Some("demo mode synthetic execution".to_string()),
+            Some(if step.parallel {
+                format!(
+                    "demo mode synthetic execution; parallel: {} sub-batches",
+                    batches.len()
+                )
+            } else {
+                "demo mode synthetic execution".to_string()
+            }),
         );
         artifacts.log_action(&record)?;
         for event in &observed {
@@ -569,33 +2051,24 @@ async fn run_demo_cancel_last(
     step_idx: usize,
     step: &CancelLastStep,
     artifacts: &Arc<Mutex<RunArtifacts>>,
-    placed_orders: &mut VecDeque<PlacedOrder>,
+    placed_orders: &mut OrderTracker,
 ) -> Result<()> {
     let submit_ts = timestamp_ms();
     let removed = if let Some(coin) = &step.coin {
-        let mut removed = None;
-        for idx in (0..placed_orders.len()).rev() {
-            if let Some(order) = placed_orders.get(idx) {
-                if &order.coin == coin {
-                    removed = placed_orders.remove(idx);
-                    break;
-                }
-            }
-        }
-        removed
+        placed_orders.pop_last(coin)
     } else {
-        placed_orders.pop_back()
+        placed_orders.pop_last_any().map(|(_, oid)| oid)
     };
 
-    let (ack_value, observed, notes) = if let Some(order) = removed {
+    let (ack_value, observed, notes) = if let Some(oid) = removed {
         let observed = json!({
             "channel": "orderUpdates",
-            "oid": order.oid,
+            "oid": oid,
             "status": "canceled",
             "demo": true
         });
         (
-            json!({ "status": "ok", "data": { "oid": order.oid } }),
+            json!({ "status": "ok", "data": { "oid": oid } }),
             Some(observed),
             None,
         )
@@ -636,34 +2109,26 @@ async fn run_demo_cancel_oids(
     step_idx: usize,
     step: &CancelOidsStep,
     artifacts: &Arc<Mutex<RunArtifacts>>,
-    placed_orders: &mut VecDeque<PlacedOrder>,
+    placed_orders: &mut OrderTracker,
 ) -> Result<()> {
     let submit_ts = timestamp_ms();
     let mut statuses = Vec::new();
     let mut observed = Vec::new();
+    // Demo tracking is authoritative, so untracked oids are always skipped.
+    let (tracked, untracked) = partition_tracked(&step.oids, placed_orders);
 
-    for oid in &step.oids {
-        let mut removed = None;
-        for idx in 0..placed_orders.len() {
-            if let Some(order) = placed_orders.get(idx) {
-                if order.oid == *oid {
-                    removed = placed_orders.remove(idx);
-                    break;
-                }
-            }
-        }
-        if let Some(order) = removed {
-            statuses.push(json!({ "kind": "success", "oid": order.oid }));
-            observed.push(json!({
-                "channel": "orderUpdates",
-                "oid": order.oid,
-                "status": "canceled",
-                "demo": true
-            }));
-        } else {
-            statuses.push(json!({ "kind": "missing", "oid": oid }));
-        }
+    for oid in &tracked {
+        placed_orders.remove(*oid);
+        statuses.push(json!({ "kind": "success", "oid": oid }));
+        observed.push(json!({
+            "channel": "orderUpdates",
+            "oid": oid,
+            "status": "canceled",
+            "demo": true
+        }));
     }
+    let mut notes = vec!["demo mode synthetic execution".to_string()];
+    notes.extend(untracked_notes(&untracked));
 
     let ack_value = json!({
         "status": "ok",
@@ -692,7 +2157,7 @@ async fn run_demo_cancel_oids(
             request_value,
             Some(ack_value),
             observed_value.clone(),
-            Some("demo mode synthetic execution".to_string()),
+            Some(notes.join("; ")),
         );
         artifacts.log_action(&record)?;
         for event in &observed {
@@ -707,26 +2172,18 @@ async fn run_demo_cancel_all(
     step_idx: usize,
     step: &CancelAllStep,
     artifacts: &Arc<Mutex<RunArtifacts>>,
-    placed_orders: &mut VecDeque<PlacedOrder>,
+    placed_orders: &mut OrderTracker,
 ) -> Result<()> {
     let submit_ts = timestamp_ms();
-    let mut removed_oids = Vec::new();
-
-    if let Some(coin) = &step.coin {
-        let mut remaining = VecDeque::new();
-        while let Some(order) = placed_orders.pop_front() {
-            if &order.coin == coin {
-                removed_oids.push(order.oid);
-            } else {
-                remaining.push_back(order);
-            }
-        }
-        *placed_orders = remaining;
-    } else {
-        while let Some(order) = placed_orders.pop_front() {
-            removed_oids.push(order.oid);
-        }
-    }
+    let removed_oids: Vec<u64> = match &step.coin {
+        Some(coin) => placed_orders.all_for_coin(coin),
+        None => placed_orders
+            .all()
+            .into_iter()
+            .map(|(_, oid)| oid)
+            .collect(),
+    };
+    placed_orders.remove_all(&removed_oids);
 
     let ack_value = json!({
         "status": "ok",
@@ -817,21 +2274,55 @@ async fn run_demo_usd_transfer(
     Ok(())
 }
 
-async fn run_demo_set_leverage(
+async fn run_demo_sub_account_transfer(
     step_idx: usize,
-    step: &SetLeverageStep,
+    step: &SubAccountTransferStep,
     artifacts: &Arc<Mutex<RunArtifacts>>,
 ) -> Result<()> {
     let submit_ts = timestamp_ms();
-    let ack_value = json!({ "status": "ok" });
     let observed = json!({
-        "channel": "setLeverage",
-        "coin": step.coin,
-        "leverage": step.leverage,
-        "cross": step.cross,
+        "channel": "userNonFundingLedgerUpdates",
+        "kind": "SubAccountTransfer",
+        "coin": "USDC",
+        "change": if step.is_deposit { -step.amount } else { step.amount },
+        "subAccount": step.to_address,
         "demo": true
     });
 
+    {
+        let mut artifacts = artifacts.lock().await;
+        let record = artifacts.make_action_record(
+            step_idx,
+            "sub_account_transfer",
+            submit_ts,
+            sub_account_transfer_request(step),
+            Some(json!({ "status": "ok" })),
+            Some(Value::Array(vec![observed.clone()])),
+            Some("demo mode synthetic execution".to_string()),
+        );
+        artifacts.log_action(&record)?;
+        artifacts.log_ws_event(&observed)?;
+    }
+
+    Ok(())
+}
+
+fn sub_account_transfer_request(step: &SubAccountTransferStep) -> Value {
+    json!({
+        "sub_account_transfer": {
+            "toAddress": step.to_address,
+            "amount": step.amount,
+            "isDeposit": step.is_deposit,
+        }
+    })
+}
+
+async fn run_demo_set_leverage(
+    step_idx: usize,
+    step: &SetLeverageStep,
+    artifacts: &Arc<Mutex<RunArtifacts>>,
+) -> Result<()> {
+    let submit_ts = timestamp_ms();
     let request_value = json!({
         "set_leverage": {
             "coin": step.coin,
@@ -840,6 +2331,29 @@ async fn run_demo_set_leverage(
         }
     });
 
+    if step.dry_set_leverage {
+        let mut artifacts = artifacts.lock().await;
+        let record = artifacts.make_action_record(
+            step_idx,
+            "set_leverage",
+            submit_ts,
+            request_value,
+            Some(json!({ "status": "skipped" })),
+            None,
+            Some("dry_set_leverage: update not submitted".to_string()),
+        );
+        return artifacts.log_action(&record);
+    }
+
+    let ack_value = json!({ "status": "ok" });
+    let observed = json!({
+        "channel": "setLeverage",
+        "coin": step.coin,
+        "leverage": step.leverage,
+        "cross": step.cross,
+        "demo": true
+    });
+
     {
         let mut artifacts = artifacts.lock().await;
         let record = artifacts.make_action_record(
@@ -858,997 +2372,4788 @@ async fn run_demo_set_leverage(
     Ok(())
 }
 
-fn demo_mid_for_coin(coin: &str) -> f64 {
-    match coin {
-        "BTC" => 60_000.0,
-        "ETH" => 3_500.0,
-        "SOL" => 180.0,
-        "APT" => 10.0,
-        _ => 100.0,
-    }
+async fn log_sleep(
+    step_idx: usize,
+    step: &SleepMsStep,
+    artifacts: &Arc<Mutex<RunArtifacts>>,
+    submit_ts: i64,
+    notes: Option<String>,
+) -> Result<()> {
+    let request_value = json!({ "sleep_ms": { "duration_ms": step.duration_ms } });
+    let mut artifacts = artifacts.lock().await;
+    let record = artifacts.make_action_record(
+        step_idx,
+        "sleep_ms",
+        submit_ts,
+        request_value,
+        Some(json!({ "status": "ok" })),
+        None,
+        notes,
+    );
+    artifacts.log_action(&record)
 }
 
-fn spawn_ws_task(
-    mut info_ws: InfoClient,
-    wallet_address: ethers::types::H160,
-    artifacts: Arc<Mutex<RunArtifacts>>,
-    broadcaster: broadcast::Sender<ObservedEvent>,
-) {
-    tokio::spawn(async move {
-        let (tx, mut rx) = mpsc::unbounded_channel();
-        let subscriptions = vec![
-            Subscription::OrderUpdates {
-                user: wallet_address,
-            },
-            Subscription::UserFills {
-                user: wallet_address,
-            },
-            Subscription::UserNonFundingLedgerUpdates {
-                user: wallet_address,
-            },
-        ];
+async fn run_demo_wait_for_fill(
+    step_idx: usize,
+    step: &WaitForFillStep,
+    artifacts: &Arc<Mutex<RunArtifacts>>,
+    placed_orders: &OrderTracker,
+) -> Result<()> {
+    let submit_ts = timestamp_ms();
+    let target = match (step.oid, step.oid_index, &step.coin) {
+        (Some(oid), _, _) => placed_orders
+            .coin_of(oid)
+            .map(|coin| (coin.to_string(), oid)),
+        (None, Some(index), _) => placed_orders.nth(index),
+        (None, None, Some(coin)) => placed_orders.last(coin).map(|oid| (coin.clone(), oid)),
+        (None, None, None) => placed_orders.last_any(),
+    };
 
-        for sub in subscriptions {
-            if let Err(err) = info_ws.subscribe(sub, tx.clone()).await {
-                error!("failed to subscribe to websocket channel: {err}");
-            }
+    let (ack_value, observed, notes) = if let Some((coin, oid)) = target {
+        let observed = json!({
+            "channel": "userFills",
+            "oid": oid,
+            "coin": coin,
+            "time": submit_ts,
+            "demo": true
+        });
+        (
+            json!({ "status": "ok" }),
+            Some(observed),
+            Some("demo mode synthetic execution".to_string()),
+        )
+    } else {
+        (
+            json!({ "status": "skipped" }),
+            None,
+            Some("demo: no tracked order to wait for".to_string()),
+        )
+    };
+
+    {
+        let mut artifacts = artifacts.lock().await;
+        let record = artifacts.make_action_record(
+            step_idx,
+            "wait_for_fill",
+            submit_ts,
+            wait_for_fill_request(step),
+            Some(ack_value),
+            observed.clone(),
+            notes,
+        );
+        artifacts.log_action(&record)?;
+        if let Some(event) = observed {
+            artifacts.log_ws_event(&event)?;
         }
+    }
 
-        drop(tx); // retain rx only
+    Ok(())
+}
 
-        while let Some(message) = rx.recv().await {
-            if let Err(err) = handle_ws_message(&artifacts, &broadcaster, message).await {
-                warn!("failed to process websocket message: {err:?}");
-            }
-        }
-    });
+/// Funding rate reported for every coin in demo mode (1 bp).
+const DEMO_FUNDING_RATE: f64 = 0.0001;
+
+/// Logs a `fetch_funding_rate` record; `rate` is `(fundingRate, premium)` when one was found.
+async fn log_funding_rate(
+    step_idx: usize,
+    step: &FetchFundingRateStep,
+    artifacts: &Arc<Mutex<RunArtifacts>>,
+    submit_ts: i64,
+    rate: Option<(f64, f64)>,
+    notes: Option<String>,
+    start: Option<Instant>,
+) -> Result<()> {
+    let (ack, observed) = match rate {
+        Some((funding_rate, premium)) => (
+            json!({ "status": "ok" }),
+            Some(json!({ "fundingRate": funding_rate, "premium": premium })),
+        ),
+        None => (json!({ "status": "err" }), None),
+    };
+    let mut artifacts = artifacts.lock().await;
+    let mut record = artifacts.make_action_record(
+        step_idx,
+        "fetch_funding_rate",
+        submit_ts,
+        json!({ "fetch_funding_rate": { "coin": step.coin } }),
+        Some(ack),
+        observed,
+        notes,
+    );
+    if let Some(start) = start {
+        record = record.with_duration(start);
+    }
+    artifacts.log_action(&record)
 }
 
-async fn handle_ws_message(
+/// Perp USDC balance reported by `assert_balance` in demo mode; the assertion always passes.
+const DEMO_PERP_BALANCE: f64 = 100.0;
+
+/// Logs an `assert_balance` record and fails the run on `violation` unless the step is fail-soft.
+async fn log_assert_balance(
+    step_idx: usize,
+    step: &AssertBalanceStep,
     artifacts: &Arc<Mutex<RunArtifacts>>,
-    broadcaster: &broadcast::Sender<ObservedEvent>,
-    message: Message,
+    submit_ts: i64,
+    balance: f64,
+    violation: Option<String>,
+    start: Option<Instant>,
 ) -> Result<()> {
-    let (value, events) = encode_message(message);
+    let notes = violation
+        .as_ref()
+        .map(|reason| format!("assertion failed: {reason}"));
+    let status = if violation.is_some() { "err" } else { "ok" };
     {
         let mut artifacts = artifacts.lock().await;
-        artifacts.log_ws_event(&value)?;
+        let mut record = artifacts.make_action_record(
+            step_idx,
+            "assert_balance",
+            submit_ts,
+            json!({ "assert_balance": step }),
+            Some(json!({ "status": status })),
+            Some(json!({ "perpUsdc": balance })),
+            notes.clone(),
+        );
+        if let Some(start) = start {
+            record = record.with_duration(start);
+        }
+        artifacts.log_action(&record)?;
     }
-    for event in events {
-        let _ = broadcaster.send(event);
+    match notes {
+        Some(reason) if !step.fail_soft => Err(anyhow!("assert_balance step {step_idx}: {reason}")),
+        Some(reason) => {
+            warn!("assert_balance step {step_idx}: {reason} (failSoft, continuing)");
+            Ok(())
+        }
+        None => Ok(()),
     }
-    Ok(())
 }
 
-fn encode_message(message: Message) -> (serde_json::Value, Vec<ObservedEvent>) {
-    match message {
-        Message::OrderUpdates(order_updates) => {
-            let mut events = Vec::new();
-            let data: Vec<_> = order_updates
-                .data
-                .iter()
-                .map(|upd| {
-                    let payload = json!({
-                        "channel": "orderUpdates",
-                        "coin": upd.order.coin.clone(),
-                        "oid": upd.order.oid,
-                        "side": upd.order.side.clone(),
-                        "limitPx": upd.order.limit_px.clone(),
-                        "sz": upd.order.sz.clone(),
-                        "status": upd.status.clone(),
-                        "statusTimestamp": upd.status_timestamp,
-                    });
-                    events.push(ObservedEvent::OrderUpdate {
-                        oid: upd.order.oid,
-                        _status: upd.status.clone(),
-                        payload: payload.clone(),
-                    });
-                    payload
-                })
-                .collect();
-            (json!({ "channel": "orderUpdates", "data": data }), events)
+fn wait_for_fill_request(step: &WaitForFillStep) -> Value {
+    json!({
+        "wait_for_fill": {
+            "oid": step.oid,
+            "oidIndex": step.oid_index,
+            "coin": step.coin,
+            "timeoutMs": step.timeout_ms,
+            "failOnTimeout": step.fail_on_timeout,
         }
-        Message::UserFills(fills) => {
-            let mut events = Vec::new();
-            let data: Vec<_> = fills
-                .data
-                .fills
-                .iter()
-                .map(|fill| {
-                    let payload = json!({
-                        "channel": "userFills",
-                        "oid": fill.oid,
-                        "coin": fill.coin.clone(),
-                        "px": fill.px.clone(),
-                        "sz": fill.sz.clone(),
-                        "time": fill.time,
-                        "side": fill.side.clone(),
-                    });
-                    events.push(ObservedEvent::UserFill {
-                        oid: fill.oid,
-                        payload: payload.clone(),
-                    });
-                    payload
-                })
-                .collect();
-            let root = json!({
-                "channel": "userFills",
-                "isSnapshot": fills.data.is_snapshot,
-                "fills": data,
-            });
-            (root, events)
+    })
+}
+
+/// Price gap between adjacent synthetic book levels, as a fraction of mid.
+const DEMO_L2_LEVEL_STEP: f64 = 0.0001;
+
+fn demo_l2_snapshot(step_idx: usize, coin: &str, mid: f64, depth: usize, ts_ms: i64) -> L2Snapshot {
+    let level = |i: usize, sign: f64| {
+        let offset = mid * DEMO_L2_LEVEL_STEP * (i as f64 + 0.5);
+        [mid + sign * offset, (i + 1) as f64]
+    };
+    L2Snapshot {
+        ts_ms,
+        coin: coin.to_string(),
+        step_idx,
+        bids: (0..depth).map(|i| level(i, -1.0)).collect(),
+        asks: (0..depth).map(|i| level(i, 1.0)).collect(),
+    }
+}
+
+/// Distinct coins of a perp_orders step, in first-seen order.
+fn step_coins(step: &PerpOrdersStep) -> Vec<String> {
+    let mut coins: Vec<String> = Vec::new();
+    for order in &step.orders {
+        if !coins.contains(&order.coin) {
+            coins.push(order.coin.clone());
         }
-        Message::UserNonFundingLedgerUpdates(ledger) => {
-            let mut events = Vec::new();
-            let data: Vec<_> = ledger
-                .data
-                .non_funding_ledger_updates
-                .iter()
-                .map(|update| {
-                    let entry = encode_ledger_update(update);
-                    if let Some(event) = entry.1 {
-                        events.push(event);
-                    }
-                    entry.0
-                })
-                .collect();
-            (
-                json!({
-                    "channel": "userNonFundingLedgerUpdates",
-                    "isSnapshot": ledger.data.is_snapshot,
-                    "updates": data,
-                }),
-                events,
-            )
-        }
-        other => (
-            json!({"channel": "other", "debug": format!("{:?}", other)}),
-            vec![ObservedEvent::Other {
-                _channel: "other".to_string(),
-                payload: json!({ "debug": format!("{:?}", other) }),
-            }],
-        ),
     }
+    coins
 }
 
-fn encode_ledger_update(update: &LedgerUpdateData) -> (serde_json::Value, Option<ObservedEvent>) {
-    match &update.delta {
-        LedgerUpdate::AccountClassTransfer(transfer) => {
-            let usdc = transfer.usdc.parse::<f64>().unwrap_or_default() / 1_000_000f64;
-            let payload = json!({
-                "channel": "accountClassTransfer",
-                "time": update.time,
-                "usdc": usdc,
-                "toPerp": transfer.to_perp,
-            });
-            (
-                payload.clone(),
-                Some(ObservedEvent::LedgerClassTransfer {
-                    to_perp: transfer.to_perp,
-                    _usdc: usdc,
-                    payload,
-                }),
-            )
+/// Fetches the book for every coin in `step` concurrently; failed fetches are
+/// logged and skipped so a flaky info endpoint never blocks order submission.
+async fn capture_l2_snapshots(
+    step_idx: usize,
+    step: &PerpOrdersStep,
+    artifacts: &Arc<Mutex<RunArtifacts>>,
+    info_http: &InfoClient,
+    depth: usize,
+) -> Result<()> {
+    let coins = step_coins(step);
+    let ts_ms = timestamp_ms();
+    let books = join_all(coins.iter().map(|coin| info_http.l2_snapshot(coin.clone()))).await;
+
+    let mut artifacts = artifacts.lock().await;
+    for (coin, book) in coins.iter().zip(books) {
+        let book = match book {
+            Ok(book) => book,
+            Err(err) => {
+                warn!("failed to fetch l2 snapshot for {coin}: {err}");
+                continue;
+            }
+        };
+        let side = |idx: usize| -> Vec<[f64; 2]> {
+            book.levels
+                .get(idx)
+                .into_iter()
+                .flatten()
+                .take(depth)
+                .filter_map(|level| Some([level.px.parse().ok()?, level.sz.parse().ok()?]))
+                .collect()
+        };
+        artifacts.log_l2_snapshot(&L2Snapshot {
+            ts_ms,
+            coin: coin.clone(),
+            step_idx,
+            bids: side(0),
+            asks: side(1),
+        })?;
+    }
+    Ok(())
+}
+
+/// `--max-notional-per-order` / `--max-notional-per-step`, in USDC.
+#[derive(Debug, Clone, Copy, Default)]
+struct NotionalLimits {
+    per_order: Option<f64>,
+    per_step: Option<f64>,
+}
+
+impl NotionalLimits {
+    fn from_cli(cli: &Cli) -> Self {
+        Self {
+            per_order: cli.max_notional_per_order,
+            per_step: cli.max_notional_per_step,
         }
-        other => {
-            let payload = json!({
-                "channel": "ledger",
-                "time": update.time,
-                "kind": format!("{:?}", other),
-            });
-            (
-                payload.clone(),
-                Some(ObservedEvent::Other {
-                    _channel: "ledger".to_string(),
-                    payload,
-                }),
-            )
+    }
+
+    /// Why a step with these `(coin, px, sz)` orders breaks a cap, if it does.
+    fn violation<'a>(
+        &self,
+        orders: impl IntoIterator<Item = (&'a str, f64, f64)>,
+    ) -> Option<String> {
+        let round = |usdc: f64| (usdc * 100.0).round() / 100.0;
+        let mut total = 0.0;
+        for (coin, px, sz) in orders {
+            let notional = (px * sz).abs();
+            if let Some(cap) = self.per_order.filter(|cap| notional > *cap) {
+                return Some(format!(
+                    "order notional cap exceeded: {coin} {} > {cap} USDC",
+                    round(notional)
+                ));
+            }
+            total += notional;
         }
+        self.per_step
+            .filter(|cap| total > *cap)
+            .map(|cap| format!("notional cap exceeded: {} > {cap} USDC", round(total)))
     }
 }
 
-fn exchange_status_json(status: &ExchangeResponseStatus) -> serde_json::Value {
-    match status {
-        ExchangeResponseStatus::Ok(resp) => {
-            let data = resp.data.as_ref().map(|collection| {
-                let entries: Vec<_> = collection
-                    .statuses
-                    .iter()
-                    .map(|status| match status {
-                        ExchangeDataStatus::Success => {
-                            json!({"kind": "success"})
-                        }
-                        ExchangeDataStatus::WaitingForFill => {
-                            json!({"kind": "waitingForFill"})
-                        }
-                        ExchangeDataStatus::WaitingForTrigger => {
-                            json!({"kind": "waitingForTrigger"})
-                        }
-                        ExchangeDataStatus::Error(err) => {
-                            json!({"kind": "error", "message": err})
-                        }
-                        ExchangeDataStatus::Resting(order) => json!({
-                            "kind": "resting",
-                            "oid": order.oid,
-                        }),
-                        ExchangeDataStatus::Filled(filled) => json!({
-                            "kind": "filled",
-                            "oid": filled.oid,
-                            "avgPx": filled.avg_px,
-                            "totalSz": filled.total_sz,
-                        }),
-                    })
-                    .collect();
-                json!({"statuses": entries})
-            });
+/// Records an order step that a notional cap kept from being submitted.
+async fn log_notional_skip(
+    step_idx: usize,
+    action: &str,
+    artifacts: &Arc<Mutex<RunArtifacts>>,
+    submit_ts: i64,
+    request: Value,
+    reason: String,
+) -> Result<()> {
+    error!("{action} step {step_idx}: {reason}; skipping step");
+    let mut artifacts = artifacts.lock().await;
+    let record = artifacts.make_action_record(
+        step_idx,
+        action,
+        submit_ts,
+        request,
+        Some(json!({ "status": "skipped" })),
+        None,
+        Some(reason),
+    );
+    artifacts.log_action(&record)
+}
+
+/// The `request` logged for a perp_orders step, with each order's resolved price.
+fn perp_orders_request(
+    step: &PerpOrdersStep,
+    resolved_prices: &[f64],
+    builder_code: Option<&str>,
+) -> Value {
+    let request_orders: Vec<_> = step
+        .orders
+        .iter()
+        .zip(resolved_prices.iter())
+        .map(|(order, limit_px)| {
             json!({
-                "status": "ok",
-                "responseType": resp.response_type,
-                "data": data,
+                "coin": order.coin,
+                "side": if order.is_buy() { "buy" } else { "sell" },
+                "sz": order.sz,
+                "tif": order.tif.as_sdk_str(),
+                "reduceOnly": order.reduce_only,
+                "builderCode": order.builder_code,
+                "px": order_price_label(&order.px),
+                "resolvedPx": limit_px,
+                "trigger": "none",
             })
+        })
+        .collect();
+    let mut request_value = json!({
+        "perp_orders": {
+            "orders": request_orders,
         }
-        ExchangeResponseStatus::Err(err) => json!({
-            "status": "err",
-            "message": err,
-        }),
+    });
+    if let Some(code) = builder_code {
+        request_value["perp_orders"]["builderCode"] = json!(code);
     }
+    request_value
 }
 
-fn extract_oids(status: &ExchangeResponseStatus) -> Vec<u64> {
-    match status {
-        ExchangeResponseStatus::Ok(resp) => resp
-            .data
-            .as_ref()
-            .map(|collection| {
-                collection
-                    .statuses
-                    .iter()
-                    .filter_map(|status| match status {
-                        ExchangeDataStatus::Resting(order) => Some(order.oid),
-                        ExchangeDataStatus::Filled(filled) => Some(filled.oid),
-                        _ => None,
-                    })
-                    .collect()
-            })
-            .unwrap_or_default(),
-        ExchangeResponseStatus::Err(_) => Vec::new(),
+/// The `request` logged for a multi_coin_orders step: the step as written plus the
+/// resolved price for each coin.
+fn multi_coin_orders_request(step: &MultiCoinOrdersStep, resolved_prices: &[f64]) -> Value {
+    let mut request_value = json!({
+        "multi_coin_orders": {
+            "coins": step.coins,
+            "side": if step.side.as_bool() { "buy" } else { "sell" },
+            "sz": step.sz,
+            "tif": step.tif.as_sdk_str(),
+            "px": order_price_label(&step.px),
+            "resolvedPx": resolved_prices,
+        }
+    });
+    if let Some(code) = &step.builder_code {
+        request_value["multi_coin_orders"]["builderCode"] = json!(code);
     }
+    request_value
 }
 
-async fn resolve_limit_price(
-    order: &PerpOrder,
-    info_http: &mut InfoClient,
-    mid_cache: &mut HashMap<String, f64>,
-) -> Result<f64> {
-    match &order.px {
-        OrderPrice::Absolute(px) => Ok(*px),
-        OrderPrice::MidPercent { .. } => {
-            if let Some(mid) = mid_cache.get(&order.coin) {
-                Ok(order.px.resolve_with_mid(*mid))
-            } else {
-                let mids = info_http
-                    .all_mids()
-                    .await
-                    .context("failed to fetch all mids")?;
-                for (coin, price_str) in mids {
-                    if let Ok(px) = price_str.parse::<f64>() {
-                        mid_cache.insert(coin, px);
-                    }
-                }
-                let mid = mid_cache
-                    .get(&order.coin)
-                    .copied()
-                    .ok_or_else(|| anyhow!("mid price unavailable for {}", order.coin))?;
-                Ok(order.px.resolve_with_mid(mid))
+/// The `request` logged for a spot_orders step: its orders with resolved prices.
+fn spot_orders_request(step: &SpotOrdersStep, resolved_prices: &[f64]) -> Value {
+    let request_orders: Vec<_> = step
+        .orders
+        .iter()
+        .zip(resolved_prices)
+        .map(|(order, limit_px)| {
+            let mut order_value = json!({
+                "coin": order.coin,
+                "side": if order.side.as_bool() { "buy" } else { "sell" },
+                "sz": order.sz,
+                "tif": order.tif.as_sdk_str(),
+                "px": order_price_label(&order.px),
+                "resolvedPx": limit_px,
+            });
+            if let Some(code) = &order.builder_code {
+                order_value["builderCode"] = json!(code);
             }
-        }
+            order_value
+        })
+        .collect();
+    let mut request_value = json!({
+        "spot_orders": { "orders": request_orders },
+    });
+    if let Some(code) = &step.builder_code {
+        request_value["spot_orders"]["builderCode"] = json!(code);
     }
+    request_value
 }
 
-fn build_client_order(order: &PerpOrder, limit_px: f64) -> Result<ClientOrderRequest> {
-    if let Some(trigger) = &order.trigger {
-        match trigger {
-            hl_common::plan::OrderTrigger::None => {}
-            _ => {
-                return Err(anyhow!(
-                    "trigger orders are not yet supported in the runner"
-                ));
-            }
-        }
-    }
+/// The plan step an order batch was built from; the batch is logged in that step's form.
+#[derive(Debug, Clone, Copy)]
+enum OrderSource<'a> {
+    Perp,
+    MultiCoin(&'a MultiCoinOrdersStep),
+    Spot(&'a SpotOrdersStep),
+}
 
-    let cloid = order
-        .cloid
-        .as_deref()
-        .and_then(|raw| Uuid::parse_str(raw).ok());
+impl OrderSource<'_> {
+    fn is_spot(&self) -> bool {
+        matches!(self, OrderSource::Spot(_))
+    }
+}
 
-    Ok(ClientOrderRequest {
-        asset: order.coin.clone(),
-        is_buy: order.is_buy(),
-        reduce_only: order.reduce_only,
-        limit_px,
-        sz: order.sz,
-        cloid,
-        order_type: ClientOrder::Limit(ClientLimit {
-            tif: order.tif.as_sdk_str().to_string(),
-        }),
-    })
+/// Action name and request for an order step, logged in the form the plan used.
+fn orders_request(
+    step: &PerpOrdersStep,
+    source: OrderSource<'_>,
+    resolved_prices: &[f64],
+    builder_code: Option<&str>,
+) -> (&'static str, Value) {
+    match source {
+        OrderSource::Perp => (
+            "perp_orders",
+            perp_orders_request(step, resolved_prices, builder_code),
+        ),
+        OrderSource::MultiCoin(multi) => (
+            "multi_coin_orders",
+            multi_coin_orders_request(multi, resolved_prices),
+        ),
+        OrderSource::Spot(spot) => ("spot_orders", spot_orders_request(spot, resolved_prices)),
+    }
 }
 
-fn order_price_label(price: &OrderPrice) -> String {
-    match price {
-        OrderPrice::Absolute(px) => px.to_string(),
-        OrderPrice::MidPercent { offset_pct } => {
-            if *offset_pct >= 0.0 {
-                format!("mid+{}%", offset_pct)
-            } else {
-                format!("mid{}%", offset_pct)
-            }
-        }
+/// Demo mid for `coin`: the `--coinprice-source` file when given, else the built-in table.
+fn demo_mid(prices: Option<&PriceFile>, coin: &str) -> Result<f64> {
+    match prices {
+        Some(prices) => prices.mid(coin),
+        None => Ok(demo_mid_for_coin(coin)),
     }
 }
 
-async fn wait_for_order_event(
-    receiver: &mut broadcast::Receiver<ObservedEvent>,
-    oid: u64,
-    timeout_duration: Duration,
-) -> Option<ObservedEvent> {
-    use tokio::time::Instant;
+/// Demo limit price for `order`; bid/ask offsets use the top of the synthetic
+/// `demo_l2_snapshot` book around the demo mid.
+fn demo_limit_price(prices: Option<&PriceFile>, order: &PerpOrder) -> Result<f64> {
+    let mid = demo_mid(prices, &order.coin)?;
+    let half_spread = mid * DEMO_L2_LEVEL_STEP * 0.5;
+    let reference = match order.px {
+        OrderPrice::BidPercent { .. } => mid - half_spread,
+        OrderPrice::AskPercent { .. } => mid + half_spread,
+        _ => mid,
+    };
+    Ok(order.px.resolve_with_reference(reference))
+}
 
-    let deadline = Instant::now() + timeout_duration;
-    loop {
-        let now = Instant::now();
-        if now >= deadline {
-            return None;
-        }
-        let remaining = deadline - now;
-        match timeout(remaining, receiver.recv()).await {
-            Ok(Ok(event)) => match &event {
-                ObservedEvent::OrderUpdate { oid: ev_oid, .. }
-                | ObservedEvent::UserFill { oid: ev_oid, .. } => {
-                    if *ev_oid == oid {
-                        return Some(event);
-                    }
-                }
-                _ => {}
-            },
-            Ok(Err(broadcast::error::RecvError::Lagged(_))) => continue,
-            Ok(Err(_)) => return None,
-            Err(_) => return None,
-        }
+fn demo_mid_for_coin(coin: &str) -> f64 {
+    match coin {
+        "BTC" => 60_000.0,
+        "ETH" => 3_500.0,
+        "SOL" => 180.0,
+        "APT" => 10.0,
+        _ => 100.0,
     }
 }
 
-async fn wait_for_ledger_event(
-    receiver: &mut broadcast::Receiver<ObservedEvent>,
-    to_perp: bool,
-    timeout_duration: Duration,
-) -> Option<ObservedEvent> {
-    use tokio::time::Instant;
+/// `--ws-reconnect-delay-ms` / `--ws-max-reconnect-delay-ms` / `--ws-max-reconnect-attempts`.
+#[derive(Debug, Clone, Copy)]
+struct WsReconnectConfig {
+    initial_delay_ms: u64,
+    max_delay_ms: u64,
+    max_attempts: u32,
+}
 
-    let deadline = Instant::now() + timeout_duration;
-    loop {
-        let now = Instant::now();
-        if now >= deadline {
-            return None;
-        }
-        let remaining = deadline - now;
-        match timeout(remaining, receiver.recv()).await {
-            Ok(Ok(event)) => {
-                if let ObservedEvent::LedgerClassTransfer {
-                    to_perp: observed, ..
-                } = &event
-                {
-                    if *observed == to_perp {
-                        return Some(event);
-                    }
-                }
-            }
-            Ok(Err(broadcast::error::RecvError::Lagged(_))) => continue,
-            Ok(Err(_)) => return None,
-            Err(_) => return None,
+impl WsReconnectConfig {
+    fn from_cli(cli: &Cli) -> Self {
+        Self {
+            initial_delay_ms: cli.ws_reconnect_delay_ms,
+            max_delay_ms: cli.ws_max_reconnect_delay_ms,
+            max_attempts: cli.ws_max_reconnect_attempts,
         }
     }
+
+    /// `initial * 2^attempt`, capped at `max_delay_ms`.
+    fn delay_ms(&self, attempt: u32) -> u64 {
+        2u64.checked_pow(attempt)
+            .and_then(|factor| self.initial_delay_ms.checked_mul(factor))
+            .unwrap_or(u64::MAX)
+            .min(self.max_delay_ms)
+    }
 }
 
-fn remove_tracked_oids(placed_orders: &mut VecDeque<PlacedOrder>, target_oids: &[u64]) {
-    placed_orders.retain(|placed| !target_oids.contains(&placed.oid));
+/// Opens one websocket session whose messages go to `tx`. The session is held until the
+/// stream reports a disconnect, then dropped.
+trait WsConnector {
+    type Session;
+
+    async fn connect(&mut self, tx: mpsc::UnboundedSender<Message>) -> Result<Self::Session>;
 }
 
-async fn execute_plan(
-    plan: Plan,
-    artifacts: Arc<Mutex<RunArtifacts>>,
-    exchange: ExchangeClient,
-    mut info_http: InfoClient,
+/// Subscribes the wallet's order, fill and ledger channels (plus `activeAssetCtx` for
+/// `--use-ws-pricing`) on a fresh `InfoClient`.
+struct InfoWsConnector {
+    http: reqwest::Client,
+    base_url: BaseUrl,
+    wallet_address: ethers::types::H160,
+    pricing_coins: Vec<String>,
+}
+
+impl WsConnector for InfoWsConnector {
+    type Session = InfoClient;
+
+    async fn connect(&mut self, tx: mpsc::UnboundedSender<Message>) -> Result<InfoClient> {
+        // Not `with_reconnect`: the SDK would then reconnect on its own after a fixed 1s and
+        // keep our channel open, so the backoff in `run_ws_loop` would never run.
+        let mut client = InfoClient::new(Some(self.http.clone()), Some(self.base_url)).await?;
+        let mut subscriptions = vec![
+            Subscription::OrderUpdates {
+                user: self.wallet_address,
+            },
+            Subscription::UserFills {
+                user: self.wallet_address,
+            },
+            Subscription::UserNonFundingLedgerUpdates {
+                user: self.wallet_address,
+            },
+        ];
+        subscriptions.extend(
+            self.pricing_coins
+                .iter()
+                .map(|coin| Subscription::ActiveAssetCtx { coin: coin.clone() }),
+        );
+        for sub in subscriptions {
+            client
+                .subscribe(sub, tx.clone())
+                .await
+                .context("failed to subscribe to websocket channel")?;
+        }
+        Ok(client)
+    }
+}
+
+fn spawn_ws_task(
+    connector: InfoWsConnector,
+    config: WsReconnectConfig,
+    artifacts: Arc<Mutex<RunArtifacts>>,
+    broadcaster: broadcast::Sender<ObservedEvent>,
+    filter: WsChannelFilter,
+    mids: Option<WsMids>,
+) {
+    tokio::spawn(run_ws_loop(
+        connector,
+        config,
+        artifacts,
+        broadcaster,
+        filter,
+        mids,
+    ));
+}
+
+/// Reads sessions from `connector` until one reports `Message::NoData` (or its channel
+/// closes), then reconnects with exponential backoff. Once `config.max_attempts`
+/// consecutive attempts fail, broadcasts `WsDisconnected` and returns.
+async fn run_ws_loop<C: WsConnector>(
+    mut connector: C,
+    config: WsReconnectConfig,
+    artifacts: Arc<Mutex<RunArtifacts>>,
     broadcaster: broadcast::Sender<ObservedEvent>,
-    default_builder_code: Option<String>,
+    filter: WsChannelFilter,
+    mids: Option<WsMids>,
+) {
+    let mut attempt = 0u32;
+    loop {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        match connector.connect(tx).await {
+            Ok(session) => {
+                while let Some(message) = rx.recv().await {
+                    if matches!(message, Message::NoData) {
+                        break;
+                    }
+                    attempt = 0;
+                    if let Err(err) =
+                        handle_ws_message(&artifacts, &broadcaster, &filter, mids.as_ref(), message)
+                            .await
+                    {
+                        warn!("failed to process websocket message: {err:?}");
+                    }
+                }
+                // Dropping the client stops the SDK's reader and ping tasks.
+                drop(session);
+            }
+            Err(err) => warn!("websocket connect attempt failed: {err:#}"),
+        }
+
+        if attempt >= config.max_attempts {
+            warn!(
+                "websocket stream closed; giving up after {} reconnect attempts, no further confirmations will arrive",
+                config.max_attempts
+            );
+            let _ = broadcaster.send(ObservedEvent::WsDisconnected {
+                payload: json!({
+                    "channel": "wsDisconnected",
+                    "attempts": config.max_attempts,
+                }),
+            });
+            return;
+        }
+        let delay_ms = config.delay_ms(attempt);
+        attempt += 1;
+        warn!(
+            "websocket stream closed; reconnect attempt {attempt}/{} in {delay_ms}ms",
+            config.max_attempts
+        );
+        tokio::time::sleep(Duration::from_millis(delay_ms)).await;
+    }
+}
+
+/// `--event-filter`: websocket channels kept out of (or, inverted, the only ones in)
+/// `ws_stream.jsonl`. The default writes every channel.
+#[derive(Debug, Clone, Default)]
+struct WsChannelFilter {
+    channels: HashSet<String>,
+    invert: bool,
+}
+
+impl WsChannelFilter {
+    fn from_cli(cli: &Cli) -> Self {
+        Self {
+            channels: cli
+                .event_filter
+                .iter()
+                .map(|channel| channel.trim().to_string())
+                .filter(|channel| !channel.is_empty())
+                .collect(),
+            invert: cli.event_filter_invert,
+        }
+    }
+
+    fn should_log(&self, event: &Value) -> bool {
+        if self.channels.is_empty() {
+            return true;
+        }
+        let listed = event
+            .get("channel")
+            .and_then(Value::as_str)
+            .is_some_and(|channel| self.channels.contains(channel));
+        listed == self.invert
+    }
+}
+
+async fn handle_ws_message(
+    artifacts: &Arc<Mutex<RunArtifacts>>,
+    broadcaster: &broadcast::Sender<ObservedEvent>,
+    filter: &WsChannelFilter,
+    mids: Option<&WsMids>,
+    message: Message,
+) -> Result<()> {
+    // Price feed only: neither an effect to correlate nor worth a ws_stream line per tick.
+    if let Message::ActiveAssetCtx(ctx) = &message {
+        if let Some(mids) = mids {
+            mids.update(&ctx.data);
+        }
+        return Ok(());
+    }
+    let (value, events) = encode_message(message);
+    if filter.should_log(&value) {
+        let mut artifacts = artifacts.lock().await;
+        artifacts.log_ws_event(&value)?;
+    }
+    for event in events {
+        let _ = broadcaster.send(event);
+    }
+    Ok(())
+}
+
+fn encode_message(message: Message) -> (serde_json::Value, Vec<ObservedEvent>) {
+    match message {
+        Message::OrderUpdates(order_updates) => {
+            let mut events = Vec::new();
+            let data: Vec<_> = order_updates
+                .data
+                .iter()
+                .map(|upd| {
+                    let payload = json!({
+                        "channel": "orderUpdates",
+                        "coin": upd.order.coin.clone(),
+                        "oid": upd.order.oid,
+                        "side": upd.order.side.clone(),
+                        "limitPx": upd.order.limit_px.clone(),
+                        "sz": upd.order.sz.clone(),
+                        "status": upd.status.clone(),
+                        "statusTimestamp": upd.status_timestamp,
+                    });
+                    events.push(ObservedEvent::OrderUpdate {
+                        oid: upd.order.oid,
+                        status: upd.status.clone(),
+                        payload: payload.clone(),
+                    });
+                    payload
+                })
+                .collect();
+            (json!({ "channel": "orderUpdates", "data": data }), events)
+        }
+        Message::UserFills(fills) => {
+            let mut events = Vec::new();
+            let data: Vec<_> = fills
+                .data
+                .fills
+                .iter()
+                .map(|fill| {
+                    let payload = json!({
+                        "channel": "userFills",
+                        "oid": fill.oid,
+                        "coin": fill.coin.clone(),
+                        "px": fill.px.clone(),
+                        "sz": fill.sz.clone(),
+                        "time": fill.time,
+                        "side": fill.side.clone(),
+                    });
+                    events.push(ObservedEvent::UserFill {
+                        oid: fill.oid,
+                        coin: fill.coin.clone(),
+                        payload: payload.clone(),
+                    });
+                    payload
+                })
+                .collect();
+            let root = json!({
+                "channel": "userFills",
+                "isSnapshot": fills.data.is_snapshot,
+                "fills": data,
+            });
+            (root, events)
+        }
+        Message::UserNonFundingLedgerUpdates(ledger) => {
+            let mut events = Vec::new();
+            let data: Vec<_> = ledger
+                .data
+                .non_funding_ledger_updates
+                .iter()
+                .map(|update| {
+                    let entry = encode_ledger_update(update);
+                    if let Some(event) = entry.1 {
+                        events.push(event);
+                    }
+                    entry.0
+                })
+                .collect();
+            (
+                json!({
+                    "channel": "userNonFundingLedgerUpdates",
+                    "isSnapshot": ledger.data.is_snapshot,
+                    "updates": data,
+                }),
+                events,
+            )
+        }
+        other => (
+            json!({"channel": "other", "debug": format!("{:?}", other)}),
+            vec![ObservedEvent::Other {
+                _channel: "other".to_string(),
+                payload: json!({ "debug": format!("{:?}", other) }),
+            }],
+        ),
+    }
+}
+
+fn encode_ledger_update(update: &LedgerUpdateData) -> (serde_json::Value, Option<ObservedEvent>) {
+    match &update.delta {
+        LedgerUpdate::AccountClassTransfer(transfer) => {
+            let usdc = transfer.usdc.parse::<f64>().unwrap_or_default() / 1_000_000f64;
+            let payload = json!({
+                "channel": "accountClassTransfer",
+                "time": update.time,
+                "usdc": usdc,
+                "toPerp": transfer.to_perp,
+            });
+            (
+                payload.clone(),
+                Some(ObservedEvent::LedgerClassTransfer {
+                    to_perp: transfer.to_perp,
+                    _usdc: usdc,
+                    payload,
+                }),
+            )
+        }
+        other => {
+            let payload = json!({
+                "channel": "ledger",
+                "time": update.time,
+                "kind": format!("{:?}", other),
+            });
+            (
+                payload.clone(),
+                Some(ObservedEvent::Other {
+                    _channel: "ledger".to_string(),
+                    payload,
+                }),
+            )
+        }
+    }
+}
+
+fn exchange_status_json(status: &ExchangeResponseStatus) -> serde_json::Value {
+    match status {
+        ExchangeResponseStatus::Ok(resp) => {
+            let data = resp.data.as_ref().map(|collection| {
+                let entries: Vec<_> = collection.statuses.iter().map(data_status_json).collect();
+                json!({"statuses": entries})
+            });
+            json!({
+                "status": "ok",
+                "responseType": resp.response_type,
+                "data": data,
+            })
+        }
+        ExchangeResponseStatus::Err(err) => json!({
+            "status": "err",
+            "message": err,
+        }),
+    }
+}
+
+fn data_status_json(status: &ExchangeDataStatus) -> serde_json::Value {
+    match status {
+        ExchangeDataStatus::Success => json!({"kind": "success"}),
+        ExchangeDataStatus::WaitingForFill => json!({"kind": "waitingForFill"}),
+        ExchangeDataStatus::WaitingForTrigger => json!({"kind": "waitingForTrigger"}),
+        ExchangeDataStatus::Error(err) => json!({"kind": "error", "message": err}),
+        ExchangeDataStatus::Resting(order) => json!({
+            "kind": "resting",
+            "oid": order.oid,
+        }),
+        ExchangeDataStatus::Filled(filled) => json!({
+            "kind": "filled",
+            "oid": filled.oid,
+            "avgPx": filled.avg_px,
+            "totalSz": filled.total_sz,
+        }),
+    }
+}
+
+fn status_oid(status: &ExchangeDataStatus) -> Option<u64> {
+    match status {
+        ExchangeDataStatus::Resting(order) => Some(order.oid),
+        ExchangeDataStatus::Filled(filled) => Some(filled.oid),
+        _ => None,
+    }
+}
+
+/// Posts bulk orders; abstracted so order retries can be tested offline.
+trait OrderPoster {
+    async fn post_orders(
+        &self,
+        orders: Vec<ClientOrderRequest>,
+        builder: Option<BuilderInfo>,
+    ) -> Result<ExchangeResponseStatus>;
+}
+
+impl OrderPoster for ExchangeClient {
+    async fn post_orders(
+        &self,
+        orders: Vec<ClientOrderRequest>,
+        builder: Option<BuilderInfo>,
+    ) -> Result<ExchangeResponseStatus> {
+        let response = match builder {
+            Some(builder) => self.bulk_order_with_builder(orders, None, builder).await,
+            None => self.bulk_order(orders, None).await,
+        };
+        Ok(response?)
+    }
+}
+
+/// Per-order outcome of `post_orders_with_retry`, merged across attempts (and, for a
+/// `parallel` step, across sub-batches).
+struct PostedOrders {
+    /// One slot per order in the step; `None` for orders outside the batch.
+    statuses: Vec<Option<ExchangeDataStatus>>,
+    response_type: String,
+    /// Set when the exchange rejected the whole request and nothing was placed.
+    rejected: Option<String>,
+    attempts: u32,
+}
+
+impl PostedOrders {
+    fn ack(&self) -> serde_json::Value {
+        if let Some(err) = &self.rejected {
+            return exchange_status_json(&ExchangeResponseStatus::Err(err.clone()));
+        }
+        json!({
+            "status": "ok",
+            "responseType": self.response_type,
+            "data": {
+                "statuses": self
+                    .statuses
+                    .iter()
+                    .map(|status| status.as_ref().map_or(Value::Null, data_status_json))
+                    .collect::<Vec<_>>(),
+            },
+        })
+    }
+
+    /// A sub-batch whose post failed outright: its orders get per-order error statuses.
+    fn failed(order_count: usize, indices: &[usize], err: String) -> Self {
+        let mut statuses = vec![None; order_count];
+        for &idx in indices {
+            statuses[idx] = Some(ExchangeDataStatus::Error(err.clone()));
+        }
+        Self {
+            statuses,
+            response_type: "order".to_string(),
+            rejected: Some(err),
+            attempts: 1,
+        }
+    }
+
+    fn oids(&self) -> Vec<Option<u64>> {
+        self.statuses
+            .iter()
+            .map(|status| status.as_ref().and_then(status_oid))
+            .collect()
+    }
+
+    /// Slot-wise merge of sub-batches covering disjoint orders; the merged ack is only a
+    /// whole-request rejection when every sub-batch was rejected.
+    fn merge(batches: Vec<PostedOrders>) -> Option<PostedOrders> {
+        batches.into_iter().reduce(|mut merged, batch| {
+            for (slot, status) in merged.statuses.iter_mut().zip(batch.statuses) {
+                if status.is_some() {
+                    *slot = status;
+                }
+            }
+            merged.rejected = merged.rejected.and(batch.rejected);
+            merged.attempts = merged.attempts.max(batch.attempts);
+            merged
+        })
+    }
+}
+
+/// Transport failures (timeouts, connection errors, 5xx) and HTTP 429 may succeed on a
+/// later attempt; anything else (a bad signature, a malformed request) would fail again.
+fn is_transient_post_error(err: &anyhow::Error) -> bool {
+    matches!(
+        err.downcast_ref::<hyperliquid_rust_sdk::Error>(),
+        Some(
+            hyperliquid_rust_sdk::Error::GenericRequest(_)
+                | hyperliquid_rust_sdk::Error::ServerRequest { .. }
+                | hyperliquid_rust_sdk::Error::ClientRequest {
+                    status_code: 429,
+                    ..
+                }
+        )
+    )
+}
+
+/// Exchange rejections worth retrying: rate limits. Per-order rejections such as
+/// insufficient margin or a bad tick would be rejected the same way again.
+fn is_rate_limit_rejection(message: &str) -> bool {
+    let message = message.to_ascii_lowercase();
+    message.contains("rate limit") || message.contains("too many")
+}
+
+/// Posts the orders of `step` at `indices` and, under its `retry` policy, re-posts the
+/// ones that failed transiently: transport errors and rate-limited requests retry every
+/// pending order, rate-limited orders retry just those. Other rejections are final, and
+/// orders already resting or filled are never re-placed. Orders keep their cloid across
+/// attempts (see `assign_retry_cloids`), so an order the exchange accepted before its
+/// response was lost is not placed a second time.
+async fn post_orders_with_retry(
+    step_idx: usize,
+    step: &PerpOrdersStep,
+    resolved_prices: &[f64],
+    indices: Vec<usize>,
+    builder: Option<BuilderInfo>,
+    poster: &impl OrderPoster,
+) -> Result<PostedOrders> {
+    let max_attempts = step.retry.map_or(1, |retry| retry.max_attempts.max(1));
+    let mut statuses: Vec<Option<ExchangeDataStatus>> = vec![None; step.orders.len()];
+    let mut pending = indices;
+    let mut response_type = "order".to_string();
+    let mut attempts = 0;
+
+    loop {
+        attempts += 1;
+        let nothing_placed = statuses
+            .iter()
+            .flatten()
+            .all(|status| matches!(status, ExchangeDataStatus::Error(_)));
+        let orders = pending
+            .iter()
+            .map(|&idx| build_client_order(&step.orders[idx], resolved_prices[idx]))
+            .collect::<Result<Vec<_>>>()?;
+        let failure = match poster.post_orders(orders, builder.clone()).await {
+            Ok(ExchangeResponseStatus::Ok(response)) => {
+                response_type = response.response_type;
+                let returned = response.data.map(|data| data.statuses).unwrap_or_default();
+                let mut still_pending = Vec::new();
+                for (pos, idx) in pending.iter().copied().enumerate() {
+                    match returned.get(pos) {
+                        Some(ExchangeDataStatus::Error(err)) => {
+                            statuses[idx] = Some(ExchangeDataStatus::Error(err.clone()));
+                            if is_rate_limit_rejection(err) {
+                                still_pending.push(idx);
+                            }
+                        }
+                        Some(status) => statuses[idx] = Some(status.clone()),
+                        None => still_pending.push(idx),
+                    }
+                }
+                pending = still_pending;
+                if pending.is_empty() {
+                    break;
+                }
+                format!("{} order(s) rejected", pending.len())
+            }
+            Ok(ExchangeResponseStatus::Err(err)) => {
+                for &idx in &pending {
+                    statuses[idx] = Some(ExchangeDataStatus::Error(err.clone()));
+                }
+                let retryable = is_rate_limit_rejection(&err);
+                if (attempts >= max_attempts || !retryable) && nothing_placed {
+                    return Ok(PostedOrders {
+                        statuses,
+                        response_type,
+                        rejected: Some(err),
+                        attempts,
+                    });
+                }
+                if !retryable {
+                    break;
+                }
+                err
+            }
+            Err(err) => {
+                let retryable = is_transient_post_error(&err);
+                if (attempts >= max_attempts || !retryable) && nothing_placed {
+                    return Err(err.context("failed to post perp orders"));
+                }
+                for &idx in &pending {
+                    statuses[idx] = Some(ExchangeDataStatus::Error(format!("{err:#}")));
+                }
+                if !retryable {
+                    break;
+                }
+                format!("{err:#}")
+            }
+        };
+        if attempts >= max_attempts {
+            break;
+        }
+        let delay_ms = step.retry.map_or(0, |retry| retry.delay_ms(attempts));
+        warn!(
+            "step {step_idx}: attempt {attempts}/{max_attempts} failed ({failure}); retrying {} order(s) in {delay_ms} ms",
+            pending.len()
+        );
+        tokio::time::sleep(Duration::from_millis(delay_ms)).await;
+    }
+
+    Ok(PostedOrders {
+        statuses,
+        response_type,
+        rejected: None,
+        attempts,
+    })
+}
+
+/// Gives every order of a `retry` step a cloid that stays the same across attempts. An
+/// order re-sent after a timeout then carries the cloid the exchange may already have
+/// accepted, instead of being a new order.
+///
+/// Only order steps retry: they are the only actions with a client id. A transfer or
+/// leverage update re-sent after a lost response could apply twice, and a cancel that
+/// failed is already reported as such.
+fn assign_retry_cloids(mut step: PerpOrdersStep) -> PerpOrdersStep {
+    if step.retry.is_some() {
+        for order in &mut step.orders {
+            order
+                .cloid
+                .get_or_insert_with(|| Uuid::new_v4().to_string());
+        }
+    }
+    step
+}
+
+/// Order indices of `step` grouped by coin, coins in first-seen order.
+fn coin_batches(step: &PerpOrdersStep) -> Vec<Vec<usize>> {
+    let mut batches: Vec<(&str, Vec<usize>)> = Vec::new();
+    for (idx, order) in step.orders.iter().enumerate() {
+        match batches.iter_mut().find(|(coin, _)| *coin == order.coin) {
+            Some((_, indices)) => indices.push(idx),
+            None => batches.push((&order.coin, vec![idx])),
+        }
+    }
+    batches.into_iter().map(|(_, indices)| indices).collect()
+}
+
+/// Splits each batch of order indices so every order in a batch has the same builder code.
+fn builder_batches(batches: Vec<Vec<usize>>, builders: &[Option<String>]) -> Vec<Vec<usize>> {
+    let builder_of = |idx: usize| builders.get(idx).and_then(Option::as_deref);
+    let mut split = Vec::new();
+    for batch in batches {
+        let mut groups: Vec<(Option<&str>, Vec<usize>)> = Vec::new();
+        for idx in batch {
+            match groups.iter_mut().find(|(code, _)| *code == builder_of(idx)) {
+                Some((_, indices)) => indices.push(idx),
+                None => groups.push((builder_of(idx), vec![idx])),
+            }
+        }
+        split.extend(groups.into_iter().map(|(_, indices)| indices));
+    }
+    split
+}
+
+/// Posts `step` as one bulk order per builder code, since a bulk order carries a single
+/// builder; with `parallel`, as one concurrent bulk order per coin and builder code.
+/// `builders` holds each order's resolved code. Returns the merged outcome and, when the
+/// step was split, a note. A sub-batch that fails outright only errors its own orders; the
+/// step fails only when every sub-batch did, so orders placed by the others are still
+/// tracked and logged.
+async fn post_step_orders(
+    step_idx: usize,
+    step: &PerpOrdersStep,
+    resolved_prices: &[f64],
+    builders: &[Option<String>],
+    poster: &impl OrderPoster,
+) -> Result<(PostedOrders, Option<String>)> {
+    let builder_for = |indices: &[usize]| {
+        indices
+            .first()
+            .and_then(|idx| builders.get(*idx).cloned().flatten())
+            .map(|code| BuilderInfo {
+                builder: code.to_lowercase(),
+                fee: 0,
+            })
+    };
+    let batches = if step.parallel {
+        coin_batches(step)
+    } else {
+        vec![(0..step.orders.len()).collect()]
+    };
+    let mut batches = builder_batches(batches, builders);
+    let batch_count = batches.len();
+    if !step.parallel && batch_count == 1 {
+        let indices = batches.pop().unwrap_or_default();
+        let builder = builder_for(&indices);
+        let posted =
+            post_orders_with_retry(step_idx, step, resolved_prices, indices, builder, poster)
+                .await?;
+        return Ok((posted, None));
+    }
+
+    let post = |indices: Vec<usize>| {
+        let builder = builder_for(&indices);
+        async move {
+            let start = Instant::now();
+            let posted = post_orders_with_retry(
+                step_idx,
+                step,
+                resolved_prices,
+                indices.clone(),
+                builder,
+                poster,
+            )
+            .await;
+            (indices, posted, start.elapsed().as_millis())
+        }
+    };
+    let results = if step.parallel {
+        join_all(batches.into_iter().map(post)).await
+    } else {
+        let mut results = Vec::with_capacity(batch_count);
+        for indices in batches {
+            results.push(post(indices).await);
+        }
+        results
+    };
+    let max_latency_ms = results.iter().map(|(_, _, ms)| *ms).max().unwrap_or(0);
+    let mut failures = 0;
+    let mut posted = Vec::with_capacity(batch_count);
+    let mut first_err = None;
+    for (indices, result, _) in results {
+        match result {
+            Ok(batch) => posted.push(batch),
+            Err(err) => {
+                failures += 1;
+                warn!("step {step_idx}: sub-batch {indices:?} failed: {err:#}");
+                posted.push(PostedOrders::failed(
+                    step.orders.len(),
+                    &indices,
+                    format!("{err:#}"),
+                ));
+                first_err.get_or_insert(err);
+            }
+        }
+    }
+    if failures == batch_count {
+        if let Some(err) = first_err {
+            return Err(err);
+        }
+    }
+    let merged =
+        PostedOrders::merge(posted).ok_or_else(|| anyhow!("step {step_idx} has no orders"))?;
+    let failed = if failures == 0 {
+        String::new()
+    } else {
+        format!(" ({failures} failed)")
+    };
+    let note = if step.parallel {
+        format!("parallel: {batch_count} sub-batches{failed}, max sub-batch latency {max_latency_ms} ms")
+    } else {
+        format!("split by builder code: {batch_count} sub-batches{failed}")
+    };
+    Ok((merged, Some(note)))
+}
+
+/// Source of venue mid prices; abstracted so the mid cache can be tested offline.
+trait MidSource {
+    async fn all_mids(&mut self) -> Result<HashMap<String, String>>;
+
+    /// Per-coin `szDecimals` used to derive tick sizes; empty when the source has no meta.
+    async fn sz_decimals(&mut self) -> Result<HashMap<String, u32>> {
+        Ok(HashMap::new())
+    }
+
+    /// Best bid and ask for `coin`, needed by `bid±X%` / `ask±X%` prices.
+    async fn best_bid_ask(&mut self, coin: &str) -> Result<(f64, f64)> {
+        Err(anyhow!("no order book available for {coin}"))
+    }
+}
+
+impl MidSource for InfoClient {
+    async fn all_mids(&mut self) -> Result<HashMap<String, String>> {
+        InfoClient::all_mids(self)
+            .await
+            .context("failed to fetch all mids")
+    }
+
+    async fn sz_decimals(&mut self) -> Result<HashMap<String, u32>> {
+        let meta = self.meta().await.context("failed to fetch meta")?;
+        Ok(meta
+            .universe
+            .into_iter()
+            .map(|asset| (asset.name, asset.sz_decimals))
+            .collect())
+    }
+
+    async fn best_bid_ask(&mut self, coin: &str) -> Result<(f64, f64)> {
+        let book = self
+            .l2_snapshot(coin.to_string())
+            .await
+            .with_context(|| format!("failed to fetch l2 book for {coin}"))?;
+        let top = |idx: usize| -> Result<f64> {
+            let level = book
+                .levels
+                .get(idx)
+                .and_then(|levels| levels.first())
+                .ok_or_else(|| anyhow!("empty {} side in {coin} book", ["bid", "ask"][idx]))?;
+            level
+                .px
+                .parse()
+                .with_context(|| format!("invalid {coin} book price {}", level.px))
+        };
+        Ok((top(0)?, top(1)?))
+    }
+}
+
+/// Fixed mids loaded from a `--coinprice-source` JSON object of coin -> price.
+#[derive(Debug, Clone)]
+struct PriceFile {
+    path: PathBuf,
+    mids: HashMap<String, f64>,
+    /// From `--coinbook`, when given; the price file itself has no tick sizes.
+    sz_decimals: HashMap<String, u32>,
+}
+
+impl PriceFile {
+    fn load(path: &Path) -> Result<Self> {
+        let raw = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read price file {}", path.display()))?;
+        let mids = serde_json::from_str(&raw).with_context(|| {
+            format!(
+                "price file {} must be a JSON object of coin -> number",
+                path.display()
+            )
+        })?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            mids,
+            sz_decimals: HashMap::new(),
+        })
+    }
+
+    fn with_coinbook(mut self, coinbook: &Coinbook) -> Self {
+        self.sz_decimals = coinbook.sz_decimals();
+        self
+    }
+
+    fn mid(&self, coin: &str) -> Result<f64> {
+        self.mids
+            .get(coin)
+            .copied()
+            .ok_or_else(|| anyhow!("no price for {coin} in {}", self.path.display()))
+    }
+}
+
+impl MidSource for PriceFile {
+    async fn all_mids(&mut self) -> Result<HashMap<String, String>> {
+        Ok(self
+            .mids
+            .iter()
+            .map(|(coin, px)| (coin.clone(), px.to_string()))
+            .collect())
+    }
+
+    async fn sz_decimals(&mut self) -> Result<HashMap<String, u32>> {
+        Ok(self.sz_decimals.clone())
+    }
+}
+
+/// Mid source for live runs: the venue, unless `--coinprice-source` replaced it.
+enum LiveMids<'a> {
+    Network(&'a mut InfoClient),
+    File(&'a mut PriceFile),
+}
+
+impl MidSource for LiveMids<'_> {
+    async fn all_mids(&mut self) -> Result<HashMap<String, String>> {
+        match self {
+            LiveMids::Network(info) => MidSource::all_mids(*info).await,
+            LiveMids::File(file) => file.all_mids().await,
+        }
+    }
+
+    async fn sz_decimals(&mut self) -> Result<HashMap<String, u32>> {
+        match self {
+            LiveMids::Network(info) => MidSource::sz_decimals(*info).await,
+            LiveMids::File(file) => file.sz_decimals().await,
+        }
+    }
+
+    async fn best_bid_ask(&mut self, coin: &str) -> Result<(f64, f64)> {
+        match self {
+            LiveMids::Network(info) => MidSource::best_bid_ask(*info, coin).await,
+            LiveMids::File(file) => file.best_bid_ask(coin).await,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct MidCacheEntry {
+    price: f64,
+    fetched_at: Instant,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct BookCacheEntry {
+    bid: f64,
+    ask: f64,
+    fetched_at: Instant,
+}
+
+/// Max age of an `activeAssetCtx` mid before orders fall back to `all_mids`.
+const WS_MID_MAX_AGE_MS: u128 = 100;
+
+/// Latest mid per coin from `activeAssetCtx`, shared by the websocket task and order steps.
+#[derive(Debug, Clone, Default)]
+struct WsMids(Arc<std::sync::Mutex<HashMap<String, MidCacheEntry>>>);
+
+impl WsMids {
+    fn update(&self, data: &ActiveAssetCtxData) {
+        let shared = match &data.ctx {
+            AssetCtx::Perps(ctx) => &ctx.shared,
+            AssetCtx::Spot(ctx) => &ctx.shared,
+        };
+        let raw = shared.mid_px.as_deref().unwrap_or(&shared.mark_px);
+        let Ok(price) = raw.parse::<f64>() else {
+            debug!("unparseable activeAssetCtx mid for {}: {raw}", data.coin);
+            return;
+        };
+        if let Ok(mut entries) = self.0.lock() {
+            entries.insert(
+                data.coin.clone(),
+                MidCacheEntry {
+                    price,
+                    fetched_at: Instant::now(),
+                },
+            );
+        }
+    }
+
+    fn fresh(&self, coin: &str) -> Option<f64> {
+        let entries = self.0.lock().ok()?;
+        let entry = entries.get(coin)?;
+        (entry.fetched_at.elapsed().as_millis() <= WS_MID_MAX_AGE_MS).then_some(entry.price)
+    }
+}
+
+/// Where [`MidCache`] reads the time from; tests pin it and move it by hand.
+#[derive(Debug, Clone, Default)]
+enum Clock {
+    #[default]
+    System,
+    #[cfg(test)]
+    Manual(Arc<std::sync::Mutex<Instant>>),
+}
+
+impl Clock {
+    fn now(&self) -> Instant {
+        match self {
+            Clock::System => Instant::now(),
+            #[cfg(test)]
+            Clock::Manual(now) => *now.lock().unwrap(),
+        }
+    }
+}
+
+/// Mid prices fetched during a live run, refreshed once older than `ttl_ms`.
+#[derive(Debug)]
+struct MidCache {
+    entries: HashMap<String, MidCacheEntry>,
+    /// Top of book per coin for bid/ask-relative prices, under the same `ttl_ms`.
+    books: HashMap<String, BookCacheEntry>,
+    ttl_ms: u64,
+    /// Fetched once, the first time an order asks for `snapToTick`.
+    sz_decimals: Option<HashMap<String, u32>>,
+    /// Checked before `entries` when `--use-ws-pricing` is on.
+    ws_mids: Option<WsMids>,
+    clock: Clock,
+}
+
+impl MidCache {
+    fn new(ttl_ms: u64) -> Self {
+        Self {
+            entries: HashMap::new(),
+            books: HashMap::new(),
+            ttl_ms,
+            sz_decimals: None,
+            ws_mids: None,
+            clock: Clock::System,
+        }
+    }
+
+    #[cfg(test)]
+    fn with_clock(mut self, clock: Clock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    fn expired(&self, fetched_at: Instant) -> bool {
+        self.clock
+            .now()
+            .saturating_duration_since(fetched_at)
+            .as_millis()
+            > u128::from(self.ttl_ms)
+    }
+
+    fn with_ws_mids(mut self, ws_mids: Option<WsMids>) -> Self {
+        self.ws_mids = ws_mids;
+        self
+    }
+
+    fn ws_fresh(&self, coin: &str) -> Option<f64> {
+        let mid = self.ws_mids.as_ref()?.fresh(coin);
+        if mid.is_some() {
+            debug!("using websocket mid for {coin}");
+        }
+        mid
+    }
+
+    fn fresh(&mut self, coin: &str) -> Option<f64> {
+        let entry = self.entries.get(coin)?;
+        if self.expired(entry.fetched_at) {
+            debug!("mid for {coin} older than {} ms; re-fetching", self.ttl_ms);
+            self.entries.remove(coin);
+            return None;
+        }
+        Some(entry.price)
+    }
+
+    fn fresh_book(&mut self, coin: &str) -> Option<(f64, f64)> {
+        let entry = self.books.get(coin)?;
+        if self.expired(entry.fetched_at) {
+            debug!("book for {coin} older than {} ms; re-fetching", self.ttl_ms);
+            self.books.remove(coin);
+            return None;
+        }
+        Some((entry.bid, entry.ask))
+    }
+
+    fn insert_book(&mut self, coin: &str, (bid, ask): (f64, f64)) {
+        self.books.insert(
+            coin.to_string(),
+            BookCacheEntry {
+                bid,
+                ask,
+                fetched_at: self.clock.now(),
+            },
+        );
+    }
+
+    fn insert_all(&mut self, mids: HashMap<String, String>) {
+        let fetched_at = self.clock.now();
+        for (coin, price_str) in mids {
+            if let Ok(price) = price_str.parse::<f64>() {
+                self.entries
+                    .insert(coin, MidCacheEntry { price, fetched_at });
+            }
+        }
+    }
+}
+
+async fn resolve_limit_price(
+    order: &PerpOrder,
+    mid_source: &mut impl MidSource,
+    mid_cache: &mut MidCache,
+) -> Result<f64> {
+    let px = match &order.px {
+        OrderPrice::Absolute(px) => *px,
+        OrderPrice::MidPercent { .. } => {
+            if let Some(mid) = mid_cache
+                .ws_fresh(&order.coin)
+                .or_else(|| mid_cache.fresh(&order.coin))
+            {
+                order.px.resolve_with_mid(mid)
+            } else {
+                let mids = mid_source.all_mids().await?;
+                mid_cache.insert_all(mids);
+                let mid = mid_cache
+                    .fresh(&order.coin)
+                    .ok_or_else(|| anyhow!("mid price unavailable for {}", order.coin))?;
+                order.px.resolve_with_mid(mid)
+            }
+        }
+        OrderPrice::BidPercent { .. } | OrderPrice::AskPercent { .. } => {
+            let (bid, ask) = match mid_cache.fresh_book(&order.coin) {
+                Some(book) => book,
+                None => {
+                    let book = mid_source.best_bid_ask(&order.coin).await?;
+                    mid_cache.insert_book(&order.coin, book);
+                    book
+                }
+            };
+            let reference = if matches!(order.px, OrderPrice::BidPercent { .. }) {
+                bid
+            } else {
+                ask
+            };
+            order.px.resolve_with_reference(reference)
+        }
+    };
+    if !order.snap_to_tick {
+        return Ok(px);
+    }
+
+    if mid_cache.sz_decimals.is_none() {
+        mid_cache.sz_decimals = Some(mid_source.sz_decimals().await?);
+    }
+    let sz_decimals = mid_cache
+        .sz_decimals
+        .as_ref()
+        .and_then(|decimals| decimals.get(&order.coin));
+    match sz_decimals {
+        Some(sz_decimals) => {
+            let decimals = perp_price_decimals(*sz_decimals, px);
+            let snapped = OrderPrice::round_to_decimals(px, decimals);
+            debug!("snapped {} px {px} to {snapped} ({decimals} decimals)", order.coin);
+            Ok(snapped)
+        }
+        None => Err(anyhow!(
+            "snapToTick: no szDecimals known for {}; offline prices need a --coinbook that lists it",
+            order.coin
+        )),
+    }
+}
+
+/// Hyperliquid perp prices allow at most `6 - szDecimals` decimals and five significant
+/// figures; integer prices are always valid.
+fn perp_price_decimals(sz_decimals: u32, px: f64) -> u32 {
+    let max_decimals = 6 - sz_decimals.min(6) as i32;
+    let int_digits = if px > 0.0 {
+        px.log10().floor() as i32 + 1
+    } else {
+        1
+    };
+    max_decimals.min(5 - int_digits).max(0) as u32
+}
+
+fn build_client_order(order: &PerpOrder, limit_px: f64) -> Result<ClientOrderRequest> {
+    if let Some(trigger) = &order.trigger {
+        match trigger {
+            hl_common::plan::OrderTrigger::None => {}
+            _ => {
+                return Err(anyhow!(
+                    "trigger orders are not yet supported in the runner"
+                ));
+            }
+        }
+    }
+
+    let cloid = order
+        .cloid
+        .as_deref()
+        .and_then(|raw| Uuid::parse_str(raw).ok());
+
+    Ok(ClientOrderRequest {
+        asset: order.coin.clone(),
+        is_buy: order.is_buy(),
+        reduce_only: order.reduce_only,
+        limit_px,
+        sz: order.sz,
+        cloid,
+        order_type: ClientOrder::Limit(ClientLimit {
+            tif: order.tif.as_sdk_str().to_string(),
+        }),
+    })
+}
+
+fn order_price_label(price: &OrderPrice) -> String {
+    let (anchor, offset_pct) = match price {
+        OrderPrice::Absolute(px) => return px.to_string(),
+        OrderPrice::MidPercent { offset_pct } => ("mid", offset_pct),
+        OrderPrice::BidPercent { offset_pct } => ("bid", offset_pct),
+        OrderPrice::AskPercent { offset_pct } => ("ask", offset_pct),
+    };
+    if *offset_pct >= 0.0 {
+        format!("{anchor}+{}%", offset_pct)
+    } else {
+        format!("{anchor}{}%", offset_pct)
+    }
+}
+
+async fn wait_for_order_event(
+    receiver: &mut broadcast::Receiver<ObservedEvent>,
+    oid: u64,
+    timeout_duration: Duration,
+) -> Option<ObservedEvent> {
+    use tokio::time::Instant;
+
+    let deadline = Instant::now() + timeout_duration;
+    loop {
+        let now = Instant::now();
+        if now >= deadline {
+            return None;
+        }
+        let remaining = deadline - now;
+        match timeout(remaining, receiver.recv()).await {
+            Ok(Ok(event)) if event.is_ws_disconnect() => return None,
+            Ok(Ok(event)) => match &event {
+                ObservedEvent::OrderUpdate { oid: ev_oid, .. }
+                | ObservedEvent::UserFill { oid: ev_oid, .. }
+                    if *ev_oid == oid =>
+                {
+                    return Some(event);
+                }
+                _ => {}
+            },
+            Ok(Err(broadcast::error::RecvError::Lagged(_))) => continue,
+            Ok(Err(_)) => return None,
+            Err(_) => return None,
+        }
+    }
+}
+
+async fn wait_for_ledger_event(
+    receiver: &mut broadcast::Receiver<ObservedEvent>,
+    to_perp: bool,
+    timeout_duration: Duration,
+) -> Option<ObservedEvent> {
+    use tokio::time::Instant;
+
+    let deadline = Instant::now() + timeout_duration;
+    loop {
+        let now = Instant::now();
+        if now >= deadline {
+            return None;
+        }
+        let remaining = deadline - now;
+        match timeout(remaining, receiver.recv()).await {
+            Ok(Ok(event)) if event.is_ws_disconnect() => return None,
+            Ok(Ok(event)) => {
+                if let ObservedEvent::LedgerClassTransfer {
+                    to_perp: observed, ..
+                } = &event
+                {
+                    if *observed == to_perp {
+                        return Some(event);
+                    }
+                }
+            }
+            Ok(Err(broadcast::error::RecvError::Lagged(_))) => continue,
+            Ok(Err(_)) => return None,
+            Err(_) => return None,
+        }
+    }
+}
+
+/// Fills seen since the run started, so a `wait_for_fill` step also settles on a fill
+/// that arrived before the step began waiting.
+struct FillLog {
+    receiver: broadcast::Receiver<ObservedEvent>,
+    /// Fill events not yet claimed by a wait, oldest first.
+    pending: Vec<ObservedEvent>,
+}
+
+impl FillLog {
+    fn new(receiver: broadcast::Receiver<ObservedEvent>) -> Self {
+        Self {
+            receiver,
+            pending: Vec::new(),
+        }
+    }
+
+    fn is_fill(event: &ObservedEvent) -> bool {
+        match event {
+            ObservedEvent::UserFill { .. } => true,
+            ObservedEvent::OrderUpdate { status, .. } => status.eq_ignore_ascii_case("filled"),
+            _ => false,
+        }
+    }
+
+    fn matches(event: &ObservedEvent, oid: Option<u64>, coin: Option<&str>) -> bool {
+        match event {
+            ObservedEvent::UserFill {
+                oid: ev_oid,
+                coin: ev_coin,
+                ..
+            } => {
+                let oid_ok = oid.map(|target| target == *ev_oid).unwrap_or(true);
+                let coin_ok = coin
+                    .map(|target| target.eq_ignore_ascii_case(ev_coin))
+                    .unwrap_or(true);
+                oid_ok && coin_ok
+            }
+            // Order updates carry no coin, so they only settle waits on a known oid.
+            ObservedEvent::OrderUpdate { oid: ev_oid, .. } => oid == Some(*ev_oid),
+            _ => false,
+        }
+    }
+
+    /// Moves fills already broadcast into `pending`. Called between steps so the
+    /// channel does not lag while nothing is waiting.
+    fn drain(&mut self) {
+        loop {
+            match self.receiver.try_recv() {
+                Ok(event) if Self::is_fill(&event) => self.pending.push(event),
+                Ok(_) | Err(broadcast::error::TryRecvError::Lagged(_)) => {}
+                Err(_) => return,
+            }
+        }
+    }
+
+    /// Removes and returns the oldest pending fill matching the target.
+    fn take(&mut self, oid: Option<u64>, coin: Option<&str>) -> Option<ObservedEvent> {
+        let pos = self
+            .pending
+            .iter()
+            .position(|event| Self::matches(event, oid, coin))?;
+        Some(self.pending.remove(pos))
+    }
+
+    /// The first matching fill, already seen or arriving within `timeout_duration`.
+    async fn wait(
+        &mut self,
+        oid: Option<u64>,
+        coin: Option<&str>,
+        timeout_duration: Duration,
+    ) -> Option<ObservedEvent> {
+        use tokio::time::Instant;
+
+        self.drain();
+        if let Some(event) = self.take(oid, coin) {
+            return Some(event);
+        }
+        let deadline = Instant::now() + timeout_duration;
+        loop {
+            let now = Instant::now();
+            if now >= deadline {
+                return None;
+            }
+            let remaining = deadline - now;
+            match timeout(remaining, self.receiver.recv()).await {
+                Ok(Ok(event)) if event.is_ws_disconnect() => return None,
+                Ok(Ok(event)) if Self::is_fill(&event) => {
+                    if Self::matches(&event, oid, coin) {
+                        return Some(event);
+                    }
+                    self.pending.push(event);
+                }
+                Ok(Ok(_)) | Ok(Err(broadcast::error::RecvError::Lagged(_))) => continue,
+                Ok(Err(_)) => return None,
+                Err(_) => return None,
+            }
+        }
+    }
+}
+
+/// Builder codes from the CLI; precedence is per-order > per-coin > per-step > global.
+#[derive(Debug, Default)]
+struct BuilderCodes {
+    global: Option<String>,
+    /// Keyed by upper-case coin.
+    per_coin: HashMap<String, String>,
+}
+
+impl BuilderCodes {
+    fn from_cli(cli: &Cli) -> Result<Self> {
+        Ok(Self {
+            global: cli.builder_code.clone(),
+            per_coin: cli
+                .builder_code_per_coin
+                .as_deref()
+                .map(parse_builder_code_per_coin)
+                .transpose()?
+                .unwrap_or_default(),
+        })
+    }
+
+    /// The step-level code, falling back to `--builder-code`.
+    fn step_code(&self, step: &PerpOrdersStep) -> Option<String> {
+        step.builder_code.clone().or_else(|| self.global.clone())
+    }
+
+    /// The code recorded for one order, given the already resolved `step_code`.
+    fn order_code(&self, order: &PerpOrder, step_code: Option<&str>) -> Option<String> {
+        order
+            .builder_code
+            .clone()
+            .or_else(|| self.per_coin.get(&order.coin.to_ascii_uppercase()).cloned())
+            .or_else(|| step_code.map(str::to_string))
+    }
+}
+
+/// Parses `ETH:0xabc,BTC:0xdef` into an upper-case coin -> code map.
+fn parse_builder_code_per_coin(raw: &str) -> Result<HashMap<String, String>> {
+    raw.split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            let (coin, code) = entry
+                .split_once(':')
+                .map(|(coin, code)| (coin.trim(), code.trim()))
+                .filter(|(coin, code)| !coin.is_empty() && !code.is_empty())
+                .ok_or_else(|| {
+                    anyhow!("invalid --builder-code-per-coin entry '{entry}', expected COIN:CODE")
+                })?;
+            Ok((coin.to_ascii_uppercase(), code.to_string()))
+        })
+        .collect()
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn execute_plan(
+    plan: Plan,
+    artifacts: Arc<Mutex<RunArtifacts>>,
+    exchange: ExchangeClient,
+    mut info_http: InfoClient,
+    wallet_address: H160,
+    broadcaster: broadcast::Sender<ObservedEvent>,
+    builder_codes: &BuilderCodes,
+    effect_timeout_ms: u64,
+    abort_on_timeout: bool,
+    mut mid_cache: MidCache,
+    mut price_file: Option<PriceFile>,
+    l2_depth: Option<usize>,
+    inject_sleep_ms: u64,
+    limits: NotionalLimits,
+    trace: &mut StepTrace,
+) -> Result<()> {
+    let mut placed_orders = OrderTracker::default();
+    let mut fills = FillLog::new(broadcaster.subscribe());
+
+    let mut queue = StepQueue::new(&plan.steps);
+    while let Some(QueuedStep { idx, branch, step }) = queue.next() {
+        artifacts.lock().await.set_branch(branch.clone());
+        fills.drain();
+        let started = trace.start();
+        match step {
+            ActionStep::If { if_step } => {
+                let artifacts = artifacts.lock().await;
+                queue.take_branch(idx, branch.as_deref(), if_step, &artifacts);
+                continue;
+            }
+            ActionStep::Loop { loop_step } => {
+                queue.take_loop(idx, branch.as_deref(), loop_step);
+                continue;
+            }
+            ActionStep::PerpOrders { perp_orders } => {
+                if let Some(depth) = l2_depth {
+                    capture_l2_snapshots(idx, perp_orders, &artifacts, &info_http, depth).await?;
+                }
+                let mut mids = match price_file.as_mut() {
+                    Some(file) => LiveMids::File(file),
+                    None => LiveMids::Network(&mut info_http),
+                };
+                execute_perp_orders(
+                    idx,
+                    perp_orders,
+                    OrderSource::Perp,
+                    &artifacts,
+                    &exchange,
+                    &mut mids,
+                    &mut mid_cache,
+                    &mut placed_orders,
+                    &broadcaster,
+                    builder_codes,
+                    effect_timeout_ms,
+                    limits,
+                )
+                .await?;
+            }
+            ActionStep::MultiCoinOrders { multi_coin_orders } => {
+                let perp_orders = &multi_coin_orders.to_perp_orders();
+                if let Some(depth) = l2_depth {
+                    capture_l2_snapshots(idx, perp_orders, &artifacts, &info_http, depth).await?;
+                }
+                let mut mids = match price_file.as_mut() {
+                    Some(file) => LiveMids::File(file),
+                    None => LiveMids::Network(&mut info_http),
+                };
+                execute_perp_orders(
+                    idx,
+                    perp_orders,
+                    OrderSource::MultiCoin(multi_coin_orders),
+                    &artifacts,
+                    &exchange,
+                    &mut mids,
+                    &mut mid_cache,
+                    &mut placed_orders,
+                    &broadcaster,
+                    builder_codes,
+                    effect_timeout_ms,
+                    limits,
+                )
+                .await?;
+            }
+            ActionStep::SpotOrders { spot_orders } => {
+                let mut mids = match price_file.as_mut() {
+                    Some(file) => LiveMids::File(file),
+                    None => LiveMids::Network(&mut info_http),
+                };
+                execute_perp_orders(
+                    idx,
+                    &spot_orders.to_perp_orders(),
+                    OrderSource::Spot(spot_orders),
+                    &artifacts,
+                    &exchange,
+                    &mut mids,
+                    &mut mid_cache,
+                    &mut placed_orders,
+                    &broadcaster,
+                    builder_codes,
+                    effect_timeout_ms,
+                    limits,
+                )
+                .await?;
+            }
+            ActionStep::CancelLast { cancel_last } => {
+                execute_cancel_last(
+                    idx,
+                    cancel_last,
+                    &artifacts,
+                    &exchange,
+                    &mut placed_orders,
+                    &broadcaster,
+                    effect_timeout_ms,
+                )
+                .await?;
+            }
+            ActionStep::CancelOids { cancel_oids } => {
+                execute_cancel_oids(
+                    idx,
+                    cancel_oids,
+                    &artifacts,
+                    &exchange,
+                    &mut placed_orders,
+                    &broadcaster,
+                    effect_timeout_ms,
+                )
+                .await?;
+            }
+            ActionStep::CancelAll { cancel_all } => {
+                execute_cancel_all(
+                    idx,
+                    cancel_all,
+                    &artifacts,
+                    &exchange,
+                    &mut placed_orders,
+                    &broadcaster,
+                    effect_timeout_ms,
+                )
+                .await?;
+            }
+            ActionStep::UsdClassTransfer { usd_class_transfer } => {
+                execute_class_transfer(
+                    idx,
+                    usd_class_transfer,
+                    &artifacts,
+                    &exchange,
+                    &broadcaster,
+                    effect_timeout_ms,
+                )
+                .await?;
+            }
+            ActionStep::SubAccountTransfer {
+                sub_account_transfer,
+            } => {
+                execute_sub_account_transfer(
+                    idx,
+                    sub_account_transfer,
+                    &artifacts,
+                    &exchange,
+                    wallet_address,
+                )
+                .await?;
+            }
+            ActionStep::SetLeverage { set_leverage } => {
+                execute_set_leverage(
+                    idx,
+                    set_leverage,
+                    &artifacts,
+                    &exchange,
+                    &info_http,
+                    wallet_address,
+                )
+                .await?;
+            }
+            ActionStep::Sleep { sleep_ms } => {
+                let submit_ts = timestamp_ms();
+                tokio::time::sleep(Duration::from_millis(sleep_ms.duration_ms)).await;
+                log_sleep(idx, sleep_ms, &artifacts, submit_ts, None).await?;
+            }
+            ActionStep::WaitForFill { wait_for_fill } => {
+                execute_wait_for_fill(
+                    idx,
+                    wait_for_fill,
+                    &artifacts,
+                    &placed_orders,
+                    &mut fills,
+                    effect_timeout_ms,
+                    abort_on_timeout,
+                )
+                .await?;
+            }
+            ActionStep::FetchFundingRate { fetch_funding_rate } => {
+                execute_fetch_funding_rate(idx, fetch_funding_rate, &artifacts, &info_http).await?;
+            }
+            ActionStep::AssertBalance { assert_balance } => {
+                execute_assert_balance(idx, assert_balance, &artifacts, &info_http, wallet_address)
+                    .await?;
+            }
+        }
+        trace.record(idx, step, started);
+        inject_step_sleep(idx, plan.steps.len(), inject_sleep_ms).await;
+    }
+
+    Ok(())
+}
+
+/// A step due to run: top-level, or inside a taken `if` branch (`branch` = `3.else.0`) or
+/// a loop pass (`branch` = `3.loop_1.inner_0`); `idx` is always the top-level step.
+struct QueuedStep<'a> {
+    idx: usize,
+    branch: Option<String>,
+    step: &'a ActionStep,
+}
+
+/// An entry in [`StepQueue`]: a step, or the loop pass still to be unrolled.
+enum QueueEntry<'a> {
+    Step(QueuedStep<'a>),
+    LoopPass {
+        idx: usize,
+        parent: String,
+        loop_step: &'a LoopStep,
+        pass: u32,
+    },
+}
+
+/// Steps left to run; a taken `if` branch or a loop pass is spliced in ahead of whatever
+/// followed it. Loops unroll one pass at a time, so the queue never holds more than the
+/// current pass of each enclosing loop.
+struct StepQueue<'a> {
+    pending: VecDeque<QueueEntry<'a>>,
+}
+
+impl<'a> StepQueue<'a> {
+    fn new(steps: &'a [ActionStep]) -> Self {
+        Self {
+            pending: steps
+                .iter()
+                .enumerate()
+                .map(|(idx, step)| {
+                    QueueEntry::Step(QueuedStep {
+                        idx,
+                        branch: None,
+                        step,
+                    })
+                })
+                .collect(),
+        }
+    }
+
+    fn next(&mut self) -> Option<QueuedStep<'a>> {
+        loop {
+            match self.pending.pop_front()? {
+                QueueEntry::Step(step) => return Some(step),
+                QueueEntry::LoopPass {
+                    idx,
+                    parent,
+                    loop_step,
+                    pass,
+                } => {
+                    if pass + 1 < loop_step.count {
+                        self.pending.push_front(QueueEntry::LoopPass {
+                            idx,
+                            parent: parent.clone(),
+                            loop_step,
+                            pass: pass + 1,
+                        });
+                    }
+                    for (inner, step) in loop_step.steps.iter().enumerate().rev() {
+                        self.pending.push_front(QueueEntry::Step(QueuedStep {
+                            idx,
+                            branch: Some(format!("{parent}.loop_{pass}.inner_{inner}")),
+                            step,
+                        }));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Evaluates `if_step` against the run so far and queues the branch it takes.
+    fn take_branch(
+        &mut self,
+        idx: usize,
+        branch: Option<&str>,
+        if_step: &'a IfStep,
+        artifacts: &RunArtifacts,
+    ) {
+        let holds = if_step.condition.holds(
+            artifacts.last_action(),
+            artifacts.last_order_action(),
+            artifacts.placed_order_count(),
+        );
+        let (name, steps) = if_step.branch(holds);
+        let parent = branch.map_or_else(|| idx.to_string(), str::to_string);
+        info!(
+            "step {parent}: {:?} is {holds}; running {name} branch ({} steps)",
+            if_step.condition,
+            steps.len()
+        );
+        for (child, step) in steps.iter().enumerate().rev() {
+            self.pending.push_front(QueueEntry::Step(QueuedStep {
+                idx,
+                branch: Some(format!("{parent}.{name}.{child}")),
+                step,
+            }));
+        }
+    }
+
+    /// Queues `count` passes over the loop body, each unrolled when it comes up.
+    fn take_loop(&mut self, idx: usize, branch: Option<&str>, loop_step: &'a LoopStep) {
+        let parent = branch.map_or_else(|| idx.to_string(), str::to_string);
+        info!(
+            "step {parent}: looping {} times over {} steps",
+            loop_step.count,
+            loop_step.steps.len()
+        );
+        if loop_step.count > 0 {
+            self.pending.push_front(QueueEntry::LoopPass {
+                idx,
+                parent,
+                loop_step,
+                pass: 0,
+            });
+        }
+    }
+}
+
+/// Applies `--inject-sleep-between-steps` after step `idx`; nothing follows the last step,
+/// so no pause (and no action record) is added there.
+async fn inject_step_sleep(idx: usize, step_count: usize, sleep_ms: u64) -> bool {
+    if sleep_ms == 0 || idx + 1 >= step_count {
+        return false;
+    }
+    debug!("injecting {sleep_ms}ms sleep after step {idx}");
+    tokio::time::sleep(Duration::from_millis(sleep_ms)).await;
+    true
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn execute_perp_orders(
+    step_idx: usize,
+    step: &PerpOrdersStep,
+    source: OrderSource<'_>,
+    artifacts: &Arc<Mutex<RunArtifacts>>,
+    exchange: &impl OrderPoster,
+    mid_source: &mut impl MidSource,
+    mid_cache: &mut MidCache,
+    placed_orders: &mut OrderTracker,
+    broadcaster: &broadcast::Sender<ObservedEvent>,
+    builder_codes: &BuilderCodes,
+    effect_timeout_ms: u64,
+    limits: NotionalLimits,
+) -> Result<()> {
+    if step.orders.is_empty() {
+        return Ok(());
+    }
+    let step = &assign_retry_cloids(step.with_tif_override());
+
+    let submit_ts = timestamp_ms();
+    let mut resolved_prices = Vec::with_capacity(step.orders.len());
+
+    for order in &step.orders {
+        let limit_px = resolve_limit_price(order, mid_source, mid_cache).await?;
+        build_client_order(order, limit_px)?;
+        resolved_prices.push(limit_px);
+    }
+
+    let builder_code = builder_codes.step_code(step);
+    let (action, mut request_value) =
+        orders_request(step, source, &resolved_prices, builder_code.as_deref());
+    if step.orders.iter().any(|order| order.cloid.is_some()) {
+        request_value[action]["cloids"] = json!(step
+            .orders
+            .iter()
+            .map(|order| order.cloid.clone())
+            .collect::<Vec<_>>());
+    }
+    let notionals = step
+        .orders
+        .iter()
+        .zip(&resolved_prices)
+        .map(|(order, px)| (order.coin.as_str(), *px, order.sz));
+    if let Some(reason) = limits.violation(notionals) {
+        return log_notional_skip(
+            step_idx,
+            action,
+            artifacts,
+            submit_ts,
+            request_value,
+            reason,
+        )
+        .await;
+    }
+
+    let order_builders: Vec<Option<String>> = step
+        .orders
+        .iter()
+        .map(|order| builder_codes.order_code(order, builder_code.as_deref()))
+        .collect();
+
+    let mut receiver = broadcaster.subscribe();
+
+    let start = Instant::now();
+    let (posted, split_note) =
+        post_step_orders(step_idx, step, &resolved_prices, &order_builders, exchange).await?;
+    let per_order_oid = posted.oids();
+    for (order, oid) in step.orders.iter().zip(&per_order_oid) {
+        if let Some(oid) = oid {
+            placed_orders.push(&order.coin, *oid);
+        }
+    }
+
+    let mut routed_records = Vec::new();
+    for (((order, limit_px), maybe_oid), builder) in step
+        .orders
+        .iter()
+        .zip(resolved_prices.iter())
+        .zip(per_order_oid.iter().cloned())
+        .zip(order_builders)
+    {
+        routed_records.push(RoutedOrderRecord {
+            ts_ms: submit_ts,
+            oid: maybe_oid,
+            coin: order.coin.clone(),
+            side: if order.is_buy() {
+                "buy".to_string()
+            } else {
+                "sell".to_string()
+            },
+            px: *limit_px,
+            sz: order.sz,
+            tif: order.tif.as_sdk_str().to_string(),
+            reduce_only: order.reduce_only,
+            builder_code: builder,
+            spot: source.is_spot(),
+        });
+    }
+
+    let mut observed_events = Vec::new();
+    let mut missing = Vec::new();
+    for maybe_oid in per_order_oid.iter().flatten() {
+        let wait = Duration::from_millis(effect_timeout_ms);
+        match wait_for_order_event(&mut receiver, *maybe_oid, wait).await {
+            Some(event) => observed_events.push(event.payload().clone()),
+            None => missing.push(*maybe_oid),
+        }
+    }
+
+    let observed_value = if observed_events.is_empty() {
+        None
+    } else {
+        Some(serde_json::Value::Array(observed_events))
+    };
+
+    let mut notes = Vec::new();
+    if step.retry.is_some() {
+        notes.push(format!("attempts: {}", posted.attempts));
+    }
+    notes.extend(split_note);
+    if !missing.is_empty() {
+        notes.push(format!("no websocket confirmation for oids: {:?}", missing));
+    }
+    let notes = (!notes.is_empty()).then(|| notes.join("; "));
+
+    {
+        let mut artifacts = artifacts.lock().await;
+        let record = artifacts.make_action_record_with_duration(
+            step_idx,
+            action,
+            submit_ts,
+            request_value,
+            Some(posted.ack()),
+            observed_value,
+            notes,
+            start,
+        );
+        artifacts.log_action(&record)?;
+        for record in routed_records {
+            artifacts.log_routed_order(&record)?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn execute_cancel_last(
+    step_idx: usize,
+    step: &CancelLastStep,
+    artifacts: &Arc<Mutex<RunArtifacts>>,
+    exchange: &ExchangeClient,
+    placed_orders: &mut OrderTracker,
+    broadcaster: &broadcast::Sender<ObservedEvent>,
+    effect_timeout_ms: u64,
+) -> Result<()> {
+    let target = if let Some(coin) = &step.coin {
+        placed_orders.last(coin).map(|oid| (coin.clone(), oid))
+    } else {
+        placed_orders.last_any()
+    };
+
+    let mut notes = None;
+    let mut observed_value = None;
+    let submit_ts = timestamp_ms();
+    let start = Instant::now();
+    let mut ack_value = json!({ "status": "skipped" });
+
+    if let Some((target_coin, target_oid)) = target {
+        let mut receiver = broadcaster.subscribe();
+        let request = ClientCancelRequest {
+            asset: target_coin,
+            oid: target_oid,
+        };
+        let response = exchange
+            .cancel(request, None)
+            .await
+            .context("failed to cancel order")?;
+        ack_value = exchange_status_json(&response);
+        if matches!(response, ExchangeResponseStatus::Ok(_)) {
+            placed_orders.remove(target_oid);
+
+            let wait = Duration::from_millis(effect_timeout_ms);
+            if let Some(event) = wait_for_order_event(&mut receiver, target_oid, wait).await {
+                observed_value = Some(event.payload().clone());
+            } else {
+                notes = Some(format!("no cancel confirmation for oid {}", target_oid));
+            }
+        } else {
+            notes = Some("cancel request rejected".to_string());
+        }
+    } else {
+        notes = Some("no tracked order available for cancel_last".to_string());
+    }
+
+    let request_value = json!({
+        "cancel_last": {
+            "coin": step.coin,
+        }
+    });
+
+    {
+        let mut artifacts = artifacts.lock().await;
+        let record = artifacts.make_action_record_with_duration(
+            step_idx,
+            "cancel_last",
+            submit_ts,
+            request_value,
+            Some(ack_value),
+            observed_value,
+            notes,
+            start,
+        );
+        artifacts.log_action(&record)?;
+    }
+
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn execute_cancel_oids(
+    step_idx: usize,
+    step: &CancelOidsStep,
+    artifacts: &Arc<Mutex<RunArtifacts>>,
+    exchange: &ExchangeClient,
+    placed_orders: &mut OrderTracker,
+    broadcaster: &broadcast::Sender<ObservedEvent>,
+    effect_timeout_ms: u64,
+) -> Result<()> {
+    if step.oids.is_empty() {
+        return Ok(());
+    }
+
+    let submit_ts = timestamp_ms();
+    let start = Instant::now();
+    let request_value = json!({
+        "cancel_oids": {
+            "coin": step.coin,
+            "oids": step.oids,
+        }
+    });
+    let (tracked, untracked) = partition_tracked(&step.oids, placed_orders);
+    let mut skipped_notes = Vec::new();
+    let oids = if step.validate_tracked.unwrap_or(false) {
+        skipped_notes = untracked_notes(&untracked);
+        tracked
+    } else {
+        if step.warn_untracked.unwrap_or(true) {
+            for oid in &untracked {
+                warn!("cancel_oids step {step_idx}: oid {oid} is not in local tracking");
+            }
+        }
+        step.oids.clone()
+    };
+
+    if oids.is_empty() {
+        let mut artifacts = artifacts.lock().await;
+        let record = artifacts.make_action_record_with_duration(
+            step_idx,
+            "cancel_oids",
+            submit_ts,
+            request_value,
+            None,
+            None,
+            Some(skipped_notes.join("; ")),
+            start,
+        );
+        return artifacts.log_action(&record);
+    }
+
+    let mut receiver = broadcaster.subscribe();
+    let cancels: Vec<ClientCancelRequest> = oids
+        .iter()
+        .map(|oid| ClientCancelRequest {
+            asset: step.coin.clone(),
+            oid: *oid,
+        })
+        .collect();
+
+    let response = exchange
+        .bulk_cancel(cancels, None)
+        .await
+        .context("failed to cancel specified oids")?;
+    let ack_value = exchange_status_json(&response);
+    let success = matches!(response, ExchangeResponseStatus::Ok(_));
+
+    let (observed_value, mut notes) = if success {
+        placed_orders.remove_all(&oids);
+
+        let mut observed = Vec::new();
+        let mut missing = Vec::new();
+        let wait = Duration::from_millis(effect_timeout_ms);
+        for oid in &oids {
+            match wait_for_order_event(&mut receiver, *oid, wait).await {
+                Some(event) => observed.push(event.payload().clone()),
+                None => missing.push(*oid),
+            }
+        }
+        let observed_value = if observed.is_empty() {
+            None
+        } else {
+            Some(serde_json::Value::Array(observed))
+        };
+        let notes = if missing.is_empty() {
+            None
+        } else {
+            Some(format!("missing cancel confirmations for {:?}", missing))
+        };
+        (observed_value, notes)
+    } else {
+        (None, Some("cancel request rejected".to_string()))
+    };
+    if !skipped_notes.is_empty() {
+        skipped_notes.extend(notes);
+        notes = Some(skipped_notes.join("; "));
+    }
+
+    {
+        let mut artifacts = artifacts.lock().await;
+        let record = artifacts.make_action_record_with_duration(
+            step_idx,
+            "cancel_oids",
+            submit_ts,
+            request_value,
+            Some(ack_value),
+            observed_value,
+            notes,
+            start,
+        );
+        artifacts.log_action(&record)?;
+    }
+
+    Ok(())
+}
+
+/// Splits `oids` into those placed by this run and those it has never seen.
+fn partition_tracked(oids: &[u64], placed_orders: &OrderTracker) -> (Vec<u64>, Vec<u64>) {
+    oids.iter()
+        .partition(|oid| placed_orders.coin_of(**oid).is_some())
+}
+
+fn untracked_notes(untracked: &[u64]) -> Vec<String> {
+    untracked
+        .iter()
+        .map(|oid| format!("OID {oid} not in local tracking, skipped"))
+        .collect()
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn execute_cancel_all(
+    step_idx: usize,
+    step: &CancelAllStep,
+    artifacts: &Arc<Mutex<RunArtifacts>>,
+    exchange: &ExchangeClient,
+    placed_orders: &mut OrderTracker,
+    broadcaster: &broadcast::Sender<ObservedEvent>,
+    effect_timeout_ms: u64,
+) -> Result<()> {
+    let targets: Vec<(String, u64)> = match &step.coin {
+        Some(coin) => placed_orders
+            .all_for_coin(coin)
+            .into_iter()
+            .map(|oid| (coin.clone(), oid))
+            .collect(),
+        None => placed_orders.all(),
+    };
+
+    let submit_ts = timestamp_ms();
+    let start = Instant::now();
+    let mut notes = None;
+    let mut ack_value = json!({ "status": "skipped" });
+    let mut observed_value = None;
+
+    if targets.is_empty() {
+        notes = Some("no orders to cancel".to_string());
+    } else {
+        let mut receiver = broadcaster.subscribe();
+        let cancels: Vec<ClientCancelRequest> = targets
+            .iter()
+            .map(|(coin, oid)| ClientCancelRequest {
+                asset: coin.clone(),
+                oid: *oid,
+            })
+            .collect();
+
+        let response = exchange
+            .bulk_cancel(cancels, None)
+            .await
+            .context("failed to cancel tracked orders")?;
+        ack_value = exchange_status_json(&response);
+        if matches!(response, ExchangeResponseStatus::Ok(_)) {
+            let oids: Vec<u64> = targets.iter().map(|(_, oid)| *oid).collect();
+            placed_orders.remove_all(&oids);
+
+            let wait = Duration::from_millis(effect_timeout_ms);
+            let mut observed = Vec::new();
+            let mut missing = Vec::new();
+            for oid in oids {
+                match wait_for_order_event(&mut receiver, oid, wait).await {
+                    Some(event) => observed.push(event.payload().clone()),
+                    None => missing.push(oid),
+                }
+            }
+
+            observed_value = if observed.is_empty() {
+                None
+            } else {
+                Some(serde_json::Value::Array(observed))
+            };
+            if !missing.is_empty() {
+                notes = Some(format!("missing cancel confirmations for {:?}", missing));
+            }
+        } else {
+            notes = Some("cancel request rejected".to_string());
+        }
+    }
+
+    let request_value = json!({
+        "cancel_all": {
+            "coin": step.coin,
+        }
+    });
+
+    {
+        let mut artifacts = artifacts.lock().await;
+        let record = artifacts.make_action_record_with_duration(
+            step_idx,
+            "cancel_all",
+            submit_ts,
+            request_value,
+            Some(ack_value),
+            observed_value,
+            notes,
+            start,
+        );
+        artifacts.log_action(&record)?;
+    }
+
+    Ok(())
+}
+
+async fn execute_class_transfer(
+    step_idx: usize,
+    step: &UsdClassTransferStep,
+    artifacts: &Arc<Mutex<RunArtifacts>>,
+    exchange: &ExchangeClient,
+    broadcaster: &broadcast::Sender<ObservedEvent>,
+    effect_timeout_ms: u64,
+) -> Result<()> {
+    let submit_ts = timestamp_ms();
+    let start = Instant::now();
+    let mut receiver = broadcaster.subscribe();
+    let response = exchange
+        .class_transfer(step.usdc, step.to_perp, None)
+        .await
+        .context("failed to submit class transfer")?;
+    let ack_value = exchange_status_json(&response);
+
+    let wait = Duration::from_millis(effect_timeout_ms);
+    let (observed_value, notes) = if matches!(response, ExchangeResponseStatus::Ok(_)) {
+        let observed = wait_for_ledger_event(&mut receiver, step.to_perp, wait).await;
+        if let Some(event) = observed {
+            (Some(event.payload().clone()), None)
+        } else {
+            (None, Some("no ledger update observed".to_string()))
+        }
+    } else {
+        (None, Some("class transfer rejected".to_string()))
+    };
+
+    let request_value = json!({
+        "usd_class_transfer": {
+            "toPerp": step.to_perp,
+            "usdc": step.usdc,
+        }
+    });
+
+    {
+        let mut artifacts = artifacts.lock().await;
+        let record = artifacts.make_action_record_with_duration(
+            step_idx,
+            "usd_class_transfer",
+            submit_ts,
+            request_value,
+            Some(ack_value),
+            observed_value,
+            notes,
+            start,
+        );
+        artifacts.log_action(&record)?;
+    }
+
+    Ok(())
+}
+
+async fn execute_sub_account_transfer(
+    step_idx: usize,
+    step: &SubAccountTransferStep,
+    artifacts: &Arc<Mutex<RunArtifacts>>,
+    exchange: &ExchangeClient,
+    wallet_address: H160,
+) -> Result<()> {
+    let sub_account = H160::from_str(&step.to_address)
+        .map_err(|e| anyhow!("invalid sub-account address {}: {e}", step.to_address))?;
+    if sub_account == wallet_address {
+        warn!(
+            "step {step_idx}: sub-account transfer targets the primary wallet {}",
+            step.to_address
+        );
+    }
+    let submit_ts = timestamp_ms();
+    let start = Instant::now();
+    let response =
+        sub_account::sub_account_transfer(exchange, sub_account, step.amount, step.is_deposit)
+            .await
+            .context("failed to submit sub-account transfer")?;
+    let notes = match &response {
+        ExchangeResponseStatus::Ok(_) => None,
+        ExchangeResponseStatus::Err(_) => Some("sub-account transfer rejected".to_string()),
+    };
+
+    {
+        let mut artifacts = artifacts.lock().await;
+        let record = artifacts.make_action_record_with_duration(
+            step_idx,
+            "sub_account_transfer",
+            submit_ts,
+            sub_account_transfer_request(step),
+            Some(exchange_status_json(&response)),
+            None,
+            notes,
+            start,
+        );
+        artifacts.log_action(&record)?;
+    }
+
+    Ok(())
+}
+
+/// The signed size of `coin`'s position, when it is open (non-zero).
+fn open_position_size<'a>(positions: &'a [AssetPosition], coin: &str) -> Option<&'a str> {
+    positions
+        .iter()
+        .map(|asset| &asset.position)
+        .find(|position| {
+            position.coin.eq_ignore_ascii_case(coin)
+                && position.szi.parse::<f64>().unwrap_or(0.0) != 0.0
+        })
+        .map(|position| position.szi.as_str())
+}
+
+async fn execute_set_leverage(
+    step_idx: usize,
+    step: &SetLeverageStep,
+    artifacts: &Arc<Mutex<RunArtifacts>>,
+    exchange: &ExchangeClient,
+    info_http: &InfoClient,
+    wallet_address: H160,
+) -> Result<()> {
+    if step.fail_if_position {
+        let state = info_http
+            .user_state(wallet_address)
+            .await
+            .context("failed to fetch clearinghouse state")?;
+        if let Some(szi) = open_position_size(&state.asset_positions, &step.coin) {
+            error!(
+                "set_leverage step {step_idx}: {} has an open position (szi {szi})",
+                step.coin
+            );
+            return Err(anyhow!(
+                "refusing to set leverage on {}: existing position of size {szi}",
+                step.coin
+            ));
+        }
+    }
+
+    let submit_ts = timestamp_ms();
+    let start = Instant::now();
+    let (ack_value, notes) = if step.dry_set_leverage {
+        (
+            json!({ "status": "skipped" }),
+            Some("dry_set_leverage: update not submitted".to_string()),
+        )
+    } else {
+        let response = exchange
+            .update_leverage(step.leverage, &step.coin, step.cross, None)
+            .await
+            .context("failed to update leverage")?;
+        let notes = if matches!(response, ExchangeResponseStatus::Ok(_)) {
+            None
+        } else {
+            Some("set leverage rejected".to_string())
+        };
+        (exchange_status_json(&response), notes)
+    };
+
+    let request_value = json!({
+        "set_leverage": {
+            "coin": step.coin,
+            "leverage": step.leverage,
+            "cross": step.cross,
+        }
+    });
+
+    {
+        let mut artifacts = artifacts.lock().await;
+        let record = artifacts.make_action_record_with_duration(
+            step_idx,
+            "set_leverage",
+            submit_ts,
+            request_value,
+            Some(ack_value),
+            None,
+            notes,
+            start,
+        );
+        artifacts.log_action(&record)?;
+    }
+
+    Ok(())
+}
+
+async fn execute_fetch_funding_rate(
+    step_idx: usize,
+    step: &FetchFundingRateStep,
+    artifacts: &Arc<Mutex<RunArtifacts>>,
+    info_http: &InfoClient,
+) -> Result<()> {
+    let submit_ts = timestamp_ms();
+    let start = Instant::now();
+    // Funding settles hourly, so a two hour lookback always covers the latest entry.
+    let start_time = (submit_ts - 2 * 60 * 60 * 1000).max(0) as u64;
+    let history = info_http
+        .funding_history(step.coin.to_ascii_uppercase(), start_time, None)
+        .await;
+    if let Err(err) = &history {
+        warn!("fetch_funding_rate step {step_idx}: {err}");
+    }
+    let (rate, notes) = latest_funding_rate(&step.coin, history);
+    log_funding_rate(
+        step_idx,
+        step,
+        artifacts,
+        submit_ts,
+        rate,
+        notes,
+        Some(start),
+    )
+    .await
+}
+
+/// Latest funding rate and premium, or the note explaining why there is none. A failed
+/// lookup becomes an err ack like an empty history rather than ending the run.
+fn latest_funding_rate<E: std::fmt::Display>(
+    coin: &str,
+    history: std::result::Result<Vec<FundingHistoryResponse>, E>,
+) -> (Option<(f64, f64)>, Option<String>) {
+    let history = match history {
+        Ok(history) => history,
+        Err(err) => {
+            return (
+                None,
+                Some(format!("failed to fetch funding history for {coin}: {err}")),
+            )
+        }
+    };
+    let latest = history.iter().max_by_key(|entry| entry.time);
+    let rate = latest.and_then(|entry| {
+        let funding_rate = entry.funding_rate.parse::<f64>().ok()?;
+        let premium = entry.premium.parse::<f64>().unwrap_or(0.0);
+        Some((funding_rate, premium))
+    });
+    let notes = rate
+        .is_none()
+        .then(|| format!("no funding history for {coin}"));
+    (rate, notes)
+}
+
+async fn execute_assert_balance(
+    step_idx: usize,
+    step: &AssertBalanceStep,
+    artifacts: &Arc<Mutex<RunArtifacts>>,
+    info_http: &InfoClient,
+    wallet_address: H160,
+) -> Result<()> {
+    let submit_ts = timestamp_ms();
+    let start = Instant::now();
+    let state = info_http
+        .user_state(wallet_address)
+        .await
+        .context("failed to fetch clearinghouse state for balance assertion")?;
+    let balance = parse_balance(&state.withdrawable, "perp withdrawable balance")?;
+    let violation = step.violation(balance);
+    log_assert_balance(
+        step_idx,
+        step,
+        artifacts,
+        submit_ts,
+        balance,
+        violation,
+        Some(start),
+    )
+    .await
+}
+
+async fn execute_wait_for_fill(
+    step_idx: usize,
+    step: &WaitForFillStep,
+    artifacts: &Arc<Mutex<RunArtifacts>>,
+    placed_orders: &OrderTracker,
+    fills: &mut FillLog,
     effect_timeout_ms: u64,
+    abort_on_timeout: bool,
 ) -> Result<()> {
-    let mut placed_orders: VecDeque<PlacedOrder> = VecDeque::new();
-    let mut mid_cache: HashMap<String, f64> = HashMap::new();
+    let (target_oid, target_coin) = match (step.oid, step.oid_index, step.coin.as_deref()) {
+        (Some(oid), _, _) => (Some(oid), None),
+        (None, Some(index), _) => (placed_orders.nth(index).map(|(_, oid)| oid), None),
+        (None, None, Some(coin)) => (None, Some(coin)),
+        (None, None, None) => (placed_orders.last_any().map(|(_, oid)| oid), None),
+    };
 
-    for (idx, step) in plan.steps.iter().enumerate() {
-        match step {
-            ActionStep::PerpOrders { perp_orders } => {
-                execute_perp_orders(
-                    idx,
-                    perp_orders,
-                    &artifacts,
-                    &exchange,
-                    &mut info_http,
-                    &mut mid_cache,
-                    &mut placed_orders,
-                    &broadcaster,
-                    default_builder_code.as_deref(),
-                    effect_timeout_ms,
-                )
-                .await?;
-            }
-            ActionStep::CancelLast { cancel_last } => {
-                execute_cancel_last(
-                    idx,
-                    cancel_last,
-                    &artifacts,
-                    &exchange,
-                    &mut placed_orders,
-                    &broadcaster,
-                    effect_timeout_ms,
-                )
-                .await?;
-            }
-            ActionStep::CancelOids { cancel_oids } => {
-                execute_cancel_oids(
-                    idx,
-                    cancel_oids,
-                    &artifacts,
-                    &exchange,
-                    &mut placed_orders,
-                    &broadcaster,
-                    effect_timeout_ms,
-                )
-                .await?;
+    let target_label = match (target_oid, target_coin) {
+        (Some(oid), _) => Some(format!("oid {oid}")),
+        (None, Some(coin)) => Some(format!("coin {coin}")),
+        (None, None) => None,
+    };
+
+    let submit_ts = timestamp_ms();
+    let start = Instant::now();
+    let mut observed_value = None;
+    let mut timed_out = false;
+    let (ack_value, notes) = if let Some(target) = target_label {
+        let wait = Duration::from_millis(step.timeout_ms.unwrap_or(effect_timeout_ms));
+        match fills.wait(target_oid, target_coin, wait).await {
+            Some(event) => {
+                observed_value = Some(event.payload().clone());
+                (json!({ "status": "ok" }), None)
             }
-            ActionStep::CancelAll { cancel_all } => {
-                execute_cancel_all(
-                    idx,
-                    cancel_all,
-                    &artifacts,
-                    &exchange,
-                    &mut placed_orders,
-                    &broadcaster,
-                    effect_timeout_ms,
+            None => {
+                timed_out = true;
+                (
+                    json!({ "status": "timeout" }),
+                    Some(format!(
+                        "no fill observed for {target} within {} ms",
+                        wait.as_millis()
+                    )),
                 )
-                .await?;
             }
-            ActionStep::UsdClassTransfer { usd_class_transfer } => {
-                execute_class_transfer(
-                    idx,
-                    usd_class_transfer,
-                    &artifacts,
-                    &exchange,
-                    &broadcaster,
-                    effect_timeout_ms,
-                )
-                .await?;
+        }
+    } else {
+        (
+            json!({ "status": "skipped" }),
+            Some("no tracked order available for wait_for_fill".to_string()),
+        )
+    };
+
+    {
+        let mut artifacts = artifacts.lock().await;
+        let record = artifacts.make_action_record_with_duration(
+            step_idx,
+            "wait_for_fill",
+            submit_ts,
+            wait_for_fill_request(step),
+            Some(ack_value),
+            observed_value,
+            notes.clone(),
+            start,
+        );
+        artifacts.log_action(&record)?;
+    }
+
+    if timed_out {
+        let message = notes.unwrap_or_default();
+        if abort_on_timeout || step.fail_on_timeout {
+            return Err(anyhow!(
+                "wait_for_fill step {step_idx} timed out: {message}"
+            ));
+        }
+        warn!("wait_for_fill step {step_idx} timed out: {message}");
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hl_common::test_support::{action_record, RunFixture};
+    use hyperliquid_rust_sdk::{ExchangeDataStatuses, ExchangeResponse, RestingOrder};
+
+    #[test]
+    fn notional_limits_check_orders_then_step_total() {
+        let limits = NotionalLimits {
+            per_order: Some(100.0),
+            per_step: Some(150.0),
+        };
+        assert_eq!(
+            limits.violation([("ETH", 3_000.0, 0.01), ("SOL", 150.0, 0.5)]),
+            None
+        );
+        assert_eq!(
+            limits.violation([("ETH", 3_000.0, 0.04)]).as_deref(),
+            Some("order notional cap exceeded: ETH 120 > 100 USDC")
+        );
+        assert_eq!(
+            limits
+                .violation([
+                    ("ETH", 3_000.0, 0.03),
+                    ("BTC", 60_000.0, 0.001),
+                    ("SOL", 1.0, 7.0)
+                ])
+                .as_deref(),
+            Some("notional cap exceeded: 157 > 150 USDC")
+        );
+        assert_eq!(
+            NotionalLimits::default().violation([("BTC", 60_000.0, 10.0)]),
+            None
+        );
+    }
+
+    #[test]
+    fn ws_reconnect_delay_doubles_up_to_cap() {
+        let config = WsReconnectConfig {
+            initial_delay_ms: 500,
+            max_delay_ms: 3_000,
+            max_attempts: 5,
+        };
+        let delays: Vec<_> = (0..5).map(|attempt| config.delay_ms(attempt)).collect();
+        assert_eq!(delays, vec![500, 1_000, 2_000, 3_000, 3_000]);
+        assert_eq!(config.delay_ms(200), 3_000);
+    }
+
+    /// Each `connect` replays the next scripted session; `None` (or an empty script) fails.
+    /// The sender is kept as the session, so like the SDK the channel never closes by itself.
+    struct ScriptedWs(VecDeque<Option<Vec<Message>>>);
+
+    impl WsConnector for ScriptedWs {
+        type Session = mpsc::UnboundedSender<Message>;
+
+        async fn connect(
+            &mut self,
+            tx: mpsc::UnboundedSender<Message>,
+        ) -> Result<mpsc::UnboundedSender<Message>> {
+            let messages = self
+                .0
+                .pop_front()
+                .flatten()
+                .ok_or_else(|| anyhow!("connection refused"))?;
+            for message in messages {
+                tx.send(message).unwrap();
             }
-            ActionStep::SetLeverage { set_leverage } => {
-                execute_set_leverage(idx, set_leverage, &artifacts, &exchange).await?;
+            Ok(tx)
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn ws_loop_reconnects_on_no_data_then_gives_up() {
+        let fixture = RunFixture::empty("hl-runner-ws-loop");
+        let out_dir = &fixture.dir;
+        let artifacts = Arc::new(Mutex::new(fixture.artifacts(false)));
+        let (broadcaster, mut events) = broadcast::channel::<ObservedEvent>(16);
+        let connector = ScriptedWs(VecDeque::from([
+            Some(vec![Message::Pong, Message::NoData]),
+            None,
+            Some(vec![Message::Pong, Message::NoData]),
+        ]));
+        let config = WsReconnectConfig {
+            initial_delay_ms: 500,
+            max_delay_ms: 3_000,
+            max_attempts: 2,
+        };
+
+        let started = tokio::time::Instant::now();
+        run_ws_loop(
+            connector,
+            config,
+            artifacts,
+            broadcaster,
+            WsChannelFilter::default(),
+            None,
+        )
+        .await;
+
+        // 500 + 1000 before the second session (a message resets the backoff), then
+        // 500 + 1000 for the two failed attempts after it.
+        assert_eq!(started.elapsed(), Duration::from_millis(3_000));
+        let mut received = Vec::new();
+        while let Ok(event) = events.try_recv() {
+            received.push(event);
+        }
+        assert_eq!(received.len(), 3);
+        assert!(matches!(received[0], ObservedEvent::Other { .. }));
+        assert!(matches!(received[1], ObservedEvent::Other { .. }));
+        assert!(received[2].is_ws_disconnect());
+        let ws_stream = std::fs::read_to_string(out_dir.join("ws_stream.jsonl")).unwrap();
+        assert_eq!(ws_stream.lines().count(), 2, "NoData is not logged");
+    }
+
+    #[test]
+    fn ack_assertions_flag_rejected_acks_and_notes() {
+        let record = |step_idx: usize, ack: Value, notes: Option<&str>| ActionLogRecord {
+            notes: notes.map(String::from),
+            ..action_record(step_idx, "perp_orders", json!({}), ack)
+        };
+        let records = vec![
+            record(0, json!({"status": "ok"}), None),
+            record(1, json!({"status": "skipped"}), Some("ws timeout")),
+            record(2, json!({"status": "err", "message": "bad"}), None),
+            record(
+                3,
+                json!({"status": "ok", "data": {"statuses": [{"kind": "error", "message": "px"}]}}),
+                None,
+            ),
+        ];
+
+        let failures = ack_assertion_failures(&records, false);
+        assert_eq!(failures.len(), 2);
+        assert!(failures[0].starts_with("step 2 perp_orders"));
+        assert!(failures[1].starts_with("step 3 perp_orders"));
+
+        let failures = ack_assertion_failures(&records, true);
+        assert_eq!(failures.len(), 3);
+        assert_eq!(failures[0], "step 1 perp_orders: notes ws timeout");
+    }
+
+    #[test]
+    fn signature_assertions_report_missing_and_count() {
+        let found: BTreeSet<String> = ["perp.order.GTC:false:none", "perp.cancel.last"]
+            .into_iter()
+            .map(String::from)
+            .collect();
+        assert!(signature_assertion_failures(
+            &found,
+            &["PERP.ORDER.gtc:false:none".to_string()],
+            Some(2)
+        )
+        .is_empty());
+
+        let failures =
+            signature_assertion_failures(&found, &["perp.cancel.all".to_string()], Some(3));
+        assert_eq!(
+            failures,
+            vec![
+                "missing signature perp.cancel.all".to_string(),
+                "2 unique signatures < required 3".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn tif_override_applies_to_every_client_order() {
+        let step: PerpOrdersStep = serde_json::from_value(json!({
+            "orders": [
+                {"coin": "ETH", "side": "buy", "sz": 0.01, "tif": "GTC", "px": 3000.0},
+                {"coin": "BTC", "side": "sell", "sz": 0.01, "tif": "GTC", "px": 60000.0}
+            ],
+            "timeInForceOverride": "IOC"
+        }))
+        .unwrap();
+
+        let step = step.with_tif_override();
+        assert!(step.time_in_force_override.is_none());
+        for order in &step.orders {
+            let client = build_client_order(order, 100.0).unwrap();
+            match client.order_type {
+                ClientOrder::Limit(limit) => assert_eq!(limit.tif, "Ioc"),
+                other => panic!("unexpected order type {other:?}"),
             }
-            ActionStep::Sleep { sleep_ms } => {
-                tokio::time::sleep(Duration::from_millis(sleep_ms.duration_ms)).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn demo_cancel_oids_skips_untracked_oids() {
+        let mut placed_orders = OrderTracker::default();
+        placed_orders.push("ETH", 1);
+        placed_orders.push("ETH", 2);
+        assert_eq!(
+            partition_tracked(&[1, 12345, 2], &placed_orders),
+            (vec![1, 2], vec![12345])
+        );
+
+        let fixture = RunFixture::empty("hl-runner-cancel");
+        let out_dir = &fixture.dir;
+        let artifacts = Arc::new(Mutex::new(fixture.artifacts(false)));
+        let step: CancelOidsStep =
+            serde_json::from_value(json!({"coin": "ETH", "oids": [1, 12345]})).unwrap();
+        run_demo_cancel_oids(0, &step, &artifacts, &mut placed_orders)
+            .await
+            .expect("cancel logged");
+
+        let per_action = std::fs::read_to_string(out_dir.join("per_action.jsonl")).unwrap();
+        let record: hl_common::ActionLogRecord = serde_json::from_str(per_action.trim()).unwrap();
+        assert_eq!(
+            record.notes.as_deref(),
+            Some("demo mode synthetic execution; OID 12345 not in local tracking, skipped")
+        );
+        let statuses = record.ack.as_ref().unwrap()["data"]["statuses"]
+            .as_array()
+            .unwrap();
+        assert_eq!(statuses.len(), 1);
+        assert_eq!(placed_orders.all(), vec![("ETH".to_string(), 2)]);
+    }
+
+    #[tokio::test]
+    async fn post_run_hook_sees_run_env_and_times_out() {
+        let fixture = RunFixture::empty("hl-runner-hook");
+        let out_dir = &fixture.dir;
+        let cli = Cli::try_parse_from([
+            "hl-runner",
+            "--plan",
+            "plan.json",
+            "--post-run-hook",
+            r#"echo "$HL_RUN_SUCCESS $HL_RUN_STEP_COUNT $EXTRA" > "$HL_RUN_OUT_DIR/hook.txt""#,
+            "--post-run-hook-env",
+            "EXTRA=a=b",
+        ])
+        .unwrap();
+        let mut output = FinalOutput::new(out_dir);
+        output.step_count = 2;
+        run_post_run_hook(&cli, &output, true).await;
+        let written = std::fs::read_to_string(out_dir.join("hook.txt")).unwrap();
+        assert_eq!(written.trim(), "1 2 a=b");
+
+        // The hook backgrounds a grandchild; the timeout must take it down too.
+        let pid_file = out_dir.join("grandchild.pid");
+        let mut sleeper = tokio::process::Command::new("sh");
+        sleeper
+            .arg("-c")
+            .arg(format!("sleep 30 & echo $! > {}; wait", pid_file.display()));
+        let status = wait_for_hook(sleeper, Duration::from_millis(300))
+            .await
+            .unwrap();
+        assert!(status.is_none());
+        #[cfg(target_os = "linux")]
+        {
+            let pid = std::fs::read_to_string(&pid_file).unwrap();
+            let stat = Path::new("/proc").join(pid.trim()).join("stat");
+            let mut alive = true;
+            for _ in 0..20 {
+                // Gone, or a zombie waiting for init to reap it.
+                alive = std::fs::read_to_string(&stat)
+                    .map(|stat| !stat.contains(") Z "))
+                    .unwrap_or(false);
+                if !alive {
+                    break;
+                }
+                tokio::time::sleep(Duration::from_millis(50)).await;
             }
+            assert!(
+                !alive,
+                "grandchild {} survived the hook timeout",
+                pid.trim()
+            );
+        }
+    }
+
+    #[test]
+    fn funding_lookup_errors_become_notes() {
+        let entry = |time: u64, rate: &str| FundingHistoryResponse {
+            coin: "ETH".to_string(),
+            funding_rate: rate.to_string(),
+            premium: "0.0002".to_string(),
+            time,
+        };
+        let ok: Result<_> = Ok(vec![entry(1, "0.0001"), entry(2, "0.0003")]);
+        assert_eq!(
+            latest_funding_rate("ETH", ok),
+            (Some((0.0003, 0.0002)), None)
+        );
+        let empty: Result<_> = Ok(Vec::new());
+        assert_eq!(
+            latest_funding_rate("ETH", empty).1.as_deref(),
+            Some("no funding history for ETH")
+        );
+        let failed: Result<Vec<FundingHistoryResponse>> = Err(anyhow!("HTTP 500"));
+        assert_eq!(
+            latest_funding_rate("ETH", failed),
+            (
+                None,
+                Some("failed to fetch funding history for ETH: HTTP 500".to_string())
+            )
+        );
+    }
+
+    #[test]
+    fn json_output_keeps_human_text_off_stdout() {
+        let (mut stdout, mut stderr) = (Vec::new(), Vec::new());
+        OutputFormat::Json
+            .write_human("PASS: 3 unique signatures", &mut stdout, &mut stderr)
+            .unwrap();
+        assert!(stdout.is_empty());
+        assert_eq!(stderr, b"PASS: 3 unique signatures\n");
+
+        let (mut stdout, mut stderr) = (Vec::new(), Vec::new());
+        OutputFormat::Text
+            .write_human("PASS: 3 unique signatures", &mut stdout, &mut stderr)
+            .unwrap();
+        assert_eq!(stdout, b"PASS: 3 unique signatures\n");
+        assert!(stderr.is_empty());
+    }
+
+    #[test]
+    fn network_trace_needs_an_llm_plan() {
+        let parse = |plan: &str| {
+            Cli::try_parse_from(["hl-runner", "--plan", plan, "--record-network-trace", "t"])
+                .unwrap()
+        };
+        let err = check_network_trace(&parse("plan.json")).unwrap_err();
+        assert!(err.to_string().contains("no --plan is one"));
+        assert!(check_network_trace(&parse("https://example.com/plan.json")).is_err());
+        assert!(check_network_trace(&parse("llm:coverage")).is_ok());
+    }
+
+    #[test]
+    fn max_runs_prunes_only_under_the_runs_root() {
+        assert!(Cli::try_parse_from(["hl-runner", "--plan", "p.json", "--max-runs", "0"]).is_err());
+
+        let fixture = RunFixture::empty("hl-max-runs");
+        let base = &fixture.dir;
+        for name in ["20250101-000000", "20250101-000001"] {
+            std::fs::create_dir_all(base.join(name)).unwrap();
         }
+        let out_dir = base.join("20250101-000001");
+        let out = out_dir.to_str().unwrap();
+        let cli = Cli::try_parse_from(["hl-runner", "--plan", "p.json", "--out", out]).unwrap();
+        prune_sibling_runs(&cli, &out_dir, 1).unwrap();
+        assert!(base.join("20250101-000000").exists());
+
+        // In watch mode --out is the runs root, so its runs are pruned, the current one kept.
+        let base_arg = base.to_str().unwrap();
+        let cli = Cli::try_parse_from([
+            "hl-runner",
+            "--plan",
+            "p.json",
+            "--watch",
+            "--out",
+            base_arg,
+        ])
+        .unwrap();
+        prune_sibling_runs(&cli, &out_dir, 1).unwrap();
+        assert!(!base.join("20250101-000000").exists());
+        assert!(out_dir.exists());
+    }
+
+    #[test]
+    fn discovered_coins_respect_max_and_exclusions() {
+        let meta = || ["BTC", "ETH", "SOL", "ARB"].map(String::from).to_vec();
+
+        assert_eq!(select_coins(meta(), 2, &[]), vec!["BTC", "ETH"]);
+        assert_eq!(select_coins(meta(), 0, &[]), meta());
+        assert_eq!(
+            select_coins(meta(), 2, &["btc".to_string()]),
+            vec!["ETH", "SOL"]
+        );
+        let everything = meta()
+            .iter()
+            .map(|coin| coin.to_lowercase())
+            .collect::<Vec<_>>();
+        assert_eq!(select_coins(meta(), 0, &everything), vec!["ETH"]);
+    }
+
+    #[test]
+    fn plan_is_optional_only_for_coinlist_from_meta() {
+        let cli = Cli::try_parse_from(["hl-runner", "--coinlist-from-meta"]).unwrap();
+        assert!(cli.coinlist_from_meta);
+        assert!(cli.plan.is_empty());
+        assert!(Cli::try_parse_from(["hl-runner"]).is_err());
+
+        let cli = Cli::try_parse_from(["hl-runner", "--plan", "plan.json"]).unwrap();
+        assert_eq!(cli.max_coins, 5);
+        assert!(cli.exclude_coins.is_none());
+    }
+
+    #[tokio::test]
+    async fn resolve_plan_fetches_url_specs() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("GET", "/plan.json")
+            .with_body(r#"{"steps":[{"set_leverage":{"coin":"ETH","leverage":3}}]}"#)
+            .create_async()
+            .await;
+        let spec = format!("{}/plan.json", server.url());
+        let cli = Cli::try_parse_from(["hl-runner", "--plan", &spec, "--plan-timeout-secs", "5"])
+            .unwrap();
+
+        let source = resolve_plan(&cli, cli.network.base_url()).await.unwrap();
+        assert_eq!(source.plan.steps.len(), 1);
+        assert_eq!(source.plan.steps[0].kind(), "set_leverage");
+        assert!(source.raw.is_none());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn demo_run_writes_parquet_only_when_asked() {
+        let fixture = RunFixture::empty("hl-runner-parquet");
+        let out_dir = fixture.dir.join("run");
+        let plan_path = fixture.write(
+            "plan.json",
+            r#"{"steps":[{"perp_orders":{"orders":[{"coin":"ETH","side":"buy","sz":0.01,"px":3000}]}}]}"#,
+        );
+        let cli = Cli::try_parse_from([
+            "hl-runner",
+            "--plan",
+            plan_path.to_str().unwrap(),
+            "--demo",
+            "--routed-format",
+            "parquet",
+        ])
+        .unwrap();
+
+        let mut output = FinalOutput::new(&out_dir);
+        run_once(&cli, "ts", &out_dir, &mut output).await.unwrap();
+        assert!(out_dir.join("routed_orders.parquet").exists());
+        assert!(!out_dir.join("orders_routed.csv").exists());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn failed_run_still_writes_parquet() {
+        let fixture = RunFixture::empty("hl-runner-parquet-fail");
+        let out_dir = fixture.dir.join("run");
+        // The second step fails: the price file has no BTC mid.
+        let plan_path = fixture.write(
+            "plan.json",
+            r#"{"steps":[{"perp_orders":{"orders":[{"coin":"ETH","side":"buy","sz":0.01,"px":3000}]}},{"perp_orders":{"orders":[{"coin":"BTC","side":"buy","sz":0.01,"px":60000}]}}]}"#,
+        );
+        let prices_path = fixture.write("prices.json", r#"{"ETH":3000.0}"#);
+        let cli = Cli::try_parse_from([
+            "hl-runner",
+            "--plan",
+            plan_path.to_str().unwrap(),
+            "--demo",
+            "--coinprice-source",
+            prices_path.to_str().unwrap(),
+            "--routed-format",
+            "parquet",
+        ])
+        .unwrap();
+
+        let mut output = FinalOutput::new(&out_dir);
+        let err = run_once(&cli, "ts", &out_dir, &mut output)
+            .await
+            .unwrap_err();
+        assert!(format!("{err:#}").contains("no price for BTC"), "{err:#}");
+        assert!(out_dir.join("routed_orders.parquet").exists());
+        assert!(!out_dir.join("orders_routed.csv").exists());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn demo_run_with_sqlite_backend_fills_database() {
+        let fixture = RunFixture::empty("hl-runner-sqlite");
+        let out_dir = fixture.dir.join("run");
+        let plan_path = fixture.write(
+            "plan.json",
+            r#"{"steps":[{"perp_orders":{"orders":[{"coin":"ETH","side":"buy","sz":0.01,"px":3000}]}}]}"#,
+        );
+        let cli = Cli::try_parse_from([
+            "hl-runner",
+            "--plan",
+            plan_path.to_str().unwrap(),
+            "--demo",
+            "--artifact-backend",
+            "sqlite",
+        ])
+        .unwrap();
+
+        let mut output = FinalOutput::new(&out_dir);
+        run_once(&cli, "ts", &out_dir, &mut output).await.unwrap();
+        assert!(!out_dir.join("per_action.jsonl").exists());
+        let db =
+            hl_common::artifact_db::ArtifactDb::open(&out_dir.join("run_artifacts.db")).unwrap();
+        assert_eq!(db.action_records().unwrap()[0].action, "perp_orders");
+        assert_eq!(db.routed_orders().unwrap().len(), 1);
+        assert_eq!(db.meta_value("demoMode").unwrap(), Some(json!(true)));
+        assert!(Cli::try_parse_from([
+            "hl-runner",
+            "--plan",
+            "p.json",
+            "--artifact-backend",
+            "sqlite",
+            "--assert-no-errors",
+        ])
+        .is_err());
+    }
+
+    #[tokio::test]
+    async fn resolve_plan_fills_plan_vars() {
+        let fixture = RunFixture::empty("hl-runner-vars");
+        let path = fixture.write(
+            "plan.json",
+            r#"{"steps":[{"set_leverage":{"coin":"{{COIN}}","leverage":{{LEV}}}}]}"#,
+        );
+        let spec = path.to_str().unwrap();
+        let cli = Cli::try_parse_from([
+            "hl-runner",
+            "--plan",
+            spec,
+            "--plan-var",
+            "COIN=BTC",
+            "--plan-var",
+            "LEV=5",
+        ])
+        .unwrap();
+        let source = resolve_plan(&cli, cli.network.base_url()).await.unwrap();
+        assert_eq!(source.plan.steps[0].coins(), vec!["BTC"]);
+
+        let cli = Cli::try_parse_from(["hl-runner", "--plan", spec]).unwrap();
+        let err = resolve_plan(&cli, cli.network.base_url())
+            .await
+            .err()
+            .unwrap();
+        assert!(format!("{err:#}").contains("{{COIN}}, {{LEV}}"), "{err:#}");
+    }
+
+    #[tokio::test]
+    async fn event_filter_keeps_channel_out_of_ws_stream() {
+        let fixture = RunFixture::empty("hl-runner-event-filter");
+        let out_dir = &fixture.dir;
+        let artifacts = Arc::new(Mutex::new(fixture.artifacts(false)));
+        let (broadcaster, _) = broadcast::channel::<ObservedEvent>(4);
+        let filter = WsChannelFilter {
+            channels: HashSet::from(["orderUpdates".to_string()]),
+            invert: false,
+        };
+        let message = || Message::OrderUpdates(hyperliquid_rust_sdk::OrderUpdates { data: vec![] });
+        handle_ws_message(&artifacts, &broadcaster, &filter, None, message())
+            .await
+            .unwrap();
+        drop(artifacts);
+        let ws_stream = std::fs::read_to_string(out_dir.join("ws_stream.jsonl")).unwrap();
+        assert!(ws_stream.is_empty());
+
+        let inverted = WsChannelFilter {
+            invert: true,
+            ..filter
+        };
+        assert!(inverted.should_log(&json!({"channel": "orderUpdates"})));
+        assert!(!inverted.should_log(&json!({"channel": "userFills"})));
+        assert!(WsChannelFilter::default().should_log(&json!({"channel": "userFills"})));
+    }
+
+    #[test]
+    fn builder_code_precedence_is_order_coin_step_global() {
+        let codes = BuilderCodes {
+            global: Some("0xglobal".to_string()),
+            per_coin: parse_builder_code_per_coin("eth:0xcoin, BTC:0xbtc").unwrap(),
+        };
+        let step: PerpOrdersStep = serde_json::from_value(json!({
+            "builderCode": "0xstep",
+            "orders": [
+                {"coin": "ETH", "side": "buy", "sz": 0.1, "px": 3000, "builderCode": "0xorder"},
+                {"coin": "ETH", "side": "buy", "sz": 0.1, "px": 3000},
+                {"coin": "SOL", "side": "buy", "sz": 1.0, "px": 150}
+            ]
+        }))
+        .unwrap();
+
+        let step_code = codes.step_code(&step);
+        assert_eq!(step_code.as_deref(), Some("0xstep"));
+        let resolved: Vec<_> = step
+            .orders
+            .iter()
+            .map(|order| codes.order_code(order, step_code.as_deref()))
+            .collect();
+        assert_eq!(
+            resolved,
+            vec![
+                Some("0xorder".to_string()),
+                Some("0xcoin".to_string()),
+                Some("0xstep".to_string())
+            ]
+        );
+
+        let no_step = PerpOrdersStep {
+            builder_code: None,
+            ..step.clone()
+        };
+        assert_eq!(codes.step_code(&no_step).as_deref(), Some("0xglobal"));
+        assert!(parse_builder_code_per_coin("ETH").is_err());
+    }
+
+    #[test]
+    fn balance_shortfall_reports_available_and_required() {
+        assert_eq!(
+            balance_shortfall("perp", 0.5, 10.0).as_deref(),
+            Some("insufficient perp balance: 0.5 USDC < required 10.0 USDC")
+        );
+        assert_eq!(balance_shortfall("spot", 10.0, 10.0), None);
+
+        assert_eq!(parse_balance("12.5", "perp balance").unwrap(), 12.5);
+        let err = parse_balance("", "perp withdrawable balance").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid perp withdrawable balance \"\" from the info API"
+        );
     }
 
-    Ok(())
-}
+    #[test]
+    fn order_tracker_preserves_insertion_order_per_coin() {
+        let mut tracker = OrderTracker::default();
+        tracker.push("ETH", 1);
+        tracker.push("BTC", 2);
+        tracker.push("ETH", 3);
+
+        assert_eq!(tracker.all_for_coin("ETH"), vec![1, 3]);
+        assert_eq!(tracker.last_any(), Some(("ETH".to_string(), 3)));
+        assert_eq!(tracker.nth(0), Some(("ETH".to_string(), 1)));
+        assert_eq!(tracker.nth(-2), Some(("BTC".to_string(), 2)));
+        assert_eq!(tracker.nth(3), None);
+        assert_eq!(tracker.nth(-4), None);
+        assert_eq!(tracker.pop_last("BTC"), Some(2));
+        assert_eq!(tracker.pop_last_any(), Some(("ETH".to_string(), 3)));
+        assert!(tracker.remove(1));
+        assert!(!tracker.remove(1));
+        assert!(tracker.all().is_empty());
+        assert_eq!(tracker.last("ETH"), None);
+    }
 
-#[allow(clippy::too_many_arguments)]
-async fn execute_perp_orders(
-    step_idx: usize,
-    step: &PerpOrdersStep,
-    artifacts: &Arc<Mutex<RunArtifacts>>,
-    exchange: &ExchangeClient,
-    info_http: &mut InfoClient,
-    mid_cache: &mut HashMap<String, f64>,
-    placed_orders: &mut VecDeque<PlacedOrder>,
-    broadcaster: &broadcast::Sender<ObservedEvent>,
-    default_builder: Option<&str>,
-    effect_timeout_ms: u64,
-) -> Result<()> {
-    if step.orders.is_empty() {
-        return Ok(());
+    struct CountingMids {
+        calls: usize,
     }
 
-    let submit_ts = timestamp_ms();
-    let mut client_orders = Vec::with_capacity(step.orders.len());
-    let mut resolved_prices = Vec::with_capacity(step.orders.len());
+    impl MidSource for CountingMids {
+        async fn all_mids(&mut self) -> Result<HashMap<String, String>> {
+            self.calls += 1;
+            Ok(HashMap::from([(
+                "ETH".to_string(),
+                format!("{}", 3000 + self.calls),
+            )]))
+        }
 
-    for order in &step.orders {
-        let limit_px = resolve_limit_price(order, info_http, mid_cache).await?;
-        resolved_prices.push(limit_px);
-        client_orders.push(build_client_order(order, limit_px)?);
+        async fn sz_decimals(&mut self) -> Result<HashMap<String, u32>> {
+            Ok(HashMap::from([("ETH".to_string(), 4)]))
+        }
+
+        async fn best_bid_ask(&mut self, _coin: &str) -> Result<(f64, f64)> {
+            self.calls += 1;
+            Ok((2990.0, 3010.0))
+        }
     }
 
-    let builder_code = step
-        .builder_code
-        .as_deref()
-        .or(default_builder)
-        .map(|code| code.to_string());
+    #[tokio::test]
+    async fn injected_sleep_skips_last_step_and_zero() {
+        assert!(inject_step_sleep(0, 2, 1).await);
+        assert!(!inject_step_sleep(1, 2, 1).await);
+        assert!(!inject_step_sleep(0, 2, 0).await);
+    }
 
-    let mut receiver = broadcaster.subscribe();
+    #[tokio::test]
+    async fn pooled_clients_share_connections() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let accepted = Arc::new(AtomicUsize::new(0));
+        let counter = accepted.clone();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                counter.fetch_add(1, Ordering::SeqCst);
+                tokio::spawn(async move {
+                    let mut buf = [0u8; 4096];
+                    while let Ok(n) = socket.read(&mut buf).await {
+                        if n == 0 {
+                            break;
+                        }
+                        let reply = b"HTTP/1.1 200 OK\r\ncontent-length: 2\r\n\r\n{}";
+                        if socket.write_all(reply).await.is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+        });
 
-    let response = match (builder_code.clone(), client_orders) {
-        (Some(code), orders) => {
-            let builder = BuilderInfo {
-                builder: code.to_lowercase(),
-                fee: 0,
-            };
-            exchange
-                .bulk_order_with_builder(orders, None, builder)
+        let http = build_http_client(4, &TlsConfig::default()).unwrap();
+        let (info, exchange) = (http.clone(), http);
+        for client in [&info, &exchange, &info] {
+            let res = client
+                .get(format!("http://{addr}/info"))
+                .send()
                 .await
+                .unwrap();
+            assert_eq!(res.text().await.unwrap(), "{}");
         }
-        (None, orders) => exchange.bulk_order(orders, None).await,
+        assert_eq!(accepted.load(Ordering::SeqCst), 1);
     }
-    .context("failed to post perp orders")?;
 
-    let ack_value = exchange_status_json(&response);
-    let ack_oids = extract_oids(&response);
-    let mut per_order_oid: Vec<Option<u64>> = step
-        .orders
-        .iter()
-        .enumerate()
-        .map(|(idx, _)| ack_oids.get(idx).copied())
-        .collect();
+    #[test]
+    fn coinbook_supplies_allowed_coins() {
+        let fixture = RunFixture::empty("hl-coinbook");
+        let path = fixture.write(
+            "meta.json",
+            r#"{"universe": [
+                {"name": "BTC", "szDecimals": 5, "maxLeverage": 50},
+                {"name": "ETH", "szDecimals": 4, "maxLeverage": 50},
+                {"name": "SOL", "szDecimals": 2, "maxLeverage": 20},
+                {"name": "HYPE", "szDecimals": 2, "maxLeverage": 10}
+            ]}"#,
+        );
+        let coinbook = Coinbook::load(&path).unwrap();
+        assert_eq!(
+            coinbook.coins_used(2, &["eth".to_string()]),
+            vec!["BTC", "SOL"]
+        );
+        assert_eq!(coinbook.coins_used(0, &[]).len(), 4);
 
-    for (idx, maybe_oid) in per_order_oid.iter_mut().enumerate() {
-        if maybe_oid.is_none() {
-            continue;
-        }
-        let oid = maybe_oid.unwrap();
-        placed_orders.push_back(PlacedOrder {
-            coin: step.orders[idx].coin.clone(),
-            oid,
-        });
+        std::fs::write(&path, r#"[{"name": "DOGE", "szDecimals": 0}]"#).unwrap();
+        assert_eq!(
+            Coinbook::load(&path).unwrap().coins_used(5, &[]),
+            vec!["DOGE"]
+        );
     }
 
-    let mut routed_records = Vec::new();
-    for ((order, limit_px), maybe_oid) in step
-        .orders
-        .iter()
-        .zip(resolved_prices.iter())
-        .zip(per_order_oid.iter().cloned())
-    {
-        let builder = order.builder_code.clone().or_else(|| builder_code.clone());
-        routed_records.push(RoutedOrderRecord {
-            ts_ms: submit_ts,
-            oid: maybe_oid,
-            coin: order.coin.clone(),
-            side: if order.is_buy() {
-                "buy".to_string()
-            } else {
-                "sell".to_string()
-            },
-            px: *limit_px,
-            sz: order.sz,
-            tif: order.tif.as_sdk_str().to_string(),
-            reduce_only: order.reduce_only,
-            builder_code: builder,
-        });
+    #[tokio::test]
+    async fn price_file_resolves_mid_percent_orders() {
+        let fixture = RunFixture::empty("hl-prices");
+        let path = fixture.write("prices.json", r#"{"BTC": 60000.0, "ETH": 3500}"#);
+        let mut prices = PriceFile::load(&path).unwrap();
+        let mut cache = MidCache::new(5_000);
+
+        let order: PerpOrder = serde_json::from_value(json!({
+            "coin": "ETH", "side": "buy", "sz": 0.01, "px": "mid-1%"
+        }))
+        .unwrap();
+        let px = resolve_limit_price(&order, &mut prices, &mut cache)
+            .await
+            .unwrap();
+        assert!((px - 3465.0).abs() < 1e-9);
+
+        let missing: PerpOrder = serde_json::from_value(json!({
+            "coin": "SOL", "side": "buy", "sz": 1.0, "px": "mid+0%"
+        }))
+        .unwrap();
+        assert!(resolve_limit_price(&missing, &mut prices, &mut cache)
+            .await
+            .is_err());
+        assert!(demo_mid(Some(&prices), "SOL").is_err());
+        assert_eq!(demo_mid(Some(&prices), "BTC").unwrap(), 60000.0);
     }
 
-    let mut observed_events = Vec::new();
-    let mut missing = Vec::new();
-    if !ack_oids.is_empty() {
-        for maybe_oid in per_order_oid.iter().flatten() {
-            let wait = Duration::from_millis(effect_timeout_ms);
-            match wait_for_order_event(&mut receiver, *maybe_oid, wait).await {
-                Some(event) => observed_events.push(event.payload().clone()),
-                None => missing.push(*maybe_oid),
-            }
-        }
+    #[tokio::test]
+    async fn snap_to_tick_rounds_resolved_price() {
+        let order: PerpOrder = serde_json::from_value(json!({
+            "coin": "ETH", "side": "buy", "sz": 0.01, "px": "mid+0.0123%", "snapToTick": true
+        }))
+        .unwrap();
+        let mut source = CountingMids { calls: 0 };
+        let mut cache = MidCache::new(5_000);
+
+        // 3001 * 1.000123 = 3001.369..., five significant figures -> tick 0.1.
+        let px = resolve_limit_price(&order, &mut source, &mut cache)
+            .await
+            .unwrap();
+        assert_eq!(px, 3001.4);
+
+        let fixture = RunFixture::empty("hl-snap");
+        let path = fixture.write("prices.json", r#"{"ETH": 3001}"#);
+        let mut prices = PriceFile::load(&path).unwrap();
+        let err = resolve_limit_price(&order, &mut prices, &mut MidCache::new(5_000))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("no szDecimals known for ETH"));
+        std::fs::write(&path, r#"[{"name": "ETH", "szDecimals": 4}]"#).unwrap();
+        let mut prices = prices.with_coinbook(&Coinbook::load(&path).unwrap());
+        let px = resolve_limit_price(&order, &mut prices, &mut MidCache::new(5_000))
+            .await
+            .unwrap();
+        assert_eq!(px, 3001.4);
+
+        assert_eq!(perp_price_decimals(4, 3001.0), 1);
+        assert_eq!(perp_price_decimals(5, 65_000.0), 0);
+        assert_eq!(perp_price_decimals(2, 0.5), 4);
     }
 
-    let observed_value = if observed_events.is_empty() {
-        None
-    } else {
-        Some(serde_json::Value::Array(observed_events))
-    };
+    #[tokio::test]
+    async fn mid_cache_refetches_after_ttl() {
+        let order: PerpOrder = serde_json::from_value(json!({
+            "coin": "ETH", "side": "buy", "sz": 0.01, "px": "mid+0%"
+        }))
+        .unwrap();
+        let mut source = CountingMids { calls: 0 };
+        let now = Arc::new(std::sync::Mutex::new(Instant::now()));
+        let mut cache = MidCache::new(20).with_clock(Clock::Manual(now.clone()));
+
+        let first = resolve_limit_price(&order, &mut source, &mut cache)
+            .await
+            .unwrap();
+        let cached = resolve_limit_price(&order, &mut source, &mut cache)
+            .await
+            .unwrap();
+        assert_eq!(source.calls, 1);
+        assert_eq!(first, cached);
 
-    let notes = if missing.is_empty() {
-        None
-    } else {
-        Some(format!("no websocket confirmation for oids: {:?}", missing))
-    };
+        *now.lock().unwrap() += Duration::from_millis(20);
+        resolve_limit_price(&order, &mut source, &mut cache)
+            .await
+            .unwrap();
+        assert_eq!(source.calls, 1, "an entry exactly ttl old is still fresh");
 
-    let request_orders: Vec<_> = step
-        .orders
-        .iter()
-        .zip(resolved_prices.iter())
-        .map(|(order, limit_px)| {
-            json!({
-                "coin": order.coin,
-                "side": if order.is_buy() { "buy" } else { "sell" },
-                "sz": order.sz,
-                "tif": order.tif.as_sdk_str(),
-                "reduceOnly": order.reduce_only,
-                "builderCode": order.builder_code,
-                "px": order_price_label(&order.px),
-                "resolvedPx": limit_px,
-                "trigger": "none",
-            })
-        })
-        .collect();
-    let mut request_value = json!({
-        "perp_orders": {
-            "orders": request_orders,
+        *now.lock().unwrap() += Duration::from_millis(1);
+        let refreshed = resolve_limit_price(&order, &mut source, &mut cache)
+            .await
+            .unwrap();
+        assert_eq!(source.calls, 2);
+        assert!(refreshed > first);
+    }
+
+    /// Replays canned responses and records how many orders (and which cloids) each post
+    /// carried.
+    struct ScriptedPoster {
+        responses: std::sync::Mutex<VecDeque<Result<ExchangeResponseStatus>>>,
+        sent: std::sync::Mutex<Vec<usize>>,
+        cloids: std::sync::Mutex<Vec<Vec<Option<Uuid>>>>,
+        builders: std::sync::Mutex<Vec<Option<String>>>,
+    }
+
+    impl ScriptedPoster {
+        fn new(responses: Vec<Result<ExchangeResponseStatus>>) -> Self {
+            Self {
+                responses: std::sync::Mutex::new(responses.into()),
+                sent: std::sync::Mutex::new(Vec::new()),
+                cloids: std::sync::Mutex::new(Vec::new()),
+                builders: std::sync::Mutex::new(Vec::new()),
+            }
         }
-    });
-    if let Some(code) = &builder_code {
-        request_value["perp_orders"]["builderCode"] = json!(code);
     }
 
-    {
-        let mut artifacts = artifacts.lock().await;
-        let record = artifacts.make_action_record(
-            step_idx,
-            "perp_orders",
-            submit_ts,
-            request_value,
-            Some(ack_value),
-            observed_value,
-            notes,
-        );
-        artifacts.log_action(&record)?;
-        for record in routed_records {
-            artifacts.log_routed_order(&record)?;
+    impl OrderPoster for ScriptedPoster {
+        async fn post_orders(
+            &self,
+            orders: Vec<ClientOrderRequest>,
+            builder: Option<BuilderInfo>,
+        ) -> Result<ExchangeResponseStatus> {
+            self.sent.lock().unwrap().push(orders.len());
+            self.builders
+                .lock()
+                .unwrap()
+                .push(builder.map(|builder| builder.builder));
+            self.cloids
+                .lock()
+                .unwrap()
+                .push(orders.iter().map(|order| order.cloid).collect());
+            self.responses
+                .lock()
+                .unwrap()
+                .pop_front()
+                .expect("unexpected post")
         }
     }
 
-    Ok(())
-}
+    fn order_response(statuses: Vec<ExchangeDataStatus>) -> Result<ExchangeResponseStatus> {
+        Ok(ExchangeResponseStatus::Ok(ExchangeResponse {
+            response_type: "order".to_string(),
+            data: Some(ExchangeDataStatuses { statuses }),
+        }))
+    }
 
-async fn execute_cancel_last(
-    step_idx: usize,
-    step: &CancelLastStep,
-    artifacts: &Arc<Mutex<RunArtifacts>>,
-    exchange: &ExchangeClient,
-    placed_orders: &mut VecDeque<PlacedOrder>,
-    broadcaster: &broadcast::Sender<ObservedEvent>,
-    effect_timeout_ms: u64,
-) -> Result<()> {
-    let target = if let Some(coin) = &step.coin {
-        placed_orders
-            .iter()
-            .rfind(|order| &order.coin == coin)
-            .cloned()
-    } else {
-        placed_orders.back().cloned()
-    };
+    fn resting(oid: u64) -> ExchangeDataStatus {
+        ExchangeDataStatus::Resting(RestingOrder { oid })
+    }
 
-    let mut notes = None;
-    let mut observed_value = None;
-    let submit_ts = timestamp_ms();
-    let mut ack_value = json!({ "status": "skipped" });
+    fn retry_step(orders: usize, max_attempts: u32) -> PerpOrdersStep {
+        assign_retry_cloids(
+            serde_json::from_value(json!({
+                "orders": (0..orders)
+                    .map(|_| json!({"coin": "ETH", "side": "buy", "sz": 0.01, "px": 3000}))
+                    .collect::<Vec<_>>(),
+                "retry": {"maxAttempts": max_attempts, "initialDelayMs": 1}
+            }))
+            .unwrap(),
+        )
+    }
 
-    if let Some(target_order) = target {
-        let mut receiver = broadcaster.subscribe();
-        let request = ClientCancelRequest {
-            asset: target_order.coin.clone(),
-            oid: target_order.oid,
+    fn timed_out() -> Result<ExchangeResponseStatus> {
+        Err(hyperliquid_rust_sdk::Error::GenericRequest("operation timed out".to_string()).into())
+    }
+
+    #[tokio::test]
+    async fn order_post_retries_until_success() {
+        let poster = ScriptedPoster::new(vec![
+            timed_out(),
+            Ok(ExchangeResponseStatus::Err("rate limited".to_string())),
+            order_response(vec![resting(7), resting(8)]),
+        ]);
+        let step = retry_step(2, 3);
+        let (posted, _) = post_step_orders(0, &step, &[3000.0, 3000.0], &[], &poster)
+            .await
+            .unwrap();
+        assert_eq!(posted.attempts, 3);
+        assert_eq!(posted.oids(), vec![Some(7), Some(8)]);
+        assert_eq!(posted.ack()["data"]["statuses"][1]["kind"], "resting");
+        assert_eq!(*poster.sent.lock().unwrap(), vec![2, 2, 2]);
+        // Re-sent orders keep their cloids, so a timed-out order is not placed twice.
+        let cloids = poster.cloids.lock().unwrap().clone();
+        assert!(cloids[0].iter().all(Option::is_some));
+        assert!(cloids.iter().all(|sent| *sent == cloids[0]));
+
+        let poster = ScriptedPoster::new(vec![timed_out()]);
+        let step = PerpOrdersStep {
+            retry: None,
+            ..retry_step(1, 1)
         };
-        let response = exchange
-            .cancel(request, None)
+        assert!(post_step_orders(0, &step, &[3000.0], &[], &poster)
             .await
-            .context("failed to cancel order")?;
-        ack_value = exchange_status_json(&response);
-        if matches!(response, ExchangeResponseStatus::Ok(_)) {
-            placed_orders.retain(|order| order.oid != target_order.oid);
-
-            let wait = Duration::from_millis(effect_timeout_ms);
-            if let Some(event) = wait_for_order_event(&mut receiver, target_order.oid, wait).await {
-                observed_value = Some(event.payload().clone());
-            } else {
-                notes = Some(format!(
-                    "no cancel confirmation for oid {}",
-                    target_order.oid
-                ));
-            }
-        } else {
-            notes = Some("cancel request rejected".to_string());
-        }
-    } else {
-        notes = Some("no tracked order available for cancel_last".to_string());
+            .is_err());
     }
 
-    let request_value = json!({
-        "cancel_last": {
-            "coin": step.coin,
-        }
-    });
-
-    {
-        let mut artifacts = artifacts.lock().await;
-        let record = artifacts.make_action_record(
-            step_idx,
-            "cancel_last",
-            submit_ts,
-            request_value,
-            Some(ack_value),
-            observed_value,
-            notes,
+    #[tokio::test]
+    async fn order_post_does_not_retry_deterministic_failures() {
+        // Not a transport error (e.g. signing failed): the same request would fail again.
+        let poster = ScriptedPoster::new(vec![Err(anyhow!("failed to sign"))]);
+        assert!(
+            post_step_orders(0, &retry_step(1, 3), &[3000.0], &[], &poster)
+                .await
+                .is_err()
         );
-        artifacts.log_action(&record)?;
-    }
+        assert_eq!(*poster.sent.lock().unwrap(), vec![1]);
 
-    Ok(())
-}
+        let poster = ScriptedPoster::new(vec![Ok(ExchangeResponseStatus::Err(
+            "invalid signature".to_string(),
+        ))]);
+        let (posted, _) = post_step_orders(0, &retry_step(1, 3), &[3000.0], &[], &poster)
+            .await
+            .unwrap();
+        assert_eq!(posted.rejected.as_deref(), Some("invalid signature"));
+        assert_eq!(posted.attempts, 1);
+    }
 
-#[allow(clippy::too_many_arguments)]
-async fn execute_cancel_oids(
-    step_idx: usize,
-    step: &CancelOidsStep,
-    artifacts: &Arc<Mutex<RunArtifacts>>,
-    exchange: &ExchangeClient,
-    placed_orders: &mut VecDeque<PlacedOrder>,
-    broadcaster: &broadcast::Sender<ObservedEvent>,
-    effect_timeout_ms: u64,
-) -> Result<()> {
-    if step.oids.is_empty() {
-        return Ok(());
+    #[tokio::test]
+    async fn order_post_retry_skips_resting_orders() {
+        let poster = ScriptedPoster::new(vec![
+            order_response(vec![
+                resting(1),
+                ExchangeDataStatus::Error("Too many cumulative requests sent".to_string()),
+                ExchangeDataStatus::Error("insufficient margin".to_string()),
+            ]),
+            order_response(vec![ExchangeDataStatus::Error(
+                "Too many cumulative requests sent".to_string(),
+            )]),
+        ]);
+        let step = retry_step(3, 2);
+        let (posted, _) = post_step_orders(0, &step, &[3000.0; 3], &[], &poster)
+            .await
+            .unwrap();
+        assert_eq!(posted.attempts, 2);
+        // Only the rate-limited order is re-sent; insufficient margin is final.
+        assert_eq!(*poster.sent.lock().unwrap(), vec![3, 1]);
+        assert_eq!(posted.oids(), vec![Some(1), None, None]);
+        let ack = posted.ack();
+        let kinds: Vec<&str> = ack["data"]["statuses"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|status| status["kind"].as_str().unwrap())
+            .collect();
+        assert_eq!(kinds, vec!["resting", "error", "error"]);
     }
 
-    let submit_ts = timestamp_ms();
-    let mut receiver = broadcaster.subscribe();
-    let cancels: Vec<ClientCancelRequest> = step
-        .oids
-        .iter()
-        .map(|oid| ClientCancelRequest {
-            asset: step.coin.clone(),
-            oid: *oid,
-        })
-        .collect();
+    fn parallel_step() -> PerpOrdersStep {
+        serde_json::from_value(json!({
+            "orders": [
+                {"coin": "ETH", "side": "buy", "sz": 0.01, "px": 3000},
+                {"coin": "BTC", "side": "buy", "sz": 0.001, "px": 60000},
+                {"coin": "ETH", "side": "sell", "sz": 0.01, "px": 3100}
+            ],
+            "parallel": true
+        }))
+        .unwrap()
+    }
 
-    let response = exchange
-        .bulk_cancel(cancels, None)
+    #[tokio::test]
+    async fn parallel_step_merges_sub_batches_in_input_order() {
+        let fixture = RunFixture::empty("hl-runner-par");
+        let artifacts = Arc::new(Mutex::new(fixture.artifacts(false)));
+        // Sub-batches post in first-seen coin order: ETH (orders 0 and 2), then BTC.
+        let poster = ScriptedPoster::new(vec![
+            order_response(vec![resting(11), resting(12)]),
+            order_response(vec![resting(20)]),
+        ]);
+        let (broadcaster, _) = broadcast::channel(8);
+        let mut placed_orders = OrderTracker::default();
+
+        execute_perp_orders(
+            0,
+            &parallel_step(),
+            OrderSource::Perp,
+            &artifacts,
+            &poster,
+            &mut CountingMids { calls: 0 },
+            &mut MidCache::new(5_000),
+            &mut placed_orders,
+            &broadcaster,
+            &BuilderCodes::default(),
+            0,
+            NotionalLimits::default(),
+        )
         .await
-        .context("failed to cancel specified oids")?;
-    let ack_value = exchange_status_json(&response);
-    let success = matches!(response, ExchangeResponseStatus::Ok(_));
+        .unwrap();
 
-    let (observed_value, notes) = if success {
-        remove_tracked_oids(placed_orders, &step.oids);
+        assert_eq!(*poster.sent.lock().unwrap(), vec![2, 1]);
+        assert_eq!(placed_orders.all_for_coin("ETH"), vec![11, 12]);
+        assert_eq!(placed_orders.all_for_coin("BTC"), vec![20]);
 
-        let mut observed = Vec::new();
-        let mut missing = Vec::new();
-        let wait = Duration::from_millis(effect_timeout_ms);
-        for oid in &step.oids {
-            match wait_for_order_event(&mut receiver, *oid, wait).await {
-                Some(event) => observed.push(event.payload().clone()),
-                None => missing.push(*oid),
-            }
-        }
-        let observed_value = if observed.is_empty() {
-            None
-        } else {
-            Some(serde_json::Value::Array(observed))
+        let artifacts = artifacts.lock().await;
+        let record = artifacts.last_action().unwrap();
+        let oids: Vec<u64> = record.ack.as_ref().unwrap()["data"]["statuses"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|status| status["oid"].as_u64().unwrap())
+            .collect();
+        assert_eq!(oids, vec![11, 20, 12]);
+        assert!(record
+            .notes
+            .as_deref()
+            .unwrap()
+            .contains("parallel: 2 sub-batches, max sub-batch latency"));
+    }
+
+    #[tokio::test]
+    async fn parallel_step_keeps_sub_batches_that_succeeded() {
+        // ETH (orders 0 and 2) fails outright, BTC rests.
+        let poster = ScriptedPoster::new(vec![
+            Err(anyhow!("connection reset")),
+            order_response(vec![resting(20)]),
+        ]);
+        let (posted, note) = post_step_orders(0, &parallel_step(), &[3000.0; 3], &[], &poster)
+            .await
+            .unwrap();
+        assert_eq!(posted.oids(), vec![None, Some(20), None]);
+        let ack = posted.ack();
+        assert_eq!(ack["status"], "ok");
+        assert_eq!(ack["data"]["statuses"][0]["kind"], "error");
+        assert_eq!(
+            ack["data"]["statuses"][2]["message"],
+            "failed to post perp orders: connection reset"
+        );
+        assert!(note.unwrap().contains("(1 failed)"));
+
+        let poster = ScriptedPoster::new(vec![
+            Err(anyhow!("connection reset")),
+            Err(anyhow!("connection reset")),
+        ]);
+        assert!(
+            post_step_orders(0, &parallel_step(), &[3000.0; 3], &[], &poster)
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn mixed_builder_codes_post_one_bulk_order_each() {
+        let step: PerpOrdersStep = serde_json::from_value(json!({
+            "orders": [
+                {"coin": "ETH", "side": "buy", "sz": 0.01, "px": 3000},
+                {"coin": "BTC", "side": "buy", "sz": 0.01, "px": 3000},
+                {"coin": "SOL", "side": "buy", "sz": 0.01, "px": 3000}
+            ]
+        }))
+        .unwrap();
+        let builders = [
+            Some("0xAAA".to_string()),
+            Some("0xbbb".to_string()),
+            Some("0xAAA".to_string()),
+        ];
+        let poster = ScriptedPoster::new(vec![
+            order_response(vec![resting(1), resting(3)]),
+            order_response(vec![resting(2)]),
+        ]);
+        let (posted, note) = post_step_orders(0, &step, &[3000.0; 3], &builders, &poster)
+            .await
+            .unwrap();
+        assert_eq!(posted.oids(), vec![Some(1), Some(2), Some(3)]);
+        assert_eq!(*poster.sent.lock().unwrap(), vec![2, 1]);
+        assert_eq!(
+            *poster.builders.lock().unwrap(),
+            vec![Some("0xaaa".to_string()), Some("0xbbb".to_string())]
+        );
+        assert_eq!(
+            note.as_deref(),
+            Some("split by builder code: 2 sub-batches")
+        );
+    }
+
+    #[tokio::test]
+    async fn demo_parallel_step_numbers_oids_per_sub_batch() {
+        let fixture = RunFixture::empty("hl-runner-par");
+        let artifacts = Arc::new(Mutex::new(fixture.artifacts(false)));
+        let mut placed_orders = OrderTracker::default();
+
+        run_demo_perp_orders(
+            0,
+            &parallel_step(),
+            OrderSource::Perp,
+            &artifacts,
+            &BuilderCodes::default(),
+            None,
+            &mut placed_orders,
+            &mut 1,
+            NotionalLimits::default(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(placed_orders.all_for_coin("ETH"), vec![1, 2]);
+        assert_eq!(placed_orders.all_for_coin("BTC"), vec![3]);
+        let artifacts = artifacts.lock().await;
+        let record = artifacts.last_action().unwrap();
+        assert_eq!(
+            record.ack.as_ref().unwrap()["data"]["statuses"][1]["oid"],
+            3
+        );
+    }
+
+    #[tokio::test]
+    async fn bid_ask_prices_use_cached_top_of_book() {
+        let order = |px: &str| -> PerpOrder {
+            serde_json::from_value(json!({"coin": "ETH", "side": "buy", "sz": 0.01, "px": px}))
+                .unwrap()
         };
-        let notes = if missing.is_empty() {
-            None
-        } else {
-            Some(format!("missing cancel confirmations for {:?}", missing))
+        let mut source = CountingMids { calls: 0 };
+        let mut cache = MidCache::new(5_000);
+
+        let bid = resolve_limit_price(&order("bid-1%"), &mut source, &mut cache)
+            .await
+            .unwrap();
+        let ask = resolve_limit_price(&order("ask+1%"), &mut source, &mut cache)
+            .await
+            .unwrap();
+        assert!((bid - 2960.1).abs() < 1e-9);
+        assert!((ask - 3040.1).abs() < 1e-9);
+        assert_eq!(source.calls, 1);
+
+        assert_eq!(order_price_label(&order("bid-0.1%").px), "bid-0.1%");
+        assert_eq!(order_price_label(&order("ask+0.05%").px), "ask+0.05%");
+
+        let mid = demo_mid_for_coin("ETH");
+        let demo_bid = demo_limit_price(None, &order("bid+0%")).unwrap();
+        let demo_ask = demo_limit_price(None, &order("ask+0%")).unwrap();
+        assert!(demo_bid < mid && mid < demo_ask);
+        assert_eq!(demo_limit_price(None, &order("mid+0%")).unwrap(), mid);
+    }
+
+    #[tokio::test]
+    async fn ws_mids_take_precedence_while_fresh() {
+        let order: PerpOrder = serde_json::from_value(json!({
+            "coin": "ETH", "side": "buy", "sz": 0.01, "px": "mid+0%"
+        }))
+        .unwrap();
+        let ws_mids = WsMids::default();
+        let ctx: ActiveAssetCtxData = serde_json::from_value(json!({
+            "coin": "ETH",
+            "ctx": {
+                "dayNtlVlm": "0", "prevDayPx": "2900", "markPx": "3100.5", "midPx": "3100",
+                "funding": "0.0001", "openInterest": "1", "oraclePx": "3100"
+            }
+        }))
+        .unwrap();
+        ws_mids.update(&ctx);
+        let mut source = CountingMids { calls: 0 };
+        let mut cache = MidCache::new(5_000).with_ws_mids(Some(ws_mids));
+
+        let px = resolve_limit_price(&order, &mut source, &mut cache)
+            .await
+            .unwrap();
+        assert_eq!(px, 3100.0);
+        assert_eq!(source.calls, 0);
+
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        let px = resolve_limit_price(&order, &mut source, &mut cache)
+            .await
+            .unwrap();
+        assert_eq!(px, 3001.0);
+        assert_eq!(source.calls, 1);
+    }
+
+    #[test]
+    fn open_positions_are_found_by_coin() {
+        let position = |coin: &str, szi: &str| -> AssetPosition {
+            serde_json::from_value(json!({
+                "type": "oneWay",
+                "position": {
+                    "coin": coin, "entryPx": "3000", "liquidationPx": null,
+                    "leverage": { "type": "cross", "value": 5 },
+                    "marginUsed": "0", "positionValue": "0", "returnOnEquity": "0",
+                    "szi": szi, "unrealizedPnl": "0", "maxLeverage": 50,
+                    "cumFunding": { "allTime": "0", "sinceOpen": "0", "sinceChange": "0" }
+                }
+            }))
+            .unwrap()
         };
-        (observed_value, notes)
-    } else {
-        (None, Some("cancel request rejected".to_string()))
-    };
+        let positions = vec![position("BTC", "0.0"), position("ETH", "-0.02")];
 
-    let request_value = json!({
-        "cancel_oids": {
-            "coin": step.coin,
-            "oids": step.oids,
-        }
-    });
+        assert_eq!(open_position_size(&positions, "eth"), Some("-0.02"));
+        assert_eq!(open_position_size(&positions, "BTC"), None);
+        assert_eq!(open_position_size(&positions, "SOL"), None);
+    }
 
-    {
-        let mut artifacts = artifacts.lock().await;
-        let record = artifacts.make_action_record(
-            step_idx,
-            "cancel_oids",
-            submit_ts,
-            request_value,
-            Some(ack_value),
-            observed_value,
-            notes,
-        );
-        artifacts.log_action(&record)?;
+    #[tokio::test]
+    async fn demo_dry_set_leverage_logs_a_skipped_ack() {
+        let fixture = RunFixture::empty("hl-runner-leverage");
+        let out_dir = &fixture.dir;
+        let plan: Plan = serde_json::from_str(
+            r#"{"steps":[{"set_leverage":{"coin":"ETH","leverage":5,"drySetLeverage":true}}]}"#,
+        )
+        .expect("plan parses");
+        let artifacts = Arc::new(Mutex::new(
+            RunArtifacts::create(out_dir, &plan.as_json(), None, None, false).expect("artifacts"),
+        ));
+
+        let ActionStep::SetLeverage { set_leverage } = &plan.steps[0] else {
+            panic!("expected set_leverage step");
+        };
+        run_demo_set_leverage(0, set_leverage, &artifacts)
+            .await
+            .expect("step logged");
+
+        let per_action = std::fs::read_to_string(out_dir.join("per_action.jsonl")).unwrap();
+        let lines: Vec<&str> = per_action.lines().collect();
+        assert_eq!(lines.len(), 1);
+        let record: Value = serde_json::from_str(lines[0]).unwrap();
+        assert_eq!(record["action"], "set_leverage");
+        assert_eq!(record["ack"]["status"], "skipped");
+        assert_eq!(record["notes"], "dry_set_leverage: update not submitted");
+        assert!(record["observed"].is_null());
     }
 
-    Ok(())
-}
+    #[tokio::test]
+    async fn demo_sleep_step_writes_action_record() {
+        let fixture = RunFixture::empty("hl-runner-sleep");
+        let out_dir = &fixture.dir;
+        let plan: Plan = serde_json::from_str(r#"{"steps":[{"sleep_ms":{"duration_ms":250}}]}"#)
+            .expect("plan parses");
+        let artifacts = Arc::new(Mutex::new(
+            RunArtifacts::create(out_dir, &plan.as_json(), None, None, false).expect("artifacts"),
+        ));
+
+        let ActionStep::Sleep { sleep_ms } = &plan.steps[0] else {
+            panic!("expected sleep step");
+        };
+        log_sleep(0, sleep_ms, &artifacts, timestamp_ms(), None)
+            .await
+            .expect("sleep logged");
+
+        let per_action = std::fs::read_to_string(out_dir.join("per_action.jsonl")).unwrap();
+        let lines: Vec<&str> = per_action.lines().collect();
+        assert_eq!(lines.len(), 1);
+        let record: Value = serde_json::from_str(lines[0]).unwrap();
+        assert_eq!(record["action"], "sleep_ms");
+        assert_eq!(record["request"]["sleep_ms"]["duration_ms"], 250);
+        assert_eq!(record["ack"]["status"], "ok");
+    }
 
-#[allow(clippy::too_many_arguments)]
-async fn execute_cancel_all(
-    step_idx: usize,
-    step: &CancelAllStep,
-    artifacts: &Arc<Mutex<RunArtifacts>>,
-    exchange: &ExchangeClient,
-    placed_orders: &mut VecDeque<PlacedOrder>,
-    broadcaster: &broadcast::Sender<ObservedEvent>,
-    effect_timeout_ms: u64,
-) -> Result<()> {
-    let targets: Vec<PlacedOrder> = placed_orders
-        .iter()
-        .filter(|order| match &step.coin {
-            Some(coin) => &order.coin == coin,
-            None => true,
-        })
-        .cloned()
-        .collect();
+    #[tokio::test]
+    async fn demo_funding_rate_step_normalizes_to_market_signature() {
+        let fixture = RunFixture::empty("hl-runner-funding");
+        let out_dir = &fixture.dir;
+        let plan: Plan =
+            serde_json::from_str(r#"{"steps":[{"fetch_funding_rate":{"coin":"eth"}}]}"#)
+                .expect("plan parses");
+        let artifacts = Arc::new(Mutex::new(
+            RunArtifacts::create(out_dir, &plan.as_json(), None, None, false).expect("artifacts"),
+        ));
+
+        let ActionStep::FetchFundingRate { fetch_funding_rate } = &plan.steps[0] else {
+            panic!("expected fetch_funding_rate step");
+        };
+        log_funding_rate(
+            0,
+            fetch_funding_rate,
+            &artifacts,
+            timestamp_ms(),
+            Some((DEMO_FUNDING_RATE, 0.0)),
+            None,
+            None,
+        )
+        .await
+        .expect("funding rate logged");
+
+        let per_action = std::fs::read_to_string(out_dir.join("per_action.jsonl")).unwrap();
+        let record: hl_common::ActionLogRecord = serde_json::from_str(per_action.trim()).unwrap();
+        assert_eq!(record.observed.as_ref().unwrap()["fundingRate"], 0.0001);
+        let (signatures, err) = hl_common::normalize::action_signatures(&record);
+        assert!(err.is_none());
+        assert_eq!(signatures, vec!["market.fundingRate.ETH".to_string()]);
+    }
 
-    let submit_ts = timestamp_ms();
-    let mut notes = None;
-    let mut ack_value = json!({ "status": "skipped" });
-    let mut observed_value = None;
+    #[tokio::test]
+    async fn demo_spot_orders_log_spot_request_and_routing() {
+        let fixture = RunFixture::empty("hl-runner-spot");
+        let out_dir = &fixture.dir;
+        let plan: Plan = serde_json::from_value(json!({
+            "steps": [{"spot_orders": {"orders": [
+                {"coin": "PURR/USDC", "side": "buy", "sz": 10.0, "px": "mid-1%", "tif": "ALO"}
+            ]}}]
+        }))
+        .unwrap();
+        let artifacts = Arc::new(Mutex::new(
+            RunArtifacts::create(out_dir, &plan.as_json(), None, None, false).expect("artifacts"),
+        ));
+        let ActionStep::SpotOrders { spot_orders } = &plan.steps[0] else {
+            panic!("expected spot_orders step");
+        };
+        run_demo_perp_orders(
+            0,
+            &spot_orders.to_perp_orders(),
+            OrderSource::Spot(spot_orders),
+            &artifacts,
+            &BuilderCodes::default(),
+            None,
+            &mut OrderTracker::default(),
+            &mut 1,
+            NotionalLimits::default(),
+        )
+        .await
+        .unwrap();
+        drop(artifacts);
+
+        let reader = RunArtifacts::open(out_dir).unwrap();
+        let records: Vec<hl_common::ActionLogRecord> =
+            reader.iter_action_records().collect::<Result<_>>().unwrap();
+        assert_eq!(records[0].action, "spot_orders");
+        assert_eq!(
+            records[0].request["spot_orders"]["orders"][0]["resolvedPx"],
+            99.0
+        );
+        let (signatures, err) = hl_common::normalize::action_signatures(&records[0]);
+        assert!(err.is_none());
+        assert_eq!(signatures, vec!["spot.order.ALO".to_string()]);
+        let routed: Vec<RoutedOrderRecord> =
+            reader.iter_routed_orders().collect::<Result<_>>().unwrap();
+        assert!(routed[0].spot && !routed[0].reduce_only);
+    }
 
-    if targets.is_empty() {
-        notes = Some("no orders to cancel".to_string());
-    } else {
-        let mut receiver = broadcaster.subscribe();
-        let cancels: Vec<ClientCancelRequest> = targets
+    #[tokio::test]
+    async fn demo_if_step_takes_else_branch() {
+        let fixture = RunFixture::empty("hl-runner-if");
+        let out_dir = &fixture.dir;
+        let plan: Plan = serde_json::from_value(json!({
+            "steps": [
+                {"perp_orders": {"orders": [{"coin": "ETH", "side": "buy", "sz": 0.01, "px": "mid-1%"}]}},
+                {"if": {
+                    "condition": "allOrdersFilled",
+                    "thenSteps": [{"cancel_all": {}}],
+                    "elseSteps": [
+                        {"cancel_last": {}},
+                        {"if": {
+                            "condition": {"placedOrderCount": {"ge": 1}},
+                            "thenSteps": [{"sleep_ms": {"duration_ms": 50}}]
+                        }}
+                    ]
+                }},
+                {"set_leverage": {"coin": "ETH", "leverage": 5}}
+            ]
+        }))
+        .unwrap();
+        let artifacts = Arc::new(Mutex::new(
+            RunArtifacts::create(out_dir, &plan.as_json(), None, None, false).expect("artifacts"),
+        ));
+
+        run_demo_steps(
+            &plan,
+            artifacts.clone(),
+            &BuilderCodes::default(),
+            None,
+            None,
+            0,
+            NotionalLimits::default(),
+            &mut StepTrace::default(),
+        )
+        .await
+        .unwrap();
+        drop(artifacts);
+
+        let records: Vec<hl_common::ActionLogRecord> = RunArtifacts::open(out_dir)
+            .unwrap()
+            .iter_action_records()
+            .collect::<Result<_>>()
+            .unwrap();
+        let steps: Vec<(usize, &str, Option<&str>)> = records
             .iter()
-            .map(|order| ClientCancelRequest {
-                asset: order.coin.clone(),
-                oid: order.oid,
+            .map(|record| {
+                (
+                    record.step_idx,
+                    record.action.as_str(),
+                    record.branch.as_deref(),
+                )
             })
             .collect();
+        assert_eq!(
+            steps,
+            vec![
+                (0, "perp_orders", None),
+                (1, "cancel_last", Some("1.else.0")),
+                (1, "sleep_ms", Some("1.else.1.then.0")),
+                (2, "set_leverage", None),
+            ]
+        );
+        assert!(records
+            .iter()
+            .all(|record| record.verify_checksum() == Some(true)));
+    }
 
-        let response = exchange
-            .bulk_cancel(cancels, None)
-            .await
-            .context("failed to cancel tracked orders")?;
-        ack_value = exchange_status_json(&response);
-        if matches!(response, ExchangeResponseStatus::Ok(_)) {
-            let oids: Vec<u64> = targets.iter().map(|order| order.oid).collect();
-            remove_tracked_oids(placed_orders, &oids);
-
-            let wait = Duration::from_millis(effect_timeout_ms);
-            let mut observed = Vec::new();
-            let mut missing = Vec::new();
-            for oid in oids {
-                match wait_for_order_event(&mut receiver, oid, wait).await {
-                    Some(event) => observed.push(event.payload().clone()),
-                    None => missing.push(oid),
+    #[test]
+    fn step_queue_unrolls_loops_one_pass_at_a_time() {
+        let plan: Plan = serde_json::from_value(json!({
+            "steps": [{"loop": {"count": 1_000_000, "steps": [
+                {"loop": {"count": 1_000_000, "steps": [{"sleep_ms": {"duration_ms": 1}}]}}
+            ]}}]
+        }))
+        .unwrap();
+        let mut queue = StepQueue::new(&plan.steps);
+        let mut branches = Vec::new();
+        while branches.len() < 3 {
+            let QueuedStep { idx, branch, step } = queue.next().unwrap();
+            match step {
+                ActionStep::Loop { loop_step } => {
+                    queue.take_loop(idx, branch.as_deref(), loop_step)
                 }
+                _ => branches.push(branch.unwrap()),
             }
-
-            observed_value = if observed.is_empty() {
-                None
-            } else {
-                Some(serde_json::Value::Array(observed))
-            };
-            if !missing.is_empty() {
-                notes = Some(format!("missing cancel confirmations for {:?}", missing));
-            }
-        } else {
-            notes = Some("cancel request rejected".to_string());
+            assert!(queue.pending.len() <= 2);
         }
+        assert_eq!(
+            branches,
+            vec![
+                "0.loop_0.inner_0.loop_0.inner_0",
+                "0.loop_0.inner_0.loop_1.inner_0",
+                "0.loop_0.inner_0.loop_2.inner_0",
+            ]
+        );
     }
 
-    let request_value = json!({
-        "cancel_all": {
-            "coin": step.coin,
-        }
-    });
-
-    {
-        let mut artifacts = artifacts.lock().await;
-        let record = artifacts.make_action_record(
-            step_idx,
-            "cancel_all",
-            submit_ts,
-            request_value,
-            Some(ack_value),
-            observed_value,
-            notes,
+    #[tokio::test]
+    async fn demo_loop_step_runs_every_pass() {
+        let fixture = RunFixture::empty("hl-runner-loop");
+        let out_dir = &fixture.dir;
+        let plan: Plan = serde_json::from_value(json!({
+            "steps": [
+                {"loop": {"count": 3, "steps": [
+                    {"perp_orders": {"orders": [{"coin": "ETH", "side": "buy", "sz": 0.01, "px": "mid-1%"}]}},
+                    {"cancel_last": {}}
+                ]}},
+                {"set_leverage": {"coin": "ETH", "leverage": 5}}
+            ]
+        }))
+        .unwrap();
+        let artifacts = Arc::new(Mutex::new(
+            RunArtifacts::create(out_dir, &plan.as_json(), None, None, false).expect("artifacts"),
+        ));
+
+        run_demo_steps(
+            &plan,
+            artifacts.clone(),
+            &BuilderCodes::default(),
+            None,
+            None,
+            0,
+            NotionalLimits::default(),
+            &mut StepTrace::default(),
+        )
+        .await
+        .unwrap();
+        drop(artifacts);
+
+        let records: Vec<hl_common::ActionLogRecord> = RunArtifacts::open(out_dir)
+            .unwrap()
+            .iter_action_records()
+            .collect::<Result<_>>()
+            .unwrap();
+        let branches: Vec<(usize, Option<&str>)> = records
+            .iter()
+            .map(|record| (record.step_idx, record.branch.as_deref()))
+            .collect();
+        assert_eq!(
+            branches,
+            vec![
+                (0, Some("0.loop_0.inner_0")),
+                (0, Some("0.loop_0.inner_1")),
+                (0, Some("0.loop_1.inner_0")),
+                (0, Some("0.loop_1.inner_1")),
+                (0, Some("0.loop_2.inner_0")),
+                (0, Some("0.loop_2.inner_1")),
+                (1, None),
+            ]
         );
-        artifacts.log_action(&record)?;
+        let placed = records
+            .iter()
+            .filter(|record| record.action == "perp_orders")
+            .count();
+        assert_eq!(placed, 3);
     }
 
-    Ok(())
-}
+    #[tokio::test]
+    async fn demo_wait_for_fill_settles_on_indexed_ioc_order() {
+        let fixture = RunFixture::empty("hl-runner-wait");
+        let out_dir = &fixture.dir;
+        let plan: Plan = serde_json::from_value(json!({
+            "steps": [
+                {"perp_orders": {"orders": [
+                    {"coin": "ETH", "side": "buy", "sz": 0.01, "px": "mid+1%", "tif": "Ioc"},
+                    {"coin": "BTC", "side": "sell", "sz": 0.001, "px": "mid+1%", "tif": "Ioc"}
+                ]}},
+                {"wait_for_fill": {"oidIndex": -2, "timeoutMs": 1000}}
+            ]
+        }))
+        .unwrap();
+        let artifacts = Arc::new(Mutex::new(
+            RunArtifacts::create(out_dir, &plan.as_json(), None, None, false).expect("artifacts"),
+        ));
+
+        run_demo_steps(
+            &plan,
+            artifacts.clone(),
+            &BuilderCodes::default(),
+            None,
+            None,
+            0,
+            NotionalLimits::default(),
+            &mut StepTrace::default(),
+        )
+        .await
+        .unwrap();
+        drop(artifacts);
+
+        let records: Vec<hl_common::ActionLogRecord> = RunArtifacts::open(out_dir)
+            .unwrap()
+            .iter_action_records()
+            .collect::<Result<_>>()
+            .unwrap();
+        let first_oid = records[0].ack.as_ref().unwrap()["data"]["statuses"][0]["oid"].clone();
+        let wait = &records[1];
+        assert_eq!(wait.action, "wait_for_fill");
+        assert_eq!(wait.ack.as_ref().unwrap()["status"], "ok");
+        let observed = wait.observed.as_ref().unwrap();
+        assert_eq!(observed["oid"], first_oid);
+        assert_eq!(observed["coin"], "ETH");
+        assert_eq!(wait.request["wait_for_fill"]["oidIndex"], -2);
+    }
 
-async fn execute_class_transfer(
-    step_idx: usize,
-    step: &UsdClassTransferStep,
-    artifacts: &Arc<Mutex<RunArtifacts>>,
-    exchange: &ExchangeClient,
-    broadcaster: &broadcast::Sender<ObservedEvent>,
-    effect_timeout_ms: u64,
-) -> Result<()> {
-    let submit_ts = timestamp_ms();
-    let mut receiver = broadcaster.subscribe();
-    let response = exchange
-        .class_transfer(step.usdc, step.to_perp, None)
+    #[tokio::test(start_paused = true)]
+    async fn wait_for_fill_settles_on_fill_seen_before_the_step() {
+        let fixture = RunFixture::empty("hl-runner-wait-early");
+        let out_dir = &fixture.dir;
+        let artifacts = Arc::new(Mutex::new(fixture.artifacts(false)));
+        let (broadcaster, _) = broadcast::channel::<ObservedEvent>(8);
+        let mut fills = FillLog::new(broadcaster.subscribe());
+        let mut placed_orders = OrderTracker::default();
+        placed_orders.push("ETH", 41);
+        placed_orders.push("BTC", 42);
+        // Both orders fill while the order step is still running, before any wait starts.
+        for (oid, coin) in [(42, "BTC"), (41, "ETH")] {
+            broadcaster
+                .send(ObservedEvent::UserFill {
+                    oid,
+                    coin: coin.to_string(),
+                    payload: json!({ "oid": oid, "coin": coin }),
+                })
+                .unwrap();
+        }
+        fills.drain();
+        let step: WaitForFillStep = serde_json::from_value(json!({
+            "oid_index": 0,
+            "timeout_ms": 1_000,
+            "fail_on_timeout": true
+        }))
+        .unwrap();
+
+        let start = tokio::time::Instant::now();
+        execute_wait_for_fill(
+            0,
+            &step,
+            &artifacts,
+            &placed_orders,
+            &mut fills,
+            5_000,
+            false,
+        )
         .await
-        .context("failed to submit class transfer")?;
-    let ack_value = exchange_status_json(&response);
+        .unwrap();
+        assert_eq!(start.elapsed(), Duration::ZERO);
+        // The claimed fill is gone; the other one still settles a later wait.
+        assert!(fills.take(Some(41), None).is_none());
+        assert!(fills.take(None, Some("btc")).is_some());
+        drop(artifacts);
+
+        let records: Vec<hl_common::ActionLogRecord> = RunArtifacts::open(out_dir)
+            .unwrap()
+            .iter_action_records()
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(records[0].ack.as_ref().unwrap()["status"], "ok");
+        assert_eq!(records[0].observed.as_ref().unwrap()["oid"], 41);
+    }
 
-    let wait = Duration::from_millis(effect_timeout_ms);
-    let (observed_value, notes) = if matches!(response, ExchangeResponseStatus::Ok(_)) {
-        let observed = wait_for_ledger_event(&mut receiver, step.to_perp, wait).await;
-        if let Some(event) = observed {
-            (Some(event.payload().clone()), None)
-        } else {
-            (None, Some("no ledger update observed".to_string()))
+    #[tokio::test]
+    async fn wait_for_fill_timeout_is_logged_before_failing() {
+        let fixture = RunFixture::empty("hl-runner-wait-timeout");
+        let out_dir = &fixture.dir;
+        let artifacts = Arc::new(Mutex::new(fixture.artifacts(false)));
+        let (broadcaster, _) = broadcast::channel::<ObservedEvent>(4);
+        let mut fills = FillLog::new(broadcaster.subscribe());
+        let mut placed_orders = OrderTracker::default();
+        placed_orders.push("ETH", 41);
+        placed_orders.push("ETH", 42);
+        let mut step: WaitForFillStep = serde_json::from_value(json!({
+            "oid_index": 0,
+            "timeout_ms": 20
+        }))
+        .unwrap();
+
+        execute_wait_for_fill(
+            0,
+            &step,
+            &artifacts,
+            &placed_orders,
+            &mut fills,
+            5_000,
+            false,
+        )
+        .await
+        .unwrap();
+        step.fail_on_timeout = true;
+        let err = execute_wait_for_fill(
+            1,
+            &step,
+            &artifacts,
+            &placed_orders,
+            &mut fills,
+            5_000,
+            false,
+        )
+        .await
+        .unwrap_err();
+        assert!(err.to_string().contains("oid 41"));
+        drop(artifacts);
+
+        let records: Vec<hl_common::ActionLogRecord> = RunArtifacts::open(out_dir)
+            .unwrap()
+            .iter_action_records()
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(records.len(), 2);
+        for record in &records {
+            assert_eq!(record.ack.as_ref().unwrap()["status"], "timeout");
+            assert!(record.observed.is_none());
+            assert_eq!(
+                record.notes.as_deref(),
+                Some("no fill observed for oid 41 within 20 ms")
+            );
+            assert_eq!(record.verify_checksum(), Some(true));
         }
-    } else {
-        (None, Some("class transfer rejected".to_string()))
-    };
+        assert_eq!(records[1].request["wait_for_fill"]["failOnTimeout"], true);
+    }
 
-    let request_value = json!({
-        "usd_class_transfer": {
-            "toPerp": step.to_perp,
-            "usdc": step.usdc,
+    #[tokio::test]
+    async fn demo_l2_snapshots_cover_each_coin_once() {
+        let fixture = RunFixture::empty("hl-runner-l2");
+        let out_dir = &fixture.dir;
+        let plan: Plan = serde_json::from_value(json!({
+            "steps": [{"perp_orders": {"orders": [
+                {"coin": "ETH", "side": "buy", "sz": 0.01, "px": "mid-1%"},
+                {"coin": "ETH", "side": "sell", "sz": 0.01, "px": "mid+1%"},
+                {"coin": "BTC", "side": "buy", "sz": 0.001, "px": "mid-1%"}
+            ]}}]
+        }))
+        .unwrap();
+        let artifacts = Arc::new(Mutex::new(
+            RunArtifacts::create(out_dir, &plan.as_json(), None, None, false).expect("artifacts"),
+        ));
+
+        let ActionStep::PerpOrders { perp_orders } = &plan.steps[0] else {
+            panic!("expected perp_orders step");
+        };
+        {
+            let mut artifacts = artifacts.lock().await;
+            for coin in step_coins(perp_orders) {
+                artifacts
+                    .log_l2_snapshot(&demo_l2_snapshot(
+                        0,
+                        &coin,
+                        demo_mid_for_coin(&coin),
+                        3,
+                        timestamp_ms(),
+                    ))
+                    .expect("snapshot logged");
+            }
         }
-    });
 
-    {
-        let mut artifacts = artifacts.lock().await;
-        let record = artifacts.make_action_record(
-            step_idx,
-            "usd_class_transfer",
-            submit_ts,
-            request_value,
-            Some(ack_value),
-            observed_value,
-            notes,
+        let raw = std::fs::read_to_string(out_dir.join("l2_snapshots.jsonl")).unwrap();
+        let snaps: Vec<L2Snapshot> = raw
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(
+            snaps.iter().map(|s| s.coin.as_str()).collect::<Vec<_>>(),
+            ["ETH", "BTC"]
         );
-        artifacts.log_action(&record)?;
+        let eth = &snaps[0];
+        assert_eq!((eth.bids.len(), eth.asks.len()), (3, 3));
+        assert!(eth.bids[0][0] < 3_500.0 && eth.asks[0][0] > 3_500.0);
+        assert!(eth.bids[1][0] < eth.bids[0][0] && eth.asks[1][0] > eth.asks[0][0]);
     }
 
-    Ok(())
-}
-
-async fn execute_set_leverage(
-    step_idx: usize,
-    step: &SetLeverageStep,
-    artifacts: &Arc<Mutex<RunArtifacts>>,
-    exchange: &ExchangeClient,
-) -> Result<()> {
-    let submit_ts = timestamp_ms();
-    let response = exchange
-        .update_leverage(step.leverage, &step.coin, step.cross, None)
-        .await
-        .context("failed to update leverage")?;
-    let ack_value = exchange_status_json(&response);
-    let notes = if matches!(response, ExchangeResponseStatus::Ok(_)) {
-        None
-    } else {
-        Some("set leverage rejected".to_string())
-    };
-
-    let request_value = json!({
-        "set_leverage": {
-            "coin": step.coin,
-            "leverage": step.leverage,
-            "cross": step.cross,
+    #[tokio::test]
+    async fn assert_balance_fails_unless_fail_soft() {
+        let fixture = RunFixture::empty("hl-runner-assert");
+        let out_dir = &fixture.dir;
+        let plan: Plan = serde_json::from_str(
+            r#"{"steps":[
+                {"assert_balance":{"minPerpUsdc":10.0}},
+                {"assert_balance":{"minPerpUsdc":10.0,"failSoft":true}}
+            ]}"#,
+        )
+        .expect("plan parses");
+        let artifacts = Arc::new(Mutex::new(
+            RunArtifacts::create(out_dir, &plan.as_json(), None, None, false).expect("artifacts"),
+        ));
+
+        for (idx, step) in plan.steps.iter().enumerate() {
+            let ActionStep::AssertBalance { assert_balance } = step else {
+                panic!("expected assert_balance step");
+            };
+            let result = log_assert_balance(
+                idx,
+                assert_balance,
+                &artifacts,
+                timestamp_ms(),
+                4.5,
+                assert_balance.violation(4.5),
+                None,
+            )
+            .await;
+            assert_eq!(result.is_err(), !assert_balance.fail_soft);
         }
-    });
 
-    {
-        let mut artifacts = artifacts.lock().await;
-        let record = artifacts.make_action_record(
-            step_idx,
-            "set_leverage",
-            submit_ts,
-            request_value,
-            Some(ack_value),
-            None,
-            notes,
+        let per_action = std::fs::read_to_string(out_dir.join("per_action.jsonl")).unwrap();
+        let records: Vec<hl_common::ActionLogRecord> = per_action
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(records.len(), 2);
+        assert_eq!(
+            records[1].notes.as_deref(),
+            Some("assertion failed: balance 4.5 < min 10.0")
         );
-        artifacts.log_action(&record)?;
+        let (signatures, err) = hl_common::normalize::action_signatures(&records[1]);
+        assert!(signatures.is_empty());
+        assert!(err.is_some());
     }
 
-    Ok(())
+    #[tokio::test]
+    async fn dry_run_with_prices_logs_resolved_prices_without_submitting() {
+        let fixture = RunFixture::empty("hl-runner-dryrun");
+        let out_dir = &fixture.dir;
+        let plan: Plan = serde_json::from_value(json!({
+            "steps": [
+                {"perp_orders": {"orders": [{"coin": "ETH", "side": "buy", "sz": 0.01, "px": "mid+0%"}]}},
+                {"cancel_last": {}},
+                {"multi_coin_orders": {"coins": ["ETH"], "side": "sell", "sz": 0.01, "px": "mid+0%"}},
+                {"spot_orders": {"orders": [{"coin": "ETH", "side": "buy", "sz": 1.0, "px": "mid+0%"}]}}
+            ]
+        }))
+        .unwrap();
+        let artifacts = Arc::new(Mutex::new(
+            RunArtifacts::create(out_dir, &plan.as_json(), None, None, false).expect("artifacts"),
+        ));
+
+        run_dry_with_prices(
+            plan,
+            artifacts.clone(),
+            CountingMids { calls: 0 },
+            5_000,
+            &BuilderCodes {
+                global: Some("0xglobal".to_string()),
+                per_coin: parse_builder_code_per_coin("ETH:0xcoin").unwrap(),
+            },
+            &mut StepTrace::default(),
+        )
+        .await
+        .unwrap();
+
+        let per_action = std::fs::read_to_string(out_dir.join("per_action.jsonl")).unwrap();
+        let records: Vec<Value> = per_action
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(records.len(), 4);
+        assert_eq!(records[0]["ack"]["status"], "dryRun");
+        assert_eq!(
+            records[0]["ack"]["data"]["statuses"][0]["resolvedPx"],
+            3001.0
+        );
+        assert_eq!(
+            records[0]["request"]["perp_orders"]["builderCode"],
+            "0xglobal"
+        );
+        assert_eq!(
+            records[0]["request"]["perp_orders"]["orders"][0]["builderCode"],
+            "0xcoin"
+        );
+        assert_eq!(records[1]["action"], "cancel_last");
+        assert_eq!(records[1]["ack"]["status"], "dryRun");
+        for (record, action) in records[2..]
+            .iter()
+            .zip(["multi_coin_orders", "spot_orders"])
+        {
+            assert_eq!(record["action"], action);
+            assert_eq!(record["request"][action]["orders"][0]["resolvedPx"], 3001.0);
+            assert_eq!(record["ack"]["data"]["statuses"][0]["resolvedPx"], 3001.0);
+        }
+    }
 }
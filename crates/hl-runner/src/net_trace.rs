@@ -0,0 +1,143 @@
+use std::{
+    fs::{self, File},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use anyhow::{Context, Result};
+use reqwest::{header::HeaderMap, Request, Response};
+use reqwest_middleware::{Middleware, Next};
+use serde_json::{json, Map, Value};
+use task_local_extensions::Extensions;
+use tracing::warn;
+
+/// Headers whose values never reach the trace files.
+const REDACTED_HEADERS: [&str; 2] = ["authorization", "proxy-authorization"];
+
+/// `--record-network-trace`: saves each HTTP exchange as `trace_NNN_req.json` /
+/// `trace_NNN_res.json` under a directory.
+pub struct NetworkTrace {
+    dir: PathBuf,
+    next_seq: AtomicUsize,
+    record_response_bodies: bool,
+}
+
+impl NetworkTrace {
+    pub fn create(dir: &Path, record_response_bodies: bool) -> Result<Self> {
+        fs::create_dir_all(dir)
+            .with_context(|| format!("failed to create trace directory {}", dir.display()))?;
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            next_seq: AtomicUsize::new(0),
+            record_response_bodies,
+        })
+    }
+
+    fn write(&self, seq: usize, kind: &str, value: &Value) -> Result<()> {
+        let path = self.dir.join(format!("trace_{seq:03}_{kind}.json"));
+        let file =
+            File::create(&path).with_context(|| format!("failed to create {}", path.display()))?;
+        serde_json::to_writer_pretty(file, value)
+            .with_context(|| format!("failed to write {}", path.display()))
+    }
+
+    fn request_entry(req: &Request) -> Value {
+        json!({
+            "method": req.method().as_str(),
+            "url": req.url().as_str(),
+            "headers": redacted_headers(req.headers()),
+            "body": req.body().and_then(|body| body.as_bytes()).map(body_value),
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl Middleware for NetworkTrace {
+    async fn handle(
+        &self,
+        req: Request,
+        extensions: &mut Extensions,
+        next: Next<'_>,
+    ) -> reqwest_middleware::Result<Response> {
+        let seq = self.next_seq.fetch_add(1, Ordering::SeqCst);
+        // A trace that cannot be written must never fail the request itself.
+        if let Err(err) = self.write(seq, "req", &Self::request_entry(&req)) {
+            warn!("network trace: {err:#}");
+        }
+
+        let response = next.run(req, extensions).await?;
+        let status = response.status();
+        let mut entry = json!({
+            "status": status.as_u16(),
+            "url": response.url().as_str(),
+            "headers": redacted_headers(response.headers()),
+        });
+        let response = if self.record_response_bodies {
+            let version = response.version();
+            let headers = response.headers().clone();
+            let bytes = response.bytes().await?;
+            entry["body"] = body_value(&bytes);
+
+            // Reading the body consumes the response, so hand the caller a rebuilt one.
+            let mut rebuilt = http::Response::new(bytes);
+            *rebuilt.status_mut() = status;
+            *rebuilt.version_mut() = version;
+            *rebuilt.headers_mut() = headers;
+            Response::from(rebuilt)
+        } else {
+            response
+        };
+        if let Err(err) = self.write(seq, "res", &entry) {
+            warn!("network trace: {err:#}");
+        }
+        Ok(response)
+    }
+}
+
+fn redacted_headers(headers: &HeaderMap) -> Value {
+    let mut out = Map::new();
+    for (name, value) in headers {
+        let value = if REDACTED_HEADERS.contains(&name.as_str()) {
+            "[REDACTED]".to_string()
+        } else {
+            String::from_utf8_lossy(value.as_bytes()).into_owned()
+        };
+        out.insert(name.as_str().to_string(), Value::String(value));
+    }
+    Value::Object(out)
+}
+
+/// JSON bodies are embedded as JSON; anything else as a lossy UTF-8 string.
+fn body_value(bytes: &[u8]) -> Value {
+    serde_json::from_slice(bytes)
+        .unwrap_or_else(|_| Value::String(String::from_utf8_lossy(bytes).into_owned()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hl_common::test_support::RunFixture;
+
+    #[test]
+    fn request_entry_redacts_authorization() {
+        let client = reqwest::Client::new();
+        let req = client
+            .post("https://example.invalid/api")
+            .header("Authorization", "Bearer secret")
+            .header("X-Title", "bench")
+            .json(&json!({"model": "m"}))
+            .build()
+            .unwrap();
+
+        let entry = NetworkTrace::request_entry(&req);
+        assert_eq!(entry["method"], "POST");
+        assert_eq!(entry["headers"]["authorization"], "[REDACTED]");
+        assert_eq!(entry["headers"]["x-title"], "bench");
+        assert_eq!(entry["body"]["model"], "m");
+
+        let fixture = RunFixture::empty("hl-net-trace");
+        let trace = NetworkTrace::create(&fixture.dir, false).unwrap();
+        trace.write(0, "req", &entry).unwrap();
+        assert!(fixture.dir.join("trace_000_req.json").exists());
+    }
+}
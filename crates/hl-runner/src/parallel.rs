@@ -0,0 +1,272 @@
+use std::{fs, path::Path, time::Duration};
+
+use anyhow::{anyhow, Context, Result};
+use hl_common::{plan::is_stdin_plan_spec, RunArtifacts};
+use serde::Serialize;
+use serde_json::Value;
+use tokio::task::JoinSet;
+use tracing::{error, info};
+
+use crate::{prune_sibling_runs, run_once, Cli, FinalOutput, OutputFormat};
+
+/// Outcome of one `--parallel` task, read back from its run directory.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ParallelRunSummary {
+    pub index: usize,
+    pub plan: String,
+    pub out_dir: String,
+    pub success: bool,
+    pub error: Option<String>,
+    pub total_orders: usize,
+    pub filled_orders: usize,
+    pub avg_order_latency_ms: Option<f64>,
+}
+
+/// Aggregate across all tasks, written to `<out>/parallel_summary.json`.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ParallelSummary {
+    pub tasks: usize,
+    pub succeeded: usize,
+    pub total_orders: usize,
+    pub filled_orders: usize,
+    pub fill_rate: f64,
+    /// Mean of the per-task averages, weighted by each task's order count.
+    pub avg_order_latency_ms: Option<f64>,
+    pub runs: Vec<ParallelRunSummary>,
+}
+
+impl ParallelSummary {
+    pub fn aggregate(runs: Vec<ParallelRunSummary>) -> Self {
+        let total_orders: usize = runs.iter().map(|run| run.total_orders).sum();
+        let filled_orders: usize = runs.iter().map(|run| run.filled_orders).sum();
+        let (weighted, weight) = runs
+            .iter()
+            .filter_map(|run| Some((run.avg_order_latency_ms?, run.total_orders.max(1) as f64)))
+            .fold((0.0, 0.0), |(sum, total), (latency, weight)| {
+                (sum + latency * weight, total + weight)
+            });
+        Self {
+            tasks: runs.len(),
+            succeeded: runs.iter().filter(|run| run.success).count(),
+            total_orders,
+            filled_orders,
+            fill_rate: if total_orders == 0 {
+                0.0
+            } else {
+                filled_orders as f64 / total_orders as f64
+            },
+            avg_order_latency_ms: (weight > 0.0).then(|| weighted / weight),
+            runs,
+        }
+    }
+}
+
+/// Pairs each of the `tasks` slots with a plan spec: one spec is repeated, otherwise
+/// there must be exactly one spec per task.
+fn plan_specs(plans: &[String], tasks: usize) -> Result<Vec<String>> {
+    if tasks == 0 {
+        return Err(anyhow!("--parallel must be at least 1"));
+    }
+    let specs = match plans {
+        [single] => vec![single.clone(); tasks],
+        many if many.len() == tasks => many.to_vec(),
+        many => {
+            return Err(anyhow!(
+                "--parallel {tasks} needs one --plan or exactly {tasks}, got {}",
+                many.len()
+            ))
+        }
+    };
+    if specs.iter().filter(|spec| is_stdin_plan_spec(spec)).count() > 1 {
+        return Err(anyhow!("stdin (--plan -) can only feed one parallel task"));
+    }
+    Ok(specs)
+}
+
+/// The options for task `index`: its own plan, profile path and network-trace directory.
+fn task_cli(cli: &Cli, index: usize, spec: &str, task_out: &Path) -> Cli {
+    let mut task_cli = cli.clone();
+    task_cli.plan = vec![spec.to_string()];
+    task_cli.parallel = None;
+    task_cli.max_runs = None;
+    task_cli.profile = cli
+        .profile
+        .as_ref()
+        .and_then(|path| path.file_name())
+        .map(|name| task_out.join(name));
+    // Each task numbers its trace files from 000, so they cannot share a directory.
+    task_cli.record_network_trace = cli
+        .record_network_trace
+        .as_ref()
+        .map(|dir| dir.join(format!("run-{}", index + 1)));
+    task_cli
+}
+
+/// Runs every task concurrently through `run_once`, so each gets its own exchange/info
+/// clients, websocket subscription and event channel while sharing the wallet.
+pub async fn run_parallel(cli: &Cli, tasks: usize, timestamp: &str, out_dir: &Path) -> Result<()> {
+    let specs = plan_specs(&cli.plan, tasks)?;
+    fs::create_dir_all(out_dir)
+        .with_context(|| format!("failed to create {}", out_dir.display()))?;
+    info!(
+        "running {tasks} plan(s) in parallel under {}",
+        out_dir.display()
+    );
+
+    let mut set = JoinSet::new();
+    for (index, spec) in specs.into_iter().enumerate() {
+        let task_out = out_dir.join(format!("run-{}", index + 1));
+        let task_cli = task_cli(cli, index, &spec, &task_out);
+        let timestamp = timestamp.to_string();
+        let stagger = Duration::from_millis(cli.parallel_stagger_ms * index as u64);
+
+        set.spawn(async move {
+            tokio::time::sleep(stagger).await;
+            let mut output = FinalOutput::new(&task_out);
+            let result = run_once(&task_cli, &timestamp, &task_out, &mut output).await;
+            (index, spec, task_out, output, result)
+        });
+    }
+
+    let mut runs = Vec::with_capacity(tasks);
+    while let Some(joined) = set.join_next().await {
+        let (index, plan, task_out, output, result) = joined.context("parallel task panicked")?;
+        if let Err(err) = &result {
+            error!("parallel run {} failed: {err:#}", index + 1);
+        }
+        let (total_orders, filled_orders) = count_orders(&task_out).unwrap_or_default();
+        runs.push(ParallelRunSummary {
+            index,
+            plan,
+            out_dir: task_out.display().to_string(),
+            success: result.is_ok(),
+            error: result.err().map(|err| format!("{err:#}")),
+            total_orders,
+            filled_orders,
+            avg_order_latency_ms: output.avg_order_latency_ms,
+        });
+    }
+    runs.sort_by_key(|run| run.index);
+
+    let summary = ParallelSummary::aggregate(runs);
+    let summary_path = out_dir.join("parallel_summary.json");
+    fs::write(&summary_path, serde_json::to_vec_pretty(&summary)?)
+        .with_context(|| format!("failed to write {}", summary_path.display()))?;
+    info!(
+        "parallel runs: {}/{} succeeded, {} orders, fill rate {:.3}, summary at {}",
+        summary.succeeded,
+        summary.tasks,
+        summary.total_orders,
+        summary.fill_rate,
+        summary_path.display()
+    );
+
+    if let Some(max_runs) = cli.max_runs {
+        prune_sibling_runs(cli, out_dir, max_runs)?;
+    }
+
+    if matches!(cli.output_format, OutputFormat::Json) {
+        println!("{}", serde_json::to_string(&summary)?);
+    }
+    if summary.succeeded < summary.tasks {
+        return Err(anyhow!(
+            "{} of {} parallel runs failed",
+            summary.tasks - summary.succeeded,
+            summary.tasks
+        ));
+    }
+    Ok(())
+}
+
+/// Counts submitted and immediately filled orders in a run's `per_action` log.
+fn count_orders(run_dir: &Path) -> Result<(usize, usize)> {
+    let mut total = 0;
+    let mut filled = 0;
+    for record in RunArtifacts::open(run_dir)?.iter_action_records() {
+        let record = record?;
+        if record.action != "perp_orders" {
+            continue;
+        }
+        let statuses = record
+            .ack
+            .as_ref()
+            .and_then(|ack| ack["data"]["statuses"].as_array())
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+        total += record.request["perp_orders"]["orders"]
+            .as_array()
+            .map_or(statuses.len(), Vec::len);
+        filled += statuses
+            .iter()
+            .filter(|status| status.get("kind").and_then(Value::as_str) == Some("filled"))
+            .count();
+    }
+    Ok((total, filled))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    #[test]
+    fn plan_specs_repeat_single_or_match_count() {
+        let one = vec!["a.json".to_string()];
+        assert_eq!(plan_specs(&one, 3).unwrap(), vec!["a.json"; 3]);
+
+        let two = vec!["a.json".to_string(), "b.json".to_string()];
+        assert_eq!(plan_specs(&two, 2).unwrap(), two);
+        assert!(plan_specs(&two, 3).is_err());
+        assert!(plan_specs(&one, 0).is_err());
+        assert!(plan_specs(&["-".to_string()], 2).is_err());
+        assert!(plan_specs(&["-:1".to_string(), "a.json".to_string()], 2).is_ok());
+    }
+
+    #[test]
+    fn tasks_get_their_own_trace_directories() {
+        let cli = Cli::try_parse_from([
+            "hl-runner",
+            "--plan",
+            "llm:ci",
+            "--parallel",
+            "2",
+            "--record-network-trace",
+            "traces",
+        ])
+        .unwrap();
+        let dirs: Vec<_> = (0..2)
+            .map(|index| {
+                task_cli(&cli, index, "llm:ci", Path::new("out"))
+                    .record_network_trace
+                    .unwrap()
+            })
+            .collect();
+        assert_eq!(dirs[0], Path::new("traces/run-1"));
+        assert_eq!(dirs[1], Path::new("traces/run-2"));
+    }
+
+    #[test]
+    fn summary_aggregates_orders_and_latency() {
+        let run = |index, total_orders, filled_orders, latency| ParallelRunSummary {
+            index,
+            plan: "p.json".to_string(),
+            out_dir: format!("run-{index}"),
+            success: true,
+            error: None,
+            total_orders,
+            filled_orders,
+            avg_order_latency_ms: latency,
+        };
+        let summary = ParallelSummary::aggregate(vec![
+            run(0, 3, 3, Some(10.0)),
+            run(1, 1, 0, Some(30.0)),
+            run(2, 0, 0, None),
+        ]);
+        assert_eq!(summary.total_orders, 4);
+        assert_eq!(summary.filled_orders, 3);
+        assert_eq!(summary.fill_rate, 0.75);
+        assert_eq!(summary.avg_order_latency_ms, Some(15.0));
+    }
+}
@@ -0,0 +1,212 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use clap::Args;
+use hl_common::plan::{
+    check_rejected_coins, load_plan_with_vars, validate, Plan, ValidationError, ValidationWarning,
+};
+use serde::Serialize;
+
+use crate::{
+    llm::{parse_allowed_coins, sanitize_errors},
+    parse_key_value, NotionalLimits,
+};
+
+/// `hl-runner plan-validate`: checks a plan with no key needed (and no network, unless the plan
+/// is a URL).
+#[derive(Args, Debug, Clone)]
+pub struct PlanValidateArgs {
+    /// Plan spec, as for --plan (JSON, YAML, JSONL with :line selector, URL, or - for stdin)
+    #[arg(long, allow_hyphen_values = true)]
+    pub plan: String,
+
+    /// Value for a `{{KEY}}` placeholder in the plan (repeatable)
+    #[arg(long = "plan-var", value_name = "KEY=VALUE", value_parser = parse_key_value)]
+    pub plan_vars: Vec<(String, String)>,
+
+    /// Comma-separated coins order steps may trade (default: any)
+    #[arg(long, value_name = "CSV")]
+    pub allowed_coins: Option<String>,
+
+    /// Comma-separated coins no step may name, including cancels and leverage changes
+    #[arg(long, value_name = "CSV")]
+    pub reject_coins: Option<String>,
+}
+
+/// Printed to stdout as one JSON object.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlanValidateReport {
+    pub valid: bool,
+    pub step_count: usize,
+    pub errors: Vec<ValidationError>,
+    pub warnings: Vec<ValidationWarning>,
+    /// Set when the plan could not be read or parsed at all.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub load_error: Option<String>,
+}
+
+/// Runs `validate`, then the LLM `sanitize_plan` checks on steps `validate` passed, then
+/// the reject list.
+pub fn check_plan(
+    plan: &Plan,
+    allowed_coins: Option<Vec<String>>,
+    reject_coins: &[String],
+) -> PlanValidateReport {
+    let mut result = validate(plan, allowed_coins.as_deref());
+    // With no allow-list every coin the plan trades is allowed.
+    let allowed = allowed_coins.unwrap_or_else(|| plan.coins_used());
+    for (step_idx, message) in sanitize_errors(plan, allowed, NotionalLimits::default()) {
+        if !result.errors.iter().any(|err| err.step_idx == step_idx) {
+            result.errors.push(ValidationError { step_idx, message });
+        }
+    }
+    check_rejected_coins(plan, reject_coins, &mut result);
+    result.errors.sort_by_key(|err| err.step_idx);
+
+    PlanValidateReport {
+        valid: result.is_valid(),
+        step_count: plan.steps.len(),
+        errors: result.errors,
+        warnings: result.warnings,
+        load_error: None,
+    }
+}
+
+/// Prints the report as one JSON line and returns it; `main` exits with status 1 when it is
+/// not `valid`.
+pub fn run(args: &PlanValidateArgs) -> Result<PlanValidateReport> {
+    let vars: HashMap<String, String> = args.plan_vars.iter().cloned().collect();
+    let report = match load_plan_with_vars(&args.plan, &vars) {
+        Ok(plan) => check_plan(
+            &plan,
+            args.allowed_coins.as_deref().map(parse_allowed_coins),
+            &args
+                .reject_coins
+                .as_deref()
+                .map(parse_allowed_coins)
+                .unwrap_or_default(),
+        ),
+        Err(err) => PlanValidateReport {
+            valid: false,
+            step_count: 0,
+            errors: Vec::new(),
+            warnings: Vec::new(),
+            load_error: Some(format!("{err:#}")),
+        },
+    };
+    println!("{}", serde_json::to_string(&report)?);
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(raw: &str, allowed: Option<&[&str]>, reject: &[&str]) -> PlanValidateReport {
+        let plan: Plan = serde_json::from_str(raw).unwrap();
+        let strings = |coins: &[&str]| coins.iter().map(|coin| coin.to_string()).collect();
+        check_plan(&plan, allowed.map(strings), &strings(reject))
+    }
+
+    fn messages(report: &PlanValidateReport) -> Vec<String> {
+        report.errors.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn clean_plan_is_valid() {
+        let report = check(
+            r#"{"steps":[
+                {"perp_orders":{"orders":[{"coin":"ETH","side":"buy","sz":0.01,"px":"mid-1%","tif":"ALO"}]}},
+                {"cancel_oids":{"coin":"ETH","oids":[12]}},
+                {"set_leverage":{"coin":"ETH","leverage":20}}
+            ]}"#,
+            None,
+            &[],
+        );
+        assert!(report.valid, "{:?}", report.errors);
+        assert_eq!(report.step_count, 3);
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["valid"], true);
+        assert!(json.get("loadError").is_none());
+    }
+
+    #[test]
+    fn zero_cancel_oids_are_errors() {
+        let report = check(
+            r#"{"steps":[{"cancel_oids":{"coin":"ETH","oids":[3,0]}}]}"#,
+            None,
+            &[],
+        );
+        assert!(!report.valid);
+        assert_eq!(
+            messages(&report),
+            vec!["step 0: cancel_oids.oids must be non-zero"]
+        );
+    }
+
+    #[test]
+    fn leverage_must_be_within_llm_bounds() {
+        let report = check(
+            r#"{"steps":[
+                {"set_leverage":{"coin":"ETH","leverage":0}},
+                {"set_leverage":{"coin":"ETH","leverage":30}}
+            ]}"#,
+            None,
+            &[],
+        );
+        // 0 fails hl-common's venue check; 30 is within the venue's 50 but above the
+        // sanitize limit of 20.
+        assert_eq!(
+            messages(&report),
+            vec![
+                "step 0: leverage 0 must be between 1 and 50",
+                "step 1: leverage 30 must be between 1 and 20"
+            ]
+        );
+    }
+
+    #[test]
+    fn sanitize_checks_apply_allow_list_and_order_size() {
+        let raw = r#"{"steps":[
+            {"perp_orders":{"orders":[{"coin":"BTC","side":"buy","sz":0.01,"px":"mid-1%"}]}},
+            {"perp_orders":{"orders":[{"coin":"ETH","side":"buy","sz":0.00001,"px":"mid-1%"}]}}
+        ]}"#;
+        let report = check(raw, Some(&["ETH"]), &[]);
+        let errors = messages(&report);
+        assert_eq!(errors.len(), 2);
+        assert!(errors[0].starts_with("step 0: "), "{errors:?}");
+        assert!(errors[1].contains("order size 0.00001"), "{errors:?}");
+
+        let report = check(raw, None, &[]);
+        assert_eq!(messages(&report).len(), 1);
+    }
+
+    #[test]
+    fn reject_list_covers_every_coin_field() {
+        let report = check(
+            r#"{"steps":[
+                {"perp_orders":{"orders":[{"coin":"ETH","side":"buy","sz":0.01,"px":"mid-1%"}]}},
+                {"cancel_all":{"coin":"doge"}}
+            ]}"#,
+            None,
+            &["DOGE"],
+        );
+        assert_eq!(messages(&report), vec!["step 1: coin doge is rejected"]);
+    }
+
+    #[test]
+    fn unreadable_plans_are_reported_instead_of_exiting() {
+        let missing =
+            std::env::temp_dir().join(format!("hl-missing-{}.json", uuid::Uuid::new_v4()));
+        let report = run(&PlanValidateArgs {
+            plan: missing.display().to_string(),
+            plan_vars: Vec::new(),
+            allowed_coins: None,
+            reject_coins: None,
+        })
+        .unwrap();
+        assert!(!report.valid);
+        assert!(report.load_error.is_some());
+    }
+}
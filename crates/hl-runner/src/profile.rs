@@ -0,0 +1,128 @@
+use std::{fs::File, path::Path, time::Instant};
+
+use anyhow::{Context, Result};
+use hl_common::{plan::ActionStep, time::timestamp_ms};
+use serde_json::{json, Value};
+
+/// Per-step wall-clock timings, written as Chrome Trace Event JSON for `--profile`.
+#[derive(Debug, Default)]
+pub struct StepTrace {
+    events: Vec<Value>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct StepStart {
+    ts_ms: i64,
+    instant: Instant,
+}
+
+impl StepTrace {
+    pub fn start(&self) -> StepStart {
+        StepStart {
+            ts_ms: timestamp_ms(),
+            instant: Instant::now(),
+        }
+    }
+
+    pub fn record(&mut self, step_idx: usize, step: &ActionStep, start: StepStart) {
+        let elapsed_us = start.instant.elapsed().as_micros() as u64;
+        self.events.push(json!({
+            "name": step.kind(),
+            "ph": "X",
+            "ts": start.ts_ms * 1000,
+            "dur": elapsed_us,
+            "pid": 1,
+            "tid": 1,
+            "args": {
+                "step_idx": step_idx,
+                "coin": step_coin(step),
+            }
+        }));
+    }
+
+    /// Mean duration in milliseconds of the recorded steps of `kind`.
+    pub fn mean_duration_ms(&self, kind: &str) -> Option<f64> {
+        let durations: Vec<f64> = self
+            .events
+            .iter()
+            .filter(|event| event["name"] == kind)
+            .filter_map(|event| event["dur"].as_u64())
+            .map(|us| us as f64 / 1000.0)
+            .collect();
+        (!durations.is_empty()).then(|| durations.iter().sum::<f64>() / durations.len() as f64)
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({ "traceEvents": self.events })
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let file =
+            File::create(path).with_context(|| format!("failed to create {}", path.display()))?;
+        serde_json::to_writer_pretty(file, &self.to_json())
+            .with_context(|| format!("failed to write trace {}", path.display()))
+    }
+}
+
+fn step_coin(step: &ActionStep) -> Option<&str> {
+    match step {
+        ActionStep::PerpOrders { perp_orders } => {
+            perp_orders.orders.first().map(|order| order.coin.as_str())
+        }
+        ActionStep::MultiCoinOrders { multi_coin_orders } => {
+            multi_coin_orders.coins.first().map(String::as_str)
+        }
+        ActionStep::SpotOrders { spot_orders } => {
+            spot_orders.orders.first().map(|order| order.coin.as_str())
+        }
+        ActionStep::CancelLast { cancel_last } => cancel_last.coin.as_deref(),
+        ActionStep::CancelOids { cancel_oids } => Some(cancel_oids.coin.as_str()),
+        ActionStep::CancelAll { cancel_all } => cancel_all.coin.as_deref(),
+        ActionStep::SetLeverage { set_leverage } => Some(set_leverage.coin.as_str()),
+        ActionStep::WaitForFill { wait_for_fill } => wait_for_fill.coin.as_deref(),
+        ActionStep::FetchFundingRate { fetch_funding_rate } => {
+            Some(fetch_funding_rate.coin.as_str())
+        }
+        ActionStep::UsdClassTransfer { .. }
+        | ActionStep::SubAccountTransfer { .. }
+        | ActionStep::Sleep { .. }
+        | ActionStep::AssertBalance { .. }
+        | ActionStep::If { .. }
+        | ActionStep::Loop { .. } => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hl_common::plan::Plan;
+
+    #[test]
+    fn trace_contains_complete_event_per_step() {
+        let plan: Plan = serde_json::from_str(
+            r#"{"steps":[
+                {"perp_orders":{"orders":[{"coin":"ETH","side":"buy","sz":0.01,"px":3000}]}},
+                {"sleep_ms":{"duration_ms":10}}
+            ]}"#,
+        )
+        .unwrap();
+
+        let mut trace = StepTrace::default();
+        for (idx, step) in plan.steps.iter().enumerate() {
+            let start = trace.start();
+            trace.record(idx, step, start);
+        }
+
+        let json = trace.to_json();
+        let events = json["traceEvents"].as_array().unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0]["ph"], "X");
+        assert_eq!(events[0]["name"], "perp_orders");
+        assert_eq!(events[0]["args"]["coin"], "ETH");
+        assert_eq!(events[1]["name"], "sleep_ms");
+        assert_eq!(events[1]["args"]["step_idx"], 1);
+        assert!(events[1]["args"]["coin"].is_null());
+        assert!(events[0]["ts"].as_i64().unwrap() > 0);
+        assert!(events[0]["dur"].is_u64());
+    }
+}
@@ -0,0 +1,181 @@
+use anyhow::{Context, Result};
+use chrono::Utc;
+use ethers::{
+    signers::LocalWallet,
+    types::{
+        transaction::eip712::{Eip712, TypedData},
+        Signature, H160, H256,
+    },
+    utils::keccak256,
+};
+use hyperliquid_rust_sdk::{ExchangeClient, ExchangeResponseStatus, MAINNET_API_URL};
+use serde::Serialize;
+use serde_json::json;
+
+/// `subAccountTransfer` L1 action, which the SDK does not wrap. Field order matters:
+/// the msgpack encoding of this struct is what gets signed.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SubAccountTransferAction {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    sub_account_user: String,
+    is_deposit: bool,
+    usd: u64,
+}
+
+/// Signs and posts a sub-account transfer the same way the SDK posts its own L1 actions.
+pub async fn sub_account_transfer(
+    exchange: &ExchangeClient,
+    sub_account: H160,
+    usdc: f64,
+    is_deposit: bool,
+) -> Result<ExchangeResponseStatus> {
+    let action = SubAccountTransferAction {
+        kind: "subAccountTransfer",
+        sub_account_user: format!("0x{sub_account:x}"),
+        is_deposit,
+        // payload expects usdc without decimals
+        usd: (usdc * 1e6).round() as u64,
+    };
+    let nonce = Utc::now().timestamp_millis() as u64;
+    let connection_id = action_hash(&action, nonce, exchange.vault_address)?;
+    let is_mainnet = exchange.http_client.base_url == MAINNET_API_URL;
+    let signature = sign_l1_action(&exchange.wallet, connection_id, is_mainnet)?;
+
+    let payload = json!({
+        "action": action,
+        "signature": signature,
+        "nonce": nonce,
+        "vaultAddress": exchange.vault_address,
+    });
+    let response = exchange
+        .http_client
+        .client
+        .post(format!("{}/exchange", exchange.http_client.base_url))
+        .json(&payload)
+        .send()
+        .await
+        .context("failed to send sub-account transfer")?;
+    let text = response
+        .text()
+        .await
+        .context("failed to read sub-account transfer response")?;
+    serde_json::from_str(&text)
+        .with_context(|| format!("unexpected sub-account transfer response: {text}"))
+}
+
+fn action_hash(
+    action: &SubAccountTransferAction,
+    nonce: u64,
+    vault_address: Option<H160>,
+) -> Result<H256> {
+    let mut bytes = rmp_serde::to_vec_named(action).context("failed to encode action")?;
+    bytes.extend(nonce.to_be_bytes());
+    match vault_address {
+        Some(vault) => {
+            bytes.push(1);
+            bytes.extend(vault.to_fixed_bytes());
+        }
+        None => bytes.push(0),
+    }
+    Ok(H256(keccak256(bytes)))
+}
+
+/// EIP-712 "phantom agent" signature over the action hash, as used for every L1 action.
+fn sign_l1_action(
+    wallet: &LocalWallet,
+    connection_id: H256,
+    is_mainnet: bool,
+) -> Result<Signature> {
+    wallet
+        .sign_hash(agent_digest(connection_id, is_mainnet)?)
+        .context("failed to sign sub-account transfer")
+}
+
+fn agent_digest(connection_id: H256, is_mainnet: bool) -> Result<H256> {
+    let typed: TypedData = serde_json::from_value(json!({
+        "types": {
+            "EIP712Domain": [
+                {"name": "name", "type": "string"},
+                {"name": "version", "type": "string"},
+                {"name": "chainId", "type": "uint256"},
+                {"name": "verifyingContract", "type": "address"}
+            ],
+            "Agent": [
+                {"name": "source", "type": "string"},
+                {"name": "connectionId", "type": "bytes32"}
+            ]
+        },
+        "primaryType": "Agent",
+        "domain": {
+            "name": "Exchange",
+            "version": "1",
+            "chainId": 1337,
+            "verifyingContract": "0x0000000000000000000000000000000000000000"
+        },
+        "message": {
+            "source": if is_mainnet { "a" } else { "b" },
+            "connectionId": format!("{connection_id:#x}")
+        }
+    }))
+    .context("failed to build agent typed data")?;
+    let digest = typed
+        .encode_eip712()
+        .context("failed to encode agent typed data")?;
+    Ok(H256::from(digest))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::{
+        contract::{Eip712, EthAbiType},
+        signers::Signer,
+    };
+    use std::str::FromStr;
+
+    /// Mirrors the SDK's own (crate-private) agent type.
+    #[derive(Debug, Clone, Eip712, EthAbiType)]
+    #[eip712(
+        name = "Exchange",
+        version = "1",
+        chain_id = 1337,
+        verifying_contract = "0x0000000000000000000000000000000000000000"
+    )]
+    struct Agent {
+        source: String,
+        connection_id: H256,
+    }
+
+    #[test]
+    fn signs_the_sdk_agent_digest() {
+        let wallet = LocalWallet::from_str(
+            "e908f86dbb4d55ac876378565aafeabc187f6690f046459397b17d9b9a19688e",
+        )
+        .unwrap();
+        let action = SubAccountTransferAction {
+            kind: "subAccountTransfer",
+            sub_account_user: "0x1111111111111111111111111111111111111111".to_string(),
+            is_deposit: true,
+            usd: 5_000_000,
+        };
+        let connection_id = action_hash(&action, 1_700_000_000_000, None).unwrap();
+        let expected = Agent {
+            source: "b".to_string(),
+            connection_id,
+        }
+        .encode_eip712()
+        .unwrap();
+        assert_eq!(
+            agent_digest(connection_id, false).unwrap(),
+            H256::from(expected)
+        );
+
+        let signature = sign_l1_action(&wallet, connection_id, false).unwrap();
+        assert_eq!(
+            signature.recover(H256::from(expected)).unwrap(),
+            wallet.address()
+        );
+    }
+}
@@ -0,0 +1,73 @@
+use std::path::Path;
+
+use anyhow::{anyhow, Context, Result};
+use reqwest::{Certificate, ClientBuilder};
+
+/// `--skip-ssl-verify` / `--ca-cert`, applied to every HTTP client the runner builds
+/// (Hyperliquid info/exchange and OpenRouter).
+#[derive(Debug, Clone, Default)]
+pub struct TlsConfig {
+    skip_verify: bool,
+    ca_cert: Option<Certificate>,
+}
+
+impl TlsConfig {
+    pub fn new(skip_verify: bool, ca_cert: Option<&Path>) -> Result<Self> {
+        let ca_cert = ca_cert
+            .map(|path| {
+                let pem = std::fs::read_to_string(path)
+                    .with_context(|| format!("failed to read CA certificate {}", path.display()))?;
+                // The rustls backend defers parsing, so garbage would only surface as
+                // failed handshakes later.
+                if !pem.contains("-----BEGIN CERTIFICATE-----") {
+                    return Err(anyhow!(
+                        "{} is not a PEM-encoded certificate",
+                        path.display()
+                    ));
+                }
+                Certificate::from_pem(pem.as_bytes())
+                    .with_context(|| format!("failed to load CA certificate {}", path.display()))
+            })
+            .transpose()?;
+        Ok(Self {
+            skip_verify,
+            ca_cert,
+        })
+    }
+
+    pub fn apply(&self, mut builder: ClientBuilder) -> ClientBuilder {
+        if let Some(cert) = &self.ca_cert {
+            builder = builder.add_root_certificate(cert.clone());
+        }
+        builder.danger_accept_invalid_certs(self.skip_verify)
+    }
+
+    /// [`TlsConfig::apply`] for the blocking client that fetches plan URLs.
+    pub fn apply_blocking(
+        &self,
+        mut builder: reqwest::blocking::ClientBuilder,
+    ) -> reqwest::blocking::ClientBuilder {
+        if let Some(cert) = &self.ca_cert {
+            builder = builder.add_root_certificate(cert.clone());
+        }
+        builder.danger_accept_invalid_certs(self.skip_verify)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hl_common::test_support::RunFixture;
+
+    #[test]
+    fn ca_cert_must_be_pem() {
+        let fixture = RunFixture::empty("hl-ca");
+        let path = fixture.write("ca.pem", "not a certificate");
+        let err = TlsConfig::new(false, Some(&path)).unwrap_err();
+        assert!(format!("{err:#}").contains("is not a PEM-encoded certificate"));
+        assert!(TlsConfig::new(false, Some(&path.with_extension("missing"))).is_err());
+
+        let tls = TlsConfig::new(true, None).unwrap();
+        assert!(tls.apply(reqwest::Client::builder()).build().is_ok());
+    }
+}
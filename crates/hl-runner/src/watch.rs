@@ -0,0 +1,159 @@
+use std::{path::PathBuf, time::Duration};
+
+use anyhow::{anyhow, Context, Result};
+use chrono::Utc;
+use hl_common::plan::{is_plan_url, is_stdin_plan_spec, plan_spec_path};
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+use tokio::sync::mpsc;
+use tracing::{error, info, warn};
+
+use crate::{llm::LlmPlanSpec, run_once, runs_root, Cli, FinalOutput};
+
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// Runs the plan once, then again every time the plan file is saved, until Ctrl-C.
+///
+/// Each run writes to its own timestamped subdirectory of `--out` (or `runs/`).
+pub async fn watch_plan(cli: &Cli) -> Result<()> {
+    if LlmPlanSpec::parse(cli.plan_spec()).is_some() {
+        return Err(anyhow!("--watch only supports file plans, not llm:* specs"));
+    }
+    if is_plan_url(cli.plan_spec()) || is_stdin_plan_spec(cli.plan_spec()) {
+        return Err(anyhow!(
+            "--watch only supports file plans, not URLs or stdin"
+        ));
+    }
+
+    let plan_path = plan_spec_path(cli.plan_spec())?;
+    let plan_path = plan_path
+        .canonicalize()
+        .with_context(|| format!("failed to resolve plan file {}", plan_path.display()))?;
+    // Watch the directory so editors that save via rename are still picked up.
+    let watch_dir = plan_path
+        .parent()
+        .map(PathBuf::from)
+        .ok_or_else(|| anyhow!("plan file {} has no parent", plan_path.display()))?;
+
+    let (tx, mut rx) = mpsc::unbounded_channel::<Event>();
+    let mut watcher = notify::recommended_watcher(move |res: notify::Result<Event>| {
+        if let Ok(event) = res {
+            let _ = tx.send(event);
+        }
+    })
+    .context("failed to create plan file watcher")?;
+    watcher
+        .watch(&watch_dir, RecursiveMode::NonRecursive)
+        .with_context(|| format!("failed to watch {}", watch_dir.display()))?;
+
+    let base_dir = runs_root(cli);
+    let mut ctrl_c = std::pin::pin!(tokio::signal::ctrl_c());
+    let mut run_idx = 0usize;
+
+    loop {
+        run_idx += 1;
+        let timestamp = Utc::now().format("%Y%m%d-%H%M%S").to_string();
+        let out_dir = base_dir.join(format!("{timestamp}-{run_idx}"));
+        cli.output_format.print_human(&format!(
+            "==================== run {run_idx} ({timestamp}) ===================="
+        ));
+
+        let mut output = FinalOutput::new(&out_dir);
+        tokio::select! {
+            result = run_once(cli, &timestamp, &out_dir, &mut output) => {
+                if let Err(err) = result {
+                    error!("run {run_idx} failed: {err:#}");
+                }
+            }
+            _ = &mut ctrl_c => {
+                info!("interrupted during run {run_idx}; stopping watch");
+                return Ok(());
+            }
+        }
+
+        info!(
+            "watching {} for changes (Ctrl-C to stop)",
+            plan_path.display()
+        );
+        loop {
+            tokio::select! {
+                event = rx.recv() => {
+                    let Some(event) = event else {
+                        warn!("plan watcher closed; stopping watch");
+                        return Ok(());
+                    };
+                    if is_plan_change(&event, &plan_path) {
+                        break;
+                    }
+                }
+                _ = &mut ctrl_c => {
+                    info!("stopping watch");
+                    return Ok(());
+                }
+            }
+        }
+
+        // Let a burst of saves settle, then drop whatever queued up meanwhile.
+        tokio::time::sleep(DEBOUNCE).await;
+        while rx.try_recv().is_ok() {}
+    }
+}
+
+fn is_plan_change(event: &Event, plan_path: &PathBuf) -> bool {
+    matches!(event.kind, EventKind::Modify(_) | EventKind::Create(_))
+        && event.paths.iter().any(|path| {
+            path == plan_path || path.canonicalize().is_ok_and(|path| &path == plan_path)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+    use notify::event::{CreateKind, ModifyKind, RemoveKind};
+
+    fn event(kind: EventKind, path: &str) -> Event {
+        Event::new(kind).add_path(PathBuf::from(path))
+    }
+
+    #[test]
+    fn only_writes_to_the_plan_file_trigger_a_rerun() {
+        let plan_path = PathBuf::from("/plans/plan.json");
+
+        assert!(is_plan_change(
+            &event(EventKind::Modify(ModifyKind::Any), "/plans/plan.json"),
+            &plan_path
+        ));
+        // Editors that save via rename show up as a create.
+        assert!(is_plan_change(
+            &event(EventKind::Create(CreateKind::File), "/plans/plan.json"),
+            &plan_path
+        ));
+        assert!(!is_plan_change(
+            &event(EventKind::Modify(ModifyKind::Any), "/plans/other.json"),
+            &plan_path
+        ));
+        assert!(!is_plan_change(
+            &event(EventKind::Remove(RemoveKind::File), "/plans/plan.json"),
+            &plan_path
+        ));
+    }
+
+    #[tokio::test]
+    async fn watch_rejects_plans_that_are_not_files() {
+        for spec in ["llm:coverage", "https://example.com/plan.json", "-"] {
+            let cli = Cli::try_parse_from(["hl-runner", "--plan", spec, "--watch"]).unwrap();
+            let err = watch_plan(&cli).await.unwrap_err();
+            assert!(
+                err.to_string().contains("--watch only supports file plans"),
+                "{spec}: {err}"
+            );
+        }
+
+        let missing = std::env::temp_dir().join(format!("hl-watch-{}.json", uuid::Uuid::new_v4()));
+        let cli =
+            Cli::try_parse_from(["hl-runner", "--plan", missing.to_str().unwrap(), "--watch"])
+                .unwrap();
+        let err = watch_plan(&cli).await.unwrap_err();
+        assert!(err.to_string().contains("failed to resolve plan file"));
+    }
+}
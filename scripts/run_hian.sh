@@ -1,6 +1,11 @@
 #!/usr/bin/env bash
 set -euo pipefail
 
-echo "HiaN evaluation flow is not yet wired up." >&2
-echo "Add hl-evaluator once implemented, then update this script." >&2
-exit 1
+# Usage: scripts/run_hian.sh <run_dir> [ground_truth.json]
+run_dir="${1:?usage: $0 <run_dir> [ground_truth.json]}"
+ground="${2:-dataset/hian/case_128k/ground_truth.json}"
+
+cargo run -p hl-evaluator -- hian \
+  --ground "$ground" \
+  --per-action "$run_dir/per_action.jsonl" \
+  --out-dir "$run_dir"